@@ -0,0 +1,148 @@
+//! # Capture Backend Module
+//!
+//! This module provides the [`CamBackend`] trait, which abstracts over the source of raw frames
+//! used by the camera streams, allowing sources other than V4L2 devices to be plugged into the
+//! same decoding and rectification pipeline.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use crate::error::Result;
+use crate::format::PixelFormat;
+use crate::{GrayFloatImage, RgbFloatImage};
+
+// -----------------------------------------------------------------------------------------------
+// EXPORTS
+// -----------------------------------------------------------------------------------------------
+
+pub use playback::{PlaybackBackend, PlaybackSource, PlaybackTimestamps};
+pub use raw::{RawFrameReader, RawFrameWriter};
+pub use raw::{RawRecordingBackend, RawRecordingSource, RawReplayBackend, RawReplaySource};
+pub use synthetic::{SyntheticBackend, SyntheticSource, TestPattern};
+pub use v4l2::V4l2Backend;
+
+// -----------------------------------------------------------------------------------------------
+// MODULES
+// -----------------------------------------------------------------------------------------------
+
+mod playback;
+mod raw;
+mod synthetic;
+mod v4l2;
+
+// -----------------------------------------------------------------------------------------------
+// TRAITS
+// -----------------------------------------------------------------------------------------------
+
+/// A source of raw frames for a camera stream.
+///
+/// A backend is opened from its `Source`, started with a [`CamConfig`], and then repeatedly asked
+/// for frames until it is stopped. Stereo streams move each backend into its own capture thread,
+/// so implementations must be `Send`.
+pub trait CamBackend: Sized + Send + 'static {
+    /// Describes where the backend gets its frames from, for example the device path of a V4L2
+    /// camera.
+    type Source;
+
+    /// Open the backend from the given source.
+    fn open(source: &Self::Source) -> Result<Self>;
+
+    /// Start acquisition with the given configuration.
+    fn start(&mut self, config: &CamConfig) -> Result<()>;
+
+    /// Capture a single raw frame, blocking until one is available.
+    fn capture(&mut self) -> Result<RawFrame>;
+
+    /// Stop acquisition.
+    fn stop(&mut self) -> Result<()>;
+}
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// Acquisition parameters passed to a backend when it is started.
+///
+/// These mirror the fields of `rscam::Config`, with the default values matching rscam's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CamConfig {
+    /// Frame interval as `(numerator, denominator)` seconds, default is `(1, 10)`.
+    pub interval: (u32, u32),
+
+    /// Width and height of the frames, default is `(640, 480)`.
+    pub resolution: (u32, u32),
+
+    /// FourCC code of the frame format, default is `b"YUYV"`.
+    pub format: [u8; 4],
+
+    /// Storage method for interlaced video, default is `rscam::FIELD_NONE`.
+    pub field: u32,
+
+    /// Number of buffers in the capture queue, default is 2.
+    pub nbuffers: u32
+}
+
+/// A single undecoded frame produced by a backend.
+#[derive(Debug, Clone)]
+pub struct RawFrame {
+    /// The encoded frame data
+    pub data: Vec<u8>,
+
+    /// The format the data is encoded in
+    pub format: PixelFormat,
+
+    /// Width and height of the frame
+    pub resolution: (u32, u32),
+
+    /// Capture timestamp of the frame in microseconds
+    pub timestamp: u64
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Default for CamConfig {
+    fn default() -> Self {
+        Self {
+            interval: (1, 10),
+            resolution: (640, 480),
+            format: *b"YUYV",
+            field: rscam::FIELD_NONE,
+            nbuffers: 2
+        }
+    }
+}
+
+impl RawFrame {
+    /// Decode the frame into a greyscale image.
+    pub fn decode_gray(&self) -> Result<GrayFloatImage> {
+        self.format.decode_gray(&self.data, self.resolution)
+    }
+
+    /// Decode the frame into a colour image.
+    pub fn decode_rgb(&self) -> Result<RgbFloatImage> {
+        self.format.decode_rgb(&self.data, self.resolution)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+pub(crate) mod tests {
+
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// Create an empty directory for a test under the system temporary directory.
+    pub(crate) fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cv_camstream_{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+}
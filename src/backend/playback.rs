@@ -0,0 +1,495 @@
+//! # Playback Backend
+//!
+//! Replays a directory of recorded images, such as PNG or JPEG files, as if they were coming from
+//! a camera.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use image::ImageFormat;
+
+use crate::backend::{CamBackend, CamConfig, RawFrame};
+use crate::error::{Error, Result};
+use crate::format::PixelFormat;
+
+// -----------------------------------------------------------------------------------------------
+// ENUMERATIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Where a [`PlaybackBackend`] gets the timestamps of its frames from.
+///
+/// All timestamps are in microseconds, matching those of frames captured from a camera.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlaybackTimestamps {
+    /// Frames are timestamped at multiples of the configured interval, starting from zero.
+    Interval,
+
+    /// Timestamps are read from a CSV file with one `<file name>,<timestamp>` row per image.
+    ///
+    /// Blank lines, lines starting with `#`, and a header row whose timestamp column is not a
+    /// number are ignored.
+    Csv(PathBuf),
+
+    /// Timestamps are parsed from the leading digits of each file's name, for example
+    /// `1596109200123456.png` or `1596109200123456_left.png`.
+    Filename
+}
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// Describes the recording replayed by a [`PlaybackBackend`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaybackSource {
+    /// Directory containing the images, which are played in order of their timestamps if they are
+    /// read from the file names or a CSV file, otherwise in natural order of their file names so
+    /// that `9.png` comes before `10.png`
+    pub dir: PathBuf,
+
+    /// Where frame timestamps are taken from
+    pub timestamps: PlaybackTimestamps,
+
+    /// If true `capture` blocks until the next frame is due according to the configured interval,
+    /// otherwise frames are played as fast as they are requested.
+    pub paced: bool
+}
+
+/// Backend which replays images from a directory.
+///
+/// Every file whose extension is recognised by the `image` crate is played, other files are
+/// ignored. Frames keep the resolution they were saved at, the configured resolution and format
+/// are ignored. Once all images have been played `capture` returns [`Error::EndOfStream`].
+pub struct PlaybackBackend {
+    frames: Vec<PlaybackFrame>,
+
+    paced: bool,
+
+    config: Option<CamConfig>,
+
+    frame_index: usize,
+
+    start_time: Instant
+}
+
+/// An image file in the recording, and its timestamp if it was not derived from the interval.
+struct PlaybackFrame {
+    path: PathBuf,
+
+    format: ImageFormat,
+
+    timestamp: Option<u64>
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl PlaybackSource {
+    /// Create a new unpaced source replaying the given directory, timestamped by the interval.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            timestamps: PlaybackTimestamps::Interval,
+            paced: false
+        }
+    }
+
+    /// Set where the frame timestamps are taken from.
+    pub fn timestamps(mut self, timestamps: PlaybackTimestamps) -> Self {
+        self.timestamps = timestamps;
+
+        self
+    }
+
+    /// Set whether frames are paced at the configured interval.
+    pub fn paced(mut self, paced: bool) -> Self {
+        self.paced = paced;
+
+        self
+    }
+}
+
+impl CamBackend for PlaybackBackend {
+    type Source = PlaybackSource;
+
+    fn open(source: &Self::Source) -> Result<Self> {
+        if !source.dir.is_dir() {
+            return Err(Error::FileNotFound(source.dir.clone()));
+        }
+
+        // Find all images in the directory, in natural order of their names
+        let mut paths = fs::read_dir(&source.dir)
+            .map_err(|e| Error::playback(&source.dir, e))?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::io::Result<Vec<_>>>()
+            .map_err(|e| Error::playback(&source.dir, e))?;
+        paths.sort_by(|a, b| natural_cmp(&file_name(a), &file_name(b)));
+
+        let images: Vec<(PathBuf, ImageFormat)> = paths.into_iter()
+            .filter(|p| p.is_file())
+            .filter_map(|p| ImageFormat::from_path(&p).ok().map(|f| (p, f)))
+            .collect();
+
+        if images.is_empty() {
+            return Err(Error::CamStreamBuildError(
+                format!("No images found in {:?}", source.dir)
+            ));
+        }
+
+        // Resolve the timestamps of every image up front so that missing ones are reported early
+        let csv = match source.timestamps {
+            PlaybackTimestamps::Csv(ref path) => Some(read_timestamp_csv(path)?),
+            _ => None
+        };
+
+        let mut frames = images.into_iter()
+            .map(|(path, format)| {
+                let timestamp = match source.timestamps {
+                    PlaybackTimestamps::Interval => None,
+                    PlaybackTimestamps::Csv(ref csv_path) => {
+                        let name = file_name(&path);
+
+                        match csv.as_ref().and_then(|c| c.get(&name)) {
+                            Some(t) => Some(*t),
+                            None => return Err(Error::TimestampError(
+                                path.clone(),
+                                format!("no entry for {} in {:?}", name, csv_path)
+                            ))
+                        }
+                    },
+                    PlaybackTimestamps::Filename => Some(timestamp_from_filename(&path)?)
+                };
+
+                Ok(PlaybackFrame { path, format, timestamp })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // Play timestamped frames in temporal order, the sort is stable so frames with the same
+        // timestamp stay in name order
+        frames.sort_by_key(|f| f.timestamp);
+
+        Ok(Self {
+            frames,
+            paced: source.paced,
+            config: None,
+            frame_index: 0,
+            start_time: Instant::now()
+        })
+    }
+
+    fn start(&mut self, config: &CamConfig) -> Result<()> {
+        if config.interval.1 == 0 {
+            return Err(Error::CamStreamBuildError(format!(
+                "A playback stream cannot have a frame interval of {}/{} seconds",
+                config.interval.0,
+                config.interval.1
+            )));
+        }
+
+        self.config = Some(*config);
+        self.frame_index = 0;
+        self.start_time = Instant::now();
+
+        Ok(())
+    }
+
+    fn capture(&mut self) -> Result<RawFrame> {
+        let config = self.config.ok_or(Error::CamNotStartedError)?;
+
+        let frame = match self.frames.get(self.frame_index) {
+            Some(f) => f,
+            None => return Err(Error::EndOfStream)
+        };
+
+        // Time of this frame since the stream was started according to the interval
+        let frame_time = self.frame_index as u64 * config.interval.0 as u64 * 1_000_000
+            / config.interval.1 as u64;
+
+        if self.paced {
+            let due = self.start_time + Duration::from_micros(frame_time);
+            let now = Instant::now();
+
+            if due > now {
+                thread::sleep(due - now);
+            }
+        }
+
+        let data = fs::read(&frame.path).map_err(|e| Error::playback(&frame.path, e))?;
+        let resolution = image::io::Reader::with_format(Cursor::new(&data), frame.format)
+            .into_dimensions()
+            .map_err(|e| Error::playback(&frame.path, e))?;
+
+        self.frame_index += 1;
+
+        Ok(RawFrame {
+            data,
+            format: PixelFormat::Encoded(frame.format),
+            resolution,
+            timestamp: frame.timestamp.unwrap_or(frame_time)
+        })
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.config = None;
+
+        Ok(())
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// PRIVATE FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Get the file name of the given path as a string.
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Compare two file names in natural order, where runs of digits are compared by their numeric
+/// value rather than character by character.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let digits = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut run = String::new();
+                    while let Some(c) = chars.peek().copied().filter(char::is_ascii_digit) {
+                        run.push(c);
+                        chars.next();
+                    }
+                    run
+                };
+                let (x, y) = (digits(&mut a), digits(&mut b));
+                let (x_value, y_value) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+
+                // Longer numbers are larger, then equal length numbers compare as strings, and
+                // finally fewer leading zeros come first
+                let ordering = x_value.len().cmp(&y_value.len())
+                    .then_with(|| x_value.cmp(y_value))
+                    .then_with(|| x.len().cmp(&y.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            },
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+/// Parse the leading digits of a file's name as a timestamp.
+fn timestamp_from_filename(path: &Path) -> Result<u64> {
+    let name = file_name(path);
+    let digits: String = name.chars().take_while(|c| c.is_ascii_digit()).collect();
+
+    digits.parse().map_err(|_| Error::TimestampError(
+        path.to_path_buf(),
+        String::from("file name does not start with a timestamp")
+    ))
+}
+
+/// Read a CSV file of `<file name>,<timestamp>` rows into a map from file name to timestamp.
+fn read_timestamp_csv(path: &Path) -> Result<HashMap<String, u64>> {
+    if !path.exists() {
+        return Err(Error::FileNotFound(path.to_path_buf()));
+    }
+
+    let contents = fs::read_to_string(path).map_err(|e| Error::playback(path, e))?;
+    let mut timestamps = HashMap::new();
+
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut cols = line.split(',').map(str::trim);
+
+        let (name, timestamp) = match (cols.next(), cols.next()) {
+            (Some(n), Some(t)) => (n, t),
+            _ => return Err(Error::TimestampError(
+                path.to_path_buf(),
+                format!("line {} does not have a file name and timestamp", i + 1)
+            ))
+        };
+
+        match timestamp.parse() {
+            Ok(t) => {
+                // Allow rows to give the name with its directory, only the file name is matched
+                timestamps.insert(file_name(Path::new(name)), t);
+            },
+            // Treat a first row that isn't a number as a header
+            Err(_) if timestamps.is_empty() => continue,
+            Err(_) => return Err(Error::TimestampError(
+                path.to_path_buf(),
+                format!("cannot parse timestamp {:?} on line {}", timestamp, i + 1)
+            ))
+        }
+    }
+
+    Ok(timestamps)
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::CamStreamBuilder;
+    use crate::backend::tests::test_dir;
+    use crate::camstream::CamStream;
+
+    /// Write a 4x2 image with the given brightness to the directory.
+    fn write_image(dir: &Path, name: &str, brightness: u8) {
+        image::GrayImage::from_pixel(4, 2, image::Luma([brightness]))
+            .save(dir.join(name))
+            .unwrap();
+    }
+
+    /// Test that images are played in name order with timestamps from a CSV file
+    #[test]
+    fn test_csv_playback() {
+        let dir = test_dir("csv_playback");
+        write_image(&dir, "b.png", 255);
+        write_image(&dir, "a.png", 0);
+        fs::write(dir.join("times.csv"), "file,time\na.png,1000\nb.png,2500\n").unwrap();
+
+        let mut stream = CamStreamBuilder::new()
+            .mono()
+            .playback(PlaybackSource::new(&dir)
+                .timestamps(PlaybackTimestamps::Csv(dir.join("times.csv")))
+            )
+            .build()
+            .expect("Cannot build playback stream");
+
+        let a = stream.capture().expect("Cannot play first image");
+        assert_eq!((a.width(), a.height()), (4, 2));
+        assert_eq!(a.get(0, 0), 0.0);
+        assert_eq!(stream.capture().expect("Cannot play second image").get(0, 0), 1.0);
+
+        match stream.capture() {
+            Err(Error::EndOfStream) => (),
+            _ => panic!("Expected the end of the stream")
+        }
+
+        let mut cam = PlaybackBackend::open(&PlaybackSource::new(&dir)
+            .timestamps(PlaybackTimestamps::Csv(dir.join("times.csv")))
+        ).unwrap();
+        cam.start(&CamConfig::default()).unwrap();
+
+        assert_eq!(cam.capture().unwrap().timestamp, 1000);
+        assert_eq!(cam.capture().unwrap().timestamp, 2500);
+    }
+
+    /// Test that stereo pairs are played from two directories with timestamps from file names
+    #[test]
+    fn test_stereo_filename_playback() {
+        let left = test_dir("stereo_playback_left");
+        let right = test_dir("stereo_playback_right");
+
+        for (i, t) in [100u64, 200, 300].iter().enumerate() {
+            write_image(&left, &format!("{}_left.png", t), i as u8);
+            write_image(&right, &format!("{}_right.png", t), i as u8);
+        }
+
+        let mut stream = CamStreamBuilder::new()
+            .stereo()
+            .playback(
+                PlaybackSource::new(&left).timestamps(PlaybackTimestamps::Filename),
+                PlaybackSource::new(&right).timestamps(PlaybackTimestamps::Filename)
+            )
+            .build()
+            .expect("Cannot build stereo playback stream");
+
+        for t in &[100, 200, 300] {
+            let frame = stream.capture().expect("Cannot play stereo frame");
+
+            assert_eq!(frame.left_timestamp, *t);
+            assert_eq!(frame.right_timestamp, *t);
+        }
+
+        assert!(stream.capture().is_err());
+    }
+
+    /// Test that frames are played in temporal order when timestamps have different numbers of
+    /// digits, and in natural name order otherwise
+    #[test]
+    fn test_playback_order() {
+        let dir = test_dir("playback_order");
+        for (i, t) in [999u64, 1000, 10000].iter().enumerate() {
+            write_image(&dir, &format!("{}_left.png", t), i as u8);
+        }
+
+        let mut cam = PlaybackBackend::open(
+            &PlaybackSource::new(&dir).timestamps(PlaybackTimestamps::Filename)
+        ).unwrap();
+        cam.start(&CamConfig::default()).unwrap();
+
+        for t in &[999, 1000, 10000] {
+            assert_eq!(cam.capture().unwrap().timestamp, *t);
+        }
+
+        let mut names = vec!["frame10.png", "frame9.png", "frame010.png", "frame1.png", "a.png"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, ["a.png", "frame1.png", "frame9.png", "frame10.png", "frame010.png"]);
+    }
+
+    /// Test that missing timestamps are reported when opening
+    #[test]
+    fn test_missing_timestamp() {
+        let dir = test_dir("missing_timestamp");
+        write_image(&dir, "image.png", 0);
+
+        match PlaybackBackend::open(
+            &PlaybackSource::new(&dir).timestamps(PlaybackTimestamps::Filename)
+        ) {
+            Err(Error::TimestampError(..)) => (),
+            _ => panic!("Expected a TimestampError")
+        }
+    }
+
+    /// Test that images which cannot be read are reported with their path
+    #[test]
+    fn test_unreadable_image() {
+        let dir = test_dir("unreadable_image");
+        write_image(&dir, "0.png", 0);
+        fs::write(dir.join("1.png"), b"not a png").unwrap();
+
+        let mut cam = PlaybackBackend::open(&PlaybackSource::new(&dir)).unwrap();
+        cam.start(&CamConfig::default()).unwrap();
+
+        let frame = cam.capture().unwrap();
+        assert_eq!(frame.resolution, image::image_dimensions(dir.join("0.png")).unwrap());
+        match cam.capture() {
+            Err(Error::PlaybackError(m)) => assert!(m.contains("1.png"), "{}", m),
+            r => panic!("Expected a PlaybackError, got {:?}", r.err())
+        }
+    }
+}
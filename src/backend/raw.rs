@@ -0,0 +1,820 @@
+//! # Raw Recording Backends
+//!
+//! Records frames exactly as they are produced by a backend, before any decoding, into a single
+//! indexed container file, and replays those containers as a camera stream.
+//!
+//! The container is laid out as follows, with all integers little endian:
+//!
+//! ```text
+//! header:  b"CVCAMRAW", version: u32, fourcc: [u8; 4], width: u32, height: u32
+//! frames:  (timestamp: u64, length: u32, data: [u8; length])*
+//! index:   (offset: u64, timestamp: u64)*
+//! footer:  frame count: u64, index offset: u64, b"CVCAMIDX"
+//! ```
+//!
+//! The index and footer are only written when the recording is stopped, if they are missing the
+//! reader recovers the index by scanning the frames, ignoring a truncated final frame. Scanning
+//! stops at the first record whose length is implausible for the recorded resolution, so the
+//! partial index of a recording interrupted while stopping is not read as frames.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::backend::{CamBackend, CamConfig, RawFrame};
+use crate::error::{Error, Result};
+use crate::format::PixelFormat;
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Magic bytes at the start of a container.
+const HEADER_MAGIC: &[u8; 8] = b"CVCAMRAW";
+
+/// Magic bytes at the end of a container with a complete index.
+const FOOTER_MAGIC: &[u8; 8] = b"CVCAMIDX";
+
+/// Version of the container layout written by this crate.
+const VERSION: u32 = 1;
+
+/// Length of the header in bytes.
+const HEADER_LEN: u64 = 24;
+
+/// Length of the footer in bytes.
+const FOOTER_LEN: u64 = 24;
+
+/// Largest number of bytes per pixel of a plausible frame when scanning a container.
+const MAX_BYTES_PER_PIXEL: u64 = 4;
+
+/// Frames up to this many bytes are always plausible, allowing for the headers of encoded frames
+/// at small resolutions.
+const MIN_FRAME_LIMIT: u64 = 64 * 1024;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// Writes raw frames into a container file.
+pub struct RawFrameWriter {
+    path: PathBuf,
+
+    file: BufWriter<File>,
+
+    fourcc: [u8; 4],
+
+    resolution: (u32, u32),
+
+    /// Offset at which the next frame will be written
+    offset: u64,
+
+    /// Offset and timestamp of each frame written so far
+    index: Vec<(u64, u64)>
+}
+
+/// Reads raw frames from a container file.
+pub struct RawFrameReader {
+    path: PathBuf,
+
+    file: BufReader<File>,
+
+    fourcc: [u8; 4],
+
+    resolution: (u32, u32),
+
+    /// Offset and timestamp of each frame in the container
+    index: Vec<(u64, u64)>
+}
+
+/// Describes a [`RawRecordingBackend`], which records the frames of another backend.
+#[derive(Debug, Clone)]
+pub struct RawRecordingSource<S> {
+    /// The source of the backend being recorded
+    pub inner: S,
+
+    /// The container file to record to, which is overwritten if it exists
+    pub path: PathBuf
+}
+
+/// Backend which passes through the frames of another backend, writing each one untouched into a
+/// container file.
+///
+/// The container is created with the format and resolution of the first frame captured after
+/// the backend is started, and its index is written when it is stopped. Every later frame must
+/// have the same format and resolution, as they are replayed with those of the container.
+pub struct RawRecordingBackend<B: CamBackend> {
+    inner: B,
+
+    path: PathBuf,
+
+    started: bool,
+
+    writer: Option<RawFrameWriter>
+}
+
+/// Describes the container replayed by a [`RawReplayBackend`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawReplaySource {
+    /// The container file to replay
+    pub path: PathBuf,
+
+    /// If true `capture` blocks until the next frame is due according to the recorded
+    /// timestamps, otherwise frames are played as fast as they are requested.
+    pub paced: bool
+}
+
+/// Backend which replays the frames in a container file with their recorded timestamps.
+///
+/// The configured resolution, interval and format are ignored in favour of those recorded. Once
+/// all frames have been played `capture` returns [`Error::EndOfStream`].
+pub struct RawReplayBackend {
+    reader: RawFrameReader,
+
+    paced: bool,
+
+    pixel_format: PixelFormat,
+
+    started: bool,
+
+    frame_index: usize,
+
+    start_time: Instant
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl RawFrameWriter {
+    /// Create a new container for frames of the given format and resolution.
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        fourcc: [u8; 4],
+        resolution: (u32, u32)
+    ) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = File::create(&path)
+            .map(BufWriter::new)
+            .map_err(|e| Error::recording(&path, e))?;
+
+        let mut header = Vec::with_capacity(HEADER_LEN as usize);
+        header.extend_from_slice(HEADER_MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&fourcc);
+        header.extend_from_slice(&resolution.0.to_le_bytes());
+        header.extend_from_slice(&resolution.1.to_le_bytes());
+        file.write_all(&header).map_err(|e| Error::recording(&path, e))?;
+
+        Ok(Self {
+            path,
+            file,
+            fourcc,
+            resolution,
+            offset: HEADER_LEN,
+            index: Vec::new()
+        })
+    }
+
+    /// Get the FourCC code of the frames in the container.
+    pub fn fourcc(&self) -> [u8; 4] {
+        self.fourcc
+    }
+
+    /// Get the resolution of the frames in the container.
+    pub fn resolution(&self) -> (u32, u32) {
+        self.resolution
+    }
+
+    /// Append a frame to the container, which must have the container's format and resolution.
+    pub fn write(&mut self, frame: &RawFrame) -> Result<()> {
+        if frame.format.fourcc() != Some(self.fourcc) || frame.resolution != self.resolution {
+            return Err(Error::recording(&self.path, format!(
+                "a {:?} frame at {}x{} does not match the {} frames at {}x{} of the container",
+                frame.format,
+                frame.resolution.0,
+                frame.resolution.1,
+                String::from_utf8_lossy(&self.fourcc),
+                self.resolution.0,
+                self.resolution.1
+            )));
+        }
+
+        let Self { path, file, .. } = self;
+
+        file.write_all(&frame.timestamp.to_le_bytes())
+            .and_then(|_| file.write_all(&(frame.data.len() as u32).to_le_bytes()))
+            .and_then(|_| file.write_all(&frame.data))
+            .map_err(|e| Error::recording(path, e))?;
+
+        self.index.push((self.offset, frame.timestamp));
+        self.offset += 12 + frame.data.len() as u64;
+
+        Ok(())
+    }
+
+    /// Get the number of frames written so far.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns true if no frames have been written.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Write the index and footer, completing the container.
+    pub fn finish(mut self) -> Result<()> {
+        let mut tail = Vec::with_capacity(self.index.len() * 16 + FOOTER_LEN as usize);
+
+        for (offset, timestamp) in &self.index {
+            tail.extend_from_slice(&offset.to_le_bytes());
+            tail.extend_from_slice(&timestamp.to_le_bytes());
+        }
+
+        tail.extend_from_slice(&(self.index.len() as u64).to_le_bytes());
+        tail.extend_from_slice(&self.offset.to_le_bytes());
+        tail.extend_from_slice(FOOTER_MAGIC);
+
+        let Self { path, file, .. } = &mut self;
+
+        file.write_all(&tail)
+            .and_then(|_| file.flush())
+            .map_err(|e| Error::recording(path, e))
+    }
+}
+
+impl RawFrameReader {
+    /// Open a container, reading its index.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        if !path.exists() {
+            return Err(Error::FileNotFound(path));
+        }
+
+        let mut file = File::open(&path)
+            .map(BufReader::new)
+            .map_err(|e| Error::raw_container(&path, e))?;
+
+        // Read and check the header
+        let mut header = [0u8; HEADER_LEN as usize];
+        file.read_exact(&mut header).map_err(|e| Error::raw_container(&path, e))?;
+
+        if &header[0..8] != HEADER_MAGIC {
+            return Err(Error::raw_container(&path, "not a raw frame container"));
+        }
+
+        let version = read_u32(&header[8..12]);
+        if version != VERSION {
+            return Err(Error::raw_container(&path, format!("unsupported version {}", version)));
+        }
+
+        let mut fourcc = [0u8; 4];
+        fourcc.copy_from_slice(&header[12..16]);
+        let resolution = (read_u32(&header[16..20]), read_u32(&header[20..24]));
+
+        let index = match read_index(&mut file, &path)? {
+            Some(index) => index,
+            None => scan_index(&mut file, &path, resolution)?
+        };
+
+        Ok(Self {
+            path,
+            file,
+            fourcc,
+            resolution,
+            index
+        })
+    }
+
+    /// Get the FourCC code of the recorded frames.
+    pub fn fourcc(&self) -> [u8; 4] {
+        self.fourcc
+    }
+
+    /// Get the resolution the frames were recorded at.
+    pub fn resolution(&self) -> (u32, u32) {
+        self.resolution
+    }
+
+    /// Get the number of frames in the container.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns true if the container has no frames.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Get the timestamp of every frame in the container.
+    pub fn timestamps(&self) -> Vec<u64> {
+        self.index.iter().map(|(_, t)| *t).collect()
+    }
+
+    /// Read the data and timestamp of the frame at the given index, returning `None` if it is past
+    /// the end of the container.
+    pub fn read(&mut self, i: usize) -> Result<Option<(Vec<u8>, u64)>> {
+        let (offset, timestamp) = match self.index.get(i) {
+            Some(entry) => *entry,
+            None => return Ok(None)
+        };
+
+        let Self { path, file, .. } = self;
+        let mut record = [0u8; 12];
+
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.read_exact(&mut record))
+            .map_err(|e| Error::raw_container(path, e))?;
+
+        let mut data = vec![0u8; read_u32(&record[8..12]) as usize];
+        file.read_exact(&mut data).map_err(|e| Error::raw_container(path, e))?;
+
+        Ok(Some((data, timestamp)))
+    }
+}
+
+impl<S> RawRecordingSource<S> {
+    /// Record the backend opened from `inner` to the container at `path`.
+    pub fn new<P: AsRef<Path>>(inner: S, path: P) -> Self {
+        Self {
+            inner,
+            path: path.as_ref().to_path_buf()
+        }
+    }
+}
+
+impl<B: CamBackend> CamBackend for RawRecordingBackend<B> {
+    type Source = RawRecordingSource<B::Source>;
+
+    fn open(source: &Self::Source) -> Result<Self> {
+        Ok(Self {
+            inner: B::open(&source.inner)?,
+            path: source.path.clone(),
+            started: false,
+            writer: None
+        })
+    }
+
+    fn start(&mut self, config: &CamConfig) -> Result<()> {
+        self.inner.start(config)?;
+        self.started = true;
+        self.writer = None;
+
+        Ok(())
+    }
+
+    fn capture(&mut self) -> Result<RawFrame> {
+        if !self.started {
+            return Err(Error::CamNotStartedError);
+        }
+
+        let frame = self.inner.capture()?;
+
+        // The configured format may not be the one the backend produces, so the container takes
+        // its format and resolution from the first frame
+        let writer = match self.writer {
+            Some(ref mut w) => w,
+            None => {
+                let fourcc = frame.format.fourcc().ok_or_else(|| Error::recording(
+                    &self.path,
+                    format!("{:?} frames cannot be recorded", frame.format)
+                ))?;
+
+                self.writer.get_or_insert(
+                    RawFrameWriter::create(&self.path, fourcc, frame.resolution)?
+                )
+            }
+        };
+        writer.write(&frame)?;
+
+        Ok(frame)
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        // Always stop the inner backend, even if the container cannot be completed
+        let stopped = self.inner.stop();
+        self.started = false;
+
+        if let Some(w) = self.writer.take() {
+            w.finish()?;
+        }
+
+        stopped
+    }
+}
+
+impl RawReplaySource {
+    /// Create a new unpaced source replaying the given container.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            paced: false
+        }
+    }
+
+    /// Set whether frames are paced according to their recorded timestamps.
+    pub fn paced(mut self, paced: bool) -> Self {
+        self.paced = paced;
+
+        self
+    }
+}
+
+impl CamBackend for RawReplayBackend {
+    type Source = RawReplaySource;
+
+    fn open(source: &Self::Source) -> Result<Self> {
+        let reader = RawFrameReader::open(&source.path)?;
+
+        // Frames must be decodable to be replayed through a stream
+        let pixel_format = PixelFormat::from_fourcc(&reader.fourcc())
+            .ok_or_else(|| Error::ImageFormatError(
+                String::from_utf8_lossy(&reader.fourcc()).into_owned()
+            ))?;
+
+        Ok(Self {
+            reader,
+            paced: source.paced,
+            pixel_format,
+            started: false,
+            frame_index: 0,
+            start_time: Instant::now()
+        })
+    }
+
+    fn start(&mut self, _: &CamConfig) -> Result<()> {
+        self.started = true;
+        self.frame_index = 0;
+        self.start_time = Instant::now();
+
+        Ok(())
+    }
+
+    fn capture(&mut self) -> Result<RawFrame> {
+        if !self.started {
+            return Err(Error::CamNotStartedError);
+        }
+
+        let (data, timestamp) = match self.reader.read(self.frame_index)? {
+            Some(f) => f,
+            None => return Err(Error::EndOfStream)
+        };
+
+        if self.paced {
+            // Play frames at the same spacing they were recorded at
+            let first = self.reader.index[0].1;
+            let due = self.start_time + Duration::from_micros(timestamp.saturating_sub(first));
+            let now = Instant::now();
+
+            if due > now {
+                thread::sleep(due - now);
+            }
+        }
+
+        self.frame_index += 1;
+
+        Ok(RawFrame {
+            data,
+            format: self.pixel_format,
+            resolution: self.reader.resolution(),
+            timestamp
+        })
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.started = false;
+
+        Ok(())
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// PRIVATE FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Read a little endian `u32` from the first four bytes of the slice.
+fn read_u32(bytes: &[u8]) -> u32 {
+    let mut b = [0u8; 4];
+    b.copy_from_slice(&bytes[0..4]);
+
+    u32::from_le_bytes(b)
+}
+
+/// Read a little endian `u64` from the first eight bytes of the slice.
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut b = [0u8; 8];
+    b.copy_from_slice(&bytes[0..8]);
+
+    u64::from_le_bytes(b)
+}
+
+/// Read the index from the end of a container, returning `None` if it has no valid footer.
+fn read_index(file: &mut BufReader<File>, path: &Path) -> Result<Option<Vec<(u64, u64)>>> {
+    let len = file.seek(SeekFrom::End(0)).map_err(|e| Error::raw_container(path, e))?;
+
+    if len < HEADER_LEN + FOOTER_LEN {
+        return Ok(None);
+    }
+
+    let mut footer = [0u8; FOOTER_LEN as usize];
+    file.seek(SeekFrom::Start(len - FOOTER_LEN))
+        .and_then(|_| file.read_exact(&mut footer))
+        .map_err(|e| Error::raw_container(path, e))?;
+
+    if &footer[16..24] != FOOTER_MAGIC {
+        return Ok(None);
+    }
+
+    let count = read_u64(&footer[0..8]);
+    let index_offset = read_u64(&footer[8..16]);
+
+    // The index must fit exactly between the frames and the footer
+    let index_len = match count.checked_mul(16) {
+        Some(l) if index_offset.checked_add(l) == Some(len - FOOTER_LEN) => l,
+        _ => return Ok(None)
+    };
+
+    let mut raw_index = vec![0u8; index_len as usize];
+    file.seek(SeekFrom::Start(index_offset))
+        .and_then(|_| file.read_exact(&mut raw_index))
+        .map_err(|e| Error::raw_container(path, e))?;
+
+    Ok(Some(raw_index
+        .chunks(16)
+        .map(|entry| (read_u64(&entry[0..8]), read_u64(&entry[8..16])))
+        .collect()
+    ))
+}
+
+/// Rebuild the index of a container without a footer by reading each frame's record in turn.
+///
+/// Scanning stops at a truncated final frame, or at a record whose length is zero or too large for
+/// a frame at the given resolution, such as part of an index whose footer was never written.
+fn scan_index(
+    file: &mut BufReader<File>,
+    path: &Path,
+    resolution: (u32, u32)
+) -> Result<Vec<(u64, u64)>> {
+    let len = file.seek(SeekFrom::End(0)).map_err(|e| Error::raw_container(path, e))?;
+    let max_frame_len = (resolution.0 as u64 * resolution.1 as u64 * MAX_BYTES_PER_PIXEL)
+        .max(MIN_FRAME_LIMIT);
+    let mut offset = HEADER_LEN;
+    let mut index = Vec::new();
+
+    while offset + 12 <= len {
+        let mut record = [0u8; 12];
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.read_exact(&mut record))
+            .map_err(|e| Error::raw_container(path, e))?;
+
+        let frame_len = read_u32(&record[8..12]) as u64;
+        if frame_len == 0 || frame_len > max_frame_len {
+            break;
+        }
+
+        // Ignore a frame which was only partially written
+        let next = offset + 12 + frame_len;
+        if next > len {
+            break;
+        }
+
+        index.push((offset, read_u64(&record[0..8])));
+        offset = next;
+    }
+
+    Ok(index)
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use image::ImageFormat;
+
+    /// Get a path for a test container under the system temporary directory.
+    fn test_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("cv_camstream_{}.cvraw", name))
+    }
+
+    /// Build a frame whose data identifies it.
+    fn frame(i: u8) -> RawFrame {
+        RawFrame {
+            data: vec![i; 10 + i as usize],
+            format: PixelFormat::Encoded(ImageFormat::Jpeg),
+            resolution: (640, 480),
+            timestamp: 1000 * i as u64
+        }
+    }
+
+    /// Test that frames are read back bit exact, with and without the index
+    #[test]
+    fn test_round_trip() {
+        let path = test_path("round_trip");
+
+        let mut writer = RawFrameWriter::create(&path, *b"MJPG", (640, 480)).unwrap();
+        for i in 0..5 {
+            writer.write(&frame(i)).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut reader = RawFrameReader::open(&path).unwrap();
+        assert_eq!(reader.fourcc(), *b"MJPG");
+        assert_eq!(reader.resolution(), (640, 480));
+        assert_eq!(reader.timestamps(), vec![0, 1000, 2000, 3000, 4000]);
+
+        for i in (0..5).rev() {
+            let (data, timestamp) = reader.read(i as usize).unwrap().unwrap();
+
+            assert_eq!(data, frame(i).data);
+            assert_eq!(timestamp, frame(i).timestamp);
+        }
+        assert!(reader.read(5).unwrap().is_none());
+
+        // Drop the index and footer, and half of the last frame, as if recording was interrupted
+        let bytes = std::fs::read(&path).unwrap();
+        let last_offset = reader.index[4].0 as usize;
+        std::fs::write(&path, &bytes[..last_offset + 16]).unwrap();
+
+        let mut reader = RawFrameReader::open(&path).unwrap();
+        assert_eq!(reader.len(), 4);
+        assert_eq!(reader.read(3).unwrap().unwrap().0, frame(3).data);
+    }
+
+    /// Test that a corrupt footer or a partially written index is not mistaken for frames
+    #[test]
+    fn test_damaged_index() {
+        let path = test_path("damaged_index");
+
+        let mut writer = RawFrameWriter::create(&path, *b"MJPG", (640, 480)).unwrap();
+        for i in 0..5 {
+            writer.write(&frame(i)).unwrap();
+        }
+        writer.finish().unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        let index_offset = bytes.len() - FOOTER_LEN as usize - 5 * 16;
+
+        // A frame count which overflows the length of the index
+        let mut corrupt = bytes.clone();
+        let footer = corrupt.len() - FOOTER_LEN as usize;
+        corrupt[footer..footer + 8].copy_from_slice(&(u64::MAX / 8).to_le_bytes());
+        std::fs::write(&path, &corrupt).unwrap();
+        let reader = RawFrameReader::open(&path).unwrap();
+        assert_eq!(reader.timestamps(), vec![0, 1000, 2000, 3000, 4000]);
+
+        // Recording stopped part way through writing the index
+        std::fs::write(&path, &bytes[..index_offset + 40]).unwrap();
+        let mut reader = RawFrameReader::open(&path).unwrap();
+        assert_eq!(reader.len(), 5);
+        assert_eq!(reader.read(4).unwrap().unwrap().0, frame(4).data);
+    }
+
+    /// Backend producing small JPEG frames, standing in for an MJPEG V4L2 camera
+    struct JpegBackend {
+        frame_index: u8
+    }
+
+    impl CamBackend for JpegBackend {
+        type Source = ();
+
+        fn open(_: &Self::Source) -> Result<Self> {
+            Ok(Self { frame_index: 0 })
+        }
+
+        fn start(&mut self, _: &CamConfig) -> Result<()> {
+            Ok(())
+        }
+
+        fn capture(&mut self) -> Result<RawFrame> {
+            let img = image::DynamicImage::ImageLuma8(
+                image::GrayImage::from_pixel(16, 8, image::Luma([self.frame_index * 50]))
+            );
+
+            let mut data = Vec::new();
+            img.write_to(&mut data, image::ImageOutputFormat::Jpeg(90))
+                .map_err(Error::ImageConversionError)?;
+
+            self.frame_index += 1;
+
+            Ok(RawFrame {
+                data,
+                format: PixelFormat::Encoded(ImageFormat::Jpeg),
+                resolution: (16, 8),
+                timestamp: 1000 + 100 * self.frame_index as u64
+            })
+        }
+
+        fn stop(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Test that a recording stream's frames are stored untouched and can be replayed as a stream
+    #[test]
+    fn test_record_and_replay() {
+        use crate::{CamStream, CamStreamBuilder};
+
+        let path = test_path("record_and_replay");
+
+        let mut stream = CamStreamBuilder::new()
+            .mono()
+            .backend::<JpegBackend>()
+            .source(())
+            .format(b"MJPG")
+            .unwrap()
+            .resolution((16, 8))
+            .record_raw(&path)
+            .build()
+            .expect("Cannot build recording stream");
+
+        let recorded: Vec<_> = (0..3).map(|_| stream.capture().unwrap()).collect();
+        stream.stop().expect("Cannot stop recording stream");
+
+        // The container holds the exact bytes produced by the backend
+        let mut reader = RawFrameReader::open(&path).unwrap();
+        let mut source = JpegBackend::open(&()).unwrap();
+        assert_eq!(reader.fourcc(), *b"MJPG");
+        assert_eq!(reader.resolution(), (16, 8));
+        assert_eq!(reader.len(), 3);
+
+        for i in 0..3 {
+            let (data, timestamp) = reader.read(i).unwrap().unwrap();
+            let expected = source.capture().unwrap();
+
+            assert_eq!(data, expected.data);
+            assert_eq!(timestamp, expected.timestamp);
+        }
+
+        // Replaying decodes to the same images
+        let mut replay = CamStreamBuilder::new()
+            .mono()
+            .replay_raw(RawReplaySource::new(&path))
+            .build()
+            .expect("Cannot build replay stream");
+
+        for img in recorded {
+            assert_eq!(replay.capture().unwrap().as_raw(), img.as_raw());
+        }
+
+        match replay.capture() {
+            Err(Error::EndOfStream) => (),
+            _ => panic!("Expected the end of the stream")
+        }
+    }
+
+    /// Test that a source which isn't a V4L2 camera is recorded in the format of its frames, not
+    /// the configured one, so that it can be replayed
+    #[test]
+    fn test_record_synthetic() {
+        use crate::backend::TestPattern;
+        use crate::{CamStream, CamStreamBuilder};
+
+        let path = test_path("record_synthetic");
+
+        let mut stream = CamStreamBuilder::new()
+            .mono()
+            .synthetic(TestPattern::FrameCounter)
+            .resolution((64, 48))
+            .record_raw(&path)
+            .build()
+            .expect("Cannot build recording stream");
+        let recorded: Vec<_> = (0..3).map(|_| stream.capture().unwrap()).collect();
+        stream.stop().expect("Cannot stop recording stream");
+
+        let reader = RawFrameReader::open(&path).unwrap();
+        assert_eq!(reader.fourcc(), *b"PNM ");
+        assert_eq!(reader.resolution(), (64, 48));
+
+        let mut replay = CamStreamBuilder::new()
+            .mono()
+            .replay_raw(RawReplaySource::new(&path))
+            .build()
+            .expect("Cannot build replay stream");
+
+        for img in recorded {
+            assert_eq!(replay.capture().unwrap().as_raw(), img.as_raw());
+        }
+
+        // Frames which don't match the container are rejected
+        let mut writer = RawFrameWriter::create(&path, *b"MJPG", (640, 480)).unwrap();
+        let mut other = frame(0);
+        other.resolution = (320, 240);
+        match writer.write(&other) {
+            Err(Error::RecordingError(m)) => assert!(m.contains("320x240"), "{}", m),
+            r => panic!("Expected a RecordingError, got {:?}", r)
+        }
+        other = RawFrame { format: PixelFormat::Yuyv, ..frame(0) };
+        assert!(writer.write(&other).is_err());
+        assert!(writer.is_empty());
+    }
+}
@@ -0,0 +1,374 @@
+//! # Synthetic Backend
+//!
+//! A virtual camera which renders deterministic test patterns, allowing streams to be exercised
+//! without any capture hardware.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use image::ImageFormat;
+
+use crate::backend::{CamBackend, CamConfig, RawFrame};
+use crate::error::{Error, Result};
+use crate::format::PixelFormat;
+use crate::GrayFloatImage;
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Number of bits of the frame index encoded by [`TestPattern::FrameCounter`].
+const COUNTER_BITS: u32 = 32;
+
+// -----------------------------------------------------------------------------------------------
+// ENUMERATIONS
+// -----------------------------------------------------------------------------------------------
+
+/// The pattern rendered by a [`SyntheticBackend`].
+///
+/// All patterns are a pure function of the pixel position and frame index, so two backends with
+/// the same source always produce identical frames.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TestPattern {
+    /// Static black and white squares with the given side length in pixels, with a white square
+    /// in the top left corner.
+    Checkerboard(u32),
+
+    /// Static horizontal ramp from black on the left to white on the right.
+    GradientRamp,
+
+    /// A white vertical bar on a black background which moves right by `speed` pixels every
+    /// frame, wrapping around at the right hand edge.
+    MovingBar {
+        /// Width of the bar in pixels
+        width: u32,
+
+        /// Distance the bar moves each frame in pixels
+        speed: u32
+    },
+
+    /// Uniform pseudo-random noise, different for every frame but reproducible from the seed.
+    Noise(u64),
+
+    /// The frame index encoded in binary along the top of a mid-grey image, see
+    /// [`TestPattern::read_frame_counter`].
+    FrameCounter
+}
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// Describes the frames produced by a [`SyntheticBackend`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SyntheticSource {
+    /// The pattern to render
+    pub pattern: TestPattern,
+
+    /// Horizontal shift of the pattern in pixels, so the pixel at `x` shows the pattern at
+    /// `x + shift`.
+    ///
+    /// Giving the right camera of a stereo pair a positive shift simulates a scene at constant
+    /// disparity.
+    pub shift: i32,
+
+    /// If true `capture` blocks until the next frame is due according to the configured interval,
+    /// otherwise frames are produced as fast as they are requested.
+    pub paced: bool
+}
+
+/// Backend which renders a [`TestPattern`] at the configured resolution.
+///
+/// Frames are timestamped at exact multiples of the configured interval, starting from zero, so
+/// timestamps are monotonically increasing and independent of how fast frames are captured.
+pub struct SyntheticBackend {
+    source: SyntheticSource,
+
+    config: Option<CamConfig>,
+
+    frame_index: u64,
+
+    start_time: Instant
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl TestPattern {
+    /// Read the frame index from an image of the [`TestPattern::FrameCounter`] pattern.
+    ///
+    /// The image must not have been shifted or rectified.
+    pub fn read_frame_counter(img: &GrayFloatImage) -> u64 {
+        let block = counter_block_size(img.width() as u32) as usize;
+
+        (0..COUNTER_BITS as usize).fold(0, |counter, bit| {
+            let centre = img.get(bit * block + block / 2, block / 2);
+
+            (counter << 1) | (centre > 0.5) as u64
+        })
+    }
+
+    /// Get the value of the pattern at the given position in the given frame.
+    fn value(&self, x: i64, y: i64, frame: u64, width: u32) -> u8 {
+        match *self {
+            TestPattern::Checkerboard(size) => {
+                let size = size.max(1) as i64;
+
+                if (x.div_euclid(size) + y.div_euclid(size)) % 2 == 0 {
+                    255
+                } else {
+                    0
+                }
+            },
+            TestPattern::GradientRamp => {
+                let max = (width.max(2) - 1) as i64;
+
+                (x.max(0).min(max) * 255 / max) as u8
+            },
+            TestPattern::MovingBar { width: bar_width, speed } => {
+                let left = (frame as i64).wrapping_mul(speed as i64);
+
+                if (x - left).rem_euclid(width.max(1) as i64) < bar_width as i64 {
+                    255
+                } else {
+                    0
+                }
+            },
+            TestPattern::Noise(seed) => {
+                let hash = splitmix64(
+                    seed ^ splitmix64(frame ^ splitmix64((x as u64) ^ splitmix64(y as u64)))
+                );
+
+                (hash >> 56) as u8
+            },
+            TestPattern::FrameCounter => {
+                let block = counter_block_size(width) as i64;
+                let bit = x.div_euclid(block);
+
+                if y < block && bit >= 0 && bit < COUNTER_BITS as i64 {
+                    if (frame >> (COUNTER_BITS as i64 - 1 - bit)) & 1 == 1 {
+                        255
+                    } else {
+                        0
+                    }
+                } else {
+                    128
+                }
+            }
+        }
+    }
+}
+
+impl SyntheticSource {
+    /// Create a new unshifted, unpaced source rendering the given pattern.
+    pub fn new(pattern: TestPattern) -> Self {
+        Self {
+            pattern,
+            shift: 0,
+            paced: false
+        }
+    }
+
+    /// Set the horizontal shift of the pattern in pixels.
+    pub fn shift(mut self, shift: i32) -> Self {
+        self.shift = shift;
+
+        self
+    }
+
+    /// Set whether frames are paced at the configured interval.
+    pub fn paced(mut self, paced: bool) -> Self {
+        self.paced = paced;
+
+        self
+    }
+}
+
+impl CamBackend for SyntheticBackend {
+    type Source = SyntheticSource;
+
+    fn open(source: &Self::Source) -> Result<Self> {
+        Ok(Self {
+            source: *source,
+            config: None,
+            frame_index: 0,
+            start_time: Instant::now()
+        })
+    }
+
+    fn start(&mut self, config: &CamConfig) -> Result<()> {
+        if config.resolution.0 == 0 || config.resolution.1 == 0 {
+            return Err(Error::CamStreamBuildError(format!(
+                "A synthetic stream cannot have a resolution of {}x{}",
+                config.resolution.0,
+                config.resolution.1
+            )));
+        }
+
+        if config.interval.1 == 0 {
+            return Err(Error::CamStreamBuildError(format!(
+                "A synthetic stream cannot have a frame interval of {}/{} seconds",
+                config.interval.0,
+                config.interval.1
+            )));
+        }
+
+        self.config = Some(*config);
+        self.frame_index = 0;
+        self.start_time = Instant::now();
+
+        Ok(())
+    }
+
+    fn capture(&mut self) -> Result<RawFrame> {
+        let config = self.config.ok_or(Error::CamNotStartedError)?;
+        let (width, height) = config.resolution;
+
+        // Exact time of this frame since the stream was started
+        let frame_time = self.frame_index * config.interval.0 as u64 * 1_000_000
+            / config.interval.1 as u64;
+
+        if self.source.paced {
+            let due = self.start_time + Duration::from_micros(frame_time);
+            let now = Instant::now();
+
+            if due > now {
+                thread::sleep(due - now);
+            }
+        }
+
+        // Render the pattern as a binary PGM, which is lossless and trivial to encode
+        let header = format!("P5\n{} {}\n255\n", width, height);
+        let mut data = Vec::with_capacity(header.len() + (width * height) as usize);
+        data.extend_from_slice(header.as_bytes());
+
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                data.push(self.source.pattern.value(
+                    x + self.source.shift as i64,
+                    y,
+                    self.frame_index,
+                    width
+                ));
+            }
+        }
+
+        self.frame_index += 1;
+
+        Ok(RawFrame {
+            data,
+            format: PixelFormat::Encoded(ImageFormat::Pnm),
+            resolution: config.resolution,
+            timestamp: frame_time
+        })
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.config = None;
+
+        Ok(())
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// PRIVATE FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Side length of each bit's block in the frame counter pattern.
+fn counter_block_size(width: u32) -> u32 {
+    (width / COUNTER_BITS).max(1)
+}
+
+/// The SplitMix64 mixing function, used as a stateless hash for the noise pattern.
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+    z ^ (z >> 31)
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::CamStreamBuilder;
+    use crate::camstream::CamStream;
+
+    /// Test that the frame counter can be read back from captured images
+    #[test]
+    fn test_frame_counter() {
+        let mut stream = CamStreamBuilder::new()
+            .mono()
+            .synthetic(TestPattern::FrameCounter)
+            .resolution((320, 240))
+            .build()
+            .expect("Cannot build synthetic stream");
+
+        for i in 0..5 {
+            let img = stream.capture().expect("Cannot capture synthetic image");
+
+            assert_eq!((img.width(), img.height()), (320, 240));
+            assert_eq!(TestPattern::read_frame_counter(&img), i);
+        }
+    }
+
+    /// Test that timestamps follow the configured interval
+    #[test]
+    fn test_timestamps() {
+        let mut cam = SyntheticBackend::open(&SyntheticSource::new(TestPattern::GradientRamp))
+            .unwrap();
+
+        assert!(cam.capture().is_err());
+
+        cam.start(&CamConfig { interval: (1, 30), ..CamConfig::default() }).unwrap();
+
+        let timestamps: Vec<u64> = (0..4).map(|_| cam.capture().unwrap().timestamp).collect();
+        assert_eq!(timestamps, vec![0, 33_333, 66_666, 100_000]);
+
+        // Invalid configurations are rejected with a description of the problem
+        match cam.start(&CamConfig { resolution: (0, 240), ..CamConfig::default() }) {
+            Err(Error::CamStreamBuildError(m)) => assert!(m.contains("0x240"), "{}", m),
+            r => panic!("Expected a CamStreamBuildError, got {:?}", r.err())
+        }
+        match cam.start(&CamConfig { interval: (1, 0), ..CamConfig::default() }) {
+            Err(Error::CamStreamBuildError(m)) => assert!(m.contains("1/0"), "{}", m),
+            r => panic!("Expected a CamStreamBuildError, got {:?}", r.err())
+        }
+    }
+
+    /// Test that the stereo shift moves the right image relative to the left
+    #[test]
+    fn test_stereo_shift() {
+        let mut stream = CamStreamBuilder::new()
+            .stereo()
+            .synthetic(TestPattern::Noise(42), 8)
+            .resolution((64, 48))
+            .build()
+            .expect("Cannot build synthetic stereo stream");
+
+        let frame = stream.capture().expect("Cannot capture synthetic frame");
+
+        for y in 0..48 {
+            for x in 0..(64 - 8) {
+                assert_eq!(frame.left.get(x + 8, y), frame.right.get(x, y));
+            }
+        }
+
+        // Noise changes between frames
+        let next = stream.capture().expect("Cannot capture synthetic frame");
+        assert!(next.left.get(0, 0) != frame.left.get(0, 0)
+            || next.left.get(1, 0) != frame.left.get(1, 0));
+
+        stream.stop().expect("Cannot stop synthetic stream");
+    }
+}
@@ -0,0 +1,87 @@
+//! # V4L2 Backend
+//!
+//! The default capture backend, which acquires frames from a V4L2 device using `rscam`.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::path::PathBuf;
+
+use rscam::{Camera, Config};
+
+use crate::backend::{CamBackend, CamConfig, RawFrame};
+use crate::error::{Error, Result};
+use crate::format::PixelFormat;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// Backend which captures frames from a V4L2 device, such as `/dev/video0`.
+pub struct V4l2Backend {
+    camera: Camera,
+
+    /// Format used to decode captured frames, resolved from the FourCC when started
+    pixel_format: Option<PixelFormat>
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl CamBackend for V4l2Backend {
+    type Source = PathBuf;
+
+    fn open(source: &Self::Source) -> Result<Self> {
+        let camera = Camera::new(source
+            .to_str()
+            .expect("Cannot convert path to &str")
+        ).map_err(|e| Error::CamStreamBuildError(format!("{}", e)))?;
+
+        Ok(Self {
+            camera,
+            pixel_format: None
+        })
+    }
+
+    fn start(&mut self, config: &CamConfig) -> Result<()> {
+        // Only start the device if the frames it produces can be decoded
+        let pixel_format = PixelFormat::from_fourcc(&config.format)
+            .ok_or_else(|| Error::ImageFormatError(
+                String::from_utf8_lossy(&config.format).into_owned()
+            ))?;
+
+        self.camera.start(&Config {
+            interval: config.interval,
+            resolution: config.resolution,
+            format: &config.format,
+            field: config.field,
+            nbuffers: config.nbuffers
+        }).map_err(Error::CamStartError)?;
+
+        self.pixel_format = Some(pixel_format);
+
+        Ok(())
+    }
+
+    fn capture(&mut self) -> Result<RawFrame> {
+        let format = self.pixel_format
+            .ok_or(Error::CamNotStartedError)?;
+
+        let frame = self.camera.capture().map_err(Error::CameraCaptureError)?;
+
+        Ok(RawFrame {
+            data: frame.to_vec(),
+            format,
+            resolution: frame.resolution,
+            timestamp: frame.get_timestamp()
+        })
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.pixel_format = None;
+
+        self.camera.stop().map_err(Error::CamStopError)
+    }
+}
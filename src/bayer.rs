@@ -0,0 +1,346 @@
+//! # Bayer Demosaicing Module
+//!
+//! Reconstructs colour images from the raw colour filter array of a Bayer sensor, where each pixel
+//! only measures one of red, green or blue.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use crate::{GrayFloatImage, RgbFloatImage};
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Index of the red channel in an RGB pixel.
+const RED: usize = 0;
+
+/// Index of the green channel in an RGB pixel.
+const GREEN: usize = 1;
+
+/// Index of the blue channel in an RGB pixel.
+const BLUE: usize = 2;
+
+// -----------------------------------------------------------------------------------------------
+// ENUMERATIONS
+// -----------------------------------------------------------------------------------------------
+
+/// The arrangement of the colour filters in a Bayer sensor, named by the colours of the top left
+/// 2x2 block read left to right then top to bottom.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BayerPattern {
+    /// Blue and green on even rows, green and red on odd rows
+    Bggr,
+
+    /// Green and blue on even rows, red and green on odd rows
+    Gbrg,
+
+    /// Green and red on even rows, blue and green on odd rows
+    Grbg,
+
+    /// Red and green on even rows, green and blue on odd rows
+    Rggb
+}
+
+/// The algorithm used to fill in the two missing colours at each pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DemosaicMethod {
+    /// Average the nearest samples of each colour, which is fast but produces colour fringes
+    /// along sharp edges.
+    Bilinear,
+
+    /// Interpolate green along the direction of the smallest gradient, corrected by the
+    /// curvature of the pixel's own colour, then interpolate red and blue as differences from
+    /// green, which greatly reduces fringing and zippering at edges.
+    #[default]
+    EdgeAware
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl BayerPattern {
+    /// Get the colour channel measured by the pixel at the given position, as an index into an RGB
+    /// pixel.
+    pub fn channel_at(&self, x: usize, y: usize) -> usize {
+        let layout = match *self {
+            BayerPattern::Bggr => [BLUE, GREEN, GREEN, RED],
+            BayerPattern::Gbrg => [GREEN, BLUE, RED, GREEN],
+            BayerPattern::Grbg => [GREEN, RED, BLUE, GREEN],
+            BayerPattern::Rggb => [RED, GREEN, GREEN, BLUE]
+        };
+
+        layout[(y % 2) * 2 + x % 2]
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// PUBLIC FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Reconstruct a colour image from a Bayer mosaic.
+///
+/// Each pixel of `mosaic` holds the value measured through its colour filter, as given by
+/// `pattern`. Pixels outside the image are mirrored back into it, which preserves the pattern at
+/// the borders.
+pub fn demosaic(
+    mosaic: &GrayFloatImage,
+    pattern: BayerPattern,
+    method: DemosaicMethod
+) -> RgbFloatImage {
+    let mosaic = Mosaic { img: mosaic, pattern };
+
+    match method {
+        DemosaicMethod::Bilinear => demosaic_bilinear(&mosaic),
+        DemosaicMethod::EdgeAware => demosaic_edge_aware(&mosaic)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// PRIVATE FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// A Bayer mosaic which can be sampled outside its bounds.
+struct Mosaic<'a> {
+    img: &'a GrayFloatImage,
+
+    pattern: BayerPattern
+}
+
+impl<'a> Mosaic<'a> {
+    /// Get the value at the given position, mirroring positions outside the image.
+    fn get(&self, x: i64, y: i64) -> f32 {
+        self.img.get(
+            mirror(x, self.img.width()),
+            mirror(y, self.img.height())
+        )
+    }
+
+    /// Get the channel measured at the given position, mirroring positions outside the image.
+    fn channel_at(&self, x: i64, y: i64) -> usize {
+        self.pattern.channel_at(mirror(x, self.img.width()), mirror(y, self.img.height()))
+    }
+}
+
+/// Mirror a coordinate into `0..len` without repeating the edge pixel, so that odd and even
+/// coordinates stay odd and even.
+fn mirror(i: i64, len: usize) -> usize {
+    let last = len as i64 - 1;
+
+    let i = if i < 0 {
+        -i
+    } else if i > last {
+        2 * last - i
+    } else {
+        i
+    };
+
+    i.max(0).min(last) as usize
+}
+
+/// Fill each missing colour with the mean of the samples of that colour in the 3x3 neighbourhood.
+fn demosaic_bilinear(mosaic: &Mosaic) -> RgbFloatImage {
+    let (width, height) = (mosaic.img.width(), mosaic.img.height());
+    let mut out = RgbFloatImage::new(width, height);
+
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            let mut sums = [0.0f32; 3];
+            let mut counts = [0u32; 3];
+
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let c = mosaic.channel_at(x + dx, y + dy);
+
+                    sums[c] += mosaic.get(x + dx, y + dy);
+                    counts[c] += 1;
+                }
+            }
+
+            // The pixel's own channel is kept exactly
+            let own = mosaic.channel_at(x, y);
+            let mut px = [0.0; 3];
+
+            for c in 0..3 {
+                px[c] = if c == own {
+                    mosaic.get(x, y)
+                } else {
+                    sums[c] / counts[c].max(1) as f32
+                };
+            }
+
+            out.put(x as usize, y as usize, px);
+        }
+    }
+
+    out
+}
+
+/// Gradient directed green interpolation followed by colour difference interpolation of red and
+/// blue, after Hamilton and Adams.
+fn demosaic_edge_aware(mosaic: &Mosaic) -> RgbFloatImage {
+    let (width, height) = (mosaic.img.width(), mosaic.img.height());
+
+    // First reconstruct the full green channel, which has twice the samples of red or blue
+    let mut green = GrayFloatImage::new(width, height);
+
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            let value = if mosaic.channel_at(x, y) == GREEN {
+                mosaic.get(x, y)
+            } else {
+                let c = mosaic.get(x, y);
+
+                let lap_h = 2.0 * c - mosaic.get(x - 2, y) - mosaic.get(x + 2, y);
+                let lap_v = 2.0 * c - mosaic.get(x, y - 2) - mosaic.get(x, y + 2);
+                let grad_h = (mosaic.get(x - 1, y) - mosaic.get(x + 1, y)).abs() + lap_h.abs();
+                let grad_v = (mosaic.get(x, y - 1) - mosaic.get(x, y + 1)).abs() + lap_v.abs();
+
+                let est_h = (mosaic.get(x - 1, y) + mosaic.get(x + 1, y)) / 2.0 + lap_h / 4.0;
+                let est_v = (mosaic.get(x, y - 1) + mosaic.get(x, y + 1)) / 2.0 + lap_v / 4.0;
+
+                if grad_h < grad_v {
+                    est_h
+                } else if grad_v < grad_h {
+                    est_v
+                } else {
+                    (est_h + est_v) / 2.0
+                }
+            };
+
+            green.put(x as usize, y as usize, value.clamp(0.0, 1.0));
+        }
+    }
+
+    let green_at = |x: i64, y: i64| green.get(mirror(x, width), mirror(y, height));
+
+    // Mean difference between the channel and green over the given neighbours which measure it
+    let mean_diff = |x: i64, y: i64, channel: usize, offsets: &[(i64, i64)]| {
+        let (sum, count) = offsets.iter()
+            .filter(|(dx, dy)| mosaic.channel_at(x + dx, y + dy) == channel)
+            .fold((0.0, 0), |(sum, count), (dx, dy)| {
+                (sum + mosaic.get(x + dx, y + dy) - green_at(x + dx, y + dy), count + 1)
+            });
+
+        if count == 0 { 0.0 } else { sum / count as f32 }
+    };
+
+    let cross = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+    let diagonal = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
+
+    // Then reconstruct red and blue as differences from green, which vary slowly across edges
+    let mut out = RgbFloatImage::new(width, height);
+
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            let own = mosaic.channel_at(x, y);
+            let g = green_at(x, y);
+            let mut px = [0.0; 3];
+
+            for c in [RED, BLUE].iter().copied() {
+                px[c] = if c == own {
+                    mosaic.get(x, y)
+                } else if own == GREEN {
+                    (g + mean_diff(x, y, c, &cross)).clamp(0.0, 1.0)
+                } else {
+                    (g + mean_diff(x, y, c, &diagonal)).clamp(0.0, 1.0)
+                };
+            }
+            px[GREEN] = g;
+
+            out.put(x as usize, y as usize, px);
+        }
+    }
+
+    out
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Build the mosaic a sensor with the given pattern would measure of a colour image.
+    fn mosaic_of(img: &RgbFloatImage, pattern: BayerPattern) -> GrayFloatImage {
+        let mut mosaic = GrayFloatImage::new(img.width(), img.height());
+
+        for y in 0..img.height() {
+            for x in 0..img.width() {
+                mosaic.put(x, y, img.get(x, y)[pattern.channel_at(x, y)]);
+            }
+        }
+
+        mosaic
+    }
+
+    /// Mean absolute error between two colour images, excluding a border of the given width.
+    fn mean_error(a: &RgbFloatImage, b: &RgbFloatImage, border: usize) -> f32 {
+        let mut sum = 0.0;
+        let mut count = 0;
+
+        for y in border..(a.height() - border) {
+            for x in border..(a.width() - border) {
+                for c in 0..3 {
+                    sum += (a.get(x, y)[c] - b.get(x, y)[c]).abs();
+                    count += 1;
+                }
+            }
+        }
+
+        sum / count as f32
+    }
+
+    /// Test that flat colours are reconstructed exactly for every pattern and method
+    #[test]
+    fn test_flat_colour() {
+        let mut img = RgbFloatImage::new(8, 6);
+        for y in 0..6 {
+            for x in 0..8 {
+                img.put(x, y, [0.8, 0.4, 0.1]);
+            }
+        }
+
+        let patterns = [
+            BayerPattern::Bggr,
+            BayerPattern::Gbrg,
+            BayerPattern::Grbg,
+            BayerPattern::Rggb
+        ];
+
+        for pattern in patterns.iter().copied() {
+            let mosaic = mosaic_of(&img, pattern);
+
+            for method in [DemosaicMethod::Bilinear, DemosaicMethod::EdgeAware].iter().copied() {
+                let out = demosaic(&mosaic, pattern, method);
+
+                assert!(mean_error(&img, &out, 0) < 1e-6, "{:?} {:?}", pattern, method);
+            }
+        }
+    }
+
+    /// Test that edge aware demosaicing is more accurate than bilinear on a sharp edge between two
+    /// colours of different brightness
+    #[test]
+    fn test_edge() {
+        let mut img = RgbFloatImage::new(16, 16);
+        for y in 0..16 {
+            for x in 0..16 {
+                let px = if x + y / 4 < 9 { [0.9, 0.8, 0.6] } else { [0.3, 0.15, 0.1] };
+
+                img.put(x, y, px);
+            }
+        }
+
+        let mosaic = mosaic_of(&img, BayerPattern::Rggb);
+        let bilinear = demosaic(&mosaic, BayerPattern::Rggb, DemosaicMethod::Bilinear);
+        let edge_aware = demosaic(&mosaic, BayerPattern::Rggb, DemosaicMethod::EdgeAware);
+
+        assert!(mean_error(&img, &edge_aware, 2) < mean_error(&img, &bilinear, 2));
+    }
+}
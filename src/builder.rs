@@ -6,16 +6,22 @@
 // IMPORTS
 // -----------------------------------------------------------------------------------------------
 
+use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
 
-use serde_any;
-use serde::de::DeserializeOwned;
-use rscam::Config;
-
+use crate::backend::{CamBackend, CamConfig, V4l2Backend};
+use crate::backend::{PlaybackBackend, PlaybackSource};
+use crate::backend::{RawRecordingBackend, RawRecordingSource, RawReplayBackend, RawReplaySource};
+use crate::backend::{SyntheticBackend, SyntheticSource, TestPattern};
+use crate::bayer::BayerPattern;
+use crate::calib_file::CalibrationParams;
 use crate::error::{Error, Result};
-use crate::rectification::{RectifParams, StereoRectifParams};
-use crate::camstream::StereoCamStream;
-use image::ImageFormat;
+use crate::format::PixelFormat;
+use crate::interpolation::{BorderMode, Interpolation};
+use crate::rectification::{OutputScale, RectifOptions, RectifParams, Rectifier};
+use crate::rectification::{StereoRectifParams, StereoSide};
+use crate::{GrayFloatImage, RgbFloatImage};
+use crate::camstream::{FrameProcessor, MonoCamStream, StereoCamStream, StreamImage};
 
 // -----------------------------------------------------------------------------------------------
 // TRAITS
@@ -23,25 +29,19 @@ use image::ImageFormat;
 
 /// Provides common methods for enabling rectification of images by a stream builder.
 pub trait Rectifiable: Sized {
-    /// The parameters to be used, must be deserialisable.
-    type Params: DeserializeOwned;
+    /// The parameters to be used, which must be loadable from calibration files.
+    type Params: CalibrationParams;
 
     fn rectif_params(self, params: Self::Params) -> Self;
 
     /// Load the rectification parameters from a file.
     ///
-    /// The file type will be guessed at runtime, any file type supported by 
+    /// OpenCV `FileStorage` YAML and XML files are recognised from their contents, otherwise the
+    /// file type will be guessed at runtime, any file type supported by 
     /// [`serde_any`](https://docs.rs/serde_any/0.5.0/serde_any/) is supported, but it must be
     /// deserialisable into `Self::Params`.
     fn rectif_params_from_file<P: AsRef<Path>>(self, path: P) -> Result<Self> {
-        // Check the file exitsts
-        if !path.as_ref().exists() {
-            return Err(Error::FileNotFound(path.as_ref().to_path_buf()));
-        }
-
-        // Load the parameters from the file, guessing which format they're in using serde_any
-        let p = serde_any::from_file(path)
-            .map_err(|e| Error::DeserialisationError(e))?;
+        let p = Self::Params::from_file(path)?;
 
         Ok(self.rectif_params(p))
     }
@@ -51,27 +51,42 @@ pub trait Rectifiable: Sized {
 // DATA STRUCTURES
 // -----------------------------------------------------------------------------------------------
 
-///
+/// Entry point for building camera streams, see [`CamStreamBuilder::mono`] and
+/// [`CamStreamBuilder::stereo`].
 pub struct CamStreamBuilder {}
 
-pub struct MonoStreamBuilder<'a> {
-    path: Option<PathBuf>,
+/// Builder for a [`MonoCamStream`], generic over the [`CamBackend`] which provides the frames and
+/// the [`StreamImage`] they are decoded into.
+pub struct MonoStreamBuilder<B: CamBackend = V4l2Backend, I: StreamImage = GrayFloatImage> {
+    source: Option<B::Source>,
 
     rectif_params: Option<RectifParams>,
 
-    config: Config<'a>
+    rectif_options: RectifOptions,
+
+    bayer_pattern: Option<BayerPattern>,
+
+    config: CamConfig,
+
+    _image: PhantomData<I>
 }
 
-pub struct StereoStreamBuilder<'a> {
-    left_path: Option<PathBuf>,
-    right_path: Option<PathBuf>,
+/// Builder for a [`StereoCamStream`], generic over the [`CamBackend`] which provides the frames and
+/// the [`StreamImage`] they are decoded into.
+pub struct StereoStreamBuilder<B: CamBackend = V4l2Backend, I: StreamImage = GrayFloatImage> {
+    left_source: Option<B::Source>,
+    right_source: Option<B::Source>,
 
     rectif_params: Option<StereoRectifParams>,
 
-    img_format: Option<ImageFormat>,
+    rectif_options: RectifOptions,
+
+    bayer_pattern: Option<BayerPattern>,
 
-    left_config: Config<'a>,
-    right_config: Config<'a>
+    left_config: CamConfig,
+    right_config: CamConfig,
+
+    _image: PhantomData<I>
 }
 
 // -----------------------------------------------------------------------------------------------
@@ -83,41 +98,115 @@ impl CamStreamBuilder {
         Self {}
     }
 
-    pub fn mono<'a>(self) -> MonoStreamBuilder<'a> {
+    /// Build a single camera stream, using a V4L2 device unless another backend is selected with
+    /// [`MonoStreamBuilder::backend`].
+    pub fn mono(self) -> MonoStreamBuilder {
         MonoStreamBuilder { 
-            path: None, 
+            source: None, 
             rectif_params: None,
-            config: Config::default() 
+            rectif_options: RectifOptions::default(),
+            bayer_pattern: None,
+            config: CamConfig::default(),
+            _image: PhantomData
         }
     }
 
-    pub fn stereo<'a>(self) -> StereoStreamBuilder<'a> {
+    /// Build a stereo camera stream, using a pair of V4L2 devices unless another backend is
+    /// selected with [`StereoStreamBuilder::backend`].
+    pub fn stereo(self) -> StereoStreamBuilder {
         StereoStreamBuilder {
-            left_path: None,
-            right_path: None,
+            left_source: None,
+            right_source: None,
             rectif_params: None,
-            img_format: None,
-            left_config: Config::default(),
-            right_config: Config::default()
+            rectif_options: RectifOptions::default(),
+            bayer_pattern: None,
+            left_config: CamConfig::default(),
+            right_config: CamConfig::default(),
+            _image: PhantomData
         }
     }
 }
 
-impl<'a> MonoStreamBuilder<'a> {
-    /// Specify the path of the camera, i.e. the device path, such as `/dev/video1`
+impl<B: CamBackend, I: StreamImage> MonoStreamBuilder<B, I> {
+    /// Use a different backend for the camera.
     ///
-    /// # Returns
-    /// - `self` if the path exists, `Err` otherwise
-    pub fn path<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
-        if path.as_ref().exists() {
-            self.path = Some(path.as_ref().to_path_buf());
+    /// The configuration and rectification parameters are kept, but the source must be set
+    /// again as it is specific to the backend.
+    pub fn backend<C: CamBackend>(self) -> MonoStreamBuilder<C, I> {
+        MonoStreamBuilder {
+            source: None,
+            rectif_params: self.rectif_params,
+            rectif_options: self.rectif_options,
+            bayer_pattern: self.bayer_pattern,
+            config: self.config,
+            _image: PhantomData
+        }
+    }
 
-            Ok(self)
-        } else {
-            Err(Error::FileNotFound(path.as_ref().to_path_buf()))
+    /// Use a synthetic camera rendering the given test pattern instead of a real device.
+    ///
+    /// The pattern is rendered at the configured resolution and timestamped at the configured
+    /// interval, the configured format is ignored.
+    pub fn synthetic(self, pattern: TestPattern) -> MonoStreamBuilder<SyntheticBackend, I> {
+        self.backend().source(SyntheticSource::new(pattern))
+    }
+
+    /// Replay a directory of recorded images instead of capturing from a real device.
+    ///
+    /// Frames keep the resolution they were recorded at, the configured interval is used to pace
+    /// playback and timestamp the frames, depending on the options set in `source`.
+    pub fn playback(self, source: PlaybackSource) -> MonoStreamBuilder<PlaybackBackend, I> {
+        self.backend().source(source)
+    }
+
+    /// Replay a container of raw frames recorded with [`MonoStreamBuilder::record_raw`].
+    ///
+    /// Frames are decoded according to the recorded format, the configured format, resolution
+    /// and interval are ignored.
+    pub fn replay_raw(self, source: RawReplaySource) -> MonoStreamBuilder<RawReplayBackend, I> {
+        self.backend().source(source)
+    }
+
+    /// Record every frame captured by the stream, untouched, to a container file at `path`.
+    ///
+    /// Frames are written before they are decoded, so for MJPEG cameras the original JPEG data is
+    /// stored. The container takes the format and resolution of the first frame, and capturing
+    /// fails if a later frame differs from them. The source must already be set.
+    pub fn record_raw<P: AsRef<Path>>(
+        self,
+        path: P
+    ) -> MonoStreamBuilder<RawRecordingBackend<B>, I> {
+        MonoStreamBuilder {
+            source: self.source.map(|s| RawRecordingSource::new(s, &path)),
+            rectif_params: self.rectif_params,
+            rectif_options: self.rectif_options,
+            bayer_pattern: self.bayer_pattern,
+            config: self.config,
+            _image: PhantomData
         }
     }
 
+    /// Decode frames into colour [`RgbFloatImage`]s instead of greyscale images.
+    ///
+    /// Rectification is applied to each channel with the same parameters.
+    pub fn rgb(self) -> MonoStreamBuilder<B, RgbFloatImage> {
+        MonoStreamBuilder {
+            source: self.source,
+            rectif_params: self.rectif_params,
+            rectif_options: self.rectif_options,
+            bayer_pattern: self.bayer_pattern,
+            config: self.config,
+            _image: PhantomData
+        }
+    }
+
+    /// Specify the source the backend will be opened from.
+    pub fn source(mut self, source: B::Source) -> Self {
+        self.source = Some(source);
+
+        self
+    }
+
     /// Set the interval of the camera.
     ///
     /// V4L2 uses intervals rather than framerates, default value is `(1, 10)`.
@@ -138,9 +227,94 @@ impl<'a> MonoStreamBuilder<'a> {
 
     /// Set the format of the images.
     ///
-    /// Uses the FourCC notation, default value is `b"YUYV"`.
-    pub fn format(mut self, format: &'a [u8]) -> Self {
-        self.config.format = format;
+    /// Uses the FourCC notation, default value is `b"YUYV"`. Supported formats are `MJPG`, `YUYV`,
+    /// `GREY`, `Y10`, `Y12`, `Y16`, `Y10P`, `Y12P`, `NV12`, `NV21`, `YU12`, `YV12` and the Bayer
+    /// formats listed in [`PixelFormat::Bayer`].
+    pub fn format(mut self, format: &[u8]) -> Result<Self> {
+        self.config.format = fourcc_if_supported(format)?;
+
+        Ok(self)
+    }
+
+    /// Set the colour filter arrangement used to demosaic Bayer frames.
+    ///
+    /// By default the pattern is inferred from the FourCC of the format, this overrides it for
+    /// sensors which report the wrong pattern. Frames which are not Bayer are unaffected.
+    pub fn bayer_pattern(mut self, pattern: BayerPattern) -> Self {
+        self.bayer_pattern = Some(pattern);
+
+        self
+    }
+
+    /// Set the interpolation used to resample images during rectification.
+    ///
+    /// Default is [`Interpolation::Bilinear`].
+    pub fn interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.rectif_options.interpolation = interpolation;
+
+        self
+    }
+
+    /// Set how rectified pixels which sample outside the captured image are filled.
+    ///
+    /// Default is [`BorderMode::Constant`] with a value of zero, leaving them black.
+    pub fn border_mode(mut self, border_mode: BorderMode) -> Self {
+        self.rectif_options.border_mode = border_mode;
+
+        self
+    }
+
+    /// Set the focal length in pixels of the rectified images, which are centred on the image.
+    ///
+    /// By default the focal length is chosen to fit the undistorted image, or for fisheye lenses
+    /// is the focal length of the camera. Replaces any field of view set with `output_fov` or
+    /// scaling set with `alpha`.
+    pub fn output_focal(mut self, focal: f64) -> Self {
+        self.rectif_options.output_scale = Some(OutputScale::Focal(focal));
+
+        self
+    }
+
+    /// Set the horizontal field of view in degrees of the rectified images, which are centred on
+    /// the image.
+    ///
+    /// This is mostly useful for fisheye lenses, where the rectified images can only show part
+    /// of the field of view. Replaces any focal length set with `output_focal` or scaling set
+    /// with `alpha`.
+    pub fn output_fov(mut self, fov: f64) -> Self {
+        self.rectif_options.output_scale = Some(OutputScale::FieldOfView(fov));
+
+        self
+    }
+
+    /// Set the free scaling parameter of the rectified images, between 0 and 1.
+    ///
+    /// At 0 the rectified images are zoomed so that every pixel is valid, and at 1 so that every
+    /// pixel of the captured images is kept, leaving black areas around them. Replaces any focal
+    /// length or field of view set with `output_focal` or `output_fov`.
+    pub fn alpha(mut self, alpha: f64) -> Self {
+        self.rectif_options.output_scale = Some(OutputScale::Alpha(alpha));
+
+        self
+    }
+
+    /// Set the resolution of the rectified images.
+    ///
+    /// By default the rectified images have the same resolution as the captured ones.
+    pub fn output_resolution(mut self, resolution: (u32, u32)) -> Self {
+        self.rectif_options.output_resolution = Some(resolution);
+
+        self
+    }
+
+    /// Crop the rectified images to the largest rectangle of valid pixels, so that no pixels are
+    /// filled by the border mode.
+    ///
+    /// The rectangle is applied after any output resolution, so the images are smaller than it.
+    /// Pixels are only valid if every pixel the interpolation reads lies within the captured
+    /// image, so wider interpolations such as Lanczos give a slightly smaller rectangle.
+    pub fn crop_to_roi(mut self) -> Self {
+        self.rectif_options.crop_to_roi = true;
 
         self
     }
@@ -162,47 +336,174 @@ impl<'a> MonoStreamBuilder<'a> {
 
         self
     }
-}
-
-impl<'a> Rectifiable for MonoStreamBuilder<'a> {
-    type Params = RectifParams;
 
-    fn rectif_params(mut self, params: Self::Params) -> Self {
-        self.rectif_params = Some(params);
+    /// Build the mono camera stream object.
+    ///
+    /// This function can fail if opening or starting the backend fails.
+    pub fn build(self) -> Result<MonoCamStream<B, I>> {
+        // Confirm that the required source is present
+        let source = match self.source {
+            Some(s) => s,
+            None => return Err(Error::CamStreamBuildError(String::from("Missing camera source")))
+        };
+
+        // Build and start the camera
+        let mut cam = B::open(&source)?;
+        cam.start(&self.config)?;
 
-        self
+        // Create new stream
+        Ok(MonoCamStream::new(
+            cam,
+            FrameProcessor::new(
+                self.rectif_params.map(Rectifier::Mono),
+                self.rectif_options,
+                self.config.resolution
+            ).bayer_pattern(self.bayer_pattern)
+        ))
     }
 }
 
-impl<'a> StereoStreamBuilder<'a> {
-    /// Specify the path of the left camera, i.e. the device path, such as `/dev/video1`
+impl<B: CamBackend<Source = PathBuf>, I: StreamImage> MonoStreamBuilder<B, I> {
+    /// Specify the path of the camera, i.e. the device path, such as `/dev/video1`
     ///
     /// # Returns
     /// - `self` if the path exists, `Err` otherwise
-    pub fn left_path<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
+    pub fn path<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
         if path.as_ref().exists() {
-            self.left_path = Some(path.as_ref().to_path_buf());
+            self.source = Some(path.as_ref().to_path_buf());
 
             Ok(self)
         } else {
             Err(Error::FileNotFound(path.as_ref().to_path_buf()))
         }
     }
+}
 
-    /// Specify the path of the right camera, i.e. the device path, such as `/dev/video1`
+impl<B: CamBackend, I: StreamImage> Rectifiable for MonoStreamBuilder<B, I> {
+    type Params = RectifParams;
+
+    fn rectif_params(mut self, params: Self::Params) -> Self {
+        self.rectif_params = Some(params);
+
+        self
+    }
+}
+
+impl<B: CamBackend, I: StreamImage> StereoStreamBuilder<B, I> {
+    /// Use a different backend for both cameras.
     ///
-    /// # Returns
-    /// - `self` if the path exists, `Err` otherwise
-    pub fn right_path<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
-        if path.as_ref().exists() {
-            self.right_path = Some(path.as_ref().to_path_buf());
+    /// The configuration and rectification parameters are kept, but the sources must be set
+    /// again as they are specific to the backend.
+    pub fn backend<C: CamBackend>(self) -> StereoStreamBuilder<C, I> {
+        StereoStreamBuilder {
+            left_source: None,
+            right_source: None,
+            rectif_params: self.rectif_params,
+            rectif_options: self.rectif_options,
+            bayer_pattern: self.bayer_pattern,
+            left_config: self.left_config,
+            right_config: self.right_config,
+            _image: PhantomData
+        }
+    }
 
-            Ok(self)
-        } else {
-            Err(Error::FileNotFound(path.as_ref().to_path_buf()))
+    /// Use a pair of synthetic cameras rendering the given test pattern instead of real devices.
+    ///
+    /// The right camera's pattern is shifted by `shift` pixels relative to the left, so that a
+    /// feature at `x` in the left image appears at `x - shift` in the right image, simulating a
+    /// scene at constant disparity. The configured format is ignored.
+    pub fn synthetic(
+        self,
+        pattern: TestPattern,
+        shift: i32
+    ) -> StereoStreamBuilder<SyntheticBackend, I> {
+        self.backend()
+            .left_source(SyntheticSource::new(pattern))
+            .right_source(SyntheticSource::new(pattern).shift(shift))
+    }
+
+    /// Replay a pair of directories of recorded images instead of capturing from real devices.
+    ///
+    /// Frames are paired in the order they are played, so both directories should contain the
+    /// same number of images.
+    pub fn playback(
+        self,
+        left: PlaybackSource,
+        right: PlaybackSource
+    ) -> StereoStreamBuilder<PlaybackBackend, I> {
+        self.backend()
+            .left_source(left)
+            .right_source(right)
+    }
+
+    /// Replay a pair of containers of raw frames recorded with
+    /// [`StereoStreamBuilder::record_raw`].
+    ///
+    /// Frames are decoded according to the recorded format, the configured format, resolution
+    /// and interval are ignored.
+    pub fn replay_raw(
+        self,
+        left: RawReplaySource,
+        right: RawReplaySource
+    ) -> StereoStreamBuilder<RawReplayBackend, I> {
+        self.backend()
+            .left_source(left)
+            .right_source(right)
+    }
+
+    /// Record every frame captured by each camera, untouched, to a container file per camera.
+    ///
+    /// Frames are written before they are decoded, so for MJPEG cameras the original JPEG data is
+    /// stored. The container takes the format and resolution of the first frame, and capturing
+    /// fails if a later frame differs from them. Both sources must already be set.
+    pub fn record_raw<P: AsRef<Path>>(
+        self,
+        left_path: P,
+        right_path: P
+    ) -> StereoStreamBuilder<RawRecordingBackend<B>, I> {
+        StereoStreamBuilder {
+            left_source: self.left_source.map(|s| RawRecordingSource::new(s, &left_path)),
+            right_source: self.right_source.map(|s| RawRecordingSource::new(s, &right_path)),
+            rectif_params: self.rectif_params,
+            rectif_options: self.rectif_options,
+            bayer_pattern: self.bayer_pattern,
+            left_config: self.left_config,
+            right_config: self.right_config,
+            _image: PhantomData
         }
     }
 
+    /// Decode frames from both cameras into colour [`RgbFloatImage`]s instead of greyscale
+    /// images.
+    ///
+    /// Rectification is applied to each channel with the same parameters.
+    pub fn rgb(self) -> StereoStreamBuilder<B, RgbFloatImage> {
+        StereoStreamBuilder {
+            left_source: self.left_source,
+            right_source: self.right_source,
+            rectif_params: self.rectif_params,
+            rectif_options: self.rectif_options,
+            bayer_pattern: self.bayer_pattern,
+            left_config: self.left_config,
+            right_config: self.right_config,
+            _image: PhantomData
+        }
+    }
+
+    /// Specify the source the left backend will be opened from.
+    pub fn left_source(mut self, source: B::Source) -> Self {
+        self.left_source = Some(source);
+
+        self
+    }
+
+    /// Specify the source the right backend will be opened from.
+    pub fn right_source(mut self, source: B::Source) -> Self {
+        self.right_source = Some(source);
+
+        self
+    }
+
     /// Set the interval of both cameras.
     ///
     /// V4L2 uses intervals rather than framerates, default value is `(1, 10)`.
@@ -225,20 +526,101 @@ impl<'a> StereoStreamBuilder<'a> {
 
     /// Set the format of the images.
     ///
-    /// Uses the FourCC notation, default value is `b"YUYV"`.
-    pub fn format(mut self, format: &'a [u8]) -> Result<Self> {
-        self.img_format = format_from_fourcc(format);
-
-        if self.img_format.is_none() {
-            return Err(Error::ImageFormatError(String::from_utf8(format.into()).unwrap()));
-        }
+    /// Uses the FourCC notation, default value is `b"YUYV"`. Supported formats are `MJPG`, `YUYV`,
+    /// `GREY`, `Y10`, `Y12`, `Y16`, `Y10P`, `Y12P`, `NV12`, `NV21`, `YU12`, `YV12` and the Bayer
+    /// formats listed in [`PixelFormat::Bayer`].
+    pub fn format(mut self, format: &[u8]) -> Result<Self> {
+        let fourcc = fourcc_if_supported(format)?;
 
-        self.left_config.format = format;
-        self.right_config.format = format;
+        self.left_config.format = fourcc;
+        self.right_config.format = fourcc;
 
         Ok(self)
     }
 
+    /// Set the colour filter arrangement used to demosaic Bayer frames from both cameras.
+    ///
+    /// By default the pattern is inferred from the FourCC of the format, this overrides it for
+    /// sensors which report the wrong pattern. Frames which are not Bayer are unaffected.
+    pub fn bayer_pattern(mut self, pattern: BayerPattern) -> Self {
+        self.bayer_pattern = Some(pattern);
+
+        self
+    }
+
+    /// Set the interpolation used to resample images during rectification.
+    ///
+    /// Default is [`Interpolation::Bilinear`].
+    pub fn interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.rectif_options.interpolation = interpolation;
+
+        self
+    }
+
+    /// Set how rectified pixels which sample outside the captured image are filled.
+    ///
+    /// Default is [`BorderMode::Constant`] with a value of zero, leaving them black.
+    pub fn border_mode(mut self, border_mode: BorderMode) -> Self {
+        self.rectif_options.border_mode = border_mode;
+
+        self
+    }
+
+    /// Set the focal length in pixels of the rectified images, which are centred on the image.
+    ///
+    /// By default the focal length is chosen to fit the undistorted image, or for fisheye lenses
+    /// is the focal length of the camera. Replaces any field of view set with `output_fov` or
+    /// scaling set with `alpha`.
+    pub fn output_focal(mut self, focal: f64) -> Self {
+        self.rectif_options.output_scale = Some(OutputScale::Focal(focal));
+
+        self
+    }
+
+    /// Set the horizontal field of view in degrees of the rectified images, which are centred on
+    /// the image.
+    ///
+    /// This is mostly useful for fisheye lenses, where the rectified images can only show part
+    /// of the field of view. Replaces any focal length set with `output_focal` or scaling set
+    /// with `alpha`.
+    pub fn output_fov(mut self, fov: f64) -> Self {
+        self.rectif_options.output_scale = Some(OutputScale::FieldOfView(fov));
+
+        self
+    }
+
+    /// Set the free scaling parameter of the rectified images, between 0 and 1.
+    ///
+    /// At 0 the rectified images are zoomed so that every pixel is valid, and at 1 so that every
+    /// pixel of the captured images is kept, leaving black areas around them. Replaces any focal
+    /// length or field of view set with `output_focal` or `output_fov`.
+    pub fn alpha(mut self, alpha: f64) -> Self {
+        self.rectif_options.output_scale = Some(OutputScale::Alpha(alpha));
+
+        self
+    }
+
+    /// Set the resolution of the rectified images.
+    ///
+    /// By default the rectified images have the same resolution as the captured ones.
+    pub fn output_resolution(mut self, resolution: (u32, u32)) -> Self {
+        self.rectif_options.output_resolution = Some(resolution);
+
+        self
+    }
+
+    /// Crop the rectified images of both cameras to the largest rectangle of pixels which is
+    /// valid in both, so that no pixels are filled by the border mode and rows stay aligned.
+    ///
+    /// The rectangle is applied after any output resolution, so the images are smaller than it.
+    /// Pixels are only valid if every pixel the interpolation reads lies within the captured
+    /// image, so wider interpolations such as Lanczos give a slightly smaller rectangle.
+    pub fn crop_to_roi(mut self) -> Self {
+        self.rectif_options.crop_to_roi = true;
+
+        self
+    }
+
     /// Set the storage method for interlaced video.
     ///
     /// Possible values are those provided by the `rscam::FIELD_x` values, default is `FIELD_NONE`.
@@ -261,42 +643,104 @@ impl<'a> StereoStreamBuilder<'a> {
 
     /// Build the stereo camera stream object.
     ///
-    /// This function can fail if the underlying V4L2 construction fails.
-    pub fn build(self) -> Result<StereoCamStream> {
-        // Confirm that required paths are present
-        if self.left_path.is_none() || self.right_path.is_none() {
-            return Err(Error::CamStreamBuildError(String::from("Missing camera path")));
-        }
-
-        // Build left camera
-        let mut left_cam = rscam::Camera::new(self.left_path
-            .unwrap()
-            .to_str()
-            .expect("Cannot convert left path to &str")
-        ).map_err(|e| Error::CamStreamBuildError(format!("{}", e)))?;
-
-        // Build right camera
-        let mut right_cam = rscam::Camera::new(self.right_path
-            .unwrap()
-            .to_str()
-            .expect("Cannot convert right path to &str")
-        ).map_err(|e| Error::CamStreamBuildError(format!("{}", e)))?;
+    /// This function can fail if opening or starting either backend fails.
+    pub fn build(self) -> Result<StereoCamStream<I>> {
+        // Confirm that required sources are present
+        let (left_source, right_source) = match (self.left_source, self.right_source) {
+            (Some(l), Some(r)) => (l, r),
+            _ => return Err(Error::CamStreamBuildError(String::from("Missing camera source")))
+        };
+
+        // Build left and right cameras
+        let mut left_cam = B::open(&left_source)?;
+        let mut right_cam = B::open(&right_source)?;
 
         // Start the cameras
-        left_cam.start(&self.left_config).map_err(|e| Error::CamStartError(e))?;
-        right_cam.start(&self.right_config).map_err(|e| Error::CamStartError(e))?;
+        left_cam.start(&self.left_config)?;
+        right_cam.start(&self.right_config)?;
+
+        // Each side is rectified using the parameters of both cameras, so both tables are built
+        // together to compute the rectification and the common ROI only once
+        let rectif_params = self.rectif_params;
+        let (left_processor, right_processor) = match rectif_params {
+            Some(srp) if self.left_config.resolution == self.right_config.resolution => {
+                let (left_table, right_table) =
+                    srp.remap_tables_with(self.left_config.resolution, &self.rectif_options);
+                let rectifier = |side| Rectifier::Stereo(Box::new(srp), side);
+
+                (
+                    FrameProcessor::with_remap_table(
+                        rectifier(StereoSide::Left), self.rectif_options, left_table
+                    ),
+                    FrameProcessor::with_remap_table(
+                        rectifier(StereoSide::Right), self.rectif_options, right_table
+                    )
+                )
+            },
+            _ => {
+                let rectifier = |side| {
+                    rectif_params.map(|srp| Rectifier::Stereo(Box::new(srp), side))
+                };
+
+                (
+                    FrameProcessor::new(
+                        rectifier(StereoSide::Left),
+                        self.rectif_options,
+                        self.left_config.resolution
+                    ),
+                    FrameProcessor::new(
+                        rectifier(StereoSide::Right),
+                        self.rectif_options,
+                        self.right_config.resolution
+                    )
+                )
+            }
+        };
 
         // Create new stream
         Ok(StereoCamStream::new(
             left_cam,
             right_cam,
-            self.img_format.unwrap(),
-            self.rectif_params
+            left_processor.bayer_pattern(self.bayer_pattern),
+            right_processor.bayer_pattern(self.bayer_pattern),
+            self.rectif_params,
+            self.rectif_options,
+            self.left_config.resolution
         ))
     }
 }
 
-impl<'a> Rectifiable for StereoStreamBuilder<'a> {
+impl<B: CamBackend<Source = PathBuf>, I: StreamImage> StereoStreamBuilder<B, I> {
+    /// Specify the path of the left camera, i.e. the device path, such as `/dev/video1`
+    ///
+    /// # Returns
+    /// - `self` if the path exists, `Err` otherwise
+    pub fn left_path<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
+        if path.as_ref().exists() {
+            self.left_source = Some(path.as_ref().to_path_buf());
+
+            Ok(self)
+        } else {
+            Err(Error::FileNotFound(path.as_ref().to_path_buf()))
+        }
+    }
+
+    /// Specify the path of the right camera, i.e. the device path, such as `/dev/video1`
+    ///
+    /// # Returns
+    /// - `self` if the path exists, `Err` otherwise
+    pub fn right_path<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
+        if path.as_ref().exists() {
+            self.right_source = Some(path.as_ref().to_path_buf());
+
+            Ok(self)
+        } else {
+            Err(Error::FileNotFound(path.as_ref().to_path_buf()))
+        }
+    }
+}
+
+impl<B: CamBackend, I: StreamImage> Rectifiable for StereoStreamBuilder<B, I> {
     type Params = StereoRectifParams;
 
     fn rectif_params(mut self, params: Self::Params) -> Self {
@@ -310,10 +754,19 @@ impl<'a> Rectifiable for StereoStreamBuilder<'a> {
 // PRIVATE FUNCTIONS
 // -----------------------------------------------------------------------------------------------
 
-fn format_from_fourcc(format: &[u8]) -> Option<ImageFormat> {
-    match format {
-        b"MJPG" => Some(ImageFormat::Jpeg),
-        _ => None
+/// Convert the given FourCC code into a fixed size array, if frames in that format can be decoded.
+///
+/// Codes shorter than four characters, such as `Y16`, are padded with spaces.
+fn fourcc_if_supported(format: &[u8]) -> Result<[u8; 4]> {
+    let mut fourcc = [b' '; 4];
+
+    if format.len() <= 4 {
+        fourcc[..format.len()].copy_from_slice(format);
+    }
+
+    match PixelFormat::from_fourcc(&fourcc) {
+        Some(_) if format.len() <= 4 => Ok(fourcc),
+        _ => Err(Error::ImageFormatError(String::from_utf8_lossy(format).into_owned()))
     }
 }
 
@@ -325,6 +778,8 @@ fn format_from_fourcc(format: &[u8]) -> Option<ImageFormat> {
 mod tests {
 
     use super::*;
+    use crate::backend::RawFrame;
+    use crate::camstream::CamStream;
 
     /// Test that mono builders work corectly
     #[test]
@@ -337,6 +792,99 @@ mod tests {
             .expect("Cannot load the rectification parameters");
     }
 
+    /// Test that a mono stream cannot be built without a path or a supported format
+    #[test]
+    fn test_mono_build_errors() {
+        match CamStreamBuilder::new().mono().build() {
+            Err(Error::CamStreamBuildError(_)) => (),
+            _ => panic!("Expected a CamStreamBuildError for a missing path")
+        }
+
+        match CamStreamBuilder::new().mono().format(b"H264") {
+            Err(Error::ImageFormatError(f)) => assert_eq!(f, "H264"),
+            _ => panic!("Expected an ImageFormatError for H264")
+        }
+
+        // Short codes are padded with spaces
+        let builder = CamStreamBuilder::new().mono().format(b"Y16").unwrap();
+        assert_eq!(&builder.config.format, b"Y16 ");
+    }
+
+    /// Backend producing a constant 4x4 PGM frame, used to test streams without hardware
+    struct MockBackend {
+        started: bool,
+        timestamp: u64
+    }
+
+    impl CamBackend for MockBackend {
+        type Source = ();
+
+        fn open(_: &Self::Source) -> Result<Self> {
+            Ok(Self { started: false, timestamp: 0 })
+        }
+
+        fn start(&mut self, _: &CamConfig) -> Result<()> {
+            self.started = true;
+
+            Ok(())
+        }
+
+        fn capture(&mut self) -> Result<RawFrame> {
+            if !self.started {
+                return Err(Error::CamNotStartedError);
+            }
+
+            self.timestamp += 100;
+
+            let mut data = b"P5\n4 4\n255\n".to_vec();
+            data.extend_from_slice(&[255; 16]);
+
+            Ok(RawFrame {
+                data,
+                format: PixelFormat::Encoded(image::ImageFormat::Pnm),
+                resolution: (4, 4),
+                timestamp: self.timestamp
+            })
+        }
+
+        fn stop(&mut self) -> Result<()> {
+            self.started = false;
+
+            Ok(())
+        }
+    }
+
+    /// Test that streams can be built and captured from with a non-V4L2 backend
+    #[test]
+    fn test_custom_backend() {
+        let mut mono = CamStreamBuilder::new()
+            .mono()
+            .backend::<MockBackend>()
+            .source(())
+            .build()
+            .expect("Cannot build mono stream");
+
+        let img = mono.capture().expect("Cannot capture mono image");
+        assert_eq!((img.width(), img.height()), (4, 4));
+        assert_eq!(img.get(0, 0), 1.0);
+        mono.stop().expect("Cannot stop mono stream");
+
+        let mut stereo = CamStreamBuilder::new()
+            .stereo()
+            .backend::<MockBackend>()
+            .left_source(())
+            .right_source(())
+            .build()
+            .expect("Cannot build stereo stream");
+
+        for i in 1..=3 {
+            let frame = stereo.capture().expect("Cannot capture stereo frame");
+            assert_eq!(frame.left_timestamp, i * 100);
+            assert_eq!(frame.right_timestamp, i * 100);
+        }
+        stereo.stop().expect("Cannot stop stereo stream");
+    }
+
     /// Test that stereo builders work correctly
     #[test]
     fn test_stereo() {
@@ -0,0 +1,194 @@
+//! # Kalibr Calibration Files
+//!
+//! Reads the `camchain.yaml` files written by Kalibr, which describe each camera under `cam0`,
+//! `cam1` and so on, with the transform from the previous camera in `T_cn_cnm1`.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::collections::BTreeMap;
+
+use nalgebra::Matrix3;
+use serde::Deserialize;
+
+use crate::error::{Error, Result};
+use crate::rectification::{RectifParams, StereoRectifParams};
+
+use super::{distortion_model_from_name, rectif_params, row_major};
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// The cameras of a Kalibr camera chain, by name.
+pub(crate) struct CamChain {
+    cameras: BTreeMap<String, KalibrCamera>
+}
+
+/// A single camera of a Kalibr camera chain.
+#[derive(Deserialize, Debug)]
+struct KalibrCamera {
+    camera_model: String,
+
+    /// Projection parameters of the camera model, `[fu, fv, pu, pv]` for a pinhole camera
+    intrinsics: Vec<f64>,
+
+    distortion_model: String,
+
+    #[serde(default)]
+    distortion_coeffs: Vec<f64>,
+
+    /// Homogeneous transform from the previous camera's frame to this camera's frame
+    #[serde(rename = "T_cn_cnm1", default)]
+    t_cn_cnm1: Option<Vec<Vec<f64>>>
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl CamChain {
+
+    /// Parse a `camchain.yaml` document.
+    pub(crate) fn parse(s: &str) -> Result<Self> {
+        let cameras = serde_yaml::from_str(s)
+            .map_err(|e| Error::CalibrationFileError(format!("Invalid Kalibr camchain: {}", e)))?;
+
+        Ok(Self { cameras })
+    }
+
+    /// Get the rectification parameters of the first camera.
+    pub(crate) fn mono_params(&self) -> Result<RectifParams> {
+        self.camera("cam0")?.rectif_params()
+    }
+
+    /// Get the rectification parameters of the first two cameras, as the left and right cameras
+    /// of a stereo pair.
+    pub(crate) fn stereo_params(&self) -> Result<StereoRectifParams> {
+        let right = self.camera("cam1")?;
+
+        let (rotation, translation) = match right.t_cn_cnm1 {
+            Some(ref t) => {
+                let valid = t.len() >= 3 && t.iter().take(3).all(|row| row.len() == 4);
+                if !valid {
+                    return Err(Error::CalibrationFileError(
+                        "T_cn_cnm1 must be a 4x4 matrix".into()
+                    ));
+                }
+
+                let r = Matrix3::from_fn(|i, j| t[i][j]);
+                (Some(row_major(r)), Some([t[0][3], t[1][3], t[2][3]]))
+            },
+            None => (None, None)
+        };
+
+        Ok(StereoRectifParams {
+            left: self.camera("cam0")?.rectif_params()?,
+            right: right.rectif_params()?,
+            rotation,
+            translation
+        })
+    }
+
+    /// Get the camera with the given name.
+    fn camera(&self, name: &str) -> Result<&KalibrCamera> {
+        self.cameras.get(name).ok_or_else(|| {
+            Error::CalibrationFileError(format!("The camera chain has no {}", name))
+        })
+    }
+}
+
+impl KalibrCamera {
+
+    /// Get the rectification parameters of the camera.
+    fn rectif_params(&self) -> Result<RectifParams> {
+        if self.camera_model != "pinhole" {
+            return Err(Error::UnsupportedDistortionModel(format!(
+                "the Kalibr \"{}\" camera model, only \"pinhole\" is supported", self.camera_model
+            )));
+        }
+
+        let (fu, fv, pu, pv) = match self.intrinsics[..] {
+            [fu, fv, pu, pv] => (fu, fv, pu, pv),
+            _ => return Err(Error::CalibrationFileError(format!(
+                "Pinhole intrinsics must have 4 elements, not {}", self.intrinsics.len()
+            )))
+        };
+
+        // Kalibr puts pixel centres on the integers as OpenCV does, which `rectif_params` converts
+        let camera_matrix = Matrix3::new(
+            fu, 0.0, pu,
+            0.0, fv, pv,
+            0.0, 0.0, 1.0
+        );
+
+        match self.distortion_model.as_str() {
+            "none" => rectif_params(&camera_matrix, &[], None),
+            name => rectif_params(
+                &camera_matrix,
+                &self.distortion_coeffs,
+                Some(distortion_model_from_name(name)?)
+            )
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::calib_file::CalibrationParams;
+    use crate::distortion::DistortionModel;
+
+    /// A stereo camera chain as written by `kalibr_calibrate_cameras`.
+    const CAMCHAIN: &str = "cam0:
+  cam_overlaps: [1]
+  camera_model: pinhole
+  distortion_coeffs: [-0.28, 0.07, 0.0002, 0.00002]
+  distortion_model: radtan
+  intrinsics: [458.6, 457.3, 367.2, 248.4]
+  resolution: [752, 480]
+  rostopic: /cam0/image_raw
+cam1:
+  T_cn_cnm1:
+  - [0.99999, 0.0023, 0.0003, -0.1101]
+  - [-0.0023, 0.99996, 0.0140, 0.0004]
+  - [-0.0003, -0.0140, 0.99990, -0.0009]
+  - [0.0, 0.0, 0.0, 1.0]
+  cam_overlaps: [0]
+  camera_model: pinhole
+  distortion_coeffs: [-0.01, 0.002, -0.001, 0.0003]
+  distortion_model: equidistant
+  intrinsics: [457.6, 456.1, 379.9, 255.2]
+  resolution: [752, 480]
+  rostopic: /cam1/image_raw
+";
+
+    /// Test loading the cameras and extrinsics of a camera chain
+    #[test]
+    fn test_kalibr_camchain() {
+        let mono = RectifParams::from_kalibr_str(CAMCHAIN).unwrap();
+        assert_eq!(mono.focals, [458.6, 457.3]);
+        assert_eq!(mono.principal_point, [367.7, 248.9]);
+        assert_eq!(mono.distortion_model(), DistortionModel::PlumbBob);
+        assert_eq!((mono.p1, mono.p2, mono.k3), (Some(0.0002), Some(0.00002), None));
+
+        let stereo = StereoRectifParams::from_kalibr_str(CAMCHAIN).unwrap();
+        assert_eq!(stereo.right.distortion_model(), DistortionModel::Equidistant);
+        assert_eq!(stereo.right.k4, Some(0.0003));
+        assert_eq!(stereo.rotation.unwrap()[1][2], 0.0140);
+        assert_eq!(stereo.translation, Some([-0.1101, 0.0004, -0.0009]));
+
+        // Other camera models are reported as unsupported
+        let omni = CAMCHAIN.replacen("camera_model: pinhole", "camera_model: omni", 1);
+        match RectifParams::from_kalibr_str(&omni) {
+            Err(Error::UnsupportedDistortionModel(m)) => assert!(m.contains("omni")),
+            r => panic!("Expected an UnsupportedDistortionModel error, got {:?}", r)
+        }
+    }
+}
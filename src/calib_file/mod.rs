@@ -0,0 +1,611 @@
+//! # Calibration File Module
+//!
+//! Loads rectification parameters from the files written by calibration tools, and writes them
+//! back out for those tools, in addition to the crate's own format which is read and written
+//! through `serde_any`.
+//!
+//! OpenCV, ROS and Kalibr put the centre of pixel `(x, y)` at `(x, y)`, while the crate puts it at
+//! `(x + 0.5, y + 0.5)`. Principal points are moved by half a pixel as they are loaded from or
+//! written to those tools' files, so that they describe the same camera in both conventions.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::path::Path;
+
+use nalgebra::{Matrix3, Rotation3, Vector3};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::distortion::DistortionModel;
+use crate::error::{Error, Result};
+use crate::rectification::{RectifParams, StereoRectifParams};
+
+use kalibr::CamChain;
+use opencv::{FileStorage, FileStorageWriter};
+use ros::{CameraInfo, StereoCameraInfo};
+
+// -----------------------------------------------------------------------------------------------
+// MODULES
+// -----------------------------------------------------------------------------------------------
+
+mod kalibr;
+mod opencv;
+mod ros;
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Offset from the pixel coordinates of OpenCV, ROS and Kalibr to the crate's, whose pixel centres
+/// lie half way between integers.
+const PIXEL_CENTRE_OFFSET: f64 = 0.5;
+
+/// Names of the mono camera matrix in OpenCV files, in order of preference.
+const CAMERA_MATRIX_KEYS: [&str; 3] = ["camera_matrix", "cameraMatrix", "K"];
+
+/// Names of the mono distortion coefficients in OpenCV files, in order of preference.
+const DISTORTION_KEYS: [&str; 3] = ["distortion_coefficients", "distCoeffs", "D"];
+
+/// Names of the left and right camera matrices in OpenCV stereo files.
+const STEREO_CAMERA_MATRIX_KEYS: [[&str; 3]; 2] = [
+    ["M1", "cameraMatrix1", "K1"],
+    ["M2", "cameraMatrix2", "K2"]
+];
+
+/// Names of the left and right distortion coefficients in OpenCV stereo files.
+const STEREO_DISTORTION_KEYS: [[&str; 2]; 2] = [["D1", "distCoeffs1"], ["D2", "distCoeffs2"]];
+
+// -----------------------------------------------------------------------------------------------
+// TRAITS
+// -----------------------------------------------------------------------------------------------
+
+/// Rectification parameters which can be loaded from and saved to calibration files.
+pub trait CalibrationParams: Serialize + DeserializeOwned {
+    /// Load the parameters from an OpenCV `FileStorage` document in YAML or XML.
+    ///
+    /// OpenCV doesn't record which model its coefficients belong to, so the model is read from a
+    /// `distortion_model` node if there is one, such as `"fisheye"` or `"plumb_bob"`. Otherwise
+    /// the Brown-Conrady model is inferred from the number of coefficients. Four coefficients are
+    /// ambiguous, as `cv::fisheye` writes `k1` to `k4` while the pinhole model writes `k1`, `k2`,
+    /// `p1` and `p2`, so files with four coefficients must name their model.
+    fn from_opencv_str(s: &str) -> Result<Self>;
+
+    /// Load the parameters from a ROS `camera_info` YAML document.
+    ///
+    /// Stereo parameters are read from a document with the `camera_info` of each camera under
+    /// `left` and `right`, see also [`StereoRectifParams::from_ros_files`].
+    fn from_ros_str(s: &str) -> Result<Self>;
+
+    /// Load the parameters from a Kalibr `camchain.yaml` document.
+    ///
+    /// Mono parameters are those of `cam0`, and stereo parameters use `cam0` and `cam1` as the
+    /// left and right cameras.
+    fn from_kalibr_str(s: &str) -> Result<Self>;
+
+    /// Load the parameters from a file, detecting which tool wrote it from its contents.
+    ///
+    /// OpenCV `FileStorage` documents are recognised by their `%YAML` header or
+    /// `<opencv_storage>` root, ROS `camera_info` files by their camera matrix, and Kalibr
+    /// camera chains by their `cam0` camera. Any other file is read by
+    /// [`serde_any`](https://docs.rs/serde_any/0.5.0/serde_any/) in the crate's own format.
+    fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+
+        if !path.exists() {
+            return Err(Error::FileNotFound(path.to_path_buf()));
+        }
+
+        if let Ok(contents) = fs::read_to_string(path) {
+            match Schema::detect(&contents) {
+                Schema::OpenCv => return Self::from_opencv_str(&contents),
+                Schema::Ros => return Self::from_ros_str(&contents),
+                Schema::Kalibr => return Self::from_kalibr_str(&contents),
+                Schema::Native => ()
+            }
+        }
+
+        serde_any::from_file(path).map_err(Error::DeserialisationError)
+    }
+
+    /// Write the parameters to a file in the crate's own format, guessing the file type from its
+    /// extension. Any file type supported by
+    /// [`serde_any`](https://docs.rs/serde_any/0.5.0/serde_any/) can be written, such as TOML,
+    /// JSON or YAML.
+    fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        serde_any::to_file_pretty(path, self).map_err(Error::SerialisationError)
+    }
+
+    /// Write the parameters as an OpenCV `FileStorage` YAML document.
+    ///
+    /// This fails for the division model, which OpenCV cannot represent.
+    fn to_opencv_string(&self) -> Result<String>;
+
+    /// Write the parameters as a ROS `camera_info` YAML document for images of the given
+    /// resolution, whose projection matrix is that of the rectified images.
+    ///
+    /// Stereo parameters are written with the `camera_info` of each camera under `left` and
+    /// `right`, see also [`StereoRectifParams::to_ros_files`]. This fails for the division model,
+    /// which ROS cannot represent.
+    fn to_ros_string(&self, resolution: (u32, u32)) -> Result<String>;
+}
+
+// -----------------------------------------------------------------------------------------------
+// ENUMERATIONS
+// -----------------------------------------------------------------------------------------------
+
+/// The tools whose calibration files can be loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Schema {
+    OpenCv,
+
+    Ros,
+
+    Kalibr,
+
+    /// The crate's own format, in any of the file types `serde_any` supports
+    Native
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Schema {
+
+    /// Detect which tool wrote a calibration file from its contents.
+    fn detect(s: &str) -> Schema {
+        if FileStorage::is_file_storage(s) {
+            return Schema::OpenCv;
+        }
+
+        // Other tools write YAML, so anything else, such as TOML, is in the crate's format
+        let doc: serde_yaml::Value = match serde_yaml::from_str(s) {
+            Ok(doc) => doc,
+            Err(_) => return Schema::Native
+        };
+
+        let is_camera_info = |v: &serde_yaml::Value| {
+            v.get("camera_matrix").or_else(|| v.get("K")).or_else(|| v.get("k")).is_some()
+        };
+
+        if doc.get("cam0").is_some() {
+            Schema::Kalibr
+        } else if is_camera_info(&doc) || doc.get("left").is_some_and(is_camera_info) {
+            Schema::Ros
+        } else {
+            Schema::Native
+        }
+    }
+}
+
+impl CalibrationParams for RectifParams {
+    fn from_opencv_str(s: &str) -> Result<Self> {
+        let storage = FileStorage::parse(s)?;
+        let model = storage.distortion_model()?;
+        let coeffs = storage.vector(&DISTORTION_KEYS)?.unwrap_or_default();
+        check_opencv_model(&coeffs, model)?;
+
+        rectif_params(&storage.camera_matrix(&CAMERA_MATRIX_KEYS)?, &coeffs, model)
+    }
+
+    fn from_ros_str(s: &str) -> Result<Self> {
+        CameraInfo::parse(s)?.rectif_params()
+    }
+
+    fn from_kalibr_str(s: &str) -> Result<Self> {
+        CamChain::parse(s)?.mono_params()
+    }
+
+    fn to_opencv_string(&self) -> Result<String> {
+        let (model, coeffs) = opencv_distortion(self)?;
+
+        Ok(FileStorageWriter::new()
+            .matrix(CAMERA_MATRIX_KEYS[0], 3, 3, camera_matrix(self).transpose().as_slice())
+            .matrix(DISTORTION_KEYS[0], 1, coeffs.len(), &coeffs)
+            .text("distortion_model", distortion_model_name(model))
+            .finish())
+    }
+
+    fn to_ros_string(&self, resolution: (u32, u32)) -> Result<String> {
+        CameraInfo::new(self, resolution, None)?.to_yaml()
+    }
+}
+
+impl CalibrationParams for StereoRectifParams {
+    fn from_opencv_str(s: &str) -> Result<Self> {
+        let storage = FileStorage::parse(s)?;
+        let model = storage.distortion_model()?;
+
+        let side = |i: usize| {
+            let coeffs = storage.vector(&STEREO_DISTORTION_KEYS[i])?.unwrap_or_default();
+            check_opencv_model(&coeffs, model)?;
+
+            rectif_params(&storage.camera_matrix(&STEREO_CAMERA_MATRIX_KEYS[i])?, &coeffs, model)
+        };
+
+        let translation = match storage.vector(&["T"])? {
+            Some(t) if t.len() == 3 => Some([t[0], t[1], t[2]]),
+            Some(t) => return Err(Error::CalibrationFileError(format!(
+                "T must have 3 elements, not {}", t.len()
+            ))),
+            None => None
+        };
+
+        Ok(StereoRectifParams {
+            left: side(0)?,
+            right: side(1)?,
+            rotation: storage.rotation("R")?.map(row_major),
+            translation
+        })
+    }
+
+    fn from_ros_str(s: &str) -> Result<Self> {
+        StereoCameraInfo::parse(s)?.rectif_params()
+    }
+
+    fn from_kalibr_str(s: &str) -> Result<Self> {
+        CamChain::parse(s)?.stereo_params()
+    }
+
+    fn to_opencv_string(&self) -> Result<String> {
+        let (left_model, left_coeffs) = opencv_distortion(&self.left)?;
+        let (right_model, right_coeffs) = opencv_distortion(&self.right)?;
+
+        let mut writer = FileStorageWriter::new()
+            .matrix(STEREO_CAMERA_MATRIX_KEYS[0][0], 3, 3,
+                camera_matrix(&self.left).transpose().as_slice())
+            .matrix(STEREO_DISTORTION_KEYS[0][0], 1, left_coeffs.len(), &left_coeffs)
+            .matrix(STEREO_CAMERA_MATRIX_KEYS[1][0], 3, 3,
+                camera_matrix(&self.right).transpose().as_slice())
+            .matrix(STEREO_DISTORTION_KEYS[1][0], 1, right_coeffs.len(), &right_coeffs);
+
+        if let Some(r) = self.rotation {
+            writer = writer.matrix("R", 3, 3, &r.concat());
+        }
+        if let Some(t) = self.translation {
+            writer = writer.matrix("T", 3, 1, &t);
+        }
+
+        // Both cameras share one model when loaded, which the Brown-Conrady models can otherwise
+        // infer from their coefficients
+        if left_model == right_model {
+            writer = writer.text("distortion_model", distortion_model_name(left_model));
+        } else if left_model == DistortionModel::Equidistant
+            || right_model == DistortionModel::Equidistant
+        {
+            return Err(Error::UnsupportedDistortionModel(
+                "a fisheye camera paired with a pinhole camera in an OpenCV file".into()
+            ));
+        }
+
+        Ok(writer.finish())
+    }
+
+    fn to_ros_string(&self, resolution: (u32, u32)) -> Result<String> {
+        StereoCameraInfo::new(self, resolution)?.to_yaml()
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// PRIVATE FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Get the row major camera matrix of rectification parameters, in the pixel coordinates of
+/// OpenCV and ROS.
+fn camera_matrix(params: &RectifParams) -> Matrix3<f64> {
+    crate_to_tool_pixels() * Matrix3::new(
+        params.focals[0], params.skew, params.principal_point[0],
+        0.0, params.focals[1], params.principal_point[1],
+        0.0, 0.0, 1.0
+    )
+}
+
+/// Get the transform of homogeneous pixel coordinates from those of OpenCV, ROS and Kalibr to the
+/// crate's, which applied to a camera or projection matrix moves its principal point.
+fn tool_to_crate_pixels() -> Matrix3<f64> {
+    Matrix3::new(
+        1.0, 0.0, PIXEL_CENTRE_OFFSET,
+        0.0, 1.0, PIXEL_CENTRE_OFFSET,
+        0.0, 0.0, 1.0
+    )
+}
+
+/// Get the transform of homogeneous pixel coordinates from the crate's to those of OpenCV, ROS
+/// and Kalibr, the inverse of [`tool_to_crate_pixels`].
+fn crate_to_tool_pixels() -> Matrix3<f64> {
+    Matrix3::new(
+        1.0, 0.0, -PIXEL_CENTRE_OFFSET,
+        0.0, 1.0, -PIXEL_CENTRE_OFFSET,
+        0.0, 0.0, 1.0
+    )
+}
+
+/// Get the distortion model and coefficients of rectification parameters in OpenCV's order.
+///
+/// Parameters without distortion are written as the plumb bob model with zero coefficients.
+fn opencv_distortion(params: &RectifParams) -> Result<(DistortionModel, Vec<f64>)> {
+    let c = |v: Option<f64>| v.unwrap_or(0.0);
+
+    match params.distortion_model() {
+        DistortionModel::Division if params.k1.is_none() => {
+            Ok((DistortionModel::PlumbBob, vec![0.0; 5]))
+        },
+        DistortionModel::Division => Err(Error::UnsupportedDistortionModel(
+            "the division model, which OpenCV and ROS cannot represent".into()
+        )),
+        DistortionModel::PlumbBob => Ok((DistortionModel::PlumbBob, vec![
+            c(params.k1), c(params.k2), c(params.p1), c(params.p2), c(params.k3)
+        ])),
+        DistortionModel::RationalPolynomial => Ok((DistortionModel::RationalPolynomial, vec![
+            c(params.k1), c(params.k2), c(params.p1), c(params.p2), c(params.k3),
+            c(params.k4), c(params.k5), c(params.k6)
+        ])),
+        DistortionModel::Equidistant => Ok((DistortionModel::Equidistant, vec![
+            c(params.k1), c(params.k2), c(params.k3), c(params.k4)
+        ]))
+    }
+}
+
+/// Get the name ROS gives a distortion model, which is also understood when loading OpenCV files.
+fn distortion_model_name(model: DistortionModel) -> &'static str {
+    match model {
+        DistortionModel::Division => "division",
+        DistortionModel::PlumbBob => "plumb_bob",
+        DistortionModel::RationalPolynomial => "rational_polynomial",
+        DistortionModel::Equidistant => "equidistant"
+    }
+}
+
+/// Get the distortion model with the given name, using the names of OpenCV, ROS and Kalibr.
+fn distortion_model_from_name(name: &str) -> Result<DistortionModel> {
+    match name {
+        "division" => Ok(DistortionModel::Division),
+        "plumb_bob" | "radtan" | "radial-tangential" => Ok(DistortionModel::PlumbBob),
+        "rational_polynomial" => Ok(DistortionModel::RationalPolynomial),
+        "equidistant" | "fisheye" | "kannala_brandt" => Ok(DistortionModel::Equidistant),
+        _ => Err(Error::UnsupportedDistortionModel(format!("\"{}\"", name)))
+    }
+}
+
+/// Build rectification parameters from a row major camera matrix in the pixel coordinates of
+/// OpenCV, ROS and Kalibr, and distortion coefficients in OpenCV's order.
+///
+/// Without a model the Brown-Conrady model is used, choosing the rational variant if any of its
+/// denominator coefficients are non-zero. OpenCV's thin prism and tilted sensor coefficients are
+/// accepted only if they are zero, as the crate cannot model them.
+fn rectif_params(
+    camera_matrix: &Matrix3<f64>,
+    coeffs: &[f64],
+    model: Option<DistortionModel>
+) -> Result<RectifParams> {
+    let camera_matrix = tool_to_crate_pixels() * camera_matrix;
+    let mut params = RectifParams {
+        focals: [camera_matrix[(0, 0)], camera_matrix[(1, 1)]],
+        principal_point: [camera_matrix[(0, 2)], camera_matrix[(1, 2)]],
+        skew: camera_matrix[(0, 1)],
+        ..RectifParams::default()
+    };
+
+    // Without coefficients the camera is an undistorted pinhole, unless it is a fisheye
+    if coeffs.is_empty() && model != Some(DistortionModel::Equidistant) {
+        return Ok(params);
+    }
+
+    let model = match model {
+        Some(DistortionModel::PlumbBob) | Some(DistortionModel::RationalPolynomial) | None => {
+            brown_conrady_model(coeffs)?
+        },
+        Some(model) => model
+    };
+
+    let expected = match model {
+        DistortionModel::Division => 1,
+        DistortionModel::Equidistant => 4,
+        _ => 0
+    };
+    if expected != 0 && coeffs.len() != expected {
+        return Err(Error::CalibrationFileError(format!(
+            "The {:?} distortion model has {} coefficients, not {}",
+            model, expected, coeffs.len()
+        )));
+    }
+
+    let c = |i: usize| coeffs.get(i).copied();
+    match model {
+        DistortionModel::Division => params.k1 = c(0),
+        DistortionModel::Equidistant => {
+            params.k1 = c(0);
+            params.k2 = c(1);
+            params.k3 = c(2);
+            params.k4 = c(3);
+        },
+        DistortionModel::PlumbBob | DistortionModel::RationalPolynomial => {
+            params.k1 = c(0);
+            params.k2 = c(1);
+            params.p1 = c(2);
+            params.p2 = c(3);
+            params.k3 = c(4);
+
+            if model == DistortionModel::RationalPolynomial {
+                params.k4 = c(5);
+                params.k5 = c(6);
+                params.k6 = c(7);
+            }
+        }
+    }
+    params.distortion_model = Some(model);
+
+    Ok(params)
+}
+
+/// Check that the distortion model of coefficients loaded from an OpenCV file is known, which it
+/// isn't for four coefficients without a named model as they may be for `cv::fisheye`.
+fn check_opencv_model(coeffs: &[f64], model: Option<DistortionModel>) -> Result<()> {
+    if model.is_none() && coeffs.len() == 4 {
+        return Err(Error::CalibrationFileError(
+            "4 distortion coefficients may be for cv::fisheye or the pinhole model, add a \
+            distortion_model of \"fisheye\" or \"plumb_bob\" to the file".into()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Choose between the plumb bob and rational models for coefficients in OpenCV's order, checking
+/// that any coefficients the crate cannot model are zero.
+fn brown_conrady_model(coeffs: &[f64]) -> Result<DistortionModel> {
+    let nonzero = |range: std::ops::Range<usize>| {
+        coeffs.iter().skip(range.start).take(range.len()).any(|&c| c != 0.0)
+    };
+
+    match coeffs.len() {
+        4 | 5 | 8 | 12 | 14 => (),
+        n => return Err(Error::CalibrationFileError(format!(
+            "Expected 4, 5, 8, 12 or 14 distortion coefficients, found {}", n
+        )))
+    }
+
+    if nonzero(8..12) {
+        return Err(Error::UnsupportedDistortionModel(
+            "thin prism distortion (s1 to s4 are non-zero)".into()
+        ));
+    }
+    if nonzero(12..14) {
+        return Err(Error::UnsupportedDistortionModel(
+            "tilted sensor distortion (tauX and tauY are non-zero)".into()
+        ));
+    }
+
+    Ok(if nonzero(5..8) { DistortionModel::RationalPolynomial } else { DistortionModel::PlumbBob })
+}
+
+/// Get a rotation from either a 3x3 matrix or a 3 element Rodrigues vector.
+fn rotation_from(values: &[f64]) -> Result<Matrix3<f64>> {
+    match values.len() {
+        9 => Ok(Matrix3::from_row_slice(values)),
+        3 => Ok(Rotation3::new(Vector3::from_row_slice(values)).into_inner()),
+        n => Err(Error::CalibrationFileError(format!(
+            "A rotation must be a 3x3 matrix or a 3 element vector, not {} elements", n
+        )))
+    }
+}
+
+/// Convert a matrix into the row major arrays used by [`StereoRectifParams`].
+fn row_major(m: Matrix3<f64>) -> [[f64; 3]; 3] {
+    [
+        [m[(0, 0)], m[(0, 1)], m[(0, 2)]],
+        [m[(1, 0)], m[(1, 1)], m[(1, 2)]],
+        [m[(2, 0)], m[(2, 1)], m[(2, 2)]]
+    ]
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::backend::tests::test_dir;
+
+    /// Test that the tool which wrote a file is detected from its contents
+    #[test]
+    fn test_detect_schema() {
+        assert_eq!(Schema::detect("%YAML:1.0\n---\nimage_width: 640\n"), Schema::OpenCv);
+        let xml = "<?xml version=\"1.0\"?>\n<opencv_storage>\n</opencv_storage>";
+        assert_eq!(Schema::detect(xml), Schema::OpenCv);
+        assert_eq!(Schema::detect("K: [1, 0, 0, 0, 1, 0, 0, 0, 1]\nD: []\n"), Schema::Ros);
+        assert_eq!(Schema::detect("left:\n  camera_matrix: {rows: 3, cols: 3, data: []}\n"),
+            Schema::Ros);
+        assert_eq!(Schema::detect("cam0:\n  camera_model: pinhole\n"), Schema::Kalibr);
+
+        // The crate's own format, in TOML and YAML
+        assert_eq!(Schema::detect("focals = [600.0, 600.0]\nk1 = -0.2\n"), Schema::Native);
+        assert_eq!(Schema::detect("left:\n  focals: [600.0, 600.0]\n"), Schema::Native);
+    }
+
+    /// Test that a loaded OpenCV calibration samples the same positions as OpenCV's
+    /// `initUndistortRectifyMap`, and is written back unchanged
+    #[test]
+    fn test_opencv_pixel_centres() {
+        let (f, c) = ([500.0, 505.0], [319.0, 241.0]);
+        let (k1, k2, p1, p2, k3) = (-0.2, 0.04, 0.001, 0.0005, 0.0);
+        let yaml = FileStorageWriter::new()
+            .matrix("camera_matrix", 3, 3, &[f[0], 0.0, c[0], 0.0, f[1], c[1], 0.0, 0.0, 1.0])
+            .matrix("distortion_coefficients", 1, 5, &[k1, k2, p1, p2, k3])
+            .finish();
+
+        let params = RectifParams::from_opencv_str(&yaml).unwrap();
+        assert_eq!(params.principal_point, [319.5, 241.5]);
+
+        // Undistort into the same pinhole camera, as with `newCameraMatrix = cameraMatrix`
+        let output = cv_pinhole::CameraIntrinsics {
+            focals: nalgebra::Vector2::new(params.focals[0], params.focals[1]),
+            principal_point: nalgebra::Point2::new(
+                params.principal_point[0],
+                params.principal_point[1]
+            ),
+            skew: 0.0
+        };
+        let table = params.remap_table_to((640, 480), &output);
+
+        for &(u, v) in [(0, 0), (639, 479), (100, 400), (320, 240)].iter() {
+            // The map OpenCV computes for pixel (u, v), whose centre it puts at (u, v)
+            let x = (u as f64 - c[0]) / f[0];
+            let y = (v as f64 - c[1]) / f[1];
+            let r2 = x * x + y * y;
+            let radial = 1.0 + k1 * r2 + k2 * r2 * r2 + k3 * r2 * r2 * r2;
+            let xd = x * radial + 2.0 * p1 * x * y + p2 * (r2 + 2.0 * x * x);
+            let yd = y * radial + p1 * (r2 + 2.0 * y * y) + 2.0 * p2 * x * y;
+            let map = (f[0] * xd + c[0], f[1] * yd + c[1]);
+
+            let source = table.source(u, v).0;
+            assert!((source.x - (map.0 + 0.5)).abs() < 1e-6, "{} != {}", source.x, map.0 + 0.5);
+            assert!((source.y - (map.1 + 0.5)).abs() < 1e-6, "{} != {}", source.y, map.1 + 0.5);
+        }
+
+        // Exporting gives back OpenCV's principal point
+        let exported = params.to_opencv_string().unwrap();
+        assert!(exported.contains("data: [ 500.0, 0.0, 319.0, 0.0, 505.0, 241.0, 0.0, 0.0, 1.0 ]"));
+    }
+
+    /// Test that parameters written in the crate's own format load back unchanged
+    #[test]
+    fn test_native_round_trip() {
+        let camera = RectifParams {
+            focals: [600.0, 602.0],
+            principal_point: [320.5, 240.25],
+            k1: Some(-0.28),
+            k2: Some(0.07),
+            p1: Some(1.0e-4),
+            ..RectifParams::default()
+        };
+        let params = StereoRectifParams {
+            left: camera,
+            right: RectifParams {
+                distortion_model: Some(DistortionModel::Equidistant),
+                k4: Some(0.01),
+                ..camera
+            },
+            rotation: Some([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]),
+            translation: Some([-0.12, 0.0, 0.001])
+        };
+
+        let dir = test_dir("native_round_trip");
+        for ext in ["toml", "json", "yaml"].iter() {
+            let path = dir.join(format!("params.{}", ext));
+
+            params.to_file(&path).unwrap();
+            let loaded = StereoRectifParams::from_file(&path).unwrap();
+            camera.to_file(&path).unwrap();
+            let loaded_mono = RectifParams::from_file(&path).unwrap();
+
+            assert_eq!(loaded, params, "{}", ext);
+            assert_eq!(loaded_mono, camera, "{}", ext);
+        }
+    }
+}
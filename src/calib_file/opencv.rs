@@ -0,0 +1,458 @@
+//! # OpenCV Calibration Files
+//!
+//! Reads the YAML and XML documents written by OpenCV's `FileStorage`, such as those saved by its
+//! `calibration` and `stereo_calib` samples, and writes YAML documents in the same dialect.
+//!
+//! YAML documents start with a `%YAML:1.0` directive, which is not valid YAML 1.1, and tag their
+//! matrices with `!!opencv-matrix`. Both are stripped before the document is parsed.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::collections::{BTreeMap, HashMap};
+
+use nalgebra::Matrix3;
+use serde::Deserialize;
+use serde::de::IgnoredAny;
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::distortion::DistortionModel;
+use crate::error::{Error, Result};
+
+use super::{distortion_model_from_name, rotation_from};
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Tags OpenCV gives its matrices in YAML documents.
+const MATRIX_TAGS: [&str; 2] = ["!!opencv-matrix", "!<tag:yaml.org,2002:opencv-matrix>"];
+
+/// Root element of OpenCV XML documents.
+const XML_ROOT: &str = "opencv_storage";
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// The top level nodes of an OpenCV `FileStorage` document.
+pub(crate) struct FileStorage {
+    nodes: BTreeMap<String, Node>
+}
+
+/// Builds an OpenCV `FileStorage` YAML document one node at a time.
+pub(crate) struct FileStorageWriter {
+    doc: String
+}
+
+// -----------------------------------------------------------------------------------------------
+// ENUMERATIONS
+// -----------------------------------------------------------------------------------------------
+
+/// A top level node of a `FileStorage` document.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Node {
+    /// A matrix of `rows` by `cols` elements, stored in row major order
+    Matrix {
+        rows: usize,
+        cols: usize,
+        data: Vec<f64>
+    },
+
+    Number(f64),
+
+    Sequence(Vec<f64>),
+
+    Text(String),
+
+    /// Any other node, which the crate doesn't use
+    Other(IgnoredAny)
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl FileStorage {
+
+    /// Check whether a document looks like it was written by OpenCV's `FileStorage`.
+    pub(crate) fn is_file_storage(s: &str) -> bool {
+        let s = s.trim_start();
+
+        s.starts_with("%YAML") || (s.starts_with('<') && s.contains(&format!("<{}>", XML_ROOT)))
+    }
+
+    /// Parse a YAML or XML document.
+    pub(crate) fn parse(s: &str) -> Result<Self> {
+        let nodes = if s.trim_start().starts_with('<') {
+            parse_xml(s)?
+        } else {
+            parse_yaml(s)?
+        };
+
+        Ok(Self { nodes })
+    }
+
+    /// Get the distortion model named by the `distortion_model` node, if there is one.
+    pub(crate) fn distortion_model(&self) -> Result<Option<DistortionModel>> {
+        match self.nodes.get("distortion_model") {
+            Some(Node::Text(name)) => distortion_model_from_name(name).map(Some),
+            Some(node) => Err(Error::CalibrationFileError(format!(
+                "distortion_model must be a string, not {:?}", node
+            ))),
+            None => Ok(None)
+        }
+    }
+
+    /// Get the first of the given nodes which exists as a list of numbers, in row major order
+    /// for matrices.
+    pub(crate) fn vector(&self, keys: &[&str]) -> Result<Option<Vec<f64>>> {
+        let (key, node) = match keys.iter().find_map(|&k| self.nodes.get(k).map(|n| (k, n))) {
+            Some(n) => n,
+            None => return Ok(None)
+        };
+
+        match node {
+            Node::Matrix { rows, cols, data } if rows * cols == data.len() => {
+                Ok(Some(data.clone()))
+            },
+            Node::Matrix { rows, cols, data } => Err(Error::CalibrationFileError(format!(
+                "{} is a {}x{} matrix but has {} elements", key, rows, cols, data.len()
+            ))),
+            Node::Number(n) => Ok(Some(vec![*n])),
+            Node::Sequence(values) => Ok(Some(values.clone())),
+            _ => Err(Error::CalibrationFileError(format!("{} is not a matrix", key)))
+        }
+    }
+
+    /// Get the first of the given nodes as a 3x3 camera matrix.
+    pub(crate) fn camera_matrix(&self, keys: &[&str]) -> Result<Matrix3<f64>> {
+        match self.vector(keys)? {
+            Some(values) if values.len() == 9 => Ok(Matrix3::from_row_slice(&values)),
+            Some(values) => Err(Error::CalibrationFileError(format!(
+                "A camera matrix must have 9 elements, not {}", values.len()
+            ))),
+            None => Err(Error::CalibrationFileError(format!(
+                "No camera matrix found, expected one of {:?}", keys
+            )))
+        }
+    }
+
+    /// Get the given node as a rotation, which may be a 3x3 matrix or a Rodrigues vector.
+    pub(crate) fn rotation(&self, key: &str) -> Result<Option<Matrix3<f64>>> {
+        self.vector(&[key])?.map(|v| rotation_from(&v)).transpose()
+    }
+}
+
+impl FileStorageWriter {
+
+    /// Start a new document.
+    pub(crate) fn new() -> Self {
+        Self {
+            doc: String::from("%YAML:1.0\n---\n")
+        }
+    }
+
+    /// Add a matrix from its elements in row major order.
+    pub(crate) fn matrix(mut self, name: &str, rows: usize, cols: usize, data: &[f64]) -> Self {
+        let data = data.iter()
+            .map(|v| format!("{:?}", v))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        self.doc += &format!(
+            "{}: !!opencv-matrix\n   rows: {}\n   cols: {}\n   dt: d\n   data: [ {} ]\n",
+            name, rows, cols, data
+        );
+
+        self
+    }
+
+    /// Add a string.
+    pub(crate) fn text(mut self, name: &str, value: &str) -> Self {
+        self.doc += &format!("{}: \"{}\"\n", name, value);
+
+        self
+    }
+
+    /// Get the finished document.
+    pub(crate) fn finish(self) -> String {
+        self.doc
+    }
+}
+
+impl Node {
+
+    /// Build a node from the text of an XML element and the text of each of its children.
+    fn from_xml(text: &str, children: &HashMap<String, String>) -> Result<Node> {
+        let numbers = |s: &str, name: &str| s.split_whitespace()
+            .map(|v| v.parse::<f64>())
+            .collect::<std::result::Result<Vec<f64>, _>>()
+            .map_err(|e| Error::CalibrationFileError(format!("Invalid {}: {}", name, e)));
+
+        if let Some(data) = children.get("data") {
+            let dim = |name: &str| children.get(name)
+                .and_then(|v| v.trim().parse::<usize>().ok())
+                .ok_or_else(|| Error::CalibrationFileError(format!("Matrix has no {}", name)));
+
+            return Ok(Node::Matrix {
+                rows: dim("rows")?,
+                cols: dim("cols")?,
+                data: numbers(data, "matrix data")?
+            });
+        }
+
+        if !children.is_empty() {
+            return Ok(Node::Other(IgnoredAny));
+        }
+
+        let text = text.trim();
+        match numbers(text, "number") {
+            Ok(ref values) if values.len() == 1 => Ok(Node::Number(values[0])),
+            Ok(ref values) if !values.is_empty() => Ok(Node::Sequence(values.clone())),
+            _ => Ok(Node::Text(text.trim_matches('"').to_string()))
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// PRIVATE FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Parse a YAML document, removing the parts of OpenCV's dialect which aren't YAML.
+fn parse_yaml(s: &str) -> Result<BTreeMap<String, Node>> {
+    let mut yaml = s.lines()
+        .filter(|l| !l.starts_with('%'))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    for tag in MATRIX_TAGS.iter() {
+        yaml = yaml.replace(tag, "");
+    }
+
+    serde_yaml::from_str(&yaml)
+        .map_err(|e| Error::CalibrationFileError(format!("Invalid OpenCV YAML: {}", e)))
+}
+
+/// Parse an XML document, keeping the text of each child of the root element and of their
+/// children.
+fn parse_xml(s: &str) -> Result<BTreeMap<String, Node>> {
+    let mut nodes = BTreeMap::new();
+
+    // Names of the elements enclosing the current position
+    let mut path: Vec<String> = Vec::new();
+    let mut text = String::new();
+    let mut children = HashMap::new();
+
+    for event in EventReader::from_str(s) {
+        let event = event
+            .map_err(|e| Error::CalibrationFileError(format!("Invalid OpenCV XML: {}", e)))?;
+
+        match event {
+            XmlEvent::StartElement { name, .. } => {
+                if path.is_empty() && name.local_name != XML_ROOT {
+                    return Err(Error::CalibrationFileError(format!(
+                        "Expected an <{}> root element, found <{}>", XML_ROOT, name.local_name
+                    )));
+                }
+
+                path.push(name.local_name);
+                if path.len() == 2 {
+                    text.clear();
+                    children.clear();
+                }
+            },
+            XmlEvent::Characters(chars) => match path.len() {
+                2 => text.push_str(&chars),
+                3 => children.entry(path[2].clone()).or_insert_with(String::new).push_str(&chars),
+                _ => ()
+            },
+            XmlEvent::EndElement { .. } => {
+                if path.len() == 2 {
+                    nodes.insert(path[1].clone(), Node::from_xml(&text, &children)?);
+                }
+                path.pop();
+            },
+            _ => ()
+        }
+    }
+
+    Ok(nodes)
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::backend::tests::test_dir;
+    use crate::calib_file::CalibrationParams;
+    use crate::rectification::{RectifParams, StereoRectifParams};
+
+    /// A file as written by OpenCV's calibration sample.
+    const MONO_YAML: &str = "%YAML:1.0
+---
+calibration_time: \"Thu 15 Oct 2026 10:00:00 BST\"
+image_width: 640
+image_height: 480
+flags: 0
+camera_matrix: !!opencv-matrix
+   rows: 3
+   cols: 3
+   dt: d
+   data: [ 5.2e+02, 0., 3.2e+02, 0., 5.3e+02, 2.4e+02, 0., 0., 1. ]
+distortion_coefficients: !!opencv-matrix
+   rows: 5
+   cols: 1
+   dt: d
+   data: [ -2.8e-01, 7.0e-02, 1.0e-03, -2.0e-04, 0. ]
+avg_reprojection_error: 2.5e-01
+";
+
+    /// A file as written by OpenCV's stereo calibration sample, with the intrinsics and
+    /// extrinsics together.
+    const STEREO_XML: &str = "<?xml version=\"1.0\"?>
+<opencv_storage>
+<M1 type_id=\"opencv-matrix\">
+  <rows>3</rows>
+  <cols>3</cols>
+  <dt>d</dt>
+  <data>
+    500. 0. 320. 0. 500. 240. 0. 0. 1.</data></M1>
+<D1 type_id=\"opencv-matrix\">
+  <rows>1</rows>
+  <cols>8</cols>
+  <dt>d</dt>
+  <data>
+    -0.1 0.01 0. 0. 0. 0.2 0. 0.</data></D1>
+<M2 type_id=\"opencv-matrix\">
+  <rows>3</rows>
+  <cols>3</cols>
+  <dt>d</dt>
+  <data>
+    510. 0. 330. 0. 510. 250. 0. 0. 1.</data></M2>
+<D2 type_id=\"opencv-matrix\">
+  <rows>1</rows>
+  <cols>5</cols>
+  <dt>d</dt>
+  <data>
+    -0.12 0.02 0. 0. 0.</data></D2>
+<R type_id=\"opencv-matrix\">
+  <rows>3</rows>
+  <cols>1</cols>
+  <dt>d</dt>
+  <data>
+    0. 0.1 0.</data></R>
+<T type_id=\"opencv-matrix\">
+  <rows>3</rows>
+  <cols>1</cols>
+  <dt>d</dt>
+  <data>
+    -0.12 0. 0.</data></T>
+</opencv_storage>
+";
+
+    /// Test loading a mono camera from OpenCV's YAML dialect
+    #[test]
+    fn test_opencv_yaml() {
+        assert!(FileStorage::is_file_storage(MONO_YAML));
+
+        let params = RectifParams::from_opencv_str(MONO_YAML).unwrap();
+        assert_eq!(params.focals, [520.0, 530.0]);
+        assert_eq!(params.principal_point, [320.5, 240.5]);
+        assert_eq!(params.distortion_model(), DistortionModel::PlumbBob);
+        assert_eq!((params.k1, params.k2, params.k3), (Some(-0.28), Some(0.07), Some(0.0)));
+        assert_eq!((params.p1, params.p2), (Some(1.0e-3), Some(-2.0e-4)));
+
+        // Loading from a file detects the format from its contents
+        let path = test_dir("opencv_yaml").join("calibration.yml");
+        std::fs::write(&path, MONO_YAML).unwrap();
+        let loaded = RectifParams::from_file(&path).unwrap();
+        assert_eq!(loaded.k1, params.k1);
+    }
+
+    /// Test loading a stereo pair from OpenCV's XML format, with a Rodrigues rotation
+    #[test]
+    fn test_opencv_xml() {
+        assert!(FileStorage::is_file_storage(STEREO_XML));
+
+        let params = StereoRectifParams::from_opencv_str(STEREO_XML).unwrap();
+        assert_eq!(params.left.focals, [500.0, 500.0]);
+        assert_eq!(params.left.distortion_model(), DistortionModel::RationalPolynomial);
+        assert_eq!(params.left.k4, Some(0.2));
+        assert_eq!(params.right.principal_point, [330.5, 250.5]);
+        assert_eq!(params.right.distortion_model(), DistortionModel::PlumbBob);
+        assert_eq!(params.translation, Some([-0.12, 0.0, 0.0]));
+
+        // A rotation of 0.1 radians about the Y axis
+        let r = params.rotation.unwrap();
+        assert!((r[0][2] - 0.1f64.sin()).abs() < 1e-12);
+        assert!((r[2][0] + 0.1f64.sin()).abs() < 1e-12);
+        assert_eq!(r[1][1], 1.0);
+    }
+
+    /// Test that written documents load back to the same parameters
+    #[test]
+    fn test_opencv_export() {
+        let params = RectifParams::from_opencv_str(MONO_YAML).unwrap();
+        let yaml = params.to_opencv_string().unwrap();
+        assert!(yaml.starts_with("%YAML:1.0\n"));
+        assert!(yaml.contains("camera_matrix: !!opencv-matrix"));
+        assert_eq!(RectifParams::from_opencv_str(&yaml).unwrap(), params);
+
+        let stereo = StereoRectifParams::from_opencv_str(STEREO_XML).unwrap();
+        let loaded = StereoRectifParams::from_opencv_str(&stereo.to_opencv_string().unwrap())
+            .unwrap();
+        assert_eq!(loaded, stereo);
+
+        // The division model has no OpenCV equivalent
+        let division = RectifParams { k1: Some(-0.2), ..RectifParams::default() };
+        match division.to_opencv_string() {
+            Err(Error::UnsupportedDistortionModel(m)) => assert!(m.contains("division")),
+            r => panic!("Expected an UnsupportedDistortionModel error, got {:?}", r)
+        }
+    }
+
+    /// Test that distortion the crate can't model is reported precisely
+    #[test]
+    fn test_unsupported_distortion() {
+        let thin_prism = MONO_YAML
+            .replace("rows: 5", "rows: 12")
+            .replace("-2.0e-04, 0. ]", "-2.0e-04, 0., 0., 0., 0., 1.0e-3, 0., 0., 0. ]");
+        match RectifParams::from_opencv_str(&thin_prism) {
+            Err(Error::UnsupportedDistortionModel(m)) => assert!(m.contains("thin prism")),
+            r => panic!("Expected an UnsupportedDistortionModel error, got {:?}", r)
+        }
+
+        // Zero thin prism coefficients are accepted
+        let zeros = thin_prism.replace("1.0e-3, 0., 0., 0. ]", "0., 0., 0., 0. ]");
+        assert!(RectifParams::from_opencv_str(&zeros).is_ok());
+
+        // Four coefficients may be for either the fisheye or the pinhole model
+        let four = MONO_YAML
+            .replace("rows: 5", "rows: 4")
+            .replace("-2.0e-04, 0. ]", "-2.0e-04 ]");
+        match RectifParams::from_opencv_str(&four) {
+            Err(Error::CalibrationFileError(m)) => assert!(m.contains("fisheye")),
+            r => panic!("Expected a CalibrationFileError, got {:?}", r)
+        }
+        let fisheye = format!("{}distortion_model: \"fisheye\"\n", four);
+        let params = RectifParams::from_opencv_str(&fisheye).unwrap();
+        assert_eq!(params.distortion_model(), DistortionModel::Equidistant);
+        assert_eq!(params.k4, Some(-2.0e-4));
+        let plumb_bob = format!("{}distortion_model: \"plumb_bob\"\n", four);
+        let params = RectifParams::from_opencv_str(&plumb_bob).unwrap();
+        assert_eq!((params.p2, params.k3), (Some(-2.0e-4), None));
+
+        let named = format!("{}distortion_model: \"omnidirectional\"\n", MONO_YAML);
+        match RectifParams::from_opencv_str(&named) {
+            Err(Error::UnsupportedDistortionModel(m)) => assert!(m.contains("omnidirectional")),
+            r => panic!("Expected an UnsupportedDistortionModel error, got {:?}", r)
+        }
+    }
+}
@@ -0,0 +1,437 @@
+//! # ROS Calibration Files
+//!
+//! Reads and writes the `camera_info` YAML files of the ROS `camera_calibration` package, and
+//! reads the `CameraInfo` messages it publishes, which name their matrices `K`, `D`, `R` and `P`.
+//!
+//! A stereo calibration is written as one file per camera, whose rectification and projection
+//! matrices give the rotation and translation between the cameras. These can be loaded with
+//! [`StereoRectifParams::from_ros_files`], or from a single file with the `camera_info` of each
+//! camera under `left` and `right`.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::path::Path;
+
+use nalgebra::{Matrix3, Matrix3x4, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::rectification::{RectifParams, StereoRectifParams, StereoSide};
+
+use super::{camera_matrix, crate_to_tool_pixels, distortion_model_from_name};
+use super::distortion_model_name;
+use super::{opencv_distortion, rectif_params, row_major, tool_to_crate_pixels};
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// The calibration of a single camera, as in a ROS `sensor_msgs/CameraInfo` message.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct CameraInfo {
+    #[serde(alias = "width", default, skip_serializing_if = "Option::is_none")]
+    image_width: Option<u32>,
+
+    #[serde(alias = "height", default, skip_serializing_if = "Option::is_none")]
+    image_height: Option<u32>,
+
+    #[serde(alias = "K", alias = "k")]
+    camera_matrix: RosMatrix,
+
+    #[serde(alias = "D", alias = "d", default)]
+    distortion_coefficients: Option<RosMatrix>,
+
+    #[serde(default)]
+    distortion_model: Option<String>,
+
+    /// Rotation from the camera's frame to its rectified frame
+    #[serde(alias = "R", alias = "r", default)]
+    rectification_matrix: Option<RosMatrix>,
+
+    /// Projection of the rectified frame into the rectified image
+    #[serde(alias = "P", alias = "p", default)]
+    projection_matrix: Option<RosMatrix>
+}
+
+/// The calibrations of both cameras of a stereo pair in a single file.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct StereoCameraInfo {
+    left: CameraInfo,
+
+    right: CameraInfo
+}
+
+// -----------------------------------------------------------------------------------------------
+// ENUMERATIONS
+// -----------------------------------------------------------------------------------------------
+
+/// A row major matrix, written with its size by `camera_calibration` or as a flat list in
+/// messages.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+enum RosMatrix {
+    Sized {
+        rows: usize,
+        cols: usize,
+        data: Vec<f64>
+    },
+
+    Flat(Vec<f64>)
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl CameraInfo {
+
+    /// Describe a camera with the given rectification parameters, whose rectified images come
+    /// from the given rotation and pinhole projection, or are only undistorted if none is given.
+    ///
+    /// The projection is in the crate's pixel coordinates, and is written in those of ROS.
+    pub(crate) fn new(
+        params: &RectifParams,
+        resolution: (u32, u32),
+        rectification: Option<(Matrix3<f64>, Matrix3x4<f64>)>
+    ) -> Result<Self> {
+        let (model, coeffs) = opencv_distortion(params)?;
+
+        let (r, p) = rectification.unwrap_or_else(|| {
+            let mut p = Matrix3x4::zeros();
+            p.fixed_columns_mut::<nalgebra::U3>(0)
+                .copy_from(&params.output_intrinsics(resolution).matrix());
+
+            (Matrix3::identity(), p)
+        });
+        let p = crate_to_tool_pixels() * p;
+
+        Ok(Self {
+            image_width: Some(resolution.0),
+            image_height: Some(resolution.1),
+            camera_matrix: RosMatrix::row_major(3, 3, camera_matrix(params).transpose().as_slice()),
+            distortion_coefficients: Some(RosMatrix::row_major(1, coeffs.len(), &coeffs)),
+            distortion_model: Some(distortion_model_name(model).to_string()),
+            rectification_matrix: Some(RosMatrix::row_major(3, 3, r.transpose().as_slice())),
+            projection_matrix: Some(RosMatrix::row_major(3, 4, p.transpose().as_slice()))
+        })
+    }
+
+    /// Parse a `camera_info` YAML document.
+    pub(crate) fn parse(s: &str) -> Result<Self> {
+        serde_yaml::from_str(s)
+            .map_err(|e| Error::CalibrationFileError(format!("Invalid camera_info: {}", e)))
+    }
+
+    /// Write the `camera_info` YAML document.
+    pub(crate) fn to_yaml(&self) -> Result<String> {
+        serde_yaml::to_string(self)
+            .map_err(|e| Error::CalibrationFileError(format!("Cannot write camera_info: {}", e)))
+    }
+
+    /// Get the rectification parameters of the camera.
+    pub(crate) fn rectif_params(&self) -> Result<RectifParams> {
+        let model = self.distortion_model.as_deref()
+            .map(distortion_model_from_name)
+            .transpose()?;
+
+        let coeffs = match self.distortion_coefficients {
+            Some(ref d) => d.data("distortion coefficients", None)?,
+            None => &[]
+        };
+
+        rectif_params(
+            &Matrix3::from_row_slice(self.camera_matrix.data("camera matrix", Some(9))?),
+            coeffs,
+            model
+        )
+    }
+
+    /// Get the rectifying rotation and projection matrix, if both are given, with the projection
+    /// in the crate's pixel coordinates.
+    fn rectification(&self) -> Result<Option<(Matrix3<f64>, Matrix3x4<f64>)>> {
+        match (&self.rectification_matrix, &self.projection_matrix) {
+            (Some(r), Some(p)) => Ok(Some((
+                Matrix3::from_row_slice(r.data("rectification matrix", Some(9))?),
+                tool_to_crate_pixels()
+                    * Matrix3x4::from_row_slice(p.data("projection matrix", Some(12))?)
+            ))),
+            _ => Ok(None)
+        }
+    }
+}
+
+impl StereoCameraInfo {
+
+    /// Describe both cameras of a stereo pair, including their rectification if the extrinsics
+    /// are known.
+    pub(crate) fn new(params: &StereoRectifParams, resolution: (u32, u32)) -> Result<Self> {
+        let rect = params.rectification(resolution);
+
+        let side = |side: StereoSide| {
+            let rectification = rect.map(|r| {
+                // Both rectified cameras share the intrinsics, and the right one is translated
+                // from the left
+                let k = r.intrinsics.matrix();
+                let mut p = Matrix3x4::zeros();
+                p.fixed_columns_mut::<nalgebra::U3>(0).copy_from(&k);
+                if side == StereoSide::Right {
+                    p.set_column(3, &(k * r.translation));
+                }
+
+                (r.rotation(side), p)
+            });
+
+            CameraInfo::new(params.side(side), resolution, rectification)
+        };
+
+        Ok(Self {
+            left: side(StereoSide::Left)?,
+            right: side(StereoSide::Right)?
+        })
+    }
+
+    /// Parse a YAML document with a `camera_info` under `left` and `right`.
+    pub(crate) fn parse(s: &str) -> Result<Self> {
+        serde_yaml::from_str(s)
+            .map_err(|e| Error::CalibrationFileError(format!("Invalid stereo camera_info: {}", e)))
+    }
+
+    /// Write the YAML document with the `camera_info` of each camera.
+    pub(crate) fn to_yaml(&self) -> Result<String> {
+        serde_yaml::to_string(self)
+            .map_err(|e| Error::CalibrationFileError(format!("Cannot write camera_info: {}", e)))
+    }
+
+    /// Get the rectification parameters of the pair.
+    ///
+    /// The rotation and translation between the cameras are recovered from the rectification
+    /// and projection matrices, as both rectified frames have the same orientation and the right
+    /// projection matrix holds the translation between them. They are `None` if either camera
+    /// has no rectification, or if the right projection matrix has no baseline as is the case
+    /// for mono calibrations.
+    pub(crate) fn rectif_params(&self) -> Result<StereoRectifParams> {
+        let mut params = StereoRectifParams {
+            left: self.left.rectif_params()?,
+            right: self.right.rectif_params()?,
+            rotation: None,
+            translation: None
+        };
+
+        if let (Some((r1, _)), Some((r2, p2))) =
+            (self.left.rectification()?, self.right.rectification()?)
+        {
+            // The translation of the right rectified frame from the left
+            let k = p2.fixed_columns::<nalgebra::U3>(0).into_owned();
+            let t = k.try_inverse()
+                .ok_or_else(|| Error::CalibrationFileError(
+                    "The right projection matrix is singular".into()
+                ))?
+                * Vector3::new(p2[(0, 3)], p2[(1, 3)], p2[(2, 3)]);
+
+            if t.norm() > 0.0 {
+                params.rotation = Some(row_major(r2.transpose() * r1));
+                params.translation = Some((r2.transpose() * t).into());
+            }
+        }
+
+        Ok(params)
+    }
+}
+
+impl StereoRectifParams {
+
+    /// Load the parameters of a stereo pair from the separate left and right `camera_info` files
+    /// written by the ROS `camera_calibration` package.
+    pub fn from_ros_files<P: AsRef<Path>>(left: P, right: P) -> Result<Self> {
+        let read = |path: &Path| {
+            if !path.exists() {
+                return Err(Error::FileNotFound(path.to_path_buf()));
+            }
+
+            CameraInfo::parse(&fs::read_to_string(path).map_err(|e| {
+                Error::CalibrationFileError(format!("Cannot read {:?}: {}", path, e))
+            })?)
+        };
+
+        StereoCameraInfo {
+            left: read(left.as_ref())?,
+            right: read(right.as_ref())?
+        }.rectif_params()
+    }
+
+    /// Write the parameters of a stereo pair to separate left and right `camera_info` files, as
+    /// the ROS `camera_calibration` package does, for images of the given resolution.
+    pub fn to_ros_files<P: AsRef<Path>>(
+        &self,
+        left: P,
+        right: P,
+        resolution: (u32, u32)
+    ) -> Result<()> {
+        let info = StereoCameraInfo::new(self, resolution)?;
+
+        for (path, camera) in [(left.as_ref(), &info.left), (right.as_ref(), &info.right)].iter() {
+            fs::write(path, camera.to_yaml()?).map_err(|e| {
+                Error::CalibrationFileError(format!("Cannot write {:?}: {}", path, e))
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+impl RosMatrix {
+
+    /// Create a sized matrix from its elements in row major order.
+    fn row_major(rows: usize, cols: usize, data: &[f64]) -> Self {
+        RosMatrix::Sized {
+            rows,
+            cols,
+            data: data.to_vec()
+        }
+    }
+
+    /// Get the elements of the matrix, checking that there are as many as its size and the
+    /// expected number if one is given.
+    fn data(&self, name: &str, expected: Option<usize>) -> Result<&[f64]> {
+        let data = match self {
+            RosMatrix::Sized { rows, cols, data } if rows * cols != data.len() => {
+                return Err(Error::CalibrationFileError(format!(
+                    "The {} is {}x{} but has {} elements", name, rows, cols, data.len()
+                )));
+            },
+            RosMatrix::Sized { data, .. } | RosMatrix::Flat(data) => data
+        };
+
+        match expected {
+            Some(n) if data.len() != n => Err(Error::CalibrationFileError(format!(
+                "The {} must have {} elements, not {}", name, n, data.len()
+            ))),
+            _ => Ok(data)
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::backend::tests::test_dir;
+    use crate::calib_file::CalibrationParams;
+    use crate::distortion::DistortionModel;
+
+    /// The left camera of a stereo calibration by `camera_calibration`.
+    const LEFT_YAML: &str = "image_width: 640
+image_height: 480
+camera_name: narrow_stereo/left
+camera_matrix:
+  rows: 3
+  cols: 3
+  data: [500.0, 0.0, 320.0, 0.0, 500.0, 240.0, 0.0, 0.0, 1.0]
+distortion_model: plumb_bob
+distortion_coefficients:
+  rows: 1
+  cols: 5
+  data: [-0.2, 0.04, 0.001, 0.0, 0.0]
+rectification_matrix:
+  rows: 3
+  cols: 3
+  data: [0.9950042, 0.0, -0.0998334, 0.0, 1.0, 0.0, 0.0998334, 0.0, 0.9950042]
+projection_matrix:
+  rows: 3
+  cols: 4
+  data: [480.0, 0.0, 300.0, 0.0, 0.0, 480.0, 240.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+";
+
+    /// The right camera of the same calibration, written as a `CameraInfo` message.
+    const RIGHT_YAML: &str = "height: 480
+width: 640
+distortion_model: \"plumb_bob\"
+D: [-0.21, 0.05, 0.0, 0.001, 0.0]
+K: [505.0, 0.0, 330.0, 0.0, 505.0, 250.0, 0.0, 0.0, 1.0]
+R: [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]
+P: [480.0, 0.0, 300.0, -57.6, 0.0, 480.0, 240.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+";
+
+    /// Test loading a single camera in both forms
+    #[test]
+    fn test_ros_camera_info() {
+        let left = RectifParams::from_ros_str(LEFT_YAML).unwrap();
+        assert_eq!(left.focals, [500.0, 500.0]);
+        assert_eq!(left.distortion_model(), DistortionModel::PlumbBob);
+        assert_eq!((left.k1, left.k3, left.p1), (Some(-0.2), Some(0.0), Some(0.001)));
+
+        let right = RectifParams::from_ros_str(RIGHT_YAML).unwrap();
+        assert_eq!(right.principal_point, [330.5, 250.5]);
+
+        // The rectified principal point moves in the same way, and the baseline column with it
+        let (_, p) = CameraInfo::parse(RIGHT_YAML).unwrap().rectification().unwrap().unwrap();
+        assert_eq!((p[(0, 2)], p[(1, 2)], p[(0, 3)]), (300.5, 240.5, -57.6));
+        assert_eq!(right.p2, Some(0.001));
+
+        // An exported camera is undistorted into its default output camera, with both principal
+        // points written in the pixel coordinates of ROS
+        let yaml = right.to_ros_string((640, 480)).unwrap();
+        assert!(yaml.contains("distortion_model: plumb_bob"));
+        assert_eq!(RectifParams::from_ros_str(&yaml).unwrap(), right);
+        let info = CameraInfo::parse(&yaml).unwrap();
+        assert_eq!(info.image_width, Some(640));
+        assert_eq!(info.camera_matrix.data("camera matrix", Some(9)).unwrap()[2], 330.0);
+        let p = info.projection_matrix.unwrap();
+        let p = p.data("projection matrix", Some(12)).unwrap();
+        let k = right.output_intrinsics((640, 480)).matrix();
+        assert_eq!(p[0], k[(0, 0)]);
+        assert_eq!((p[2], p[6]), (k[(0, 2)] - 0.5, k[(1, 2)] - 0.5));
+    }
+
+    /// Test recovering the extrinsics of a stereo pair from its rectification
+    #[test]
+    fn test_ros_stereo() {
+        let dir = test_dir("ros_stereo");
+        std::fs::write(dir.join("left.yaml"), LEFT_YAML).unwrap();
+        std::fs::write(dir.join("right.yaml"), RIGHT_YAML).unwrap();
+
+        let params =
+            StereoRectifParams::from_ros_files(dir.join("left.yaml"), dir.join("right.yaml"))
+                .unwrap();
+
+        // The rotation undoes the left rectification, and the 0.12 baseline lies along the
+        // rectified X axis
+        let r = params.rotation.unwrap();
+        assert!((r[0][2] + 0.0998334).abs() < 1e-6);
+        let t = params.translation.unwrap();
+        assert!((t[0] + 0.12).abs() < 1e-9 && t[1].abs() < 1e-9 && t[2].abs() < 1e-9);
+
+        // Writing the files and loading them again gives the same cameras and extrinsics
+        let dir = test_dir("ros_export");
+        params.to_ros_files(dir.join("left.yaml"), dir.join("right.yaml"), (640, 480)).unwrap();
+        let exported =
+            StereoRectifParams::from_ros_files(dir.join("left.yaml"), dir.join("right.yaml"))
+                .unwrap();
+
+        assert_eq!(exported.left, params.left);
+        assert_eq!(exported.right, params.right);
+        // The rounded rectification in the original file is not quite orthonormal, so the
+        // extrinsics only agree to its precision
+        let (r, exported_r) = (params.rotation.unwrap(), exported.rotation.unwrap());
+        for i in 0..3 {
+            for j in 0..3 {
+                assert!((r[i][j] - exported_r[i][j]).abs() < 1e-6);
+            }
+            assert!((t[i] - exported.translation.unwrap()[i]).abs() < 1e-6);
+        }
+
+        // The same pair can be given in a single file
+        let indent = |s: &str| s.lines().map(|l| format!("  {}\n", l)).collect::<String>();
+        let combined = format!("left:\n{}right:\n{}", indent(LEFT_YAML), indent(RIGHT_YAML));
+        let loaded = StereoRectifParams::from_ros_str(&combined).unwrap();
+        assert_eq!(loaded.rotation, params.rotation);
+        assert_eq!(loaded.right.focals, [505.0, 505.0]);
+    }
+}
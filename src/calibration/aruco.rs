@@ -0,0 +1,877 @@
+//! # ArUco Markers
+//!
+//! Detection and decoding of ArUco square fiducial markers. Each marker is a grid of black and
+//! white cells surrounded by a black border one cell wide, and the inner cells encode the
+//! marker's identifier in one of the codes of an [`ArucoDictionary`].
+//!
+//! Markers are found by thresholding the image against its local mean at several window sizes, then
+//! fitting a quadrilateral to the convex hull of each dark connected region. The corners of each
+//! quadrilateral are refined to sub-pixel accuracy by fitting lines to its four edges, and the
+//! cells are read through the homography from the marker's grid to the image. A candidate is
+//! accepted if its border is dark and its cells match a code of the dictionary in one of the four
+//! orientations of the marker.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use nalgebra::{Matrix3, Point2, Vector3};
+
+use crate::error::{Error, Result};
+use crate::interpolation::{sample, BorderMode, Interpolation};
+use crate::GrayFloatImage;
+
+use super::zhang::homography;
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Number of inner cells along each side of the markers of the original ArUco dictionary.
+const ORIGINAL_MARKER_BITS: usize = 5;
+
+/// The rows of the markers of the original ArUco dictionary, one for each pair of bits of the
+/// identifier, with the most significant cell on the left.
+const ORIGINAL_ROW_WORDS: [u64; 4] = [0b10000, 0b10111, 0b01001, 0b01110];
+
+/// Largest number of inner cells along each side of a marker, so that every cell fits in a
+/// `u64`.
+const MAX_MARKER_BITS: usize = 8;
+
+/// Sizes of the windows used to find the local mean when thresholding, in pixels.
+const THRESHOLD_WINDOWS: [usize; 3] = [3, 13, 23];
+
+/// Pixels are dark if they are this far below the mean of the window around them.
+const THRESHOLD_OFFSET: f32 = 0.03;
+
+/// Markers must have sides at least this long, in pixels.
+const MIN_SIDE: f64 = 12.0;
+
+/// The quadrilateral fitted to a dark region must cover at least this fraction of the region's
+/// convex hull.
+const MIN_QUAD_FILL: f64 = 0.9;
+
+/// Markers must lie at least this many pixels from the edges of the image.
+const IMAGE_MARGIN: f64 = 2.0;
+
+/// Number of points found along each side of a marker when fitting lines to its edges.
+const EDGE_SAMPLES: usize = 16;
+
+/// Fraction of each side of a marker, centred on its middle, along which the edge is sampled.
+const EDGE_SPAN: f64 = 0.8;
+
+/// The distance searched either side of each side of a marker for its edge, as a fraction of the
+/// size of a cell.
+const EDGE_SEARCH_FRACTION: f64 = 0.75;
+
+/// Limits on the distance searched either side of each side of a marker for its edge, in pixels.
+const EDGE_SEARCH_LIMITS: (f64, f64) = (2.0, 6.0);
+
+/// Spacing of the samples taken across each edge of a marker, in pixels.
+const EDGE_STEP: f64 = 0.25;
+
+/// Number of samples along each axis of a cell when reading its value.
+const CELL_SAMPLES: usize = 3;
+
+/// Fraction of the width of a cell which is sampled around its centre.
+const CELL_SAMPLE_SPAN: f64 = 0.5;
+
+/// Minimum difference between the darkest and lightest cells of a marker.
+const MIN_CELL_CONTRAST: f32 = 0.1;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// A dictionary of ArUco markers, giving the code of each marker identifier.
+///
+/// Codes are stored with one bit per inner cell, row by row from the top left of the marker with
+/// the first cell in the most significant of the used bits, and set bits are white cells.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArucoDictionary {
+    marker_bits: usize,
+
+    codes: Vec<u64>,
+
+    max_correction_bits: u32
+}
+
+/// Finds the markers of an ArUco dictionary in images.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArucoDetector {
+    dictionary: ArucoDictionary
+}
+
+/// An ArUco marker found in an image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArucoMarker {
+    /// The identifier of the marker in its dictionary
+    pub id: usize,
+
+    /// The corners of the marker in the image, to sub-pixel accuracy, in clockwise order from the
+    /// marker's top left corner
+    pub corners: [Point2<f64>; 4]
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl ArucoDictionary {
+
+    /// Create a dictionary from the codes of its markers, where `codes[id]` is the code of the
+    /// marker with that identifier.
+    ///
+    /// OpenCV's predefined dictionaries can be loaded with [`from_bytes_list`] instead.
+    ///
+    /// [`from_bytes_list`]: #method.from_bytes_list
+    pub fn new(marker_bits: usize, codes: Vec<u64>) -> Result<Self> {
+        if marker_bits == 0 || marker_bits > MAX_MARKER_BITS {
+            return Err(Error::InvalidDictionary(format!(
+                "Markers must have between 1 and {} bits along each side, not {}",
+                MAX_MARKER_BITS, marker_bits
+            )));
+        }
+
+        let mask = cell_mask(marker_bits);
+        if let Some(id) = codes.iter().position(|&code| code & !mask != 0) {
+            return Err(Error::InvalidDictionary(format!(
+                "The code of marker {} has more than {} bits", id, marker_bits * marker_bits
+            )));
+        }
+
+        Ok(Self {
+            marker_bits,
+            codes,
+            max_correction_bits: 0
+        })
+    }
+
+    /// Create a dictionary from OpenCV's `bytesList` of its markers, where `bytes_list[id]` holds
+    /// the bytes of the marker with that identifier.
+    ///
+    /// Each marker has the bytes of its cells in OpenCV's packing, optionally followed by those of
+    /// its three other rotations as in OpenCV's predefined dictionaries, which are ignored. For
+    /// example the rows of `DICT_4X4_50` in OpenCV's `predefined_dictionaries.hpp` are loaded
+    /// with `marker_bits` of 4.
+    pub fn from_bytes_list<B: AsRef<[u8]>>(marker_bits: usize, bytes_list: &[B]) -> Result<Self> {
+        if marker_bits == 0 || marker_bits > MAX_MARKER_BITS {
+            return Self::new(marker_bits, Vec::new());
+        }
+
+        let count = marker_bits * marker_bits;
+        let byte_count = count.div_ceil(8);
+
+        let codes = bytes_list.iter()
+            .enumerate()
+            .map(|(id, bytes)| {
+                let bytes = bytes.as_ref();
+                if bytes.len() != byte_count && bytes.len() != 4 * byte_count {
+                    return Err(Error::InvalidDictionary(format!(
+                        "Marker {} has {} bytes instead of {} or {}",
+                        id, bytes.len(), byte_count, 4 * byte_count
+                    )));
+                }
+
+                // Every byte is full except the last, which holds the remaining cells in its
+                // least significant bits
+                let last_bits = count - 8 * (byte_count - 1);
+                if (bytes[byte_count - 1] as u64) >> last_bits != 0 {
+                    return Err(Error::InvalidDictionary(format!(
+                        "The last byte of marker {} has more than {} bits", id, last_bits
+                    )));
+                }
+
+                Ok(bytes[..byte_count].iter().enumerate().fold(0, |code, (i, &byte)| {
+                    let bits = if i + 1 == byte_count { last_bits } else { 8 };
+                    (code << bits) | byte as u64
+                }))
+            })
+            .collect::<Result<Vec<u64>>>()?;
+
+        Self::new(marker_bits, codes)
+    }
+
+    /// Get the original ArUco dictionary of 1024 markers with 5 by 5 cells, which is OpenCV's
+    /// `DICT_ARUCO_ORIGINAL`.
+    pub fn original() -> Self {
+        let codes = (0..1024u64)
+            .map(|id| (0..ORIGINAL_MARKER_BITS).fold(0, |code, row| {
+                let pair = (id >> (2 * (ORIGINAL_MARKER_BITS - 1 - row))) & 0b11;
+                (code << ORIGINAL_MARKER_BITS) | ORIGINAL_ROW_WORDS[pair as usize]
+            }))
+            .collect();
+
+        Self {
+            marker_bits: ORIGINAL_MARKER_BITS,
+            codes,
+            max_correction_bits: 0
+        }
+    }
+
+    /// Set the number of incorrectly read cells which are corrected when identifying a marker,
+    /// which is 0 by default.
+    ///
+    /// This should be less than half the smallest number of cells by which any two markers of
+    /// the dictionary differ, in any of their orientations. It is limited to the number of inner
+    /// cells of the markers.
+    pub fn max_correction_bits(mut self, bits: u32) -> Self {
+        self.max_correction_bits = bits.min((self.marker_bits * self.marker_bits) as u32);
+
+        self
+    }
+
+    /// Get the number of inner cells along each side of the markers.
+    pub fn marker_bits(&self) -> usize {
+        self.marker_bits
+    }
+
+    /// Get the number of markers in the dictionary.
+    pub fn len(&self) -> usize {
+        self.codes.len()
+    }
+
+    /// Check whether the dictionary has no markers.
+    pub fn is_empty(&self) -> bool {
+        self.codes.is_empty()
+    }
+
+    /// Get the code of a marker, or `None` if it is not in the dictionary.
+    pub fn code(&self, id: usize) -> Option<u64> {
+        self.codes.get(id).copied()
+    }
+
+    /// Get whether each inner cell of a marker is white, row by row from its top left, or `None`
+    /// if it is not in the dictionary.
+    pub fn cells(&self, id: usize) -> Option<Vec<bool>> {
+        let code = self.code(id)?;
+        let count = self.marker_bits * self.marker_bits;
+
+        Some((0..count).map(|i| (code >> (count - 1 - i)) & 1 == 1).collect())
+    }
+
+    /// Identify a marker from the code read from its cells, returning its identifier and the
+    /// number of clockwise quarter turns from the marker's orientation to the code's, or `None`
+    /// if it matches no marker.
+    pub fn identify(&self, code: u64) -> Option<(usize, usize)> {
+        let mut best = None;
+        let mut best_distance = self.max_correction_bits + 1;
+        let mut rotated = code;
+
+        for rotation in 0..4 {
+            for (id, &candidate) in self.codes.iter().enumerate() {
+                let distance = (candidate ^ rotated).count_ones();
+
+                if distance < best_distance {
+                    best = Some((id, rotation));
+                    best_distance = distance;
+                }
+            }
+
+            rotated = rotate_code(rotated, self.marker_bits);
+        }
+
+        best
+    }
+}
+
+impl ArucoDetector {
+
+    /// Create a new detector for the markers of a dictionary.
+    pub fn new(dictionary: ArucoDictionary) -> Self {
+        Self {
+            dictionary
+        }
+    }
+
+    /// Get the dictionary of the markers which are detected.
+    pub fn dictionary(&self) -> &ArucoDictionary {
+        &self.dictionary
+    }
+
+    /// Find the markers of the dictionary in an image.
+    ///
+    /// Markers which are only partly in the image are not found, and if the same identifier is
+    /// found more than once each is returned.
+    pub fn detect(&self, image: &GrayFloatImage) -> Vec<ArucoMarker> {
+        let integral = integral_image(image);
+        let mut markers: Vec<ArucoMarker> = Vec::new();
+
+        for &window in THRESHOLD_WINDOWS.iter() {
+            let dark = threshold(image, &integral, window);
+
+            for quad in dark_regions(&dark, image.width(), image.height())
+                .iter()
+                .filter_map(|region| fit_quad(region, image.width(), image.height()))
+            {
+                // Each marker is found at several window sizes
+                let centre = quad_centre(&quad);
+                if markers.iter().any(|m| contains(&m.corners, &centre)) {
+                    continue;
+                }
+
+                if let Some(marker) = self.decode(image, quad) {
+                    markers.push(marker);
+                }
+            }
+        }
+
+        markers
+    }
+
+    /// Refine the corners of a candidate quadrilateral and read its cells, returning the marker
+    /// it shows, if any.
+    fn decode(&self, image: &GrayFloatImage, quad: [Point2<f64>; 4]) -> Option<ArucoMarker> {
+        let grid = self.dictionary.marker_bits + 2;
+        let side = (0..4)
+            .map(|i| (quad[(i + 1) % 4] - quad[i]).norm())
+            .fold(f64::INFINITY, f64::min);
+        let search = (side / grid as f64 * EDGE_SEARCH_FRACTION)
+            .max(EDGE_SEARCH_LIMITS.0)
+            .min(EDGE_SEARCH_LIMITS.1);
+        let quad = refine_quad(image, &quad, search).unwrap_or(quad);
+
+        // The homography from the marker's grid of cells to the image
+        let grid_corners = [
+            Point2::new(0.0, 0.0),
+            Point2::new(grid as f64, 0.0),
+            Point2::new(grid as f64, grid as f64),
+            Point2::new(0.0, grid as f64)
+        ];
+        let h = homography(&grid_corners, &quad)?;
+
+        let cells: Vec<f32> = (0..grid * grid)
+            .map(|i| cell_value(image, &h, i % grid, i / grid))
+            .collect();
+        let min = cells.iter().cloned().fold(f32::INFINITY, f32::min);
+        let max = cells.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        if max - min < MIN_CELL_CONTRAST {
+            return None;
+        }
+        let mid = (min + max) * 0.5;
+
+        let is_border = |x: usize, y: usize| x == 0 || y == 0 || x == grid - 1 || y == grid - 1;
+        let mut code = 0;
+        for y in 0..grid {
+            for x in 0..grid {
+                let white = cells[y * grid + x] > mid;
+
+                if is_border(x, y) {
+                    if white {
+                        return None;
+                    }
+                } else {
+                    code = (code << 1) | white as u64;
+                }
+            }
+        }
+
+        let (id, rotation) = self.dictionary.identify(code)?;
+
+        // The cells read are the marker turned clockwise, so its top left corner is the same
+        // number of corners clockwise from the first corner of the quadrilateral
+        let mut corners = quad;
+        corners.rotate_left(rotation);
+
+        Some(ArucoMarker {
+            id,
+            corners
+        })
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// PRIVATE FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Get the mask of the bits used by the codes of markers with the given number of cells.
+fn cell_mask(marker_bits: usize) -> u64 {
+    let count = marker_bits * marker_bits;
+
+    if count >= 64 { !0 } else { (1 << count) - 1 }
+}
+
+/// Rotate the cells of a code a quarter turn anticlockwise.
+fn rotate_code(code: u64, marker_bits: usize) -> u64 {
+    let n = marker_bits;
+    let count = n * n;
+    let bit = |row: usize, column: usize| (code >> (count - 1 - (row * n + column))) & 1;
+
+    (0..count).fold(0, |rotated, i| {
+        let (row, column) = (i / n, i % n);
+        (rotated << 1) | bit(column, n - 1 - row)
+    })
+}
+
+/// Find the sums of the image over every rectangle from the origin, with an extra row and column
+/// of zeros at the top and left.
+fn integral_image(image: &GrayFloatImage) -> Vec<f64> {
+    let (width, height) = (image.width(), image.height());
+    let mut integral = vec![0.0; (width + 1) * (height + 1)];
+
+    for y in 0..height {
+        let mut row = 0.0;
+        for x in 0..width {
+            row += image.get(x, y) as f64;
+            integral[(y + 1) * (width + 1) + x + 1] = integral[y * (width + 1) + x + 1] + row;
+        }
+    }
+
+    integral
+}
+
+/// Mark the pixels which are darker than the mean of the window around them.
+fn threshold(image: &GrayFloatImage, integral: &[f64], window: usize) -> Vec<bool> {
+    let (width, height) = (image.width(), image.height());
+    let half = window / 2;
+    let stride = width + 1;
+
+    let mut dark = vec![false; width * height];
+    for y in 0..height {
+        let (y0, y1) = (y.saturating_sub(half), (y + half + 1).min(height));
+
+        for x in 0..width {
+            let (x0, x1) = (x.saturating_sub(half), (x + half + 1).min(width));
+            let sum = integral[y1 * stride + x1] - integral[y0 * stride + x1]
+                - integral[y1 * stride + x0] + integral[y0 * stride + x0];
+            let mean = sum / ((x1 - x0) * (y1 - y0)) as f64;
+
+            dark[y * width + x] = (image.get(x, y) as f64) < mean - THRESHOLD_OFFSET as f64;
+        }
+    }
+
+    dark
+}
+
+/// Find the 4-connected regions of dark pixels, returning the centres of the pixels on the edge of
+/// each region.
+///
+/// Regions which touch the edge of the image are left out, as are those too small to be a marker.
+fn dark_regions(dark: &[bool], width: usize, height: usize) -> Vec<Vec<Point2<f64>>> {
+    let mut visited = vec![false; dark.len()];
+    let mut regions = Vec::new();
+    let mut stack = Vec::new();
+
+    for start in 0..dark.len() {
+        if !dark[start] || visited[start] {
+            continue;
+        }
+
+        let mut edge = Vec::new();
+        let mut touches_border = false;
+        let (mut min_x, mut max_x, mut min_y, mut max_y) = (width, 0, height, 0);
+
+        visited[start] = true;
+        stack.push(start);
+        while let Some(i) = stack.pop() {
+            let (x, y) = (i % width, i / width);
+            min_x = min_x.min(x);
+            max_x = max_x.max(x);
+            min_y = min_y.min(y);
+            max_y = max_y.max(y);
+
+            if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                touches_border = true;
+            }
+
+            let neighbours = [
+                (x > 0, i.wrapping_sub(1)),
+                (x + 1 < width, i + 1),
+                (y > 0, i.wrapping_sub(width)),
+                (y + 1 < height, i + width)
+            ];
+
+            let mut on_edge = false;
+            for &(inside, j) in neighbours.iter() {
+                if !inside || !dark[j] {
+                    on_edge = true;
+                } else if !visited[j] {
+                    visited[j] = true;
+                    stack.push(j);
+                }
+            }
+
+            if on_edge {
+                edge.push(Point2::new(x as f64 + 0.5, y as f64 + 0.5));
+            }
+        }
+
+        let size = (max_x - min_x).min(max_y - min_y) as f64;
+        if !touches_border && size >= MIN_SIDE {
+            regions.push(edge);
+        }
+    }
+
+    regions
+}
+
+/// Fit a quadrilateral to the convex hull of a region, returning its corners in clockwise order,
+/// or `None` if the region is not close to a quadrilateral.
+fn fit_quad(points: &[Point2<f64>], width: usize, height: usize) -> Option<[Point2<f64>; 4]> {
+    let hull = convex_hull(points);
+    if hull.len() < 4 {
+        return None;
+    }
+
+    // The furthest apart points are opposite corners, and the other two corners are the points
+    // furthest from the diagonal between them on either side
+    let mut diagonal = (0, 0, 0.0);
+    for i in 0..hull.len() {
+        for j in i + 1..hull.len() {
+            let d = (hull[j] - hull[i]).norm_squared();
+            if d > diagonal.2 {
+                diagonal = (i, j, d);
+            }
+        }
+    }
+    let (a, c) = (diagonal.0, diagonal.1);
+
+    let side = |p: &Point2<f64>| cross(&(hull[c] - hull[a]), &(p - hull[a]));
+    let furthest = |range: &mut dyn Iterator<Item = usize>, sign: f64| {
+        range.max_by(|&i, &j| {
+            (sign * side(&hull[i])).total_cmp(&(sign * side(&hull[j])))
+        })
+    };
+    let b = furthest(&mut (a + 1..c), -1.0)?;
+    let d = furthest(&mut (c + 1..hull.len()).chain(0..a), 1.0)?;
+
+    let quad = [hull[a], hull[b], hull[c], hull[d]];
+    let quad_area = polygon_area(&quad);
+    let hull_area = polygon_area(&hull);
+
+    let in_image = quad.iter().all(|p| {
+        p.x >= IMAGE_MARGIN && p.y >= IMAGE_MARGIN
+            && p.x <= width as f64 - IMAGE_MARGIN && p.y <= height as f64 - IMAGE_MARGIN
+    });
+    let min_side = (0..4)
+        .map(|i| (quad[(i + 1) % 4] - quad[i]).norm())
+        .fold(f64::INFINITY, f64::min);
+
+    if !in_image || min_side < MIN_SIDE || quad_area < MIN_QUAD_FILL * hull_area {
+        return None;
+    }
+
+    // The hull runs anticlockwise with the y axis up, which is clockwise in the image
+    Some(quad)
+}
+
+/// Find the convex hull of points with Andrew's monotone chain, in anticlockwise order with the y
+/// axis up.
+fn convex_hull(points: &[Point2<f64>]) -> Vec<Point2<f64>> {
+    let mut sorted = points.to_vec();
+    sorted.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    sorted.dedup();
+
+    if sorted.len() < 3 {
+        return sorted;
+    }
+
+    let mut hull: Vec<Point2<f64>> = Vec::with_capacity(2 * sorted.len());
+    for pass in 0..2 {
+        let start = hull.len();
+        let iter: Box<dyn Iterator<Item = &Point2<f64>>> = if pass == 0 {
+            Box::new(sorted.iter())
+        } else {
+            Box::new(sorted.iter().rev())
+        };
+
+        for p in iter {
+            while hull.len() >= start + 2 {
+                let (o, a) = (hull[hull.len() - 2], hull[hull.len() - 1]);
+                if cross(&(a - o), &(p - o)) > 0.0 {
+                    break;
+                }
+                hull.pop();
+            }
+            hull.push(*p);
+        }
+
+        // The last point of each chain is the first of the next
+        hull.pop();
+    }
+
+    hull
+}
+
+/// Get the z component of the cross product of two vectors in the plane.
+fn cross(a: &nalgebra::Vector2<f64>, b: &nalgebra::Vector2<f64>) -> f64 {
+    a.x * b.y - a.y * b.x
+}
+
+/// Get the area of a polygon.
+fn polygon_area(points: &[Point2<f64>]) -> f64 {
+    let n = points.len();
+
+    (0..n).map(|i| cross(&points[i].coords, &points[(i + 1) % n].coords)).sum::<f64>().abs() * 0.5
+}
+
+/// Get the centre of a quadrilateral.
+fn quad_centre(quad: &[Point2<f64>; 4]) -> Point2<f64> {
+    Point2::from(quad.iter().fold(nalgebra::Vector2::zeros(), |sum, p| sum + p.coords) / 4.0)
+}
+
+/// Check whether a convex quadrilateral contains a point.
+fn contains(quad: &[Point2<f64>; 4], point: &Point2<f64>) -> bool {
+    let sides: Vec<f64> = (0..4)
+        .map(|i| cross(&(quad[(i + 1) % 4] - quad[i]), &(point - quad[i])))
+        .collect();
+
+    sides.iter().all(|&s| s >= 0.0) || sides.iter().all(|&s| s <= 0.0)
+}
+
+/// Refine the corners of a marker by fitting lines to its four outer edges and intersecting
+/// them, searching for each edge up to the given distance either side of the quadrilateral.
+///
+/// Returns `None` if an edge cannot be found, or if the refined corners are further than the
+/// search distance from the original ones.
+fn refine_quad(
+    image: &GrayFloatImage,
+    quad: &[Point2<f64>; 4],
+    search: f64
+) -> Option<[Point2<f64>; 4]> {
+    let centre = quad_centre(quad);
+    let steps = (search / EDGE_STEP).round() as isize;
+    let value = |p: Point2<f64>| {
+        sample(image, p.x, p.y, Interpolation::Bilinear, BorderMode::Replicate)
+    };
+
+    // Each edge as a point on it and its direction
+    let mut lines = Vec::with_capacity(4);
+    for i in 0..4 {
+        let (a, b) = (quad[i], quad[(i + 1) % 4]);
+        let along = (b - a).normalize();
+        let mut normal = nalgebra::Vector2::new(-along.y, along.x);
+        if normal.dot(&(a - centre)) < 0.0 {
+            normal = -normal;
+        }
+
+        // The edge crosses each profile where it passes halfway from the dark border inside the
+        // marker to the light margin outside it, at the crossing closest to the side
+        let mut points = Vec::with_capacity(EDGE_SAMPLES);
+        for s in 0..EDGE_SAMPLES {
+            let t = 0.5 + EDGE_SPAN * ((s as f64 + 0.5) / EDGE_SAMPLES as f64 - 0.5);
+            let origin = a + (b - a) * t;
+            let profile: Vec<f32> = (-steps..=steps)
+                .map(|k| value(origin + normal * (k as f64 * EDGE_STEP)))
+                .collect();
+
+            let min = profile.iter().cloned().fold(f32::INFINITY, f32::min);
+            let max = profile.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+            if max - min < MIN_CELL_CONTRAST {
+                continue;
+            }
+            let mid = (min + max) * 0.5;
+
+            let crossing = (0..profile.len() - 1)
+                .filter(|&k| profile[k] <= mid && profile[k + 1] > mid)
+                .map(|k| {
+                    let f = ((mid - profile[k]) / (profile[k + 1] - profile[k])) as f64;
+                    (k as f64 + f - steps as f64) * EDGE_STEP
+                })
+                .min_by(|x, y| x.abs().total_cmp(&y.abs()));
+
+            if let Some(offset) = crossing {
+                points.push(origin + normal * offset);
+            }
+        }
+
+        if points.len() < EDGE_SAMPLES / 2 {
+            return None;
+        }
+
+        // The total least squares line through the points
+        let mean = points.iter().fold(nalgebra::Vector2::zeros(), |sum, p| sum + p.coords)
+            / points.len() as f64;
+        let scatter = points.iter().fold(nalgebra::Matrix2::zeros(), |sum, p| {
+            let d = p.coords - mean;
+            sum + d * d.transpose()
+        });
+        let eigen = scatter.symmetric_eigen();
+        let largest = if eigen.eigenvalues[0] > eigen.eigenvalues[1] { 0 } else { 1 };
+
+        lines.push((Point2::from(mean), eigen.eigenvectors.column(largest).into_owned()));
+    }
+
+    // Each corner is where the edges either side of it meet
+    let mut refined = *quad;
+    for i in 0..4 {
+        let (p, u) = &lines[(i + 3) % 4];
+        let (q, v) = &lines[i];
+
+        let det = cross(u, v);
+        if det.abs() < 1e-9 {
+            return None;
+        }
+        let corner = p + u * (cross(&(q - p), v) / det);
+
+        if (corner - quad[i]).norm() > search {
+            return None;
+        }
+        refined[i] = corner;
+    }
+
+    Some(refined)
+}
+
+/// Get the mean intensity around the centre of a cell of a marker, given the homography from the
+/// marker's grid of cells to the image.
+fn cell_value(image: &GrayFloatImage, h: &Matrix3<f64>, x: usize, y: usize) -> f32 {
+    let mut sum = 0.0;
+
+    for sy in 0..CELL_SAMPLES {
+        for sx in 0..CELL_SAMPLES {
+            let offset = |s: usize| {
+                0.5 + CELL_SAMPLE_SPAN * ((s as f64 + 0.5) / CELL_SAMPLES as f64 - 0.5)
+            };
+            let p = h * Vector3::new(x as f64 + offset(sx), y as f64 + offset(sy), 1.0);
+
+            sum += sample(
+                image,
+                p.x / p.z,
+                p.y / p.z,
+                Interpolation::Bilinear,
+                BorderMode::Replicate
+            );
+        }
+    }
+
+    sum / (CELL_SAMPLES * CELL_SAMPLES) as f32
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::calibration::tests::{marker_cell, render_target};
+
+    /// Render a marker filling the unit square of the target, with a white margin half its size
+    /// on a grey background.
+    fn render_marker(dictionary: &ArucoDictionary, id: usize, h: &Matrix3<f64>) -> GrayFloatImage {
+        let inner = dictionary.cells(id).unwrap();
+        let cells = dictionary.marker_bits() + 2;
+
+        render_target((320, 240), h, |x, y| {
+            if x < -0.5 || y < -0.5 || x > 1.5 || y > 1.5 {
+                0.5
+            } else if x < 0.0 || y < 0.0 || x >= 1.0 || y >= 1.0 {
+                0.9
+            } else {
+                marker_cell(&inner, cells, (x * cells as f64) as usize, (y * cells as f64) as usize)
+            }
+        })
+    }
+
+    /// Test the codes of the original dictionary and identifying them in every orientation
+    #[test]
+    fn test_dictionary() {
+        let dictionary = ArucoDictionary::original();
+        assert_eq!(dictionary.len(), 1024);
+        assert_eq!(dictionary.marker_bits(), 5);
+
+        // Marker 0 has the first word in every row, and marker 1023 the last
+        let expected = (0..5).fold(0, |code, _| (code << 5) | 0b10000);
+        assert_eq!(dictionary.code(0), Some(expected));
+        assert_eq!(&dictionary.cells(1023).unwrap()[..5], &[false, true, true, true, false]);
+        assert_eq!(dictionary.code(1024), None);
+
+        let code = dictionary.code(605).unwrap();
+        assert_eq!(dictionary.identify(code), Some((605, 0)));
+        assert_eq!(dictionary.identify(rotate_code(code, 5)), Some((605, 3)));
+        assert_eq!(rotate_code(rotate_code(rotate_code(rotate_code(code, 5), 5), 5), 5), code);
+
+        // Errors are only corrected up to the given number of cells
+        assert_eq!(dictionary.identify(code ^ 0b100), None);
+        let dictionary = dictionary.max_correction_bits(1);
+        assert_eq!(dictionary.identify(code ^ 0b100), Some((605, 0)));
+        let dictionary = dictionary.max_correction_bits(u32::MAX);
+        assert_eq!(dictionary.identify(code ^ 0b100), Some((605, 0)));
+        assert!(dictionary.identify(!code & cell_mask(5)).is_some());
+
+        assert!(ArucoDictionary::new(4, vec![0xffff, 0x1234]).is_ok());
+        assert!(ArucoDictionary::new(4, vec![0x10000]).is_err());
+        assert!(ArucoDictionary::new(9, vec![]).is_err());
+    }
+
+    /// Test loading dictionaries from OpenCV's packing of the cells into bytes
+    #[test]
+    fn test_bytes_list() {
+        // The first marker of OpenCV's DICT_ARUCO_ORIGINAL, followed by its other rotations
+        let first: &[u8] = &[132, 33, 8, 0, 248, 0, 0, 0, 8, 66, 16, 1, 0, 0, 15, 1];
+        let dictionary = ArucoDictionary::from_bytes_list(5, &[first]).unwrap();
+        assert_eq!(dictionary.code(0), ArucoDictionary::original().code(0));
+
+        // Packing every code as OpenCV does gives back the same dictionary
+        let original = ArucoDictionary::original();
+        let bytes_list: Vec<Vec<u8>> = (0..original.len())
+            .map(|id| {
+                let cells = original.cells(id).unwrap();
+                cells.chunks(8)
+                    .map(|chunk| chunk.iter().fold(0, |byte, &cell| (byte << 1) | cell as u8))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(ArucoDictionary::from_bytes_list(5, &bytes_list).unwrap(), original);
+
+        let dictionary =
+            ArucoDictionary::from_bytes_list(4, &[[0xb5, 0x2e], [0x12, 0x34]]).unwrap();
+        assert_eq!(dictionary.code(0), Some(0xb52e));
+        assert_eq!(dictionary.code(1), Some(0x1234));
+
+        assert!(ArucoDictionary::from_bytes_list(4, &[[0xb5, 0x2e, 0x00]]).is_err());
+        assert!(ArucoDictionary::from_bytes_list(5, &[[0x84, 0x21, 0x08, 0x02]]).is_err());
+        assert!(ArucoDictionary::from_bytes_list(9, &[[0u8; 11]]).is_err());
+    }
+
+    /// Test that markers are found and identified at any orientation, with their corners in order
+    #[test]
+    fn test_detect_markers() {
+        let detector = ArucoDetector::new(ArucoDictionary::original());
+
+        for &(id, angle, scale) in [(7, 0.1, 70.0), (605, 1.9, 80.0), (1000, -2.6, 60.0)].iter() {
+            let (s, c) = f64::sin_cos(angle);
+            let h = Matrix3::new(
+                scale * c, -scale * s, 160.0,
+                scale * s, scale * c, 120.0,
+                0.0002, -0.0001, 1.0
+            );
+            let image = render_marker(detector.dictionary(), id, &h);
+
+            let markers = detector.detect(&image);
+            assert_eq!(markers.len(), 1, "{:?}", markers);
+            assert_eq!(markers[0].id, id);
+
+            let expected = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+            for (corner, &(x, y)) in markers[0].corners.iter().zip(expected.iter()) {
+                let p = h * Vector3::new(x, y, 1.0);
+                let p = Point2::new(p.x / p.z, p.y / p.z);
+                assert!((corner - p).norm() < 0.05, "{} != {}", corner, p);
+            }
+        }
+
+        // Nothing is found in an empty image
+        assert!(detector.detect(&GrayFloatImage::new(320, 240)).is_empty());
+
+        // Non-finite pixels and contour points are tolerated
+        let h = Matrix3::new(70.0, 0.0, 160.0, 0.0, 70.0, 120.0, 0.0, 0.0, 1.0);
+        let mut image = render_marker(detector.dictionary(), 7, &h);
+        for y in (0..240).filter(|y| y % 5 == 0) {
+            for x in 0..320 {
+                image.put(x, y, f32::NAN);
+            }
+        }
+        detector.detect(&image);
+        let mut image = GrayFloatImage::new(320, 240);
+        image.mut_array2().fill(f32::NAN);
+        assert!(detector.detect(&image).is_empty());
+        let mut points: Vec<_> = (0..40)
+            .map(|i| Point2::new(100.0 + (i % 10) as f64 * 10.0, 50.0 + (i / 10) as f64 * 30.0))
+            .collect();
+        points[13] = Point2::new(f64::NAN, 80.0);
+        convex_hull(&points);
+        assert!(fit_quad(&points, 320, 240).is_none());
+    }
+}
@@ -0,0 +1,428 @@
+//! # ChArUco Targets
+//!
+//! Detection of ChArUco calibration targets, chessboards with an ArUco marker in each white
+//! square. The markers identify the squares around them, so the corners of the board can be found
+//! and identified even when only part of the board is in view.
+//!
+//! Each inner corner of the board lies between two white squares. Once the markers in those
+//! squares are found, the homography from the plane of the board to the image through their
+//! corners predicts the position of the chessboard corner, which is then refined to sub-pixel
+//! accuracy. Predicting each corner only from the markers beside it keeps the prediction close
+//! even when the lens is strongly distorted.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::collections::HashMap;
+
+use nalgebra::{Point2, Vector2};
+
+use crate::GrayFloatImage;
+
+use super::aruco::{ArucoDetector, ArucoDictionary, ArucoMarker};
+use super::corners::refine_corners;
+use super::zhang::homography;
+use super::{CalibrationTarget, TargetDetection};
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Number of markers beside a corner which must be found for the corner to be used.
+const MIN_ADJACENT_MARKERS: usize = 2;
+
+/// The half window used to refine corners, as a fraction of the gap between a corner and the
+/// nearest marker.
+const REFINE_WINDOW_FRACTION: f64 = 0.8;
+
+/// Limits on the half window used to refine corners, in pixels.
+const REFINE_WINDOW_LIMITS: (usize, usize) = (2, 10);
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// A ChArUco calibration target.
+///
+/// The board has `squares_x` by `squares_y` squares, with a black square at its top left, and
+/// the white squares hold markers from the dictionary numbered from 0 row by row from the top
+/// left. The inner corners of the board are numbered in the same way, and the corner with
+/// identifier `row·(squares_x - 1) + column` lies at
+/// `((column + 1)·square_size, (row + 1)·square_size)` on the target.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CharucoBoard {
+    squares_x: usize,
+
+    squares_y: usize,
+
+    square_size: f64,
+
+    marker_size: f64,
+
+    detector: ArucoDetector
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl CharucoBoard {
+
+    /// Create a new board with the given number of squares along each axis, the size of its
+    /// squares and of its markers in the units the calibration should use, and the dictionary of
+    /// its markers.
+    pub fn new(
+        squares_x: usize,
+        squares_y: usize,
+        square_size: f64,
+        marker_size: f64,
+        dictionary: ArucoDictionary
+    ) -> Self {
+        Self {
+            squares_x,
+            squares_y,
+            square_size,
+            marker_size,
+            detector: ArucoDetector::new(dictionary)
+        }
+    }
+
+    /// Get the number of squares along each row of the board.
+    pub fn squares_x(&self) -> usize {
+        self.squares_x
+    }
+
+    /// Get the number of squares along each column of the board.
+    pub fn squares_y(&self) -> usize {
+        self.squares_y
+    }
+
+    /// Get the size of the squares of the board.
+    pub fn square_size(&self) -> f64 {
+        self.square_size
+    }
+
+    /// Get the size of the markers of the board.
+    pub fn marker_size(&self) -> f64 {
+        self.marker_size
+    }
+
+    /// Get the dictionary of the board's markers.
+    pub fn dictionary(&self) -> &ArucoDictionary {
+        self.detector.dictionary()
+    }
+
+    /// Get the positions of the inner corners on the board, in the order of their identifiers.
+    pub fn target_points(&self) -> Vec<Point2<f64>> {
+        let columns = self.squares_x.saturating_sub(1);
+
+        (0..self.corner_count())
+            .map(|id| self.corner_position(id % columns, id / columns))
+            .collect()
+    }
+
+    /// Get the number of inner corners of the board.
+    pub fn corner_count(&self) -> usize {
+        self.squares_x.saturating_sub(1) * self.squares_y.saturating_sub(1)
+    }
+
+    /// Get the number of markers on the board.
+    pub fn marker_count(&self) -> usize {
+        (self.squares_x * self.squares_y) / 2
+    }
+
+    /// Get the square which holds a marker, as its column and row, or `None` if the marker is not
+    /// on the board.
+    pub fn marker_square(&self, id: usize) -> Option<(usize, usize)> {
+        self.white_squares().nth(id)
+    }
+
+    /// Get the positions of the corners of a marker on the board, clockwise from its top left, or
+    /// `None` if the marker is not on the board.
+    pub fn marker_corners(&self, id: usize) -> Option<[Point2<f64>; 4]> {
+        let (column, row) = self.marker_square(id)?;
+        let centre = Point2::new(
+            (column as f64 + 0.5) * self.square_size,
+            (row as f64 + 0.5) * self.square_size
+        );
+        let half = self.marker_size * 0.5;
+
+        Some([
+            centre + Vector2::new(-half, -half),
+            centre + Vector2::new(half, -half),
+            centre + Vector2::new(half, half),
+            centre + Vector2::new(-half, half)
+        ])
+    }
+
+    /// Find the markers of the board in an image.
+    ///
+    /// Markers which are not on the board are left out.
+    pub fn detect_markers(&self, image: &GrayFloatImage) -> Vec<ArucoMarker> {
+        self.detector.detect(image)
+            .into_iter()
+            .filter(|m| m.id < self.marker_count())
+            .collect()
+    }
+
+    /// Find the inner corners of the board in an image, refined to sub-pixel accuracy, returning
+    /// the identifier and position of each corner which was found.
+    pub fn find_corners(&self, image: &GrayFloatImage) -> Vec<(usize, Point2<f64>)> {
+        self.interpolate_corners(image, &self.detect_markers(image))
+    }
+
+    /// Find the inner corners of the board in an image from markers of the board which have
+    /// already been found in it, returning the identifier and position of each corner.
+    ///
+    /// Corners are only found if both markers beside them were found.
+    pub fn interpolate_corners(
+        &self,
+        image: &GrayFloatImage,
+        markers: &[ArucoMarker]
+    ) -> Vec<(usize, Point2<f64>)> {
+        let markers: HashMap<usize, &ArucoMarker> = markers.iter().map(|m| (m.id, m)).collect();
+        let columns = self.squares_x.saturating_sub(1);
+        let gap = (self.square_size - self.marker_size) * 0.5;
+
+        let mut corners = Vec::new();
+        for id in 0..self.corner_count() {
+            let (column, row) = (id % columns, id / columns);
+
+            let mut target = Vec::new();
+            let mut image_points = Vec::new();
+            let mut adjacent = 0;
+            for &(dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+                let square = (column + dx, row + dy);
+                let marker = self.square_marker(square).and_then(|m| markers.get(&m));
+
+                if let Some(marker) = marker {
+                    target.extend(self.marker_corners(marker.id).unwrap().iter());
+                    image_points.extend(marker.corners.iter());
+                    adjacent += 1;
+                }
+            }
+
+            if adjacent < MIN_ADJACENT_MARKERS {
+                continue;
+            }
+
+            let h = match homography(&target, &image_points) {
+                Some(h) => h,
+                None => continue
+            };
+            let project = |p: Point2<f64>| {
+                let q = h * p.to_homogeneous();
+                Point2::new(q.x / q.z, q.y / q.z)
+            };
+
+            // The window must stay clear of the markers, which are a gap away along both axes
+            let position = self.corner_position(column, row);
+            let predicted = project(position);
+            let gap_px = [Vector2::new(gap, 0.0), Vector2::new(0.0, gap)]
+                .iter()
+                .map(|offset| (project(position + offset) - predicted).norm())
+                .fold(f64::INFINITY, f64::min);
+
+            let half_window = ((gap_px * REFINE_WINDOW_FRACTION) as usize)
+                .max(REFINE_WINDOW_LIMITS.0)
+                .min(REFINE_WINDOW_LIMITS.1);
+
+            let mut corner = [predicted];
+            refine_corners(image, &mut corner, half_window);
+            corners.push((id, corner[0]));
+        }
+
+        corners
+    }
+
+    /// Get the position of an inner corner on the board.
+    fn corner_position(&self, column: usize, row: usize) -> Point2<f64> {
+        Point2::new(
+            (column + 1) as f64 * self.square_size,
+            (row + 1) as f64 * self.square_size
+        )
+    }
+
+    /// Iterate over the white squares of the board, row by row.
+    fn white_squares(&self) -> impl Iterator<Item = (usize, usize)> {
+        let squares_x = self.squares_x;
+
+        (0..self.squares_y)
+            .flat_map(move |row| (0..squares_x).map(move |column| (column, row)))
+            .filter(|(column, row)| (column + row) % 2 == 1)
+    }
+
+    /// Get the marker in a square, or `None` if the square is black.
+    fn square_marker(&self, (column, row): (usize, usize)) -> Option<usize> {
+        if (column + row) % 2 == 0 || column >= self.squares_x || row >= self.squares_y {
+            return None;
+        }
+
+        Some((row * self.squares_x + column) / 2)
+    }
+}
+
+impl CalibrationTarget for CharucoBoard {
+    fn detect(&self, image: &GrayFloatImage) -> Option<TargetDetection> {
+        let corners = self.find_corners(image);
+        let target_points = self.target_points();
+
+        // Too few corners, or corners along a single line of the board, can't be calibrated from
+        let detection = TargetDetection {
+            ids: corners.iter().map(|(id, _)| *id).collect(),
+            target_points: corners.iter().map(|(id, _)| target_points[*id]).collect(),
+            image_points: corners.iter().map(|(_, p)| *p).collect()
+        };
+        if !detection.is_usable() {
+            return None;
+        }
+
+        Some(detection)
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use nalgebra::Vector3;
+
+    use super::*;
+    use crate::calibration::tests::{charuco_pattern, poses_around, render_target, view_homography};
+    use crate::calibration::MonoCalibrator;
+    use crate::RectifParams;
+
+    /// A board of 6 by 4 squares of 40 mm with 30 mm markers.
+    fn test_board() -> CharucoBoard {
+        CharucoBoard::new(6, 4, 0.04, 0.03, ArucoDictionary::original())
+    }
+
+    /// Poses of the test board in front of the camera at different angles.
+    fn board_poses() -> Vec<super::super::Pose> {
+        poses_around(Vector3::new(0.12, 0.08, 0.0))
+    }
+
+    /// Test the layout of the markers and corners on the board
+    #[test]
+    fn test_board_layout() {
+        let board = test_board();
+        assert_eq!(board.marker_count(), 12);
+        assert_eq!(board.corner_count(), 15);
+        assert_eq!(board.marker_square(0), Some((1, 0)));
+        assert_eq!(board.marker_square(3), Some((0, 1)));
+        assert_eq!(board.marker_square(12), None);
+        assert_eq!(board.target_points()[6], Point2::new(0.08, 0.08));
+
+        for id in 0..board.marker_count() {
+            assert_eq!(board.square_marker(board.marker_square(id).unwrap()), Some(id));
+        }
+
+        let corners = board.marker_corners(3).unwrap();
+        assert!((corners[0] - Point2::new(0.005, 0.045)).norm() < 1e-12);
+        assert!((corners[2] - Point2::new(0.035, 0.075)).norm() < 1e-12);
+    }
+
+    /// Test that every corner of a board in full view is found and identified
+    #[test]
+    fn test_find_corners() {
+        let params = RectifParams {
+            focals: [600.0, 600.0],
+            principal_point: [320.0, 240.0],
+            ..RectifParams::default()
+        };
+        let board = test_board();
+        let target_points = board.target_points();
+
+        for pose in board_poses().iter().take(3) {
+            let homography = view_homography(&params, pose);
+            let image = render_target((640, 480), &homography, charuco_pattern(&board));
+
+            assert_eq!(board.detect_markers(&image).len(), 12);
+            let corners = board.find_corners(&image);
+            assert_eq!(corners.len(), 15);
+
+            for (id, corner) in corners.iter() {
+                let expected = homography * target_points[*id].to_homogeneous();
+                let expected = Point2::from(expected.xy() / expected.z);
+                assert!((corner - expected).norm() < 0.1, "{} != {}", corner, expected);
+            }
+        }
+    }
+
+    /// Test that the visible corners of a board partly out of view are found and identified
+    #[test]
+    fn test_find_partial_corners() {
+        let params = RectifParams {
+            focals: [600.0, 600.0],
+            principal_point: [320.0, 240.0],
+            ..RectifParams::default()
+        };
+        let board = test_board();
+        let target_points = board.target_points();
+
+        let mut pose = board_poses()[0];
+        pose.translation.x += 0.12;
+        let homography = view_homography(&params, &pose);
+        let image = render_target((640, 480), &homography, charuco_pattern(&board));
+
+        let detection = board.detect(&image).expect("The board was not found");
+        assert!(detection.ids.len() >= 6 && detection.ids.len() < 15, "{:?}", detection.ids);
+
+        for (id, corner) in detection.ids.iter().zip(detection.image_points.iter()) {
+            let expected = homography * target_points[*id].to_homogeneous();
+            let expected = Point2::from(expected.xy() / expected.z);
+            assert!((corner - expected).norm() < 0.1, "{} != {}", corner, expected);
+        }
+    }
+
+    /// Test that a view of a single row of corners is rejected, as its points are collinear
+    #[test]
+    fn test_find_one_row() {
+        let params = RectifParams {
+            focals: [600.0, 600.0],
+            principal_point: [320.0, 240.0],
+            ..RectifParams::default()
+        };
+        let board = test_board();
+
+        // Hide the board below the second row of corners, so only the first row has markers
+        // found on both sides of it
+        let pattern = charuco_pattern(&board);
+        let hidden = |x: f64, y: f64| if y > 0.08 { 0.5 } else { pattern(x, y) };
+        let homography = view_homography(&params, &board_poses()[0]);
+        let image = render_target((640, 480), &homography, hidden);
+
+        let corners = board.find_corners(&image);
+        assert!(corners.len() >= 2, "{:?}", corners);
+        assert!(corners.iter().all(|(id, _)| *id < 5), "{:?}", corners);
+        assert!(board.detect(&image).is_none());
+    }
+
+    /// Test calibrating a camera from rendered images of a ChArUco board
+    #[test]
+    fn test_calibrate_charuco() {
+        let params = RectifParams {
+            focals: [600.0, 600.0],
+            principal_point: [318.0, 243.0],
+            ..RectifParams::default()
+        };
+        let board = test_board();
+
+        let mut calibrator = MonoCalibrator::new(board.clone());
+        for pose in board_poses().iter() {
+            let homography = view_homography(&params, pose);
+            let image = render_target((640, 480), &homography, charuco_pattern(&board));
+            assert!(calibrator.add_image(&image));
+        }
+
+        let calibration = calibrator.calibrate().unwrap();
+        let estimated = calibration.params;
+        assert!(calibration.rms_error < 0.1, "{}", calibration.rms_error);
+        assert!((estimated.focals[0] - 600.0).abs() < 3.0, "{:?}", estimated);
+        assert!((estimated.principal_point[0] - 318.0).abs() < 3.0, "{:?}", estimated);
+    }
+}
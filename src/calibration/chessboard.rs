@@ -0,0 +1,514 @@
+//! # Chessboard Targets
+//!
+//! Detection of chessboard calibration targets. The inner corners of the board are found as
+//! saddle points, which are then linked into a grid by growing it outwards from a seed corner,
+//! predicting the position of each new corner from those already in the grid. The board is found
+//! once the grid has exactly as many rows and columns as the board, so corners in the background
+//! and the outer corners of the board, which are not saddle points, are never included.
+//!
+//! Only the strongest saddle points are tried as seeds, and the corners of a grid which isn't the
+//! board are not tried again, as they would grow the same grid. Corners are looked up in buckets
+//! of nearby points, so cluttered images with many saddle points are still searched quickly.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::collections::HashMap;
+
+use nalgebra::{Point2, Vector2};
+
+use crate::GrayFloatImage;
+
+use super::corners::{refine_corners, saddle_points};
+use super::{CalibrationTarget, TargetDetection};
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// A corner is added to the grid if it lies within this fraction of the spacing of its
+/// neighbours from its predicted position.
+const GRID_TOLERANCE: f64 = 0.3;
+
+/// Opposite neighbours of the seed corner must have directions whose cosine is below this.
+const OPPOSITE_COSINE: f64 = -0.8;
+
+/// The half window used to refine corners, as a fraction of the smallest corner spacing.
+const REFINE_WINDOW_FRACTION: f64 = 0.4;
+
+/// Limits on the half window used to refine corners, in pixels.
+const REFINE_WINDOW_LIMITS: (usize, usize) = (2, 10);
+
+/// Offsets of the neighbours of a grid cell.
+const NEIGHBOURS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+/// Offsets of the diagonal neighbours of a grid cell.
+const DIAGONALS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+/// At most this many times as many saddle points as the board has corners are tried as seeds.
+const SEED_FACTOR: usize = 2;
+
+/// The size of the square buckets which corners are looked up in, in pixels.
+const BUCKET_SIZE: f64 = 16.0;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// A chessboard calibration target.
+///
+/// The board is described by its inner corners, where four squares meet, so a board of 10 by 7
+/// squares has 9 columns and 6 rows of corners. Corners are reported row by row, starting from
+/// the corner nearest the top left of the image with the columns running to the right, and lie at
+/// `(column·square_size, row·square_size)` on the target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Chessboard {
+    columns: usize,
+
+    rows: usize,
+
+    square_size: f64
+}
+
+/// Points bucketed by their position in the image, to find the nearest point to a prediction
+/// without searching them all.
+struct PointBuckets<'a> {
+    points: &'a [Point2<f64>],
+
+    buckets: HashMap<(i32, i32), Vec<usize>>
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Chessboard {
+
+    /// Create a new chessboard with the given number of inner corners along each row and column,
+    /// and the size of its squares in the units the calibration should use.
+    pub fn new(columns: usize, rows: usize, square_size: f64) -> Self {
+        Self {
+            columns,
+            rows,
+            square_size
+        }
+    }
+
+    /// Get the number of inner corners along each row of the board.
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Get the number of inner corners along each column of the board.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Get the size of the squares of the board.
+    pub fn square_size(&self) -> f64 {
+        self.square_size
+    }
+
+    /// Get the positions of the inner corners on the board, row by row.
+    pub fn target_points(&self) -> Vec<Point2<f64>> {
+        (0..self.rows)
+            .flat_map(|row| (0..self.columns).map(move |column| Point2::new(
+                column as f64 * self.square_size,
+                row as f64 * self.square_size
+            )))
+            .collect()
+    }
+
+    /// Find the inner corners of the board in an image, refined to sub-pixel accuracy and ordered
+    /// as [`Chessboard::target_points`], or `None` if the whole board cannot be found.
+    pub fn find_corners(&self, image: &GrayFloatImage) -> Option<Vec<Point2<f64>>> {
+        if self.columns < 2 || self.rows < 2 {
+            return None;
+        }
+
+        let candidates = saddle_points(image);
+        if candidates.len() < self.columns * self.rows {
+            return None;
+        }
+
+        // Try the strongest corners as seeds first, as they are the most likely to be on the board
+        let buckets = PointBuckets::new(&candidates);
+        let max_extent = self.columns.max(self.rows) as i32;
+        let seeds = candidates.len().min(SEED_FACTOR * self.columns * self.rows);
+        let mut tried = vec![false; candidates.len()];
+        for seed in 0..seeds {
+            if tried[seed] {
+                continue;
+            }
+
+            let grid = match grow_grid(&buckets, seed, max_extent) {
+                Some(grid) => grid,
+                None => continue
+            };
+            let mut corners = match self.order(&candidates, &grid) {
+                Some(corners) => corners,
+                None => {
+                    // A grid with as many corners as the board which isn't the board means the
+                    // board is bigger or is not seen whole, so no other seed will find it
+                    if grid.len() >= self.columns * self.rows {
+                        return None;
+                    }
+
+                    for &index in grid.values() {
+                        tried[index] = true;
+                    }
+                    continue;
+                }
+            };
+
+            let spacing = corners.chunks(self.columns)
+                .flat_map(|row| row.windows(2).map(|w| (w[1] - w[0]).norm()))
+                .chain((self.columns..corners.len())
+                    .map(|i| (corners[i] - corners[i - self.columns]).norm()))
+                .fold(f64::INFINITY, f64::min);
+            let half_window = ((spacing * REFINE_WINDOW_FRACTION) as usize)
+                .max(REFINE_WINDOW_LIMITS.0)
+                .min(REFINE_WINDOW_LIMITS.1);
+
+            refine_corners(image, &mut corners, half_window);
+            return Some(corners);
+        }
+
+        None
+    }
+
+    /// Order the corners of a complete grid row by row, or return `None` if the grid does not have
+    /// the size of the board.
+    fn order(
+        &self,
+        points: &[Point2<f64>],
+        grid: &HashMap<(i32, i32), usize>
+    ) -> Option<Vec<Point2<f64>>> {
+        let min_i = grid.keys().map(|c| c.0).min()?;
+        let max_i = grid.keys().map(|c| c.0).max()?;
+        let min_j = grid.keys().map(|c| c.1).min()?;
+        let max_j = grid.keys().map(|c| c.1).max()?;
+        let (width, height) = ((max_i - min_i + 1) as usize, (max_j - min_j + 1) as usize);
+
+        if grid.len() != width * height {
+            return None;
+        }
+
+        let at = |i: i32, j: i32| points[grid[&(i, j)]];
+
+        // Directions of the grid's axes in the image, averaged over opposite edges of the board
+        let u = (at(max_i, min_j) - at(min_i, min_j)) + (at(max_i, max_j) - at(min_i, max_j));
+        let v = (at(min_i, max_j) - at(min_i, min_j)) + (at(max_i, max_j) - at(max_i, min_j));
+
+        // Choose the axis running along the rows of the board from the size of the grid, or for
+        // a square board the axis closest to the image rows
+        let u_along_rows = match (
+            (width, height) == (self.columns, self.rows),
+            (height, width) == (self.columns, self.rows)
+        ) {
+            (true, true) => u.x.abs() / u.norm() >= v.x.abs() / v.norm(),
+            (true, false) => true,
+            (false, true) => false,
+            (false, false) => return None
+        };
+        let (along_rows, along_columns) = if u_along_rows { (u, v) } else { (v, u) };
+
+        // Columns run to the right, and rows run down the image when viewed from the front
+        let flip_columns = along_rows.x < 0.0;
+        let along_rows = if flip_columns { -along_rows } else { along_rows };
+        let flip_rows = along_rows.x * along_columns.y - along_rows.y * along_columns.x < 0.0;
+
+        let mut corners = Vec::with_capacity(grid.len());
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                let a = if flip_columns { self.columns - 1 - column } else { column } as i32;
+                let b = if flip_rows { self.rows - 1 - row } else { row } as i32;
+
+                corners.push(if u_along_rows {
+                    at(min_i + a, min_j + b)
+                } else {
+                    at(min_i + b, min_j + a)
+                });
+            }
+        }
+
+        Some(corners)
+    }
+}
+
+impl<'a> PointBuckets<'a> {
+
+    /// Bucket the points.
+    fn new(points: &'a [Point2<f64>]) -> Self {
+        let mut buckets: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        for (i, p) in points.iter().enumerate() {
+            buckets.entry(Self::bucket(p)).or_default().push(i);
+        }
+
+        Self {
+            points,
+            buckets
+        }
+    }
+
+    /// Find the nearest point to a position that is not used and lies within a radius of it.
+    fn nearest(&self, position: &Point2<f64>, radius: f64, used: &[bool]) -> Option<usize> {
+        let (bi, bj) = Self::bucket(position);
+        let reach = (radius / BUCKET_SIZE).ceil() as i32;
+
+        let mut nearest: Option<(usize, f64)> = None;
+        for i in bi - reach..=bi + reach {
+            for j in bj - reach..=bj + reach {
+                for &index in self.buckets.get(&(i, j)).into_iter().flatten() {
+                    let distance = (self.points[index] - position).norm();
+                    if used[index] || distance >= radius {
+                        continue;
+                    }
+
+                    // Ties go to the earlier, stronger point
+                    match nearest {
+                        Some((best, d)) if d < distance || (d == distance && best < index) => {},
+                        _ => nearest = Some((index, distance))
+                    }
+                }
+            }
+        }
+
+        nearest.map(|(index, _)| index)
+    }
+
+    /// Get the bucket a position lies in.
+    fn bucket(p: &Point2<f64>) -> (i32, i32) {
+        ((p.x / BUCKET_SIZE).floor() as i32, (p.y / BUCKET_SIZE).floor() as i32)
+    }
+}
+
+impl CalibrationTarget for Chessboard {
+    fn detect(&self, image: &GrayFloatImage) -> Option<TargetDetection> {
+        let image_points = self.find_corners(image)?;
+
+        Some(TargetDetection {
+            ids: (0..image_points.len()).collect(),
+            target_points: self.target_points(),
+            image_points
+        })
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// PRIVATE FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Grow a grid of corners from a seed, returning the index of the corner in each cell, or `None`
+/// if the seed has no grid around it. Growth stops once the grid is wider than `max_extent` along
+/// either axis.
+fn grow_grid(
+    buckets: &PointBuckets,
+    seed: usize,
+    max_extent: i32
+) -> Option<HashMap<(i32, i32), usize>> {
+    let points = buckets.points;
+    let neighbours = seed_neighbours(points, seed)?;
+
+    let mut grid = HashMap::new();
+    let mut used = vec![false; points.len()];
+    grid.insert((0, 0), seed);
+    used[seed] = true;
+    for (&cell, &index) in NEIGHBOURS.iter().zip(neighbours.iter()) {
+        grid.insert(cell, index);
+        used[index] = true;
+    }
+
+    loop {
+        let mut frontier: Vec<(i32, i32)> = grid.keys()
+            .flat_map(|&(i, j)| NEIGHBOURS.iter().map(move |(di, dj)| (i + di, j + dj)))
+            .filter(|cell| !grid.contains_key(cell))
+            .collect();
+        frontier.sort_unstable();
+        frontier.dedup();
+
+        let mut grown = false;
+        for cell in frontier {
+            let (prediction, spacing) = match predict(points, &grid, cell) {
+                Some(p) => p,
+                None => continue
+            };
+
+            if let Some(index) = buckets.nearest(&prediction, GRID_TOLERANCE * spacing, &used) {
+                grid.insert(cell, index);
+                used[index] = true;
+                grown = true;
+            }
+        }
+
+        let extent = |axis: fn(&(i32, i32)) -> i32| {
+            grid.keys().map(axis).max().unwrap() - grid.keys().map(axis).min().unwrap() + 1
+        };
+        if !grown || extent(|c| c.0) > max_extent || extent(|c| c.1) > max_extent {
+            return Some(grid);
+        }
+    }
+}
+
+/// Find the neighbours of a seed corner along the positive and negative directions of each axis
+/// of the grid, in the order of [`NEIGHBOURS`].
+///
+/// The four nearest corners must form two pairs in opposite directions, as the neighbours of an
+/// inner corner of a chessboard do.
+fn seed_neighbours(points: &[Point2<f64>], seed: usize) -> Option<[usize; 4]> {
+    let mut nearest: Vec<(f64, usize)> = points.iter()
+        .enumerate()
+        .filter(|(i, _)| *i != seed)
+        .map(|(i, p)| ((p - points[seed]).norm_squared(), i))
+        .collect();
+    if nearest.len() < 4 {
+        return None;
+    }
+    nearest.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let direction = |k: usize| -> Vector2<f64> {
+        (points[nearest[k].1] - points[seed]).normalize()
+    };
+    let cosine = |a: usize, b: usize| direction(a).dot(&direction(b));
+
+    // Pair the nearest corner with the one most nearly opposite it
+    let opposite = (1..4).min_by(|&a, &b| cosine(0, a).total_cmp(&cosine(0, b)))?;
+    let mut others = (1..4).filter(|&k| k != opposite);
+    let (a, b) = (others.next()?, others.next()?);
+
+    if cosine(0, opposite) > OPPOSITE_COSINE
+        || cosine(a, b) > OPPOSITE_COSINE
+        || cosine(0, a).abs() > -OPPOSITE_COSINE
+    {
+        return None;
+    }
+
+    Some([nearest[0].1, nearest[opposite].1, nearest[a].1, nearest[b].1])
+}
+
+/// Predict the position of the corner in a cell of the grid, along with the spacing of the
+/// corners the prediction is made from.
+///
+/// Corners are extrapolated along a row or column of the grid where possible, which allows for
+/// perspective, and otherwise complete a parallelogram with three neighbouring corners.
+fn predict(
+    points: &[Point2<f64>],
+    grid: &HashMap<(i32, i32), usize>,
+    (i, j): (i32, i32)
+) -> Option<(Point2<f64>, f64)> {
+    for (di, dj) in NEIGHBOURS.iter() {
+        let near = grid.get(&(i - di, j - dj));
+        let far = grid.get(&(i - 2 * di, j - 2 * dj));
+
+        if let (Some(&near), Some(&far)) = (near, far) {
+            let step = points[near] - points[far];
+            return Some((points[near] + step, step.norm()));
+        }
+    }
+
+    for (di, dj) in DIAGONALS.iter() {
+        let a = grid.get(&(i - di, j));
+        let b = grid.get(&(i, j - dj));
+        let corner = grid.get(&(i - di, j - dj));
+
+        if let (Some(&a), Some(&b), Some(&corner)) = (a, b, corner) {
+            let spacing = (points[a] - points[corner]).norm()
+                .min((points[b] - points[corner]).norm());
+            return Some((points[a] + (points[b] - points[corner]), spacing));
+        }
+    }
+
+    None
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::calibration::tests::{
+        chessboard_pattern, render_target, test_poses, view_homography
+    };
+    use crate::RectifParams;
+    use nalgebra::Matrix3;
+
+    /// Test that every inner corner of a board is found, in order and to sub-pixel accuracy
+    #[test]
+    fn test_find_corners() {
+        let params = RectifParams {
+            focals: [500.0, 500.0],
+            principal_point: [320.0, 240.0],
+            ..RectifParams::default()
+        };
+        let board = Chessboard::new(9, 6, 0.025);
+
+        for pose in test_poses().iter().take(3) {
+            let homography = view_homography(&params, pose);
+            let image = render_target((640, 480), &homography, chessboard_pattern(&board));
+
+            let corners = board.find_corners(&image).expect("The board was not found");
+            assert_eq!(corners.len(), 54);
+
+            let mut total_error = 0.0;
+            for (corner, target) in corners.iter().zip(board.target_points()) {
+                let expected = homography * target.to_homogeneous();
+                let expected = Point2::from(expected.xy() / expected.z);
+                assert!((corner - expected).norm() < 0.1, "{} != {}", corner, expected);
+                total_error += (corner - expected).norm();
+            }
+            assert!(total_error / 54.0 < 0.03, "{}", total_error / 54.0);
+        }
+
+        // A board with a different number of corners is not found
+        let homography = view_homography(&params, &test_poses()[0]);
+        let image = render_target((640, 480), &homography, chessboard_pattern(&board));
+        assert!(Chessboard::new(8, 6, 0.025).find_corners(&image).is_none());
+        assert!(board.find_corners(&GrayFloatImage::new(640, 480)).is_none());
+
+        // Non-finite pixels hide the corners under them, and non-finite corners are never
+        // ordered by a panicking comparison
+        let mut image = render_target((640, 480), &homography, chessboard_pattern(&board));
+        for y in 0..480 {
+            for x in (0..640).filter(|x| x % 7 == 0) {
+                image.put(x, y, f32::NAN);
+            }
+        }
+        assert!(board.find_corners(&image).is_none());
+        let mut image = GrayFloatImage::new(640, 480);
+        image.mut_array2().fill(f32::NAN);
+        assert!(board.find_corners(&image).is_none());
+        let mut points: Vec<_> = (0..9)
+            .map(|i| Point2::new((i % 3) as f64 * 20.0, (i / 3) as f64 * 20.0))
+            .collect();
+        points[1] = Point2::new(f64::NAN, 0.0);
+        seed_neighbours(&points, 4);
+    }
+
+    /// Test that an image full of saddle points that aren't the board is rejected
+    #[test]
+    fn test_cluttered() {
+        let board = Chessboard::new(9, 6, 0.025);
+        let texture = |x: f64, y: f64| {
+            if ((x / 12.0).floor() + (y / 12.0).floor()).rem_euclid(2.0) == 0.0 { 0.1 } else { 0.9 }
+        };
+        let image = render_target((640, 480), &Matrix3::identity(), texture);
+
+        assert!(saddle_points(&image).len() > 1000);
+        assert!(board.find_corners(&image).is_none());
+
+        // The nearest unused point within the radius is found across buckets
+        let points: Vec<_> = (0..100)
+            .map(|i| Point2::new((i % 10) as f64 * 7.0, (i / 10) as f64 * 7.0))
+            .collect();
+        let buckets = PointBuckets::new(&points);
+        let mut used = vec![false; points.len()];
+        assert_eq!(buckets.nearest(&Point2::new(30.0, 15.0), 5.0, &used), Some(24));
+        used[24] = true;
+        assert_eq!(buckets.nearest(&Point2::new(30.0, 15.0), 5.0, &used), None);
+        assert_eq!(buckets.nearest(&Point2::new(30.0, 15.0), 40.0, &used), Some(25));
+    }
+}
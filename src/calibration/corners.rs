@@ -0,0 +1,276 @@
+//! # Corner Detection
+//!
+//! Finds the saddle points where the dark and light squares of a calibration target meet, and
+//! refines their positions to sub-pixel accuracy.
+//!
+//! Saddle points are found as peaks of the negated determinant of the Hessian of the blurred
+//! image, which is large where the intensity curves up along one direction and down along the
+//! other. The corners of a single square also give a weaker response, so each peak is accepted
+//! only if a circle around it crosses four alternating dark and light sectors.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::f64::consts::PI;
+
+use nalgebra::{Matrix2, Point2, Vector2};
+
+use crate::image::gaussian_blur;
+use crate::interpolation::{sample, BorderMode, Interpolation};
+use crate::GrayFloatImage;
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Standard deviation of the blur applied before finding saddle points, in pixels.
+const BLUR_SIGMA: f32 = 1.5;
+
+/// Saddle points must have at least this fraction of the strongest response in the image.
+const RESPONSE_THRESHOLD: f32 = 0.1;
+
+/// Saddle points must be the strongest response within this many pixels along each axis.
+const SUPPRESSION_RADIUS: usize = 3;
+
+/// Radius of the circle sampled around a saddle point to check that it is a junction between
+/// four squares, in pixels.
+const JUNCTION_RADIUS: f64 = 4.0;
+
+/// Number of samples taken around the junction circle.
+const JUNCTION_SAMPLES: usize = 32;
+
+/// Minimum difference between the darkest and lightest samples around a junction.
+const MIN_JUNCTION_CONTRAST: f32 = 0.05;
+
+/// Standard deviation of the blur applied before refining corners, in pixels.
+const GRADIENT_SIGMA: f32 = 1.0;
+
+/// Maximum number of iterations used to refine a corner.
+const REFINE_MAX_ITERATIONS: usize = 40;
+
+/// Refinement of a corner stops once it moves less than this distance, in pixels.
+const REFINE_TOLERANCE: f64 = 1e-3;
+
+// -----------------------------------------------------------------------------------------------
+// PUBLIC FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Refine the positions of corners in an image to sub-pixel accuracy.
+///
+/// Each corner is moved to the point which best fits the image gradients in a window of
+/// `2·half_window + 1` pixels around it, as the gradient at every pixel on an edge leading to the
+/// corner is perpendicular to the direction of the corner. This is the method of OpenCV's
+/// `cornerSubPix`, and works for both chessboard saddle points and the corners of single squares.
+/// The image is lightly blurred first so that the gradients vary smoothly across each edge, which
+/// reduces the bias from the edges falling between pixel centres. The window should be smaller
+/// than the distance between neighbouring corners.
+///
+/// Corners which cannot be refined, or which would move further than `half_window`, are left
+/// unchanged.
+pub fn refine_corners(image: &GrayFloatImage, corners: &mut [Point2<f64>], half_window: usize) {
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let half = half_window as i64;
+    let sigma = (half_window as f64).max(1.0);
+    let blurred = gaussian_blur(image, GRADIENT_SIGMA);
+
+    let gradient = |x: i64, y: i64| {
+        let get = |x: i64, y: i64| blurred.get(x as usize, y as usize) as f64;
+
+        Vector2::new(
+            (get(x + 1, y) - get(x - 1, y)) * 0.5,
+            (get(x, y + 1) - get(x, y - 1)) * 0.5
+        )
+    };
+
+    for corner in corners.iter_mut() {
+        let mut estimate = corner.coords;
+
+        for _ in 0..REFINE_MAX_ITERATIONS {
+            let (cx, cy) = (estimate.x.floor() as i64, estimate.y.floor() as i64);
+            let mut lhs = Matrix2::zeros();
+            let mut rhs = Vector2::zeros();
+
+            for y in (cy - half).max(1)..=(cy + half).min(height - 2) {
+                for x in (cx - half).max(1)..=(cx + half).min(width - 2) {
+                    let centre = Vector2::new(x as f64 + 0.5, y as f64 + 0.5);
+                    let weight = (-(centre - estimate).norm_squared() / (2.0 * sigma * sigma))
+                        .exp();
+
+                    let g = gradient(x, y);
+                    let outer = g * g.transpose() * weight;
+                    lhs += outer;
+                    rhs += outer * centre;
+                }
+            }
+
+            let next = match lhs.try_inverse() {
+                Some(inverse) => inverse * rhs,
+                None => break
+            };
+
+            let shift = (next - estimate).norm();
+            estimate = next;
+
+            if !shift.is_finite() || shift < REFINE_TOLERANCE {
+                break;
+            }
+        }
+
+        if estimate.iter().all(|v| v.is_finite())
+            && (estimate - corner.coords).norm() <= half_window as f64
+        {
+            *corner = Point2::from(estimate);
+        }
+    }
+}
+
+/// Find the junctions between four squares in an image, strongest first.
+///
+/// Positions are only accurate to the nearest pixel centre, and should be refined with
+/// [`refine_corners`].
+pub(crate) fn saddle_points(image: &GrayFloatImage) -> Vec<Point2<f64>> {
+    let blurred = gaussian_blur(image, BLUR_SIGMA);
+    let (width, height) = (blurred.width(), blurred.height());
+    let border = SUPPRESSION_RADIUS.max(JUNCTION_RADIUS.ceil() as usize) + 1;
+
+    if width <= 2 * border || height <= 2 * border {
+        return Vec::new();
+    }
+
+    // The negated determinant of the Hessian, which is positive at saddle points
+    let mut response = vec![0.0f32; width * height];
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let get = |dx: isize, dy: isize| {
+                blurred.get((x as isize + dx) as usize, (y as isize + dy) as usize)
+            };
+
+            let fxx = get(1, 0) + get(-1, 0) - 2.0 * get(0, 0);
+            let fyy = get(0, 1) + get(0, -1) - 2.0 * get(0, 0);
+            let fxy = (get(1, 1) - get(1, -1) - get(-1, 1) + get(-1, -1)) * 0.25;
+
+            response[y * width + x] = (fxy * fxy - fxx * fyy).max(0.0);
+        }
+    }
+
+    let max_response = response.iter().cloned().fold(0.0, f32::max);
+    if max_response <= 0.0 {
+        return Vec::new();
+    }
+    let threshold = RESPONSE_THRESHOLD * max_response;
+
+    let mut points = Vec::new();
+    for y in border..height - border {
+        for x in border..width - border {
+            let i = y * width + x;
+            if response[i] < threshold {
+                continue;
+            }
+
+            // Equal responses are resolved in favour of the first in raster order
+            let r = SUPPRESSION_RADIUS;
+            let is_peak = (y - r..=y + r).all(|ny| (x - r..=x + r).all(|nx| {
+                let j = ny * width + nx;
+                j == i || response[j] < response[i] || (response[j] == response[i] && j > i)
+            }));
+
+            let point = Point2::new(x as f64 + 0.5, y as f64 + 0.5);
+            if is_peak && is_junction(&blurred, point) {
+                points.push((response[i], point));
+            }
+        }
+    }
+
+    points.sort_by(|a, b| b.0.total_cmp(&a.0));
+    points.into_iter().map(|(_, p)| p).collect()
+}
+
+// -----------------------------------------------------------------------------------------------
+// PRIVATE FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Check that a circle around a point crosses four alternating dark and light sectors, as it
+/// does around the junction of four squares but not at the corner of a single square.
+fn is_junction(image: &GrayFloatImage, point: Point2<f64>) -> bool {
+    let samples: Vec<f32> = (0..JUNCTION_SAMPLES)
+        .map(|i| {
+            let angle = 2.0 * PI * i as f64 / JUNCTION_SAMPLES as f64;
+            sample(
+                image,
+                point.x + JUNCTION_RADIUS * angle.cos(),
+                point.y + JUNCTION_RADIUS * angle.sin(),
+                Interpolation::Bilinear,
+                BorderMode::Replicate
+            )
+        })
+        .collect();
+
+    let min = samples.iter().cloned().fold(f32::INFINITY, f32::min);
+    let max = samples.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    if max - min < MIN_JUNCTION_CONTRAST {
+        return false;
+    }
+
+    let mid = (min + max) * 0.5;
+    let transitions = (0..JUNCTION_SAMPLES)
+        .filter(|&i| (samples[i] > mid) != (samples[(i + 1) % JUNCTION_SAMPLES] > mid))
+        .count();
+
+    transitions == 4
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use nalgebra::Matrix3;
+
+    use super::*;
+    use crate::calibration::tests::render_target;
+
+    /// Homography placing the origin of a target at the given pixel, rotated by the given angle.
+    fn placement(x: f64, y: f64, angle: f64) -> Matrix3<f64> {
+        let (s, c) = angle.sin_cos();
+
+        Matrix3::new(
+            c, -s, x,
+            s, c, y,
+            0.0, 0.0, 1.0
+        )
+    }
+
+    /// Test that junctions of four squares are found but the corners of a single square are not
+    #[test]
+    fn test_saddle_points() {
+        let junction = render_target((64, 64), &placement(30.3, 33.6, 0.3), |x, y| {
+            if (x > 0.0) == (y > 0.0) { 0.9 } else { 0.1 }
+        });
+        let points = saddle_points(&junction);
+        assert_eq!(points.len(), 1, "{:?}", points);
+        assert!((points[0] - Point2::new(30.3, 33.6)).norm() < 1.0, "{:?}", points);
+
+        let square = render_target((64, 64), &placement(20.0, 20.0, 0.3), |x, y| {
+            if x > 0.0 && y > 0.0 && x < 20.0 && y < 20.0 { 0.9 } else { 0.1 }
+        });
+        assert!(saddle_points(&square).is_empty());
+    }
+
+    /// Test that corners are refined to sub-pixel accuracy
+    #[test]
+    fn test_refine_corners() {
+        let image = render_target((64, 64), &placement(31.37, 30.81, -0.4), |x, y| {
+            if (x > 0.0) == (y > 0.0) { 0.8 } else { 0.2 }
+        });
+
+        let mut corners = [Point2::new(32.5, 29.5), Point2::new(30.5, 31.5)];
+        refine_corners(&image, &mut corners, 5);
+
+        for corner in corners.iter() {
+            assert!((corner - Point2::new(31.37, 30.81)).norm() < 0.05, "{:?}", corner);
+        }
+    }
+}
@@ -0,0 +1,699 @@
+//! # Calibration Module
+//!
+//! Estimates the intrinsics and distortion of a camera from images of a planar calibration
+//! target, producing the [`RectifParams`] used to rectify its images.
+//!
+//! Targets are found in images through the [`CalibrationTarget`] trait, which [`Chessboard`] and
+//! [`CharucoBoard`] implement. ChArUco boards can be calibrated from views which only show part of
+//! the board, as each corner is identified by the ArUco markers beside it. The camera matrix and
+//! the pose of the target in each view are first estimated in closed form with Zhang's method,
+//! ignoring distortion, and are then refined together with the distortion coefficients by
+//! minimising the reprojection error of every point with Levenberg-Marquardt. Stereo rigs are
+//! calibrated by [`StereoCalibrator`], which also estimates the rotation and translation between
+//! the cameras.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use cv_core::{CameraModel, KeyPoint};
+use cv_pinhole::NormalizedKeyPoint;
+use nalgebra::{DMatrix, Point2, Point3, Rotation3, Vector3};
+
+use crate::distortion::{DistortionModel, LensModel};
+use crate::error::{Error, Result};
+use crate::rectification::RectifParams;
+use crate::GrayFloatImage;
+
+use optimise::{levenberg_marquardt, LeastSquares};
+
+// -----------------------------------------------------------------------------------------------
+// EXPORTS
+// -----------------------------------------------------------------------------------------------
+
+pub use aruco::{ArucoDetector, ArucoDictionary, ArucoMarker};
+pub use charuco::CharucoBoard;
+pub use chessboard::Chessboard;
+pub use corners::refine_corners;
+pub use stereo::{StereoCalibration, StereoCalibrator};
+
+// -----------------------------------------------------------------------------------------------
+// MODULES
+// -----------------------------------------------------------------------------------------------
+
+mod aruco;
+mod charuco;
+mod chessboard;
+mod corners;
+mod optimise;
+mod stereo;
+mod zhang;
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Minimum number of views of the target needed to estimate every intrinsic parameter.
+const MIN_VIEWS: usize = 3;
+
+/// Minimum number of points in a view of the target, which is the number needed to find the
+/// homography between the target and the image.
+const MIN_POINTS: usize = 4;
+
+/// The points of a view of the target are collinear if the smaller singular value of the centred
+/// points on the target is below this fraction of the larger.
+const COLLINEAR_TOLERANCE: f64 = 1e-3;
+
+/// Number of intrinsic parameters before the distortion coefficients, which are the focal
+/// lengths, principal point and skew.
+const CAMERA_PARAMS: usize = 5;
+
+/// Number of parameters describing the pose of a view, a rotation vector and a translation.
+const POSE_PARAMS: usize = 6;
+
+// -----------------------------------------------------------------------------------------------
+// TRAITS
+// -----------------------------------------------------------------------------------------------
+
+/// A planar target which can be found in images to calibrate a camera.
+pub trait CalibrationTarget {
+    /// Find the target in an image, returning `None` if it cannot be found.
+    fn detect(&self, image: &GrayFloatImage) -> Option<TargetDetection>;
+}
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// The points of a calibration target found in an image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TargetDetection {
+    /// Identifiers of the points, which are unique within the target
+    pub ids: Vec<usize>,
+
+    /// Positions of the points on the plane of the target, in the target's units
+    pub target_points: Vec<Point2<f64>>,
+
+    /// Positions of the points in the image, in pixels
+    pub image_points: Vec<Point2<f64>>
+}
+
+/// Calibrates a single camera from images of a calibration target.
+///
+/// ```no_run
+/// # use cv_camstream::{CalibrationParams, Chessboard, GrayFloatImage, MonoCalibrator};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let images: Vec<GrayFloatImage> = Vec::new();
+/// // A board of 10 by 7 squares, each 25 mm across
+/// let mut calibrator = MonoCalibrator::new(Chessboard::new(9, 6, 0.025));
+///
+/// for image in images.iter() {
+///     calibrator.add_image(image);
+/// }
+///
+/// let calibration = calibrator.calibrate()?;
+/// println!("RMS reprojection error: {} px", calibration.rms_error);
+/// calibration.params.to_file("mono_rectif_params.toml")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct MonoCalibrator<T> {
+    target: T,
+
+    distortion_model: DistortionModel,
+
+    detections: Vec<TargetDetection>
+}
+
+/// The result of calibrating a single camera.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonoCalibration {
+    /// The estimated intrinsics and distortion of the camera
+    pub params: RectifParams,
+
+    /// The root mean square reprojection error of each view, in the order they were added, in
+    /// pixels
+    pub reprojection_errors: Vec<f64>,
+
+    /// The root mean square reprojection error over every point of every view, in pixels
+    pub rms_error: f64
+}
+
+/// The pose of a calibration target in a view, which maps points on the target into the frame
+/// of the camera.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Pose {
+    pub(crate) rotation: Rotation3<f64>,
+
+    pub(crate) translation: Vector3<f64>
+}
+
+/// The refinement of a camera and the pose of each of its views.
+///
+/// The parameters are the intrinsics of the camera followed by the pose of each view, and each
+/// view is a block of residuals.
+struct MonoProblem<'a> {
+    model: DistortionModel,
+
+    detections: &'a [TargetDetection]
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl<T: CalibrationTarget> MonoCalibrator<T> {
+
+    /// Create a new calibrator for the given target, which estimates the plumb bob distortion
+    /// model.
+    pub fn new(target: T) -> Self {
+        Self {
+            target,
+            distortion_model: DistortionModel::PlumbBob,
+            detections: Vec::new()
+        }
+    }
+
+    /// Set the distortion model to estimate.
+    ///
+    /// The plumb bob, rational and equidistant models can be estimated, but the division model
+    /// cannot.
+    pub fn distortion_model(mut self, model: DistortionModel) -> Self {
+        self.distortion_model = model;
+
+        self
+    }
+
+    /// Find the target in an image and add it to the calibration, returning whether it was found.
+    pub fn add_image(&mut self, image: &GrayFloatImage) -> bool {
+        match self.target.detect(image) {
+            Some(detection) => self.add_detection(detection),
+            None => false
+        }
+    }
+
+    /// Add a view of the target which has already been found, returning whether it has enough
+    /// points to be used, which must not all lie on one line of the target.
+    pub fn add_detection(&mut self, detection: TargetDetection) -> bool {
+        if !detection.is_usable() {
+            return false;
+        }
+
+        self.detections.push(detection);
+        true
+    }
+
+    /// Get the views of the target which have been added.
+    pub fn detections(&self) -> &[TargetDetection] {
+        &self.detections
+    }
+
+    /// Calibrate the camera from the views which have been added, which needs at least three
+    /// views of the target in different orientations.
+    pub fn calibrate(&self) -> Result<MonoCalibration> {
+        let (params, poses) = calibrate_camera(&self.detections, self.distortion_model)?;
+        let lens = params.lens_model();
+
+        let mut residuals = Vec::new();
+        let mut total = 0.0;
+        let mut reprojection_errors = Vec::with_capacity(self.detections.len());
+
+        for (detection, pose) in self.detections.iter().zip(poses.iter()) {
+            residuals.clear();
+            reprojection_residuals(&lens, pose, detection, &mut residuals);
+
+            let squared: f64 = residuals.iter().map(|r| r * r).sum();
+            reprojection_errors.push((squared / detection.image_points.len() as f64).sqrt());
+            total += squared;
+        }
+
+        let points: usize = self.detections.iter().map(|d| d.image_points.len()).sum();
+
+        Ok(MonoCalibration {
+            params,
+            reprojection_errors,
+            rms_error: (total / points as f64).sqrt()
+        })
+    }
+}
+
+impl TargetDetection {
+
+    /// Check whether the view has enough points to be used.
+    ///
+    /// Points which all lie on one line of the target, such as a single row of a partly hidden
+    /// board, don't determine the homography between the target and the image.
+    pub(crate) fn is_usable(&self) -> bool {
+        let count = self.target_points.len();
+        if count < MIN_POINTS || self.image_points.len() != count {
+            return false;
+        }
+
+        let centroid = self.target_points.iter().fold(Vector3::zeros(), |sum, p| {
+            sum + p.to_homogeneous()
+        }) / count as f64;
+        let centred = DMatrix::from_fn(count, 2, |i, j| self.target_points[i][j] - centroid[j]);
+        let singular_values = centred.singular_values();
+
+        singular_values.min() > COLLINEAR_TOLERANCE * singular_values.max()
+    }
+}
+
+impl Pose {
+
+    /// Build a pose from a rotation vector followed by a translation.
+    fn from_params(params: &[f64]) -> Self {
+        Self {
+            rotation: Rotation3::new(Vector3::new(params[0], params[1], params[2])),
+            translation: Vector3::new(params[3], params[4], params[5])
+        }
+    }
+
+    /// Append the rotation vector and translation of the pose to `params`.
+    fn push_params(&self, params: &mut Vec<f64>) {
+        params.extend(self.rotation.scaled_axis().iter());
+        params.extend(self.translation.iter());
+    }
+
+    /// Transform a point on the target into the frame of the camera.
+    fn transform(&self, point: &Point2<f64>) -> Point3<f64> {
+        self.rotation * Point3::new(point.x, point.y, 0.0) + self.translation
+    }
+}
+
+impl<'a> LeastSquares for MonoProblem<'a> {
+    fn block_count(&self) -> usize {
+        self.detections.len()
+    }
+
+    fn depends_on(&self, block: usize, param: usize) -> bool {
+        let intrinsics = intrinsic_count(self.model);
+
+        param < intrinsics || (param - intrinsics) / POSE_PARAMS == block
+    }
+
+    fn residuals(&self, params: &[f64], block: usize, out: &mut Vec<f64>) {
+        let intrinsics = intrinsic_count(self.model);
+        let lens = params_from_vector(self.model, &params[..intrinsics]).lens_model();
+        let start = intrinsics + block * POSE_PARAMS;
+        let pose = Pose::from_params(&params[start..start + POSE_PARAMS]);
+
+        reprojection_residuals(&lens, &pose, &self.detections[block], out);
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// PRIVATE FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Estimate the parameters of a camera and the pose of the target in each of its views.
+fn calibrate_camera(
+    detections: &[TargetDetection],
+    model: DistortionModel
+) -> Result<(RectifParams, Vec<Pose>)> {
+    check_model(model)?;
+
+    if detections.len() < MIN_VIEWS {
+        return Err(Error::CalibrationError(format!(
+            "At least {} views of the target are needed, found {}", MIN_VIEWS, detections.len()
+        )));
+    }
+
+    let (camera_matrix, poses) = zhang::initialise(detections)?;
+
+    let mut initial = vec![
+        camera_matrix[(0, 0)],
+        camera_matrix[(1, 1)],
+        camera_matrix[(0, 2)],
+        camera_matrix[(1, 2)],
+        camera_matrix[(0, 1)]
+    ];
+    initial.resize(intrinsic_count(model), 0.0);
+    for pose in poses.iter() {
+        pose.push_params(&mut initial);
+    }
+
+    let refined = levenberg_marquardt(&MonoProblem { model, detections }, initial)?;
+    let intrinsics = intrinsic_count(model);
+
+    Ok((
+        params_from_vector(model, &refined[..intrinsics]),
+        refined[intrinsics..].chunks(POSE_PARAMS).map(Pose::from_params).collect()
+    ))
+}
+
+/// Check that a distortion model can be estimated.
+fn check_model(model: DistortionModel) -> Result<()> {
+    match model {
+        DistortionModel::Division => Err(Error::CalibrationError(
+            "The division distortion model cannot be estimated".into()
+        )),
+        _ => Ok(())
+    }
+}
+
+/// Get the number of intrinsic parameters estimated for a distortion model.
+fn intrinsic_count(model: DistortionModel) -> usize {
+    CAMERA_PARAMS + match model {
+        DistortionModel::Division => 0,
+        DistortionModel::PlumbBob => 5,
+        DistortionModel::RationalPolynomial => 8,
+        DistortionModel::Equidistant => 4
+    }
+}
+
+/// Build rectification parameters from the focal lengths, principal point and skew followed by
+/// the distortion coefficients in OpenCV's order.
+fn params_from_vector(model: DistortionModel, v: &[f64]) -> RectifParams {
+    let mut params = RectifParams {
+        focals: [v[0], v[1]],
+        principal_point: [v[2], v[3]],
+        skew: v[4],
+        distortion_model: Some(model),
+        ..RectifParams::default()
+    };
+
+    let c = |i: usize| v.get(CAMERA_PARAMS + i).copied();
+    match model {
+        DistortionModel::Division => (),
+        DistortionModel::Equidistant => {
+            params.k1 = c(0);
+            params.k2 = c(1);
+            params.k3 = c(2);
+            params.k4 = c(3);
+        },
+        DistortionModel::PlumbBob | DistortionModel::RationalPolynomial => {
+            params.k1 = c(0);
+            params.k2 = c(1);
+            params.p1 = c(2);
+            params.p2 = c(3);
+            params.k3 = c(4);
+
+            if model == DistortionModel::RationalPolynomial {
+                params.k4 = c(5);
+                params.k5 = c(6);
+                params.k6 = c(7);
+            }
+        }
+    }
+
+    params
+}
+
+/// Project a point on the target into the image.
+fn project(lens: &LensModel, pose: &Pose, point: &Point2<f64>) -> Point2<f64> {
+    let p = pose.transform(point);
+    let KeyPoint(pixel) = lens.uncalibrate(NormalizedKeyPoint(Point2::new(p.x / p.z, p.y / p.z)));
+
+    pixel
+}
+
+/// Append the difference between the projected and detected position of every point in a view
+/// to `out`, as pairs of x and y differences.
+fn reprojection_residuals(
+    lens: &LensModel,
+    pose: &Pose,
+    detection: &TargetDetection,
+    out: &mut Vec<f64>
+) {
+    for (target, image) in detection.target_points.iter().zip(detection.image_points.iter()) {
+        let projected = project(lens, pose, target);
+
+        out.push(projected.x - image.x);
+        out.push(projected.y - image.y);
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+pub(crate) mod tests {
+
+    use nalgebra::Matrix3;
+
+    use super::*;
+
+    /// Number of samples along each axis of a pixel when rendering a target.
+    const SUPERSAMPLING: usize = 8;
+
+    /// Render a target seen through the homography from its plane to the image, where `pattern`
+    /// gives the intensity at each point on the target.
+    ///
+    /// Only pixels whose corners differ in intensity are supersampled, which keeps rendering fast
+    /// for patterns made of large uniform regions.
+    pub(crate) fn render_target<F>(
+        resolution: (usize, usize),
+        homography: &Matrix3<f64>,
+        pattern: F
+    ) -> GrayFloatImage
+    where
+        F: Fn(f64, f64) -> f32
+    {
+        let inverse = homography.try_inverse().unwrap();
+        let m: Vec<f64> = inverse.transpose().iter().cloned().collect();
+        let intensity = |x: f64, y: f64| {
+            let w = m[6] * x + m[7] * y + m[8];
+            pattern((m[0] * x + m[1] * y + m[2]) / w, (m[3] * x + m[4] * y + m[5]) / w)
+        };
+
+        let mut image = GrayFloatImage::new(resolution.0, resolution.1);
+        let step = 1.0 / SUPERSAMPLING as f64;
+
+        for y in 0..resolution.1 {
+            for x in 0..resolution.0 {
+                let (x0, y0) = (x as f64, y as f64);
+                let corners = [
+                    intensity(x0, y0),
+                    intensity(x0 + 1.0, y0),
+                    intensity(x0, y0 + 1.0),
+                    intensity(x0 + 1.0, y0 + 1.0)
+                ];
+
+                let value = if corners.iter().all(|&c| c == corners[0]) {
+                    corners[0]
+                } else {
+                    let mut sum = 0.0;
+                    for sy in 0..SUPERSAMPLING {
+                        for sx in 0..SUPERSAMPLING {
+                            sum += intensity(
+                                x0 + (sx as f64 + 0.5) * step,
+                                y0 + (sy as f64 + 0.5) * step
+                            );
+                        }
+                    }
+                    sum / (SUPERSAMPLING * SUPERSAMPLING) as f32
+                };
+
+                image.put(x, y, value);
+            }
+        }
+
+        image
+    }
+
+    /// The intensity of a chessboard at each point on it, with a white border one square wide
+    /// on a grey background.
+    pub(crate) fn chessboard_pattern(board: &Chessboard) -> impl Fn(f64, f64) -> f32 {
+        let size = board.square_size();
+        let (columns, rows) = (board.columns() as f64, board.rows() as f64);
+
+        move |x, y| {
+            let (i, j) = ((x / size).floor(), (y / size).floor());
+
+            if i < -2.0 || j < -2.0 || i > columns || j > rows {
+                0.5
+            } else if i < -1.0 || j < -1.0 || i > columns - 1.0 || j > rows - 1.0 {
+                0.9
+            } else if (i + j).rem_euclid(2.0) == 0.0 {
+                0.1
+            } else {
+                0.9
+            }
+        }
+    }
+
+    /// The intensity of a ChArUco board at each point on it, with a white border one square wide
+    /// on a grey background.
+    pub(crate) fn charuco_pattern(board: &CharucoBoard) -> impl Fn(f64, f64) -> f32 {
+        let board = board.clone();
+        let size = board.square_size();
+        let (columns, rows) = (board.squares_x() as f64, board.squares_y() as f64);
+        let cells = board.dictionary().marker_bits() + 2;
+        let markers: Vec<Vec<bool>> = (0..board.marker_count())
+            .map(|id| board.dictionary().cells(id).unwrap())
+            .collect();
+
+        move |x, y| {
+            let (i, j) = ((x / size).floor(), (y / size).floor());
+
+            if i < -1.0 || j < -1.0 || i > columns || j > rows {
+                return 0.5;
+            } else if i < 0.0 || j < 0.0 || i > columns - 1.0 || j > rows - 1.0 {
+                return 0.9;
+            } else if (i + j).rem_euclid(2.0) == 0.0 {
+                return 0.1;
+            }
+
+            let id = (j as usize * board.squares_x() + i as usize) / 2;
+            let corners = board.marker_corners(id).unwrap();
+            let cell = board.marker_size() / cells as f64;
+            let (cx, cy) = ((x - corners[0].x) / cell, (y - corners[0].y) / cell);
+
+            if cx < 0.0 || cy < 0.0 || cx >= cells as f64 || cy >= cells as f64 {
+                0.9
+            } else {
+                marker_cell(&markers[id], cells, cx as usize, cy as usize)
+            }
+        }
+    }
+
+    /// The intensity of a cell of a marker, including its border, given whether each inner cell
+    /// is white.
+    pub(crate) fn marker_cell(inner: &[bool], cells: usize, x: usize, y: usize) -> f32 {
+        if x == 0 || y == 0 || x == cells - 1 || y == cells - 1 {
+            0.1
+        } else if inner[(y - 1) * (cells - 2) + x - 1] {
+            0.9
+        } else {
+            0.1
+        }
+    }
+
+    /// Poses of a 9 by 6 board of 25 mm squares in front of the camera at different angles.
+    pub(crate) fn test_poses() -> Vec<Pose> {
+        poses_around(Vector3::new(0.1, 0.0625, 0.0))
+    }
+
+    /// Poses of a target in front of the camera at different angles, where `centre` is the centre
+    /// of the target on its plane.
+    pub(crate) fn poses_around(centre: Vector3<f64>) -> Vec<Pose> {
+        let views = [
+            ((0.3, 0.0, 0.05), (0.0, 0.0, 0.45)),
+            ((-0.3, 0.1, -0.1), (0.03, -0.02, 0.5)),
+            ((0.0, 0.35, 0.1), (-0.04, 0.01, 0.42)),
+            ((0.1, -0.35, 0.0), (0.02, 0.03, 0.48)),
+            ((0.25, 0.25, 0.3), (0.0, -0.03, 0.55)),
+            ((-0.2, -0.25, -0.2), (-0.02, 0.0, 0.4))
+        ];
+
+        views.iter()
+            .map(|&((roll, pitch, yaw), (x, y, z))| {
+                let rotation = Rotation3::from_euler_angles(roll, pitch, yaw);
+
+                Pose {
+                    rotation,
+                    translation: Vector3::new(x, y, z) - rotation * centre
+                }
+            })
+            .collect()
+    }
+
+    /// Get the homography from the plane of a target to the image of an undistorted camera.
+    pub(crate) fn view_homography(params: &RectifParams, pose: &Pose) -> Matrix3<f64> {
+        let camera_matrix = Matrix3::new(
+            params.focals[0], params.skew, params.principal_point[0],
+            0.0, params.focals[1], params.principal_point[1],
+            0.0, 0.0, 1.0
+        );
+        let r = pose.rotation.matrix();
+
+        camera_matrix * Matrix3::from_columns(&[
+            r.column(0).into_owned(),
+            r.column(1).into_owned(),
+            pose.translation
+        ])
+    }
+
+    /// Project the points of a board into a camera as a view of the board.
+    pub(crate) fn synthetic_detection(
+        board: &Chessboard,
+        params: &RectifParams,
+        pose: &Pose
+    ) -> TargetDetection {
+        let lens = params.lens_model();
+        let target_points = board.target_points();
+
+        TargetDetection {
+            ids: (0..target_points.len()).collect(),
+            image_points: target_points.iter().map(|p| project(&lens, pose, p)).collect(),
+            target_points
+        }
+    }
+
+    /// Test that a distorted camera is recovered exactly from perfect views
+    #[test]
+    fn test_calibrate_detections() {
+        let board = Chessboard::new(9, 6, 0.025);
+        let params = RectifParams {
+            focals: [500.0, 505.0],
+            principal_point: [322.0, 238.0],
+            k1: Some(-0.25),
+            k2: Some(0.08),
+            p1: Some(0.001),
+            p2: Some(-0.0005),
+            k3: Some(-0.01),
+            distortion_model: Some(DistortionModel::PlumbBob),
+            ..RectifParams::default()
+        };
+
+        let mut calibrator = MonoCalibrator::new(board);
+        for pose in test_poses().iter() {
+            assert!(calibrator.add_detection(synthetic_detection(&board, &params, pose)));
+        }
+
+        let calibration = calibrator.calibrate().unwrap();
+        let estimated = calibration.params;
+        assert!(calibration.rms_error < 1e-6, "{}", calibration.rms_error);
+        assert_eq!(calibration.reprojection_errors.len(), 6);
+        assert!((estimated.focals[0] - 500.0).abs() < 1e-4, "{:?}", estimated);
+        assert!((estimated.focals[1] - 505.0).abs() < 1e-4, "{:?}", estimated);
+        assert!((estimated.principal_point[0] - 322.0).abs() < 1e-4, "{:?}", estimated);
+        assert!(estimated.skew.abs() < 1e-4, "{:?}", estimated);
+        assert!((estimated.k1.unwrap() + 0.25).abs() < 1e-5, "{:?}", estimated);
+        assert!((estimated.p1.unwrap() - 0.001).abs() < 1e-6, "{:?}", estimated);
+
+        // A view of one row of the board is collinear and can't be used
+        let mut row = synthetic_detection(&board, &params, &test_poses()[0]);
+        for points in [&mut row.target_points, &mut row.image_points].iter_mut() {
+            points.truncate(board.columns());
+        }
+        row.ids.truncate(board.columns());
+        assert!(!calibrator.add_detection(row));
+        assert_eq!(calibrator.detections().len(), 6);
+
+        // Too few views, or a model which cannot be estimated, are errors
+        let mut calibrator = MonoCalibrator::new(board);
+        calibrator.add_detection(synthetic_detection(&board, &params, &test_poses()[0]));
+        assert!(calibrator.calibrate().is_err());
+        let calibrator = MonoCalibrator::new(board).distortion_model(DistortionModel::Division);
+        assert!(calibrator.calibrate().is_err());
+    }
+
+    /// Test calibrating a camera from rendered images of a chessboard
+    #[test]
+    fn test_calibrate_images() {
+        let board = Chessboard::new(9, 6, 0.025);
+        let params = RectifParams {
+            focals: [480.0, 480.0],
+            principal_point: [318.0, 243.0],
+            ..RectifParams::default()
+        };
+
+        let mut calibrator = MonoCalibrator::new(board);
+        for pose in test_poses().iter() {
+            let homography = view_homography(&params, pose);
+            let image = render_target((640, 480), &homography, chessboard_pattern(&board));
+            assert!(calibrator.add_image(&image));
+        }
+
+        let calibration = calibrator.calibrate().unwrap();
+        let estimated = calibration.params;
+        assert!(calibration.rms_error < 0.05, "{}", calibration.rms_error);
+        assert!(calibration.reprojection_errors.iter().all(|&e| e < 0.05));
+        assert!((estimated.focals[0] - 480.0).abs() < 2.0, "{:?}", estimated);
+        assert!((estimated.principal_point[1] - 243.0).abs() < 2.0, "{:?}", estimated);
+        assert!(estimated.k1.unwrap().abs() < 0.01, "{:?}", estimated);
+    }
+}
@@ -0,0 +1,228 @@
+//! # Non-linear Least Squares
+//!
+//! A Levenberg-Marquardt solver for the calibration problems. The Jacobian is found by finite
+//! differences, re-evaluating only the blocks of residuals which depend on each parameter, so that
+//! the pose of one view does not cost an evaluation of every other view.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use nalgebra::{DMatrix, DVector};
+
+use crate::error::{Error, Result};
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Maximum number of accepted steps taken by the solver.
+const MAX_ITERATIONS: usize = 200;
+
+/// Damping applied to the first step, relative to the diagonal of `JᵀJ`.
+const INITIAL_DAMPING: f64 = 1e-3;
+
+/// The solver gives up once the damping needed to reduce the cost exceeds this.
+const MAX_DAMPING: f64 = 1e12;
+
+/// The solver stops once a step reduces the cost by less than this fraction.
+const COST_TOLERANCE: f64 = 1e-14;
+
+/// Step used for the finite difference Jacobian, relative to the magnitude of each parameter.
+const JACOBIAN_STEP: f64 = 1e-7;
+
+// -----------------------------------------------------------------------------------------------
+// TRAITS
+// -----------------------------------------------------------------------------------------------
+
+/// A least squares problem whose residuals are divided into blocks, each of which depends on a
+/// subset of the parameters.
+pub(crate) trait LeastSquares {
+    /// Get the number of blocks of residuals.
+    fn block_count(&self) -> usize;
+
+    /// Check whether a block of residuals depends on a parameter.
+    fn depends_on(&self, block: usize, param: usize) -> bool;
+
+    /// Evaluate a block of residuals, appending them to `out`.
+    ///
+    /// Each block must always produce the same number of residuals.
+    fn residuals(&self, params: &[f64], block: usize, out: &mut Vec<f64>);
+}
+
+// -----------------------------------------------------------------------------------------------
+// PUBLIC FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Minimise the sum of the squared residuals of a problem from an initial estimate of its
+/// parameters, returning the refined parameters, or an error if their cost is not finite.
+pub(crate) fn levenberg_marquardt<P: LeastSquares>(
+    problem: &P,
+    initial: Vec<f64>
+) -> Result<Vec<f64>> {
+    let mut params = initial;
+    let (mut residuals, offsets) = evaluate(problem, &params);
+    let mut cost = residuals.norm_squared();
+    let mut damping = INITIAL_DAMPING;
+
+    for _ in 0..MAX_ITERATIONS {
+        let jacobian = jacobian(problem, &params, &residuals, &offsets);
+        let jtj = jacobian.tr_mul(&jacobian);
+        let jtr = jacobian.tr_mul(&residuals);
+
+        // Increase the damping until a step reduces the cost
+        let mut accepted = None;
+        while damping < MAX_DAMPING {
+            let mut lhs = jtj.clone();
+            for i in 0..params.len() {
+                lhs[(i, i)] += damping * jtj[(i, i)].max(f64::EPSILON);
+            }
+
+            if let Some(cholesky) = lhs.cholesky() {
+                let step = cholesky.solve(&-&jtr);
+                let candidate: Vec<f64> = params.iter().zip(step.iter())
+                    .map(|(p, s)| p + s)
+                    .collect();
+                let (candidate_residuals, _) = evaluate(problem, &candidate);
+                let candidate_cost = candidate_residuals.norm_squared();
+
+                if candidate_cost.is_finite() && candidate_cost < cost {
+                    accepted = Some((candidate, candidate_residuals, candidate_cost));
+                    break;
+                }
+            }
+
+            damping *= 10.0;
+        }
+
+        match accepted {
+            Some((candidate, candidate_residuals, candidate_cost)) => {
+                let converged = cost - candidate_cost <= COST_TOLERANCE * cost;
+
+                params = candidate;
+                residuals = candidate_residuals;
+                cost = candidate_cost;
+                damping = (damping / 10.0).max(f64::EPSILON);
+
+                if converged {
+                    break;
+                }
+            },
+            None => break
+        }
+    }
+
+    // A cost which isn't finite can never be reduced, so the initial estimate was returned as is
+    if !cost.is_finite() {
+        return Err(Error::CalibrationError(
+            "The calibration did not converge to a finite reprojection error".into()
+        ));
+    }
+
+    Ok(params)
+}
+
+// -----------------------------------------------------------------------------------------------
+// PRIVATE FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Evaluate every block of residuals, returning them along with the offset of each block.
+fn evaluate<P: LeastSquares>(problem: &P, params: &[f64]) -> (DVector<f64>, Vec<usize>) {
+    let mut residuals = Vec::new();
+    let mut offsets = Vec::with_capacity(problem.block_count());
+
+    for block in 0..problem.block_count() {
+        offsets.push(residuals.len());
+        problem.residuals(params, block, &mut residuals);
+    }
+
+    (DVector::from_vec(residuals), offsets)
+}
+
+/// Find the Jacobian of the residuals by forward differences.
+fn jacobian<P: LeastSquares>(
+    problem: &P,
+    params: &[f64],
+    residuals: &DVector<f64>,
+    offsets: &[usize]
+) -> DMatrix<f64> {
+    let mut jacobian = DMatrix::zeros(residuals.len(), params.len());
+    let mut perturbed = params.to_vec();
+    let mut block_residuals = Vec::new();
+
+    for param in 0..params.len() {
+        let step = JACOBIAN_STEP * params[param].abs().max(1.0);
+        perturbed[param] = params[param] + step;
+
+        for (block, offset) in offsets.iter().enumerate() {
+            if !problem.depends_on(block, param) {
+                continue;
+            }
+
+            block_residuals.clear();
+            problem.residuals(&perturbed, block, &mut block_residuals);
+
+            for (i, r) in block_residuals.iter().enumerate() {
+                let row = offset + i;
+                jacobian[(row, param)] = (r - residuals[row]) / step;
+            }
+        }
+
+        perturbed[param] = params[param];
+    }
+
+    jacobian
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Fits `a·exp(b·x)` to samples, with one block per sample.
+    struct ExponentialFit {
+        samples: Vec<(f64, f64)>
+    }
+
+    impl LeastSquares for ExponentialFit {
+        fn block_count(&self) -> usize {
+            self.samples.len()
+        }
+
+        fn depends_on(&self, _block: usize, _param: usize) -> bool {
+            true
+        }
+
+        fn residuals(&self, params: &[f64], block: usize, out: &mut Vec<f64>) {
+            let (x, y) = self.samples[block];
+            out.push(params[0] * (params[1] * x).exp() - y);
+        }
+    }
+
+    /// Test that the solver recovers the parameters of a non-linear model
+    #[test]
+    fn test_levenberg_marquardt() {
+        let problem = ExponentialFit {
+            samples: (0..20).map(|i| {
+                let x = i as f64 * 0.1;
+                (x, 2.5 * (-1.3 * x).exp())
+            }).collect()
+        };
+
+        let params = levenberg_marquardt(&problem, vec![1.0, 0.0]).unwrap();
+        assert!((params[0] - 2.5).abs() < 1e-6, "{:?}", params);
+        assert!((params[1] + 1.3).abs() < 1e-6, "{:?}", params);
+
+        // A sample which isn't finite gives a cost which can't be minimised
+        let mut problem = problem;
+        problem.samples.push((1.0, f64::NAN));
+        match levenberg_marquardt(&problem, vec![1.0, 0.0]) {
+            Err(Error::CalibrationError(_)) => {},
+            r => panic!("Expected a CalibrationError, got {:?}", r)
+        }
+    }
+}
@@ -0,0 +1,643 @@
+//! # Stereo Calibration
+//!
+//! Calibrates a pair of cameras from synchronised views of a calibration target. Each camera is
+//! first calibrated on its own, which gives the pose of the target relative to both cameras in
+//! every view and so an estimate of the rotation and translation between them. The intrinsics of
+//! both cameras, the extrinsics and the pose of the target relative to the left camera are then
+//! refined together, so that every point constrains the extrinsics.
+//!
+//! Views which fit the solution much worse than the rest, for example because the board moved
+//! between the left and right images being captured, are rejected one at a time and the
+//! calibration repeated without them.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::collections::HashMap;
+
+use cv_core::KeyPoint;
+use nalgebra::{Matrix3, Rotation3, Vector3};
+
+use crate::camstream::StereoFrame;
+use crate::distortion::DistortionModel;
+use crate::error::{Error, Result};
+use crate::rectification::{RectifParams, StereoRectifParams};
+use crate::GrayFloatImage;
+
+use super::optimise::{levenberg_marquardt, LeastSquares};
+use super::{
+    calibrate_camera, check_model, intrinsic_count, params_from_vector, reprojection_residuals,
+    CalibrationTarget, Pose, TargetDetection, MIN_VIEWS, POSE_PARAMS
+};
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Default ratio between the error of a view and the median error of all views above which the
+/// view is rejected as an outlier.
+const DEFAULT_OUTLIER_FACTOR: f64 = 3.0;
+
+/// Views are never rejected if their RMS reprojection error is below this, in pixels, so that
+/// views of a near perfect calibration are kept.
+const MIN_OUTLIER_ERROR: f64 = 0.5;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// Calibrates a pair of stereo cameras from synchronised images of a calibration target.
+///
+/// The images must not have been rectified, so frames should come from a stream built without
+/// rectification parameters.
+///
+/// ```no_run
+/// # use cv_camstream::prelude::*;
+/// # use cv_camstream::{CalibrationParams, Chessboard, StereoCalibrator};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut camera = CamStreamBuilder::new()
+///     .stereo()
+///     .left_path("/dev/video0")?
+///     .right_path("/dev/video2")?
+///     .build()?;
+///
+/// let mut calibrator = StereoCalibrator::new(Chessboard::new(9, 6, 0.025));
+/// while calibrator.detections().len() < 20 {
+///     calibrator.add_frame(&camera.capture()?);
+/// }
+///
+/// let calibration = calibrator.calibrate()?;
+/// println!(
+///     "RMS reprojection error: {} px, RMS epipolar error: {} px",
+///     calibration.rms_error,
+///     calibration.epipolar_error
+/// );
+/// calibration.params.to_file("stereo_rectif_params.toml")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct StereoCalibrator<T> {
+    target: T,
+
+    distortion_model: DistortionModel,
+
+    outlier_factor: f64,
+
+    detections: Vec<(TargetDetection, TargetDetection)>
+}
+
+/// The result of calibrating a pair of stereo cameras.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StereoCalibration {
+    /// The estimated intrinsics and distortion of both cameras, and the rotation and translation
+    /// from the left camera to the right, with the translation in the units of the target
+    pub params: StereoRectifParams,
+
+    /// The root mean square reprojection error over both images of each view which was used, in
+    /// the order they were added, in pixels
+    pub reprojection_errors: Vec<f64>,
+
+    /// The indices of the views which were rejected as outliers, in the order they were added
+    pub rejected_views: Vec<usize>,
+
+    /// The root mean square reprojection error over every point of every view used, in pixels
+    pub rms_error: f64,
+
+    /// The root mean square distance of the undistorted points of the target from the epipolar
+    /// lines of their matches in the other image, in pixels
+    pub epipolar_error: f64
+}
+
+/// The joint refinement of both cameras, the extrinsics between them and the pose of the target
+/// relative to the left camera in each view.
+///
+/// The parameters are the intrinsics of the left and right cameras, the rotation vector and
+/// translation from the left camera to the right, then the pose of each view. Each view is a block
+/// of residuals, with those of the left image followed by those of the right.
+struct StereoProblem<'a> {
+    model: DistortionModel,
+
+    views: Vec<&'a (TargetDetection, TargetDetection)>
+}
+
+/// The solution of the stereo calibration problem for a set of views.
+struct StereoSolution {
+    params: StereoRectifParams,
+
+    extrinsics: Pose,
+
+    poses: Vec<Pose>
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl<T: CalibrationTarget> StereoCalibrator<T> {
+
+    /// Create a new calibrator for the given target, which estimates the plumb bob distortion
+    /// model for both cameras.
+    pub fn new(target: T) -> Self {
+        Self {
+            target,
+            distortion_model: DistortionModel::PlumbBob,
+            outlier_factor: DEFAULT_OUTLIER_FACTOR,
+            detections: Vec::new()
+        }
+    }
+
+    /// Set the distortion model to estimate for both cameras.
+    ///
+    /// The plumb bob, rational and equidistant models can be estimated, but the division model
+    /// cannot.
+    pub fn distortion_model(mut self, model: DistortionModel) -> Self {
+        self.distortion_model = model;
+
+        self
+    }
+
+    /// Set the ratio between the reprojection error of a view and the median error of all views
+    /// above which the view is rejected as an outlier, which is 3 by default.
+    ///
+    /// Views with an error below half a pixel are always kept, and an infinite factor disables
+    /// the rejection of outliers.
+    pub fn outlier_factor(mut self, factor: f64) -> Self {
+        self.outlier_factor = factor;
+
+        self
+    }
+
+    /// Find the target in both images of a frame and add them to the calibration, returning
+    /// whether it was found in both.
+    pub fn add_frame(&mut self, frame: &StereoFrame<GrayFloatImage>) -> bool {
+        self.add_images(&frame.left, &frame.right)
+    }
+
+    /// Find the target in a pair of synchronised images and add them to the calibration,
+    /// returning whether it was found in both.
+    pub fn add_images(&mut self, left: &GrayFloatImage, right: &GrayFloatImage) -> bool {
+        match (self.target.detect(left), self.target.detect(right)) {
+            (Some(left), Some(right)) => self.add_detections(left, right),
+            _ => false
+        }
+    }
+
+    /// Add a pair of views of the target which have already been found, returning whether they
+    /// have enough points to be used, which must not all lie on one line of the target.
+    pub fn add_detections(&mut self, left: TargetDetection, right: TargetDetection) -> bool {
+        if !left.is_usable() || !right.is_usable() {
+            return false;
+        }
+
+        self.detections.push((left, right));
+        true
+    }
+
+    /// Get the pairs of views of the target which have been added, as left and right views.
+    pub fn detections(&self) -> &[(TargetDetection, TargetDetection)] {
+        &self.detections
+    }
+
+    /// Calibrate the cameras from the views which have been added, which needs at least three
+    /// views of the target in different orientations.
+    pub fn calibrate(&self) -> Result<StereoCalibration> {
+        check_model(self.distortion_model)?;
+
+        let mut views: Vec<usize> = (0..self.detections.len()).collect();
+        let mut rejected_views = Vec::new();
+
+        loop {
+            let problem = StereoProblem {
+                model: self.distortion_model,
+                views: views.iter().map(|&i| &self.detections[i]).collect()
+            };
+            let solution = problem.solve()?;
+            let errors = problem.view_errors(&solution);
+
+            // Reject the worst view if it is an outlier, then solve again without it
+            let (worst, worst_error) = errors.iter()
+                .cloned()
+                .enumerate()
+                .fold((0, 0.0), |best, (i, e)| if e > best.1 { (i, e) } else { best });
+            let mut sorted = errors.clone();
+            sorted.sort_by(f64::total_cmp);
+            let median = sorted[sorted.len() / 2];
+
+            if views.len() > MIN_VIEWS
+                && worst_error > MIN_OUTLIER_ERROR
+                && worst_error > self.outlier_factor * median
+            {
+                rejected_views.push(views.remove(worst));
+                continue;
+            }
+
+            let points: usize = problem.views.iter()
+                .map(|(l, r)| l.image_points.len() + r.image_points.len())
+                .sum();
+            let total: f64 = problem.views.iter()
+                .zip(errors.iter())
+                .map(|((l, r), e)| e * e * (l.image_points.len() + r.image_points.len()) as f64)
+                .sum();
+
+            rejected_views.sort_unstable();
+
+            return Ok(StereoCalibration {
+                epipolar_error: problem.epipolar_error(&solution),
+                params: solution.params,
+                reprojection_errors: errors,
+                rejected_views,
+                rms_error: (total / points as f64).sqrt()
+            });
+        }
+    }
+}
+
+impl<'a> StereoProblem<'a> {
+
+    /// Calibrate each camera separately, then refine both together with the extrinsics.
+    fn solve(&self) -> Result<StereoSolution> {
+        if self.views.len() < MIN_VIEWS {
+            return Err(Error::CalibrationError(format!(
+                "At least {} views of the target are needed, found {}",
+                MIN_VIEWS,
+                self.views.len()
+            )));
+        }
+
+        let left: Vec<TargetDetection> = self.views.iter().map(|v| v.0.clone()).collect();
+        let right: Vec<TargetDetection> = self.views.iter().map(|v| v.1.clone()).collect();
+        let (left_params, left_poses) = calibrate_camera(&left, self.model)?;
+        let (right_params, right_poses) = calibrate_camera(&right, self.model)?;
+
+        // Each view gives the extrinsics from the target's poses, so average them
+        let mut rotation_sum = Matrix3::zeros();
+        let mut translation_sum = Vector3::zeros();
+        for (l, r) in left_poses.iter().zip(right_poses.iter()) {
+            let rotation = r.rotation * l.rotation.inverse();
+
+            rotation_sum += rotation.matrix();
+            translation_sum += r.translation - rotation * l.translation;
+        }
+        let extrinsics = Pose {
+            rotation: closest_rotation(&rotation_sum)?,
+            translation: translation_sum / left_poses.len() as f64
+        };
+
+        let mut initial = intrinsic_vector(self.model, &left_params);
+        initial.extend(intrinsic_vector(self.model, &right_params));
+        extrinsics.push_params(&mut initial);
+        for pose in left_poses.iter() {
+            pose.push_params(&mut initial);
+        }
+
+        Ok(self.solution(&levenberg_marquardt(self, initial)?))
+    }
+
+    /// Unpack a parameter vector into the solution it describes.
+    fn solution(&self, params: &[f64]) -> StereoSolution {
+        let intrinsics = intrinsic_count(self.model);
+        let left = params_from_vector(self.model, &params[..intrinsics]);
+        let right = params_from_vector(self.model, &params[intrinsics..2 * intrinsics]);
+        let extrinsics = Pose::from_params(&params[2 * intrinsics..]);
+
+        let r = extrinsics.rotation.matrix();
+        let mut rotation = [[0.0; 3]; 3];
+        for (i, row) in rotation.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = r[(i, j)];
+            }
+        }
+
+        StereoSolution {
+            params: StereoRectifParams {
+                rotation: Some(rotation),
+                translation: Some(extrinsics.translation.into()),
+                left,
+                right
+            },
+            extrinsics,
+            poses: params[2 * intrinsics + POSE_PARAMS..]
+                .chunks(POSE_PARAMS)
+                .map(Pose::from_params)
+                .collect()
+        }
+    }
+
+    /// Get the root mean square reprojection error of each view over both of its images.
+    fn view_errors(&self, solution: &StereoSolution) -> Vec<f64> {
+        let mut residuals = Vec::new();
+
+        self.views.iter()
+            .zip(solution.poses.iter())
+            .map(|((left, right), pose)| {
+                residuals.clear();
+                view_residuals(
+                    &solution.params,
+                    &solution.extrinsics,
+                    pose,
+                    left,
+                    right,
+                    &mut residuals
+                );
+
+                let squared: f64 = residuals.iter().map(|r| r * r).sum();
+                (squared / (left.image_points.len() + right.image_points.len()) as f64).sqrt()
+            })
+            .collect()
+    }
+
+    /// Get the root mean square distance of the points of the target from the epipolar lines of
+    /// their matches, after undistortion, over both images of every view.
+    ///
+    /// Points are matched by their identifiers, and points only found in one image are ignored.
+    fn epipolar_error(&self, solution: &StereoSolution) -> f64 {
+        let (left, right) = (&solution.params.left, &solution.params.right);
+        let Pose { rotation, translation } = solution.extrinsics;
+
+        let left_matrix = camera_matrix(left);
+        let right_matrix = camera_matrix(right);
+        let fundamental = match (left_matrix.try_inverse(), right_matrix.try_inverse()) {
+            (Some(left_inverse), Some(right_inverse)) => {
+                right_inverse.transpose()
+                    * translation.cross_matrix()
+                    * rotation.matrix()
+                    * left_inverse
+            },
+            _ => return f64::NAN
+        };
+
+        let undistort = |params: &RectifParams, matrix: &Matrix3<f64>, point| {
+            let normalised = params.undistort_point(KeyPoint(point)).0;
+            matrix * normalised.to_homogeneous()
+        };
+        let line_distance = |line: Vector3<f64>, point: &Vector3<f64>| {
+            line.dot(point) / (line.x * line.x + line.y * line.y).sqrt()
+        };
+
+        let mut total = 0.0;
+        let mut count = 0;
+        for (left_view, right_view) in self.views.iter() {
+            let right_points: HashMap<usize, _> = right_view.ids.iter()
+                .cloned()
+                .zip(right_view.image_points.iter())
+                .collect();
+
+            for (id, left_point) in left_view.ids.iter().zip(left_view.image_points.iter()) {
+                let right_point = match right_points.get(id) {
+                    Some(&&p) => p,
+                    None => continue
+                };
+
+                let l = undistort(left, &left_matrix, *left_point);
+                let r = undistort(right, &right_matrix, right_point);
+
+                total += line_distance(fundamental * l, &r).powi(2);
+                total += line_distance(fundamental.transpose() * r, &l).powi(2);
+                count += 2;
+            }
+        }
+
+        (total / count.max(1) as f64).sqrt()
+    }
+}
+
+impl<'a> LeastSquares for StereoProblem<'a> {
+    fn block_count(&self) -> usize {
+        self.views.len()
+    }
+
+    fn depends_on(&self, block: usize, param: usize) -> bool {
+        let shared = 2 * intrinsic_count(self.model) + POSE_PARAMS;
+
+        param < shared || (param - shared) / POSE_PARAMS == block
+    }
+
+    fn residuals(&self, params: &[f64], block: usize, out: &mut Vec<f64>) {
+        let intrinsics = intrinsic_count(self.model);
+        let left = params_from_vector(self.model, &params[..intrinsics]);
+        let right = params_from_vector(self.model, &params[intrinsics..2 * intrinsics]);
+        let extrinsics = Pose::from_params(&params[2 * intrinsics..]);
+
+        let start = 2 * intrinsics + POSE_PARAMS * (block + 1);
+        let pose = Pose::from_params(&params[start..start + POSE_PARAMS]);
+        let (left_view, right_view) = self.views[block];
+
+        let stereo = StereoRectifParams { rotation: None, translation: None, left, right };
+        view_residuals(&stereo, &extrinsics, &pose, left_view, right_view, out);
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// PRIVATE FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Append the reprojection residuals of the left and then the right image of a view to `out`,
+/// where `pose` is the pose of the target relative to the left camera.
+fn view_residuals(
+    params: &StereoRectifParams,
+    extrinsics: &Pose,
+    pose: &Pose,
+    left: &TargetDetection,
+    right: &TargetDetection,
+    out: &mut Vec<f64>
+) {
+    let right_pose = Pose {
+        rotation: extrinsics.rotation * pose.rotation,
+        translation: extrinsics.rotation * pose.translation + extrinsics.translation
+    };
+
+    reprojection_residuals(&params.left.lens_model(), pose, left, out);
+    reprojection_residuals(&params.right.lens_model(), &right_pose, right, out);
+}
+
+/// Get the intrinsic parameter vector of a camera, the inverse of [`params_from_vector`].
+fn intrinsic_vector(model: DistortionModel, params: &RectifParams) -> Vec<f64> {
+    let c = |v: Option<f64>| v.unwrap_or(0.0);
+    let mut v = vec![
+        params.focals[0],
+        params.focals[1],
+        params.principal_point[0],
+        params.principal_point[1],
+        params.skew
+    ];
+
+    match model {
+        DistortionModel::Division => (),
+        DistortionModel::Equidistant => {
+            v.extend(&[c(params.k1), c(params.k2), c(params.k3), c(params.k4)]);
+        },
+        DistortionModel::PlumbBob | DistortionModel::RationalPolynomial => {
+            v.extend(&[c(params.k1), c(params.k2), c(params.p1), c(params.p2), c(params.k3)]);
+
+            if model == DistortionModel::RationalPolynomial {
+                v.extend(&[c(params.k4), c(params.k5), c(params.k6)]);
+            }
+        }
+    }
+
+    v
+}
+
+/// Get the camera matrix of a camera's intrinsics.
+fn camera_matrix(params: &RectifParams) -> Matrix3<f64> {
+    Matrix3::new(
+        params.focals[0], params.skew, params.principal_point[0],
+        0.0, params.focals[1], params.principal_point[1],
+        0.0, 0.0, 1.0
+    )
+}
+
+/// Find the rotation closest to a matrix in the Frobenius norm.
+fn closest_rotation(m: &Matrix3<f64>) -> Result<Rotation3<f64>> {
+    let svd = m.svd(true, true);
+    let (u, v_t) = match (svd.u, svd.v_t) {
+        (Some(u), Some(v_t)) => (u, v_t),
+        _ => return Err(Error::CalibrationError(
+            "Cannot estimate the rotation between the cameras".into()
+        ))
+    };
+
+    // Flip the least significant axis if needed so that the result is a rotation, not a reflection
+    let mut d = Matrix3::identity();
+    if (u * v_t).determinant() < 0.0 {
+        let smallest = svd.singular_values.imin();
+        d[(smallest, smallest)] = -1.0;
+    }
+
+    Ok(Rotation3::from_matrix_unchecked(u * d * v_t))
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::calibration::tests::{
+        chessboard_pattern, render_target, synthetic_detection, test_poses, view_homography
+    };
+    use crate::calibration::Chessboard;
+
+    /// Get the pose of the target relative to the right camera of a rig.
+    fn right_pose(extrinsics: &Pose, pose: &Pose) -> Pose {
+        Pose {
+            rotation: extrinsics.rotation * pose.rotation,
+            translation: extrinsics.rotation * pose.translation + extrinsics.translation
+        }
+    }
+
+    /// A rig of two distorted cameras 6 cm apart, with the right camera turned slightly inwards.
+    fn test_rig() -> (RectifParams, RectifParams, Pose) {
+        let left = RectifParams {
+            focals: [500.0, 502.0],
+            principal_point: [318.0, 242.0],
+            k1: Some(-0.2),
+            k2: Some(0.05),
+            p1: Some(0.0005),
+            p2: Some(-0.0003),
+            k3: Some(0.0),
+            distortion_model: Some(DistortionModel::PlumbBob),
+            ..RectifParams::default()
+        };
+        let right = RectifParams {
+            focals: [505.0, 506.0],
+            principal_point: [324.0, 236.0],
+            k1: Some(-0.18),
+            k2: Some(0.04),
+            ..left
+        };
+        let extrinsics = Pose {
+            rotation: Rotation3::from_euler_angles(0.01, -0.05, 0.005),
+            translation: Vector3::new(-0.06, 0.001, 0.002)
+        };
+
+        (left, right, extrinsics)
+    }
+
+    /// Test that a rig is recovered exactly from perfect views, and that a corrupted view is
+    /// rejected
+    #[test]
+    fn test_calibrate_stereo_detections() {
+        let board = Chessboard::new(9, 6, 0.025);
+        let (left, right, extrinsics) = test_rig();
+
+        let mut calibrator = StereoCalibrator::new(board);
+        for (i, pose) in test_poses().iter().enumerate() {
+            let right_pose = right_pose(&extrinsics, pose);
+            let mut right_detection = synthetic_detection(&board, &right, &right_pose);
+
+            // The corners of the third view were badly detected in the right image
+            if i == 2 {
+                for (j, point) in right_detection.image_points.iter_mut().enumerate() {
+                    point.y += if j % 2 == 0 { 3.0 } else { -3.0 };
+                }
+            }
+
+            assert!(calibrator.add_detections(
+                synthetic_detection(&board, &left, pose),
+                right_detection
+            ));
+        }
+
+        let calibration = calibrator.calibrate().unwrap();
+        let params = calibration.params;
+        assert_eq!(calibration.rejected_views, vec![2]);
+        assert_eq!(calibration.reprojection_errors.len(), 5);
+        assert!(calibration.rms_error < 1e-6, "{}", calibration.rms_error);
+        assert!(calibration.epipolar_error < 1e-6, "{}", calibration.epipolar_error);
+
+        let (rotation, translation) = params.extrinsics().unwrap();
+        assert!((rotation - extrinsics.rotation.matrix()).norm() < 1e-6, "{}", rotation);
+        assert!((translation - extrinsics.translation).norm() < 1e-6, "{}", translation);
+        assert!((params.left.focals[0] - 500.0).abs() < 1e-4, "{:?}", params.left);
+        assert!((params.right.principal_point[1] - 236.0).abs() < 1e-4, "{:?}", params.right);
+        assert!((params.right.k1.unwrap() + 0.18).abs() < 1e-5, "{:?}", params.right);
+
+        // Without outlier rejection the corrupted view is kept
+        let calibration = calibrator.outlier_factor(f64::INFINITY).calibrate().unwrap();
+        assert!(calibration.rejected_views.is_empty());
+        assert!(calibration.rms_error > 0.1, "{}", calibration.rms_error);
+        assert!(calibration.epipolar_error > 0.1, "{}", calibration.epipolar_error);
+    }
+
+    /// Test calibrating a rig from rendered stereo frames of a chessboard
+    #[test]
+    fn test_calibrate_stereo_frames() {
+        let board = Chessboard::new(9, 6, 0.025);
+        let (left, right, extrinsics) = test_rig();
+        let undistorted = |p: &RectifParams| RectifParams {
+            focals: p.focals,
+            principal_point: p.principal_point,
+            ..RectifParams::default()
+        };
+        let (left, right) = (undistorted(&left), undistorted(&right));
+
+        let mut calibrator = StereoCalibrator::new(board);
+        for pose in test_poses().iter() {
+            let render = |params: &RectifParams, pose: &Pose| {
+                let homography = view_homography(params, pose);
+                render_target((640, 480), &homography, chessboard_pattern(&board))
+            };
+
+            let frame = StereoFrame {
+                left: render(&left, pose),
+                right: render(&right, &right_pose(&extrinsics, pose)),
+                left_timestamp: 0,
+                right_timestamp: 0
+            };
+            assert!(calibrator.add_frame(&frame));
+        }
+
+        let calibration = calibrator.calibrate().unwrap();
+        assert!(calibration.rejected_views.is_empty());
+        assert!(calibration.rms_error < 0.05, "{}", calibration.rms_error);
+        assert!(calibration.epipolar_error < 0.05, "{}", calibration.epipolar_error);
+
+        let (rotation, translation) = calibration.params.extrinsics().unwrap();
+        assert!((rotation - extrinsics.rotation.matrix()).norm() < 2e-3, "{}", rotation);
+        assert!((translation - extrinsics.translation).norm() < 1e-3, "{}", translation);
+    }
+}
@@ -0,0 +1,258 @@
+//! # Zhang's Method
+//!
+//! Closed form estimates of a camera's intrinsics and the pose of each view of a planar target,
+//! from the homographies between the target's plane and each image, following Zhang's "A Flexible
+//! New Technique for Camera Calibration". Distortion is ignored, so the estimates only seed the
+//! non-linear refinement.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use nalgebra::{DMatrix, Matrix3, Point2, Rotation3, SymmetricEigen, Vector3};
+
+use crate::error::{Error, Result};
+
+use super::{Pose, TargetDetection};
+
+// -----------------------------------------------------------------------------------------------
+// PUBLIC FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Estimate the camera matrix and the pose of the target in each view.
+pub(crate) fn initialise(detections: &[TargetDetection]) -> Result<(Matrix3<f64>, Vec<Pose>)> {
+    // Conditioning the image points keeps the elements of the image of the absolute conic, which
+    // would otherwise span many orders of magnitude, comparable
+    let image_points: Vec<Point2<f64>> = detections.iter()
+        .flat_map(|d| d.image_points.iter().cloned())
+        .collect();
+    let normaliser = normaliser(&image_points);
+
+    let homographies = detections.iter()
+        .map(|d| {
+            homography(&d.target_points, &d.image_points).ok_or_else(|| {
+                Error::CalibrationError("The target points of a view are degenerate".into())
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let normalised: Vec<Matrix3<f64>> = homographies.iter().map(|h| normaliser * h).collect();
+    let camera_matrix = normaliser.try_inverse().unwrap() * intrinsics(&normalised)?;
+
+    let poses = homographies.iter()
+        .map(|h| pose(&camera_matrix, h))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok((camera_matrix, poses))
+}
+
+/// Find the homography mapping target points onto image points with the normalised direct linear
+/// transform, or `None` if the points are degenerate.
+pub(crate) fn homography(target: &[Point2<f64>], image: &[Point2<f64>]) -> Option<Matrix3<f64>> {
+    if target.len() < 4 || target.len() != image.len() {
+        return None;
+    }
+
+    let target_normaliser = normaliser(target);
+    let image_normaliser = normaliser(image);
+
+    // Accumulate AᵀA of the linear system A·h = 0 directly, as A has two rows per point
+    let mut ata = DMatrix::zeros(9, 9);
+    for (t, i) in target.iter().zip(image.iter()) {
+        let t = target_normaliser * t.to_homogeneous();
+        let i = image_normaliser * i.to_homogeneous();
+        let (x, y) = (t.x / t.z, t.y / t.z);
+        let (u, v) = (i.x / i.z, i.y / i.z);
+
+        let rows = [
+            [-x, -y, -1.0, 0.0, 0.0, 0.0, u * x, u * y, u],
+            [0.0, 0.0, 0.0, -x, -y, -1.0, v * x, v * y, v]
+        ];
+        for row in rows.iter() {
+            for j in 0..9 {
+                for k in 0..9 {
+                    ata[(j, k)] += row[j] * row[k];
+                }
+            }
+        }
+    }
+
+    let h = smallest_eigenvector(ata);
+    let normalised = Matrix3::from_row_slice(h.as_slice());
+    let homography = image_normaliser.try_inverse()? * normalised * target_normaliser;
+
+    if homography.iter().all(|v| v.is_finite()) && homography.norm() > 0.0 {
+        Some(homography / homography.norm())
+    } else {
+        None
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// PRIVATE FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Recover the camera matrix from the constraints each homography places on the image of the
+/// absolute conic `B = K⁻ᵀ·K⁻¹`.
+fn intrinsics(homographies: &[Matrix3<f64>]) -> Result<Matrix3<f64>> {
+    let v = |h: &Matrix3<f64>, i: usize, j: usize| [
+        h[(0, i)] * h[(0, j)],
+        h[(0, i)] * h[(1, j)] + h[(1, i)] * h[(0, j)],
+        h[(1, i)] * h[(1, j)],
+        h[(2, i)] * h[(0, j)] + h[(0, i)] * h[(2, j)],
+        h[(2, i)] * h[(1, j)] + h[(1, i)] * h[(2, j)],
+        h[(2, i)] * h[(2, j)]
+    ];
+
+    let mut rows = Vec::new();
+    for h in homographies {
+        let h = h / h.norm();
+        let (v11, v12, v22) = (v(&h, 0, 0), v(&h, 0, 1), v(&h, 1, 1));
+
+        rows.push(v12);
+        rows.push([
+            v11[0] - v22[0], v11[1] - v22[1], v11[2] - v22[2],
+            v11[3] - v22[3], v11[4] - v22[4], v11[5] - v22[5]
+        ]);
+    }
+
+    // With two views the skew cannot be estimated, so it is assumed to be zero
+    if homographies.len() < 3 {
+        rows.push([0.0, 1.0, 0.0, 0.0, 0.0, 0.0]);
+    }
+
+    let mut vtv = DMatrix::zeros(6, 6);
+    for row in rows.iter() {
+        for j in 0..6 {
+            for k in 0..6 {
+                vtv[(j, k)] += row[j] * row[k];
+            }
+        }
+    }
+
+    // B is positive definite, which fixes the sign of the solution
+    let mut b = smallest_eigenvector(vtv);
+    if b[0] < 0.0 {
+        b = -b;
+    }
+    let (b11, b12, b22, b13, b23, b33) = (b[0], b[1], b[2], b[3], b[4], b[5]);
+
+    let denominator = b11 * b22 - b12 * b12;
+    let v0 = (b12 * b13 - b11 * b23) / denominator;
+    let lambda = b33 - (b13 * b13 + v0 * (b12 * b13 - b11 * b23)) / b11;
+    let alpha = (lambda / b11).sqrt();
+    let beta = (lambda * b11 / denominator).sqrt();
+    let gamma = -b12 * alpha * alpha * beta / lambda;
+    let u0 = gamma * v0 / beta - b13 * alpha * alpha / lambda;
+
+    let camera_matrix = Matrix3::new(
+        alpha, gamma, u0,
+        0.0, beta, v0,
+        0.0, 0.0, 1.0
+    );
+
+    if camera_matrix.iter().all(|v| v.is_finite()) && alpha > 0.0 && beta > 0.0 {
+        Ok(camera_matrix)
+    } else {
+        Err(Error::CalibrationError(
+            "Cannot estimate the intrinsics, the views of the target may be too similar".into()
+        ))
+    }
+}
+
+/// Recover the pose of the target from its homography, with the target in front of the camera.
+fn pose(camera_matrix: &Matrix3<f64>, homography: &Matrix3<f64>) -> Result<Pose> {
+    let m = camera_matrix.try_inverse().unwrap() * homography;
+    let (h1, h2, h3) = (m.column(0), m.column(1), m.column(2));
+
+    let mut scale = 2.0 / (h1.norm() + h2.norm());
+    if h3.z < 0.0 {
+        scale = -scale;
+    }
+
+    let r1 = h1 * scale;
+    let r2 = h2 * scale;
+    let rotation = Matrix3::from_columns(&[r1, r2, r1.cross(&r2)]);
+
+    // The columns are only approximately orthonormal, so take the closest rotation
+    let svd = rotation.svd(true, true);
+    let (u, v_t) = match (svd.u, svd.v_t) {
+        (Some(u), Some(v_t)) => (u, v_t),
+        _ => return Err(Error::CalibrationError("Cannot estimate the pose of a view".into()))
+    };
+
+    Ok(Pose {
+        rotation: Rotation3::from_matrix_unchecked(u * v_t),
+        translation: Vector3::from(h3 * scale)
+    })
+}
+
+/// Get the similarity which moves the centroid of points to the origin and scales their mean
+/// distance from it to √2.
+fn normaliser(points: &[Point2<f64>]) -> Matrix3<f64> {
+    let n = points.len().max(1) as f64;
+    let centroid = points.iter().fold(Vector3::zeros(), |sum, p| sum + p.to_homogeneous()) / n;
+    let mean_distance = points.iter()
+        .map(|p| (p.coords - centroid.xy()).norm())
+        .sum::<f64>() / n;
+
+    let scale = if mean_distance > 0.0 { std::f64::consts::SQRT_2 / mean_distance } else { 1.0 };
+
+    Matrix3::new(
+        scale, 0.0, -scale * centroid.x,
+        0.0, scale, -scale * centroid.y,
+        0.0, 0.0, 1.0
+    )
+}
+
+/// Get the eigenvector of a symmetric matrix with the smallest eigenvalue.
+fn smallest_eigenvector(m: DMatrix<f64>) -> nalgebra::DVector<f64> {
+    let eigen = SymmetricEigen::new(m);
+    let (smallest, _) = eigen.eigenvalues.iter()
+        .enumerate()
+        .fold((0, f64::INFINITY), |best, (i, &e)| if e < best.1 { (i, e) } else { best });
+
+    eigen.eigenvectors.column(smallest).into_owned()
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::calibration::tests::{synthetic_detection, test_poses};
+    use crate::calibration::Chessboard;
+    use crate::RectifParams;
+
+    /// Test that the intrinsics and poses are recovered exactly from undistorted views
+    #[test]
+    fn test_zhang_initialisation() {
+        let params = RectifParams {
+            focals: [520.0, 515.0],
+            principal_point: [330.0, 245.0],
+            skew: 0.5,
+            ..RectifParams::default()
+        };
+        let board = Chessboard::new(9, 6, 0.025);
+        let poses = test_poses();
+        let detections: Vec<TargetDetection> = poses.iter()
+            .map(|pose| synthetic_detection(&board, &params, pose))
+            .collect();
+
+        let (camera_matrix, estimates) = initialise(&detections).unwrap();
+        let expected = Matrix3::new(
+            520.0, 0.5, 330.0,
+            0.0, 515.0, 245.0,
+            0.0, 0.0, 1.0
+        );
+        assert!((camera_matrix - expected).norm() < 1e-6, "{}", camera_matrix);
+
+        for (estimate, pose) in estimates.iter().zip(poses.iter()) {
+            assert!((estimate.rotation.matrix() - pose.rotation.matrix()).norm() < 1e-8);
+            assert!((estimate.translation - pose.translation).norm() < 1e-8);
+        }
+    }
+}
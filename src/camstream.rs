@@ -7,21 +7,56 @@
 // IMPORTS
 // -----------------------------------------------------------------------------------------------
 
+use std::marker::PhantomData;
 use std::sync::mpsc::{channel, Sender, Receiver};
+use std::sync::Arc;
 use std::thread;
 
-use image::{DynamicImage, GrayImage, ImageFormat};
-use rscam::{Camera, Frame};
+use cv_pinhole::CameraIntrinsics;
+use image::{DynamicImage, GrayImage, RgbImage};
+use nalgebra::{Matrix3, Matrix3x4, Matrix4};
 
+use crate::backend::{CamBackend, RawFrame, V4l2Backend};
+use crate::bayer::BayerPattern;
 use crate::error::{Result, Error};
-use crate::rectification::{RectifParams, StereoRectifParams};
-use crate::GrayFloatImage;
+use crate::rectification::{RectifOptions, Rectifier, RemapTable, Roi};
+use crate::rectification::{StereoRectification, StereoRectifParams, StereoSide};
+use crate::{GrayFloatImage, RgbFloatImage};
 use thread::JoinHandle;
 
+// -----------------------------------------------------------------------------------------------
+// TYPES
+// -----------------------------------------------------------------------------------------------
+
+/// An image along with its capture timestamp in microseconds.
+pub type TimestampedImage<I = GrayFloatImage> = (I, u64);
+
+/// A captured frame along with the image and timestamp from each camera to be recorded.
+pub type RecordedFrame<F, I = GrayFloatImage> = (F, Vec<TimestampedImage<I>>);
+
 // -----------------------------------------------------------------------------------------------
 // TRAITS
 // -----------------------------------------------------------------------------------------------
 
+/// An image type which camera streams can produce, either [`GrayFloatImage`] (the default) or
+/// [`RgbFloatImage`].
+pub trait StreamImage: Clone + Send + 'static {
+    /// Decode a raw frame into an image of this type.
+    fn decode(frame: &RawFrame) -> Result<Self>;
+
+    /// Rectify the image with a precomputed table, applying the same correction to every channel.
+    fn remap(&self, table: &RemapTable) -> Self;
+
+    /// Get the width of the image.
+    fn width(&self) -> usize;
+
+    /// Get the height of the image.
+    fn height(&self) -> usize;
+
+    /// Convert the image into a 16 bit dynamic image, used when recording.
+    fn to_dynamic16(&self) -> DynamicImage;
+}
+
 pub trait CamStream {
     type Frame;
 
@@ -29,36 +64,78 @@ pub trait CamStream {
     fn capture(&mut self) -> Result<Self::Frame>;
 }
 
+/// A camera stream whose images can be saved by a [`Recorder`](crate::Recorder).
+pub trait RecordableStream: CamStream {
+    /// The type of the images recorded from each camera.
+    type Image: StreamImage;
+
+    /// Names of the cameras in the stream, in the order their images are returned by
+    /// `capture_recorded`.
+    fn camera_names(&self) -> &'static [&'static str];
+
+    /// Capture a frame from the camera stream, also returning a copy of each camera's image and
+    /// timestamp to be recorded.
+    ///
+    /// If `raw` is true the copies are taken before rectification, otherwise they are the same
+    /// as the images in the returned frame.
+    fn capture_recorded(
+        &mut self,
+        raw: bool
+    ) -> Result<RecordedFrame<Self::Frame, Self::Image>>;
+}
+
 // -----------------------------------------------------------------------------------------------
 // DATA STRUCTS
 // -----------------------------------------------------------------------------------------------
 
-pub struct MonoCamStream {
-    camera: Camera,
+/// A stream of images from a single camera.
+///
+/// The stream is generic over the [`CamBackend`] which provides its raw frames, defaulting to a
+/// V4L2 device, and the [`StreamImage`] it produces, defaulting to a [`GrayFloatImage`].
+pub struct MonoCamStream<B: CamBackend = V4l2Backend, I: StreamImage = GrayFloatImage> {
+    camera: B,
 
-    img_format: ImageFormat,
+    processor: FrameProcessor,
 
-    rectif_params: Option<RectifParams>
+    _image: PhantomData<I>
 }
 
-pub struct StereoCamStream {
-    left_jh: JoinHandle<()>,
-    right_jh: JoinHandle<()>,
+/// A stream of synchronised image pairs from a stereo camera.
+///
+/// Each camera's backend is moved into its own capture thread when the stream is created, so
+/// unlike [`MonoCamStream`] this type does not need to name the backend.
+pub struct StereoCamStream<I: StreamImage = GrayFloatImage> {
+    left_jh: JoinHandle<Result<()>>,
+    right_jh: JoinHandle<Result<()>>,
 
     left_tx: Sender<WorkerCmd>,
-    left_rx: Receiver<Result<(GrayFloatImage, u64)>>,
+    left_rx: Receiver<Result<WorkerFrame<I>>>,
 
     right_tx: Sender<WorkerCmd>,
-    right_rx: Receiver<Result<(GrayFloatImage, u64)>>,
+    right_rx: Receiver<Result<WorkerFrame<I>>>,
+
+    rectif_params: Option<StereoRectifParams>,
+
+    rectif_options: RectifOptions,
+
+    /// Resolution of the most recent frame, or the configured resolution before the first
+    resolution: (u32, u32),
+
+    /// Stereo rectification at `resolution`, recomputed only when the resolution changes
+    rectification: Option<StereoRectification>,
+
+    /// The left and right rectification tables the workers used for the most recent frame, or
+    /// built for the configured resolution before the first
+    remap_tables: Option<(Arc<RemapTable>, Arc<RemapTable>)>
 }
 
 /// A frame from a stereo camera stream containing both images.
-pub struct StereoFrame {
+pub struct StereoFrame<I = GrayFloatImage> {
     /// The left image
-    pub left: GrayFloatImage,
+    pub left: I,
 
     /// The right image
-    pub right: GrayFloatImage,
+    pub right: I,
 
     /// The timestamp of the left image
     pub left_timestamp: u64,
@@ -67,14 +144,47 @@ pub struct StereoFrame {
     pub right_timestamp: u64
 }
 
+/// Decodes and rectifies the raw frames of a single camera.
+pub(crate) struct FrameProcessor {
+    rectifier: Option<Rectifier>,
+
+    rectif_options: RectifOptions,
+
+    /// Rectification table for the resolution of the most recent frame, shared with the stream
+    /// so that it can describe the rectified images without building its own
+    remap_table: Option<Arc<RemapTable>>,
+
+    /// Overrides the Bayer pattern given by the frame format
+    bayer_pattern: Option<BayerPattern>
+}
+
+/// An image captured by a worker thread.
+struct WorkerFrame<I> {
+    /// The decoded and rectified image
+    img: I,
+
+    /// The image before rectification, if it was requested and differs from `img`
+    raw: Option<I>,
+
+    /// The capture timestamp
+    timestamp: u64,
+
+    /// The resolution of the image before rectification
+    resolution: (u32, u32),
+
+    /// The table the image was rectified with, if any
+    remap_table: Option<Arc<RemapTable>>
+}
+
 // -----------------------------------------------------------------------------------------------
 // ENUMERATIONS
 // -----------------------------------------------------------------------------------------------
 
 /// Commands that can be sent by the main thread to the worker threads.
 enum WorkerCmd {
-    /// Capture an image from the camera
-    Capture,
+    /// Capture an image from the camera, keeping a copy of the image before rectification if
+    /// `keep_raw` is true
+    Capture { keep_raw: bool },
 
     /// Stop acquisition
     Stop
@@ -84,66 +194,131 @@ enum WorkerCmd {
 // IMPLEMENTATIONS
 // -----------------------------------------------------------------------------------------------
 
-impl CamStream for MonoCamStream {
-    type Frame = GrayFloatImage;
+impl<B: CamBackend, I: StreamImage> MonoCamStream<B, I> {
+
+    /// Create a new instance of the camera stream
+    ///
+    /// The camera must already have been started.
+    pub(crate) fn new(
+        camera: B,
+        processor: FrameProcessor
+    ) -> Self {
+        Self {
+            camera,
+            processor,
+            _image: PhantomData
+        }
+    }
+
+    /// Stop the stream
+    pub fn stop(mut self) -> Result<()> {
+        self.camera.stop()
+    }
+
+    /// Get the intrinsics of the rectified images, or `None` if the stream doesn't rectify.
+    ///
+    /// These describe the images of the most recent capture, or of the configured resolution
+    /// before the first.
+    pub fn intrinsics(&self) -> Option<CameraIntrinsics> {
+        self.processor.remap_table.as_ref().map(|t| t.intrinsics())
+    }
+
+    /// Get the camera matrix of the rectified images, or `None` if the stream doesn't rectify.
+    pub fn camera_matrix(&self) -> Option<Matrix3<f64>> {
+        self.intrinsics().map(|i| i.matrix())
+    }
+
+    /// Get the mask of valid pixels in the rectified images, see [`RemapTable::valid_mask`], or
+    /// `None` if the stream doesn't rectify.
+    pub fn valid_mask(&self) -> Option<GrayFloatImage> {
+        self.processor.remap_table.as_ref().map(|t| t.valid_mask())
+    }
+
+    /// Get the largest rectangle of valid pixels in the rectified images, or `None` if the
+    /// stream doesn't rectify.
+    pub fn valid_roi(&self) -> Option<Roi> {
+        self.processor.remap_table.as_ref().map(|t| t.valid_roi())
+    }
+}
+
+impl<B: CamBackend, I: StreamImage> CamStream for MonoCamStream<B, I> {
+    type Frame = I;
 
     /// Capture an image from the camera.
     fn capture(&mut self) -> Result<Self::Frame> {
         // Get the frame from the camera
-        let rscam_frame = self.camera.capture()
-            .map_err(|e| Error::CameraCaptureError(e))?;
+        let frame = self.camera.capture()?;
 
-        // Convert the frame into an image
-        let img = GrayFloatImage::from_dynamic(
-            &rscam_frame_to_dynamic_image(rscam_frame, self.img_format)?
-        );
+        // Decode and rectify the frame in the same way as each side of a stereo stream
+        Ok(self.processor.process(frame, false)?.img)
+    }
+}
 
-        // Rectify the images if there is a value for rectif_params
-        match self.rectif_params {
-            Some(ref r) => Ok(r.rectify(&img)),
-            None => Ok(img)
-        }
+impl<B: CamBackend, I: StreamImage> RecordableStream for MonoCamStream<B, I> {
+    type Image = I;
+
+    fn camera_names(&self) -> &'static [&'static str] {
+        &["mono"]
+    }
+
+    fn capture_recorded(
+        &mut self,
+        raw: bool
+    ) -> Result<RecordedFrame<Self::Frame, I>> {
+        let frame = self.camera.capture()?;
+        let (img, recorded) = self.processor.process(frame, raw)?.split_recorded();
+
+        Ok((img, vec![recorded]))
     }
 }
 
-impl StereoCamStream {
+impl<I: StreamImage> StereoCamStream<I> {
 
     /// Create a new instance of the camera stream
     ///
-    /// 
-    pub(crate) fn new(
-        left_cam: Camera, 
-        right_cam: Camera, 
-        format: ImageFormat, 
-        rectif_params: Option<StereoRectifParams>
+    /// The cameras must already have been started.
+    ///
+    /// The rectification parameters and options must be those the processors were created with,
+    /// and are used to describe the rectified cameras.
+    pub(crate) fn new<B: CamBackend>(
+        left_cam: B, 
+        right_cam: B, 
+        left_processor: FrameProcessor,
+        right_processor: FrameProcessor,
+        rectif_params: Option<StereoRectifParams>,
+        rectif_options: RectifOptions,
+        resolution: (u32, u32)
     ) -> Self {
         
+        // Keep the tables the processors built for the configured resolution to describe the
+        // rectified images until the first capture
+        let remap_tables = left_processor.remap_table.clone()
+            .and_then(|l| right_processor.remap_table.clone().map(|r| (l, r)));
+        let rectification = describe_rectification(
+            rectif_params.as_ref(),
+            &rectif_options,
+            resolution,
+            remap_tables.as_ref()
+        );
+
         // Create all sync objects
         let (left_tx_cmd, left_rx_cmd) = channel();
         let (left_tx_img, left_rx_img) = channel();
         let (right_tx_cmd, right_rx_cmd) = channel();
         let (right_tx_img, right_rx_img) = channel();
 
-        // Break out rectif params
-        let (left_rp, right_rp) = match rectif_params {
-            Some(srp) => (Some(srp.left), Some(srp.right)),
-            None => (None, None)
-        };
-
         // Start processing threads
         let left_jh = img_cap_thread(
             left_cam, 
             left_rx_cmd, 
             left_tx_img, 
-            format, 
-            left_rp
+            left_processor
         );
         let right_jh = img_cap_thread(
             right_cam, 
             right_rx_cmd, 
             right_tx_img, 
-            format, 
-            right_rp
+            right_processor
         );
 
         Self {
@@ -154,7 +329,13 @@ impl StereoCamStream {
             left_rx: left_rx_img,
 
             right_tx: right_tx_cmd,
-            right_rx: right_rx_img
+            right_rx: right_rx_img,
+
+            rectif_params,
+            rectif_options,
+            resolution,
+            rectification,
+            remap_tables
         }
     }
 
@@ -163,44 +344,332 @@ impl StereoCamStream {
         self.left_tx.send(WorkerCmd::Stop).map_err(|_| Error::ChannelSendError)?;
         self.right_tx.send(WorkerCmd::Stop).map_err(|_| Error::ChannelSendError)?;
 
-        self.left_jh.join().map_err(|_| Error::ThreadJoinError)?;
-        self.right_jh.join().map_err(|_| Error::ThreadJoinError)?;
+        // Wait for both threads to finish before reporting any error from stopping the cameras
+        let left = self.left_jh.join().map_err(|_| Error::ThreadJoinError)?;
+        let right = self.right_jh.join().map_err(|_| Error::ThreadJoinError)?;
 
-        Ok(())
+        left.and(right)
     }
-}
 
-impl CamStream for StereoCamStream {
-    type Frame = StereoFrame;
+    /// Get the stereo rectification of the images, or `None` if the stream doesn't rectify or
+    /// the rotation and translation between the cameras are not known.
+    ///
+    /// This describes the images of the most recent capture, or of the configured resolution
+    /// before the first. It is computed once for each resolution, so all of the accessors below
+    /// are cheap enough to call for every frame.
+    pub fn rectification(&self) -> Option<StereoRectification> {
+        self.rectification
+    }
 
-    /// Capture a frame from the pair of stereo cameras.
-    fn capture(&mut self) -> Result<Self::Frame> {
+    /// Get the intrinsics of the rectified left images, or `None` if the stream doesn't rectify.
+    pub fn left_intrinsics(&self) -> Option<CameraIntrinsics> {
+        self.side_intrinsics(StereoSide::Left)
+    }
+
+    /// Get the intrinsics of the rectified right images, or `None` if the stream doesn't
+    /// rectify.
+    ///
+    /// With stereo rectification these are the same as the left intrinsics.
+    pub fn right_intrinsics(&self) -> Option<CameraIntrinsics> {
+        self.side_intrinsics(StereoSide::Right)
+    }
+
+    /// Get the camera matrix of the rectified left images, or `None` if the stream doesn't
+    /// rectify.
+    pub fn left_camera_matrix(&self) -> Option<Matrix3<f64>> {
+        self.left_intrinsics().map(|i| i.matrix())
+    }
+
+    /// Get the camera matrix of the rectified right images, or `None` if the stream doesn't
+    /// rectify.
+    pub fn right_camera_matrix(&self) -> Option<Matrix3<f64>> {
+        self.right_intrinsics().map(|i| i.matrix())
+    }
+
+    /// Get the left and right projection matrices of the rectified cameras, see
+    /// [`StereoRectification::projection_matrices`].
+    pub fn projection_matrices(&self) -> Option<(Matrix3x4<f64>, Matrix3x4<f64>)> {
+        self.rectification().map(|r| r.projection_matrices())
+    }
+
+    /// Get the distance between the cameras, in the units of the stereo translation.
+    pub fn baseline(&self) -> Option<f64> {
+        self.rectification().map(|r| r.baseline())
+    }
+
+    /// Get the disparity to depth reprojection matrix, see
+    /// [`StereoRectification::reprojection_matrix`].
+    pub fn reprojection_matrix(&self) -> Option<Matrix4<f64>> {
+        self.rectification().map(|r| r.reprojection_matrix())
+    }
+
+    /// Get the masks of valid pixels in the left and right rectified images, see
+    /// [`RemapTable::valid_mask`], or `None` if the stream doesn't rectify.
+    ///
+    /// The masks are read from the stream's remap tables, which costs one pass over each table.
+    pub fn valid_masks(&self) -> Option<(GrayFloatImage, GrayFloatImage)> {
+        self.remap_tables.as_ref().map(|(l, r)| (l.valid_mask(), r.valid_mask()))
+    }
+
+    /// Get the largest rectangles of valid pixels in the left and right rectified images, or
+    /// `None` if the stream doesn't rectify.
+    pub fn valid_rois(&self) -> Option<(Roi, Roi)> {
+        self.remap_tables.as_ref().map(|(l, r)| (l.valid_roi(), r.valid_roi()))
+    }
+
+    /// Get the largest rectangle of pixels which are valid in both rectified images, or `None` if
+    /// the stream doesn't rectify.
+    pub fn common_roi(&self) -> Option<Roi> {
+        self.remap_tables.as_ref().map(|(l, r)| l.common_roi(r))
+    }
+
+    /// Get the intrinsics of the rectified images from one of the cameras.
+    fn side_intrinsics(&self, side: StereoSide) -> Option<CameraIntrinsics> {
+        self.remap_tables.as_ref().map(|(l, r)| match side {
+            StereoSide::Left => l.intrinsics(),
+            StereoSide::Right => r.intrinsics()
+        })
+    }
+
+    /// Capture an image from both cameras, optionally keeping the images before rectification.
+    fn capture_pair(&mut self, keep_raw: bool) -> Result<(WorkerFrame<I>, WorkerFrame<I>)> {
         // Send the capture commands
-        self.left_tx.send(WorkerCmd::Capture).map_err(|_| Error::ChannelSendError)?;
-        self.right_tx.send(WorkerCmd::Capture).map_err(|_| Error::ChannelSendError)?;
+        self.left_tx.send(WorkerCmd::Capture { keep_raw }).map_err(|_| Error::ChannelSendError)?;
+        self.right_tx.send(WorkerCmd::Capture { keep_raw }).map_err(|_| Error::ChannelSendError)?;
+
+        // Wait for the images from both threads before checking either, so that an error on one
+        // side doesn't leave the other side's image queued up for the next capture
+        let left = self.left_rx.recv();
+        let right = self.right_rx.recv();
 
-        // Wait for the images from each thread
-        let left = match self.left_rx.recv() {
+        let left = match left {
             Ok(Ok(i)) => i,
             Ok(Err(e)) => return Err(e),
             Err(e) => return Err(Error::ChannelReceiveError(e))
         };
-        let right = match self.right_rx.recv() {
+        let right = match right {
             Ok(Ok(i)) => i,
             Ok(Err(e)) => return Err(e),
             Err(e) => return Err(Error::ChannelReceiveError(e))
         };
 
+        if let (Some(l), Some(r)) = (&left.remap_table, &right.remap_table) {
+            self.remap_tables = Some((l.clone(), r.clone()));
+        }
+
+        if left.resolution != self.resolution {
+            self.resolution = left.resolution;
+            self.rectification = describe_rectification(
+                self.rectif_params.as_ref(),
+                &self.rectif_options,
+                self.resolution,
+                self.remap_tables.as_ref()
+            );
+        }
+
+        Ok((left, right))
+    }
+}
+
+impl<I: StreamImage> CamStream for StereoCamStream<I> {
+    type Frame = StereoFrame<I>;
+
+    /// Capture a frame from the pair of stereo cameras.
+    fn capture(&mut self) -> Result<Self::Frame> {
+        let (left, right) = self.capture_pair(false)?;
+
         Ok(StereoFrame {
-            left: left.0,
-            right: right.0,
-            left_timestamp: left.1,
-            right_timestamp: right.1
+            left: left.img,
+            right: right.img,
+            left_timestamp: left.timestamp,
+            right_timestamp: right.timestamp
         })
     }
 }
 
-impl StereoFrame {
+impl<I: StreamImage> RecordableStream for StereoCamStream<I> {
+    type Image = I;
+
+    fn camera_names(&self) -> &'static [&'static str] {
+        &["left", "right"]
+    }
+
+    fn capture_recorded(
+        &mut self,
+        raw: bool
+    ) -> Result<RecordedFrame<Self::Frame, I>> {
+        let (left, right) = self.capture_pair(raw)?;
+
+        let left_timestamp = left.timestamp;
+        let right_timestamp = right.timestamp;
+        let (left, left_recorded) = left.split_recorded();
+        let (right, right_recorded) = right.split_recorded();
+
+        Ok((
+            StereoFrame {
+                left,
+                right,
+                left_timestamp,
+                right_timestamp
+            },
+            vec![left_recorded, right_recorded]
+        ))
+    }
+}
+
+impl FrameProcessor {
+
+    /// Create a processor which rectifies frames with the given rectifier and options, if any.
+    ///
+    /// The rectification table is computed up front for frames of the given resolution, and is
+    /// only recomputed if a frame of a different resolution is captured.
+    pub(crate) fn new(
+        rectifier: Option<Rectifier>,
+        rectif_options: RectifOptions,
+        resolution: (u32, u32)
+    ) -> Self {
+        let remap_table = rectifier.as_ref()
+            .map(|r| Arc::new(rectif_options.remap_table(r, resolution)));
+
+        Self {
+            rectifier,
+            rectif_options,
+            remap_table,
+            bayer_pattern: None
+        }
+    }
+
+    /// Create a processor which rectifies frames with a table already built with the given
+    /// rectifier and options, which is recomputed if a frame of a different resolution is captured.
+    pub(crate) fn with_remap_table(
+        rectifier: Rectifier,
+        rectif_options: RectifOptions,
+        remap_table: RemapTable
+    ) -> Self {
+        Self {
+            rectifier: Some(rectifier),
+            rectif_options,
+            remap_table: Some(Arc::new(remap_table)),
+            bayer_pattern: None
+        }
+    }
+
+    /// Override the pattern used to demosaic Bayer frames.
+    pub(crate) fn bayer_pattern(mut self, pattern: Option<BayerPattern>) -> Self {
+        self.bayer_pattern = pattern;
+
+        self
+    }
+
+    /// Decode a captured frame into an image, rectifying it if parameters are given.
+    ///
+    /// If `keep_raw` is true and the image was rectified, the image before rectification is also
+    /// returned.
+    fn process<I: StreamImage>(
+        &mut self,
+        mut frame: RawFrame,
+        keep_raw: bool
+    ) -> Result<WorkerFrame<I>> {
+        if let Some(pattern) = self.bayer_pattern {
+            frame.format = frame.format.with_bayer_pattern(pattern);
+        }
+
+        let img = I::decode(&frame)?;
+        let resolution = (img.width() as u32, img.height() as u32);
+
+        // Rebuild the table if the resolution isn't the one it was built for
+        if let Some(ref r) = self.rectifier {
+            if self.remap_table.as_ref().map(|t| t.source_resolution()) != Some(resolution) {
+                self.remap_table = Some(Arc::new(self.rectif_options.remap_table(r, resolution)));
+            }
+        }
+
+        Ok(match self.remap_table {
+            Some(ref t) => WorkerFrame {
+                img: img.remap(t),
+                raw: if keep_raw { Some(img) } else { None },
+                timestamp: frame.timestamp,
+                resolution,
+                remap_table: Some(t.clone())
+            },
+            None => WorkerFrame {
+                img,
+                raw: None,
+                timestamp: frame.timestamp,
+                resolution,
+                remap_table: None
+            }
+        })
+    }
+}
+
+impl StreamImage for GrayFloatImage {
+    fn decode(frame: &RawFrame) -> Result<Self> {
+        frame.decode_gray()
+    }
+
+    fn remap(&self, table: &RemapTable) -> Self {
+        table.remap(self)
+    }
+
+    fn width(&self) -> usize {
+        GrayFloatImage::width(self)
+    }
+
+    fn height(&self) -> usize {
+        GrayFloatImage::height(self)
+    }
+
+    fn to_dynamic16(&self) -> DynamicImage {
+        self.to_dynamic_luma16()
+    }
+}
+
+impl StreamImage for RgbFloatImage {
+    fn decode(frame: &RawFrame) -> Result<Self> {
+        frame.decode_rgb()
+    }
+
+    fn remap(&self, table: &RemapTable) -> Self {
+        let channels: Vec<GrayFloatImage> = (0..3)
+            .map(|c| table.remap(&self.channel(c)))
+            .collect();
+
+        RgbFloatImage::from_channels(&channels[0], &channels[1], &channels[2])
+    }
+
+    fn width(&self) -> usize {
+        RgbFloatImage::width(self)
+    }
+
+    fn height(&self) -> usize {
+        RgbFloatImage::height(self)
+    }
+
+    fn to_dynamic16(&self) -> DynamicImage {
+        self.to_dynamic_rgb16()
+    }
+}
+
+impl<I> WorkerFrame<I> {
+
+    /// Split the frame into its image and the copy of it to be recorded with its timestamp.
+    ///
+    /// The recorded copy is the raw image if one was kept, otherwise it is the image itself.
+    fn split_recorded(self) -> (I, TimestampedImage<I>)
+    where
+        I: Clone
+    {
+        let recorded = match self.raw {
+            Some(raw) => raw,
+            None => self.img.clone()
+        };
+
+        (self.img, (recorded, self.timestamp))
+    }
+}
+
+impl<I: StreamImage> StereoFrame<I> {
 
     /// Get the width of an individual image in the frame
     pub fn width(&self) -> u32 {
@@ -211,6 +680,9 @@ impl StereoFrame {
     pub fn height(&self) -> u32 {
         self.left.height() as u32
     }
+}
+
+impl StereoFrame {
 
     /// Convert the frame into a pair of luma images
     pub fn to_luma8_pair(self) -> (GrayImage, GrayImage) {
@@ -218,41 +690,81 @@ impl StereoFrame {
     }
 }
 
+impl StereoFrame<RgbFloatImage> {
+
+    /// Convert the frame into a pair of 8 bit RGB images
+    pub fn to_rgb8_pair(self) -> (RgbImage, RgbImage) {
+        (to_rgb8(&self.left), to_rgb8(&self.right))
+    }
+
+    /// Convert the frame into a pair of luma images
+    pub fn to_luma8_pair(self) -> (GrayImage, GrayImage) {
+        self.to_gray().to_luma8_pair()
+    }
+
+    /// Convert both images of the frame to greyscale, keeping the timestamps
+    pub fn to_gray(&self) -> StereoFrame {
+        StereoFrame {
+            left: self.left.to_gray(),
+            right: self.right.to_gray(),
+            left_timestamp: self.left_timestamp,
+            right_timestamp: self.right_timestamp
+        }
+    }
+}
+
 // -----------------------------------------------------------------------------------------------
 // PRIVATE FUNCTIONS
 // -----------------------------------------------------------------------------------------------
 
-/// Convert an `rscam::Frame` struct into an `image::DynamicImage` struct.
-fn rscam_frame_to_dynamic_image(frame: Frame, format: ImageFormat) -> Result<DynamicImage> {
-    image::load_from_memory_with_format(&frame, format)
-        .map_err(|e| Error::ImageConversionError(e))
+/// Convert a colour image into an 8 bit RGB image.
+fn to_rgb8(img: &RgbFloatImage) -> RgbImage {
+    match img.to_dynamic_rgb8() {
+        DynamicImage::ImageRgb8(rgb) => rgb,
+        _ => unreachable!()
+    }
+}
+
+/// Describe the stereo rectification of images of the given resolution.
+///
+/// The intrinsics are taken from the left table the workers rectify with, so that cropping to the
+/// common ROI is accounted for without building the tables again to find it.
+fn describe_rectification(
+    rectif_params: Option<&StereoRectifParams>,
+    rectif_options: &RectifOptions,
+    resolution: (u32, u32),
+    remap_tables: Option<&(Arc<RemapTable>, Arc<RemapTable>)>
+) -> Option<StereoRectification> {
+    let mut rectification = rectif_params?.uncropped_rectification(resolution, rectif_options)?;
+
+    if let Some((left, _)) = remap_tables {
+        rectification.intrinsics = left.intrinsics();
+    }
+
+    Some(rectification)
 }
 
 /// Capture images from the given camera in a seprate thread.
-fn img_cap_thread(
-    cam: Camera, 
+fn img_cap_thread<B: CamBackend, I: StreamImage>(
+    mut cam: B, 
     cmd_rx: Receiver<WorkerCmd>, 
-    img_tx: Sender<Result<(GrayFloatImage, u64)>>,
-    format: ImageFormat,
-    rectif_params: Option<RectifParams>
-) -> JoinHandle<()> {
+    img_tx: Sender<Result<WorkerFrame<I>>>,
+    mut processor: FrameProcessor
+) -> JoinHandle<Result<()>> {
     thread::spawn(move || {
         while let Ok(cmd) = cmd_rx.recv() {
             match cmd {
-                WorkerCmd::Capture => {
+                WorkerCmd::Capture { keep_raw } => {
                     
                     let frame = match cam.capture() {
                         Ok(f) => f,
                         Err(e) => {
-                            img_tx.send(Err(Error::CameraCaptureError(e)))
-                                .expect("Failed to send reply to main thread");
+                            img_tx.send(Err(e)).expect("Failed to send reply to main thread");
                             continue
                         }
                     };
 
-                    let timestamp = frame.get_timestamp();
-
-                    let dyn_img = match rscam_frame_to_dynamic_image(frame, format) {
+                    let img = match processor.process(frame, keep_raw) {
                         Ok(i) => i,
                         Err(e) => {
                             img_tx.send(Err(e)).expect("Failed to send reply to main thread");
@@ -260,16 +772,7 @@ fn img_cap_thread(
                         }
                     };
 
-                    let mut img = GrayFloatImage::from_dynamic(&dyn_img);
-
-                    match rectif_params {
-                        Some(r) => {
-                            img = r.rectify(&img);
-                        },
-                        None => ()
-                    };
-
-                    img_tx.send(Ok((img, timestamp)))
+                    img_tx.send(Ok(img))
                         .expect("Error sending image to main thread");
                 },
                 WorkerCmd::Stop => {
@@ -277,5 +780,230 @@ fn img_cap_thread(
                 }
             }
         }
+
+        cam.stop()
     })
-}
\ No newline at end of file
+}
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::backend::tests::test_dir;
+    use crate::rectification::RectifParams;
+    use crate::TestPattern;
+    use crate::{BorderMode, CamStreamBuilder, Interpolation, PlaybackSource, Rectifiable};
+
+    /// Write a colour image with a different pattern in each channel into an empty directory.
+    fn write_colour_image(name: &str) -> (std::path::PathBuf, RgbFloatImage) {
+        let dir = test_dir(name);
+
+        let img = image::RgbImage::from_fn(64, 48, |x, y| {
+            let check = if (x / 8 + y / 8) % 2 == 0 { 255 } else { 0 };
+
+            image::Rgb([(x * 4) as u8, (y * 5) as u8, check])
+        });
+        img.save(dir.join("000000.png")).unwrap();
+
+        (dir, RgbFloatImage::from_dynamic(&DynamicImage::ImageRgb8(img)))
+    }
+
+    /// Test that colour streams rectify every channel in the same way as a greyscale image
+    #[test]
+    fn test_rgb_streams() {
+        let (dir, original) = write_colour_image("rgb_streams");
+        let params = RectifParams {
+            focals: [60.0, 60.0],
+            principal_point: [32.0, 24.0],
+            skew: 0.0,
+            k1: Some(-0.3),
+            ..RectifParams::default()
+        };
+
+        let mut mono = CamStreamBuilder::new()
+            .mono()
+            .playback(PlaybackSource::new(&dir))
+            .rgb()
+            .rectif_params(params)
+            .build()
+            .expect("Cannot build colour stream");
+
+        let img = mono.capture().expect("Cannot capture colour image");
+
+        for c in 0..3 {
+            assert_eq!(img.channel(c).0, params.rectify(&original.channel(c)).0);
+        }
+
+        let mut stereo = CamStreamBuilder::new()
+            .stereo()
+            .rgb()
+            .playback(PlaybackSource::new(&dir), PlaybackSource::new(&dir))
+            .build()
+            .expect("Cannot build colour stereo stream");
+
+        let frame = stereo.capture().expect("Cannot capture colour frame");
+        assert_eq!(frame.left.0, original.0);
+        assert_eq!(frame.to_gray().left.0, original.to_gray().0);
+
+        let (left, right) = frame.to_rgb8_pair();
+        assert_eq!(left.dimensions(), (64, 48));
+        assert_eq!(left, right);
+
+        stereo.stop().expect("Cannot stop colour stereo stream");
+    }
+
+    /// Test that the interpolation and border mode selected on the builder are used to rectify
+    /// frames
+    #[test]
+    fn test_interpolation_options() {
+        let (dir, original) = write_colour_image("interpolation_options");
+        let params = RectifParams {
+            focals: [60.0, 60.0],
+            principal_point: [32.0, 24.0],
+            skew: 0.0,
+            k1: Some(-0.3),
+            ..RectifParams::default()
+        };
+
+        let mut mono = CamStreamBuilder::new()
+            .mono()
+            .playback(PlaybackSource::new(&dir))
+            .rgb()
+            .rectif_params(params)
+            .interpolation(Interpolation::Lanczos)
+            .border_mode(BorderMode::Replicate)
+            .build()
+            .expect("Cannot build stream");
+
+        let img = mono.capture().expect("Cannot capture image");
+
+        let table = params.remap_table((64, 48))
+            .interpolation(Interpolation::Lanczos)
+            .border_mode(BorderMode::Replicate);
+
+        assert_eq!(img.channel(0).0, table.remap(&original.channel(0)).0);
+        assert_ne!(img.channel(0).0, params.rectify(&original.channel(0)).0);
+    }
+
+    /// Test that streams describe the cameras their rectified images come from
+    #[test]
+    fn test_rectified_cameras() {
+        let camera = RectifParams {
+            focals: [100.0, 102.0],
+            principal_point: [31.0, 25.0],
+            k1: Some(-0.1),
+            k2: Some(0.01),
+            ..RectifParams::default()
+        };
+
+        let mut mono = CamStreamBuilder::new()
+            .mono()
+            .resolution((64, 48))
+            .synthetic(TestPattern::GradientRamp)
+            .rectif_params(camera)
+            .build()
+            .expect("Cannot build mono stream");
+
+        let intrinsics = mono.intrinsics().expect("Mono stream should be rectified");
+        assert_eq!(intrinsics, camera.output_intrinsics((64, 48)));
+        mono.capture().expect("Cannot capture image");
+        assert_eq!(mono.camera_matrix(), Some(intrinsics.matrix()));
+
+        let params = StereoRectifParams {
+            left: camera,
+            right: camera,
+            rotation: Some([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]),
+            translation: Some([-0.2, 0.0, 0.0])
+        };
+
+        let mut stereo = CamStreamBuilder::new()
+            .stereo()
+            .resolution((64, 48))
+            .synthetic(TestPattern::GradientRamp, 0)
+            .rectif_params(params)
+            .output_focal(90.0)
+            .build()
+            .expect("Cannot build stereo stream");
+
+        // The cameras are described by the rectification the images were produced with
+        let rectification = stereo.rectification().unwrap();
+        assert_eq!(stereo.left_intrinsics(), Some(rectification.intrinsics));
+        stereo.capture().expect("Cannot capture frame");
+        assert_eq!(stereo.rectification(), Some(rectification));
+        assert_eq!(stereo.left_intrinsics(), stereo.right_intrinsics());
+        assert!((stereo.baseline().unwrap() - 0.2).abs() < 1e-12);
+
+        let (p1, p2) = stereo.projection_matrices().unwrap();
+        assert_eq!(p1[(0, 0)], 90.0);
+        assert!((p2[(0, 3)] - 90.0 * -0.2).abs() < 1e-9);
+
+        // A point 3 units away has a disparity of f·B/Z pixels
+        let q = stereo.reprojection_matrix().unwrap();
+        let point = q * nalgebra::Vector4::new(40.0, 20.0, 90.0 * 0.2 / 3.0, 1.0);
+        assert!((point.z / point.w - 3.0).abs() < 1e-9);
+
+        let unrectified = CamStreamBuilder::new()
+            .stereo()
+            .synthetic(TestPattern::GradientRamp, 0)
+            .build()
+            .expect("Cannot build stereo stream");
+
+        assert!(unrectified.left_intrinsics().is_none());
+        assert!(unrectified.rectification().is_none());
+        stereo.stop().unwrap();
+        unrectified.stop().unwrap();
+    }
+
+    /// Test that streams crop their rectified images to the valid rectangle when asked
+    #[test]
+    fn test_crop_to_roi() {
+        let camera = RectifParams {
+            focals: [60.0, 60.0],
+            principal_point: [32.0, 24.0],
+            k1: Some(-0.3),
+            ..RectifParams::default()
+        };
+        let params = StereoRectifParams {
+            left: camera,
+            right: camera,
+            rotation: Some([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]),
+            translation: Some([-0.2, 0.0, 0.0])
+        };
+        let builder = || CamStreamBuilder::new()
+            .stereo()
+            .resolution((64, 48))
+            .synthetic(TestPattern::GradientRamp, 0)
+            .rectif_params(params)
+            .alpha(1.0);
+
+        // Without cropping the middle of the edges of the images are invalid
+        let full = builder().build().expect("Cannot build stereo stream");
+        let (left_mask, _) = full.valid_masks().unwrap();
+        assert_eq!(left_mask.get(0, 24), 0.0);
+        assert_eq!(left_mask.get(32, 24), 1.0);
+        let roi = full.common_roi().unwrap();
+        assert!(roi.width < 64 && roi.height < 48);
+
+        // Cropped images have the size of the common rectangle and are valid everywhere
+        let mut cropped = builder().crop_to_roi().build().expect("Cannot build stereo stream");
+        let frame = cropped.capture().expect("Cannot capture frame");
+        let size = (frame.left.width() as u32, frame.left.height() as u32);
+        assert_eq!(size, (roi.width, roi.height));
+        assert_eq!(cropped.common_roi().unwrap().area(), roi.area());
+
+        let offset = full.left_intrinsics().unwrap().principal_point
+            - cropped.left_intrinsics().unwrap().principal_point;
+        assert!((offset.x - roi.x as f64).abs() < 1e-9 && (offset.y - roi.y as f64).abs() < 1e-9);
+        assert_eq!(cropped.rectification().map(|r| r.intrinsics), cropped.left_intrinsics());
+        assert_eq!(
+            cropped.rectification().unwrap().translation,
+            full.rectification().unwrap().translation
+        );
+
+        full.stop().unwrap();
+        cropped.stop().unwrap();
+    }
+}
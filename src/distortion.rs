@@ -0,0 +1,389 @@
+//! # Lens Distortion Module
+//!
+//! Camera models which include lens distortion beyond the single coefficient division model
+//! provided by `cv-pinhole`, including fisheye lenses, along with [`LensModel`] which selects
+//! between all supported models.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use cv_core::{CameraModel, ImagePoint, KeyPoint};
+use cv_pinhole::{CameraIntrinsics, CameraIntrinsicsK1Distortion, NormalizedKeyPoint};
+use nalgebra::{Matrix2, Point2, Vector2};
+use serde::{Deserialize, Serialize};
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Maximum number of Newton iterations used to undistort a point.
+const UNDISTORT_MAX_ITERATIONS: usize = 20;
+
+/// Undistortion stops once the distorted estimate is within this distance of the target, in
+/// normalised coordinates.
+const UNDISTORT_TOLERANCE: f64 = 1e-12;
+
+/// Step used for the finite difference Jacobian of the distortion.
+const JACOBIAN_STEP: f64 = 1e-7;
+
+/// Largest incidence angle a fisheye point can be undistorted to, just short of 90 degrees where
+/// the pinhole projection becomes infinite.
+const MAX_FISHEYE_THETA: f64 = std::f64::consts::FRAC_PI_2 - 1e-6;
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// The Brown-Conrady radial-tangential distortion model, as used by OpenCV.
+///
+/// A normalised point `(x, y)` with `r² = x² + y²` is distorted to
+///
+/// ```text
+/// x' = x·R + 2·p1·x·y + p2·(r² + 2·x²)
+/// y' = y·R + p1·(r² + 2·y²) + 2·p2·x·y
+/// R  = (1 + k1·r² + k2·r⁴ + k3·r⁶) / (1 + k4·r² + k5·r⁴ + k6·r⁶)
+/// ```
+///
+/// With `k4`, `k5` and `k6` zero this is the five coefficient plumb bob model, otherwise it is
+/// the eight coefficient rational model.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BrownConrady {
+    /// The intrinsics of the undistorted camera
+    pub intrinsics: CameraIntrinsics,
+
+    /// Radial coefficients `k1` to `k6`
+    pub radial: [f64; 6],
+
+    /// Tangential coefficients `p1` and `p2`
+    pub tangential: [f64; 2]
+}
+
+/// The Kannala-Brandt equidistant fisheye model, as used by OpenCV's `fisheye` module.
+///
+/// A normalised point at angle `θ = atan(r)` from the optical axis is imaged at a distance
+///
+/// ```text
+/// θd = θ·(1 + k1·θ² + k2·θ⁴ + k3·θ⁶ + k4·θ⁸)
+/// ```
+///
+/// from the principal point in the distorted normalised coordinates, so that unlike the pinhole
+/// model fields of view approaching 180 degrees can be imaged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KannalaBrandt {
+    /// The intrinsics of the fisheye camera
+    pub intrinsics: CameraIntrinsics,
+
+    /// Distortion coefficients `k1` to `k4`
+    pub coeffs: [f64; 4]
+}
+
+// -----------------------------------------------------------------------------------------------
+// ENUMERATIONS
+// -----------------------------------------------------------------------------------------------
+
+/// The distortion models which rectification parameters can describe.
+///
+/// The names match those used by ROS `camera_info` messages.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DistortionModel {
+    /// The single coefficient division model of `cv-pinhole`, in which a distorted point is
+    /// undistorted by dividing it by `1 + k1·r²`
+    Division,
+
+    /// The five coefficient Brown-Conrady model using `k1`, `k2`, `k3`, `p1` and `p2`
+    PlumbBob,
+
+    /// The eight coefficient Brown-Conrady model, adding `k4`, `k5` and `k6`
+    RationalPolynomial,
+
+    /// The Kannala-Brandt fisheye model using `k1`, `k2`, `k3` and `k4`
+    Equidistant
+}
+
+/// A camera model with any of the supported distortion models, which can convert between image
+/// and normalised coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LensModel {
+    /// A distortion free pinhole camera
+    Pinhole(CameraIntrinsics),
+
+    /// A pinhole camera with single coefficient division distortion
+    K1(CameraIntrinsicsK1Distortion),
+
+    /// A pinhole camera with Brown-Conrady distortion
+    BrownConrady(BrownConrady),
+
+    /// A fisheye camera
+    KannalaBrandt(KannalaBrandt)
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl BrownConrady {
+
+    /// Apply the distortion to a point in normalised coordinates.
+    pub fn distort(&self, point: Point2<f64>) -> Point2<f64> {
+        let [k1, k2, k3, k4, k5, k6] = self.radial;
+        let [p1, p2] = self.tangential;
+        let (x, y) = (point.x, point.y);
+
+        let r2 = x * x + y * y;
+        let r4 = r2 * r2;
+        let r6 = r4 * r2;
+        let radial = (1.0 + k1 * r2 + k2 * r4 + k3 * r6) / (1.0 + k4 * r2 + k5 * r4 + k6 * r6);
+
+        Point2::new(
+            x * radial + 2.0 * p1 * x * y + p2 * (r2 + 2.0 * x * x),
+            y * radial + p1 * (r2 + 2.0 * y * y) + 2.0 * p2 * x * y
+        )
+    }
+
+    /// Remove the distortion from a point in normalised coordinates.
+    ///
+    /// The distortion has no closed form inverse, so this uses Newton's method starting from the
+    /// distorted point, which converges in a few iterations within the field of view of the
+    /// lens.
+    pub fn undistort(&self, point: Point2<f64>) -> Point2<f64> {
+        let mut estimate = point;
+
+        for _ in 0..UNDISTORT_MAX_ITERATIONS {
+            let residual = self.distort(estimate) - point;
+            if residual.norm() < UNDISTORT_TOLERANCE {
+                break;
+            }
+
+            let jacobian = self.jacobian(estimate);
+            let step = match jacobian.try_inverse() {
+                Some(inv) => inv * residual,
+                None => break
+            };
+
+            estimate -= step;
+        }
+
+        estimate
+    }
+
+    /// Finite difference Jacobian of the distortion at the given point.
+    fn jacobian(&self, point: Point2<f64>) -> Matrix2<f64> {
+        let dx = Vector2::new(JACOBIAN_STEP, 0.0);
+        let dy = Vector2::new(0.0, JACOBIAN_STEP);
+
+        let col_x = (self.distort(point + dx) - self.distort(point - dx)) / (2.0 * JACOBIAN_STEP);
+        let col_y = (self.distort(point + dy) - self.distort(point - dy)) / (2.0 * JACOBIAN_STEP);
+
+        Matrix2::from_columns(&[col_x, col_y])
+    }
+}
+
+impl CameraModel for BrownConrady {
+    type Projection = NormalizedKeyPoint;
+
+    fn calibrate<P>(&self, point: P) -> NormalizedKeyPoint
+    where
+        P: ImagePoint
+    {
+        let distorted = self.intrinsics.calibrate(point);
+
+        NormalizedKeyPoint(self.undistort(distorted.0))
+    }
+
+    fn uncalibrate(&self, projection: NormalizedKeyPoint) -> KeyPoint {
+        self.intrinsics.uncalibrate(NormalizedKeyPoint(self.distort(projection.0)))
+    }
+}
+
+impl KannalaBrandt {
+
+    /// Get the distorted angle of a ray at the given angle from the optical axis.
+    fn distort_theta(&self, theta: f64) -> f64 {
+        let [k1, k2, k3, k4] = self.coeffs;
+        let t2 = theta * theta;
+
+        theta * (1.0 + t2 * (k1 + t2 * (k2 + t2 * (k3 + t2 * k4))))
+    }
+
+    /// Apply the distortion to a point in undistorted normalised coordinates.
+    pub fn distort(&self, point: Point2<f64>) -> Point2<f64> {
+        let r = point.coords.norm();
+        if r < UNDISTORT_TOLERANCE {
+            return point;
+        }
+
+        let theta_d = self.distort_theta(r.atan());
+
+        Point2::from(point.coords * (theta_d / r))
+    }
+
+    /// Remove the distortion from a point in distorted normalised coordinates.
+    ///
+    /// The angle from the optical axis is found with Newton's method. Points beyond 90 degrees
+    /// cannot be represented in undistorted normalised coordinates, so are clamped to just short
+    /// of it.
+    pub fn undistort(&self, point: Point2<f64>) -> Point2<f64> {
+        let theta_d = point.coords.norm();
+        if theta_d < UNDISTORT_TOLERANCE {
+            return point;
+        }
+
+        let [k1, k2, k3, k4] = self.coeffs;
+        let mut theta = theta_d;
+
+        for _ in 0..UNDISTORT_MAX_ITERATIONS {
+            let residual = self.distort_theta(theta) - theta_d;
+            if residual.abs() < UNDISTORT_TOLERANCE {
+                break;
+            }
+
+            let t2 = theta * theta;
+            let slope = 1.0 + t2 * (3.0 * k1 + t2 * (5.0 * k2 + t2 * (7.0 * k3 + t2 * 9.0 * k4)));
+            if slope.abs() < UNDISTORT_TOLERANCE {
+                break;
+            }
+
+            theta -= residual / slope;
+        }
+
+        let theta = theta.clamp(0.0, MAX_FISHEYE_THETA);
+
+        Point2::from(point.coords * (theta.tan() / theta_d))
+    }
+}
+
+impl CameraModel for KannalaBrandt {
+    type Projection = NormalizedKeyPoint;
+
+    fn calibrate<P>(&self, point: P) -> NormalizedKeyPoint
+    where
+        P: ImagePoint
+    {
+        let distorted = self.intrinsics.calibrate(point);
+
+        NormalizedKeyPoint(self.undistort(distorted.0))
+    }
+
+    fn uncalibrate(&self, projection: NormalizedKeyPoint) -> KeyPoint {
+        self.intrinsics.uncalibrate(NormalizedKeyPoint(self.distort(projection.0)))
+    }
+}
+
+impl CameraModel for LensModel {
+    type Projection = NormalizedKeyPoint;
+
+    fn calibrate<P>(&self, point: P) -> NormalizedKeyPoint
+    where
+        P: ImagePoint
+    {
+        match self {
+            LensModel::Pinhole(m) => m.calibrate(point),
+            LensModel::K1(m) => m.calibrate(point),
+            LensModel::BrownConrady(m) => m.calibrate(point),
+            LensModel::KannalaBrandt(m) => m.calibrate(point)
+        }
+    }
+
+    fn uncalibrate(&self, projection: NormalizedKeyPoint) -> KeyPoint {
+        match self {
+            LensModel::Pinhole(m) => m.uncalibrate(projection),
+            LensModel::K1(m) => m.uncalibrate(projection),
+            LensModel::BrownConrady(m) => m.uncalibrate(projection),
+            LensModel::KannalaBrandt(m) => m.uncalibrate(projection)
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Test that undistortion inverts distortion for the plumb bob and rational models
+    #[test]
+    fn test_undistort() {
+        let intrinsics = CameraIntrinsics {
+            focals: Vector2::new(600.0, 610.0),
+            principal_point: Point2::new(320.0, 240.0),
+            skew: 0.0
+        };
+
+        let models = [
+            BrownConrady {
+                intrinsics,
+                radial: [-0.28, 0.07, -0.01, 0.0, 0.0, 0.0],
+                tangential: [0.001, -0.0005]
+            },
+            BrownConrady {
+                intrinsics,
+                radial: [0.9, -0.2, 0.01, 1.2, -0.1, 0.02],
+                tangential: [0.0002, 0.0003]
+            }
+        ];
+
+        for model in models.iter() {
+            for (x, y) in [(0.0, 0.0), (0.3, -0.2), (-0.45, 0.35), (0.5, 0.38)].iter().copied() {
+                let point = Point2::new(x, y);
+                let round_trip = model.undistort(model.distort(point));
+
+                assert!((round_trip - point).norm() < 1e-9, "{:?} {:?}", model, point);
+
+                // And through pixel coordinates
+                let kp = model.uncalibrate(NormalizedKeyPoint(point));
+                assert!((model.calibrate(kp).0 - point).norm() < 1e-9);
+            }
+        }
+    }
+
+    /// Test the distortion of a point against the model equations evaluated by hand
+    #[test]
+    fn test_distort() {
+        let model = BrownConrady {
+            intrinsics: CameraIntrinsics::identity(),
+            radial: [-0.28, 0.07, -0.01, 0.0, 0.0, 0.0],
+            tangential: [0.001, -0.0005]
+        };
+
+        // r² = 0.13, R = 1 - 0.0364 + 0.001183 - 0.00002197
+        let distorted = model.distort(Point2::new(0.3, -0.2));
+        let radial = 1.0 - 0.28 * 0.13 + 0.07 * 0.0169 - 0.01 * 0.002197;
+
+        assert!((distorted.x - (0.3 * radial - 0.00012 - 0.0005 * 0.31)).abs() < 1e-12);
+        assert!((distorted.y - (-0.2 * radial + 0.001 * 0.21 + 0.00006)).abs() < 1e-12);
+    }
+
+    /// Test that fisheye undistortion inverts distortion out to wide angles
+    #[test]
+    fn test_fisheye() {
+        let model = KannalaBrandt {
+            intrinsics: CameraIntrinsics {
+                focals: Vector2::new(285.0, 285.5),
+                principal_point: Point2::new(424.0, 400.0),
+                skew: 0.0
+            },
+            coeffs: [-0.006, 0.04, -0.037, 0.006]
+        };
+
+        // Up to about 80 degrees from the optical axis
+        for (x, y) in [(0.0, 0.0), (0.2, -0.1), (-1.5, 2.0), (4.0, 3.5)].iter().copied() {
+            let point = Point2::new(x, y);
+            let round_trip = model.undistort(model.distort(point));
+
+            assert!((round_trip - point).norm() < 1e-6 * (1.0 + point.coords.norm()));
+        }
+
+        // A point on a ray at 60 degrees is imaged at the distorted angle
+        let point = Point2::new((60.0f64).to_radians().tan(), 0.0);
+        let theta = (60.0f64).to_radians();
+        let theta_d = theta * (1.0 - 0.006 * theta.powi(2) + 0.04 * theta.powi(4)
+            - 0.037 * theta.powi(6) + 0.006 * theta.powi(8));
+
+        assert!((model.distort(point).x - theta_d).abs() < 1e-12);
+    }
+}
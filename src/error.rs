@@ -6,7 +6,10 @@
 // IMPORTS
 // -----------------------------------------------------------------------------------------------
 
-use std::path::PathBuf;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use crate::distortion::DistortionModel;
 
 use serde_any;
 use thiserror;
@@ -27,6 +30,9 @@ pub enum Error {
     #[error("Error deserialising data: {0}")]
     DeserialisationError(serde_any::Error),
 
+    #[error("Error serialising data: {0}")]
+    SerialisationError(serde_any::Error),
+
     #[error(
         "Cannot convert RectifParams to CameraIntrisics struct as this would discard the \
         RectifParams::k1 value which is {0:?}"
@@ -39,6 +45,24 @@ pub enum Error {
     )]
     RectifToCamIntrisicsK1DistortionError,
 
+    #[error(
+        "Cannot convert RectifParams to the requested camera model as it uses the {0:?} \
+        distortion model"
+    )]
+    RectifDistortionModelError(DistortionModel),
+
+    #[error("Error reading calibration file: {0}")]
+    CalibrationFileError(String),
+
+    #[error("The calibration file uses an unsupported distortion model: {0}")]
+    UnsupportedDistortionModel(String),
+
+    #[error("Error calibrating camera: {0}")]
+    CalibrationError(String),
+
+    #[error("The ArUco dictionary is invalid: {0}")]
+    InvalidDictionary(String),
+
     #[error("Error capturing camera image: {0}")]
     CameraCaptureError(std::io::Error),
 
@@ -51,9 +75,39 @@ pub enum Error {
     #[error("Error starting the camera stream: {0}")]
     CamStartError(rscam::Error),
 
-    #[error("Provided FourCC image format code ({0}) is not supported by the image library")]
+    #[error("Error stopping the camera stream: {0}")]
+    CamStopError(std::io::Error),
+
+    #[error("Cannot capture from a camera which has not been started")]
+    CamNotStartedError,
+
+    #[error("The end of the stream has been reached")]
+    EndOfStream,
+
+    #[error("Cannot get the timestamp of {0:?}: {1}")]
+    TimestampError(PathBuf, String),
+
+    #[error("Error reading playback images: {0}")]
+    PlaybackError(String),
+
+    #[error(
+        "The recorder's writer thread has fallen behind, {0} frames are already waiting to be \
+        written"
+    )]
+    RecorderOverflow(usize),
+
+    #[error("Error writing recording: {0}")]
+    RecordingError(String),
+
+    #[error("Error reading raw frame container: {0}")]
+    RawContainerError(String),
+
+    #[error("Provided FourCC image format code ({0}) is not supported")]
     ImageFormatError(String),
 
+    #[error("Cannot decode frame: {0}")]
+    FrameDecodeError(String),
+
     #[error("Error recieving message from thread: {0}")]
     ChannelReceiveError(std::sync::mpsc::RecvError),
 
@@ -63,3 +117,26 @@ pub enum Error {
     #[error("Error while joining a thread")]
     ThreadJoinError
 }
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Error {
+    /// Build an [`Error::RecordingError`] describing a failure to write to the given path.
+    pub(crate) fn recording<E: Display>(path: &Path, e: E) -> Self {
+        Error::RecordingError(format!("{:?}: {}", path, e))
+    }
+
+    /// Build an [`Error::PlaybackError`] describing a failure to read the file or directory at the
+    /// given path.
+    pub(crate) fn playback<E: Display>(path: &Path, e: E) -> Self {
+        Error::PlaybackError(format!("{:?}: {}", path, e))
+    }
+
+    /// Build an [`Error::RawContainerError`] describing a failure to read the container at the
+    /// given path.
+    pub(crate) fn raw_container<E: Display>(path: &Path, e: E) -> Self {
+        Error::RawContainerError(format!("{:?}: {}", path, e))
+    }
+}
@@ -0,0 +1,705 @@
+//! # Pixel Format Module
+//!
+//! Describes the formats raw frames can be delivered in, and decodes them into the images used by
+//! the streams. Compressed formats are decoded by the `image` crate, uncompressed formats are
+//! unpacked directly from the frame data.
+//!
+//! YUV formats are converted to colour using the BT.601 limited range coefficients, which is the
+//! default colourspace of V4L2 devices, while their greyscale images are the unscaled Y channel.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use image::{ImageBuffer, ImageFormat, Luma, Rgb};
+
+use crate::error::{Error, Result};
+use crate::bayer::{demosaic, BayerPattern, DemosaicMethod};
+use crate::{GrayFloatImage, RgbFloatImage};
+
+// -----------------------------------------------------------------------------------------------
+// ENUMERATIONS
+// -----------------------------------------------------------------------------------------------
+
+/// The format of the data in a [`RawFrame`](crate::RawFrame).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// A compressed or self describing image decoded by the `image` crate, such as the JPEG
+    /// frames of an `MJPG` camera.
+    ///
+    /// JPEG has the FourCC `MJPG`. PNG, PNM, BMP and TIFF images, as produced by the playback and
+    /// synthetic backends, are given the FourCCs `PNG `, `PNM `, `BMP ` and `TIFF` so that they
+    /// can be stored in raw containers.
+    Encoded(ImageFormat),
+
+    /// Packed 4:2:2 YUV with the byte order `Y0 U Y1 V`, FourCC `YUYV`.
+    Yuyv,
+
+    /// 8 bit greyscale, FourCC `GREY`.
+    Grey,
+
+    /// 10 bit greyscale stored in the low bits of little endian 16 bit words, FourCC `Y10 `.
+    Y10,
+
+    /// 12 bit greyscale stored in the low bits of little endian 16 bit words, FourCC `Y12 `.
+    Y12,
+
+    /// 16 bit little endian greyscale, FourCC `Y16 `.
+    Y16,
+
+    /// 10 bit greyscale packed four pixels to five bytes as in MIPI CSI-2, FourCC `Y10P`.
+    Y10Packed,
+
+    /// 12 bit greyscale packed two pixels to three bytes as in MIPI CSI-2, FourCC `Y12P`.
+    Y12Packed,
+
+    /// 4:2:0 YUV with a Y plane followed by a plane of interleaved U and V samples, FourCC `NV12`.
+    Nv12,
+
+    /// 4:2:0 YUV with a Y plane followed by a plane of interleaved V and U samples, FourCC `NV21`.
+    Nv21,
+
+    /// 4:2:0 YUV with separate Y, U and V planes, FourCC `YU12`, also known as I420.
+    Yu12,
+
+    /// 4:2:0 YUV with separate Y, V and U planes, FourCC `YV12`.
+    Yv12,
+
+    /// Raw Bayer mosaic with the given colour filter arrangement, with 8 bit samples or samples
+    /// stored in the low `bits` of little endian 16 bit words.
+    ///
+    /// FourCCs `BA81`, `GBRG`, `GRBG` and `RGGB` for 8 bits, `BG10`, `GB10`, `BA10` and `RG10`
+    /// for 10 bits, `BG12`, `GB12`, `BA12` and `RG12` for 12 bits, and `BYR2`, `GB16`, `GR16`
+    /// and `RG16` for 16 bits.
+    Bayer {
+        /// Arrangement of the colour filters
+        pattern: BayerPattern,
+
+        /// Number of bits per sample
+        bits: u8
+    }
+}
+
+/// How the chroma samples of a 4:2:0 frame are arranged after the Y plane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChromaLayout {
+    /// A single plane of alternating chroma samples
+    Interleaved,
+
+    /// Two consecutive planes, one for each chroma channel
+    Planar
+}
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// The lengths of the rows of the planes of a 4:2:0 frame in bytes, including any padding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PlanarStrides {
+    /// Row stride of the Y plane
+    luma: usize,
+
+    /// Row stride of each chroma plane
+    chroma: usize
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl PixelFormat {
+    /// Get the format of frames with the given FourCC code, if they can be decoded.
+    pub fn from_fourcc(fourcc: &[u8]) -> Option<Self> {
+        match fourcc {
+            b"MJPG" => Some(PixelFormat::Encoded(ImageFormat::Jpeg)),
+            b"PNG " => Some(PixelFormat::Encoded(ImageFormat::Png)),
+            b"PNM " => Some(PixelFormat::Encoded(ImageFormat::Pnm)),
+            b"BMP " => Some(PixelFormat::Encoded(ImageFormat::Bmp)),
+            b"TIFF" => Some(PixelFormat::Encoded(ImageFormat::Tiff)),
+            b"YUYV" => Some(PixelFormat::Yuyv),
+            b"GREY" => Some(PixelFormat::Grey),
+            b"Y10 " => Some(PixelFormat::Y10),
+            b"Y12 " => Some(PixelFormat::Y12),
+            b"Y16 " => Some(PixelFormat::Y16),
+            b"Y10P" => Some(PixelFormat::Y10Packed),
+            b"Y12P" => Some(PixelFormat::Y12Packed),
+            b"NV12" => Some(PixelFormat::Nv12),
+            b"NV21" => Some(PixelFormat::Nv21),
+            b"YU12" => Some(PixelFormat::Yu12),
+            b"YV12" => Some(PixelFormat::Yv12),
+            b"BA81" => Some(bayer(BayerPattern::Bggr, 8)),
+            b"GBRG" => Some(bayer(BayerPattern::Gbrg, 8)),
+            b"GRBG" => Some(bayer(BayerPattern::Grbg, 8)),
+            b"RGGB" => Some(bayer(BayerPattern::Rggb, 8)),
+            b"BG10" => Some(bayer(BayerPattern::Bggr, 10)),
+            b"GB10" => Some(bayer(BayerPattern::Gbrg, 10)),
+            b"BA10" => Some(bayer(BayerPattern::Grbg, 10)),
+            b"RG10" => Some(bayer(BayerPattern::Rggb, 10)),
+            b"BG12" => Some(bayer(BayerPattern::Bggr, 12)),
+            b"GB12" => Some(bayer(BayerPattern::Gbrg, 12)),
+            b"BA12" => Some(bayer(BayerPattern::Grbg, 12)),
+            b"RG12" => Some(bayer(BayerPattern::Rggb, 12)),
+            b"BYR2" => Some(bayer(BayerPattern::Bggr, 16)),
+            b"GB16" => Some(bayer(BayerPattern::Gbrg, 16)),
+            b"GR16" => Some(bayer(BayerPattern::Grbg, 16)),
+            b"RG16" => Some(bayer(BayerPattern::Rggb, 16)),
+            _ => None
+        }
+    }
+
+    /// Get the FourCC code of this format, the inverse of [`PixelFormat::from_fourcc`], or `None`
+    /// for an encoded format without one.
+    pub fn fourcc(&self) -> Option<[u8; 4]> {
+        let fourcc = match *self {
+            PixelFormat::Encoded(ImageFormat::Jpeg) => b"MJPG",
+            PixelFormat::Encoded(ImageFormat::Png) => b"PNG ",
+            PixelFormat::Encoded(ImageFormat::Pnm) => b"PNM ",
+            PixelFormat::Encoded(ImageFormat::Bmp) => b"BMP ",
+            PixelFormat::Encoded(ImageFormat::Tiff) => b"TIFF",
+            PixelFormat::Encoded(_) => return None,
+            PixelFormat::Yuyv => b"YUYV",
+            PixelFormat::Grey => b"GREY",
+            PixelFormat::Y10 => b"Y10 ",
+            PixelFormat::Y12 => b"Y12 ",
+            PixelFormat::Y16 => b"Y16 ",
+            PixelFormat::Y10Packed => b"Y10P",
+            PixelFormat::Y12Packed => b"Y12P",
+            PixelFormat::Nv12 => b"NV12",
+            PixelFormat::Nv21 => b"NV21",
+            PixelFormat::Yu12 => b"YU12",
+            PixelFormat::Yv12 => b"YV12",
+            PixelFormat::Bayer { pattern, bits } => match (pattern, bits) {
+                (BayerPattern::Bggr, 8) => b"BA81",
+                (BayerPattern::Gbrg, 8) => b"GBRG",
+                (BayerPattern::Grbg, 8) => b"GRBG",
+                (BayerPattern::Rggb, 8) => b"RGGB",
+                (BayerPattern::Bggr, 10) => b"BG10",
+                (BayerPattern::Gbrg, 10) => b"GB10",
+                (BayerPattern::Grbg, 10) => b"BA10",
+                (BayerPattern::Rggb, 10) => b"RG10",
+                (BayerPattern::Bggr, 12) => b"BG12",
+                (BayerPattern::Gbrg, 12) => b"GB12",
+                (BayerPattern::Grbg, 12) => b"BA12",
+                (BayerPattern::Rggb, 12) => b"RG12",
+                (BayerPattern::Bggr, 16) => b"BYR2",
+                (BayerPattern::Gbrg, 16) => b"GB16",
+                (BayerPattern::Grbg, 16) => b"GR16",
+                (BayerPattern::Rggb, 16) => b"RG16",
+                _ => return None
+            }
+        };
+
+        Some(*fourcc)
+    }
+
+    /// Replace the colour filter arrangement of a Bayer format, other formats are unchanged.
+    ///
+    /// This is needed for sensors which report the wrong pattern, for example because their
+    /// readout is flipped.
+    pub fn with_bayer_pattern(self, pattern: BayerPattern) -> Self {
+        match self {
+            PixelFormat::Bayer { bits, .. } => PixelFormat::Bayer { pattern, bits },
+            other => other
+        }
+    }
+
+    /// Decode frame data in this format into a greyscale image with values between 0 and 1.
+    ///
+    /// Greyscale formats are normalised by the largest value of their bit depth, so a 10 bit
+    /// value of 1023 becomes 1, and Bayer formats are demosaiced and converted to luma. The
+    /// resolution is only used by uncompressed formats, encoded images carry their own.
+    pub fn decode_gray(&self, data: &[u8], resolution: (u32, u32)) -> Result<GrayFloatImage> {
+        match *self {
+            PixelFormat::Encoded(format) => {
+                let dyn_img = image::load_from_memory_with_format(data, format)
+                    .map_err(Error::ImageConversionError)?;
+
+                Ok(GrayFloatImage::from_dynamic(&dyn_img))
+            },
+            PixelFormat::Yuyv => decode_yuyv_luma(data, resolution),
+            PixelFormat::Grey => decode_grey(data, resolution),
+            PixelFormat::Y10 => decode_grey16(data, resolution, 10),
+            PixelFormat::Y12 => decode_grey16(data, resolution, 12),
+            PixelFormat::Y16 => decode_grey16(data, resolution, 16),
+            PixelFormat::Y10Packed => decode_y10_packed(data, resolution),
+            PixelFormat::Y12Packed => decode_y12_packed(data, resolution),
+            PixelFormat::Nv12 | PixelFormat::Nv21 => {
+                decode_planar_luma(data, resolution, ChromaLayout::Interleaved)
+            },
+            PixelFormat::Yu12 | PixelFormat::Yv12 => {
+                decode_planar_luma(data, resolution, ChromaLayout::Planar)
+            },
+            PixelFormat::Bayer { .. } => self.decode_rgb(data, resolution).map(|c| c.to_gray())
+        }
+    }
+
+    /// Decode frame data in this format into a colour image with values between 0 and 1.
+    ///
+    /// Greyscale formats produce an image with equal red, green and blue channels, Bayer formats
+    /// are demosaiced with [`DemosaicMethod::EdgeAware`].
+    pub fn decode_rgb(&self, data: &[u8], resolution: (u32, u32)) -> Result<RgbFloatImage> {
+        use ChromaLayout::{Interleaved, Planar};
+
+        match *self {
+            PixelFormat::Encoded(format) => {
+                let dyn_img = image::load_from_memory_with_format(data, format)
+                    .map_err(Error::ImageConversionError)?;
+
+                Ok(RgbFloatImage::from_dynamic(&dyn_img))
+            },
+            PixelFormat::Yuyv => decode_yuyv_rgb(data, resolution),
+            PixelFormat::Nv12 => decode_planar_rgb(data, resolution, Interleaved, false),
+            PixelFormat::Nv21 => decode_planar_rgb(data, resolution, Interleaved, true),
+            PixelFormat::Yu12 => decode_planar_rgb(data, resolution, Planar, false),
+            PixelFormat::Yv12 => decode_planar_rgb(data, resolution, Planar, true),
+            PixelFormat::Bayer { pattern, bits } => {
+                let mosaic = match bits {
+                    8 => decode_grey(data, resolution)?,
+                    _ => decode_grey16(data, resolution, bits as u32)?
+                };
+
+                Ok(demosaic(&mosaic, pattern, DemosaicMethod::EdgeAware))
+            },
+            PixelFormat::Grey
+            | PixelFormat::Y10
+            | PixelFormat::Y12
+            | PixelFormat::Y16
+            | PixelFormat::Y10Packed
+            | PixelFormat::Y12Packed => {
+                self.decode_gray(data, resolution).map(|g| RgbFloatImage::from_gray(&g))
+            }
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// PRIVATE FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Decode an uncompressed frame row by row.
+///
+/// Each row is `row_bytes` long, and may be followed by padding added by the driver. The
+/// `decode_row` function must append at least `width` pixels to the output for each row, any
+/// extra pixels produced by incomplete pixel groups at the end of the row are discarded.
+fn decode_rows<F>(
+    data: &[u8],
+    resolution: (u32, u32),
+    row_bytes: usize,
+    decode_row: F
+) -> Result<GrayFloatImage>
+where
+    F: Fn(&[u8], &mut Vec<f32>)
+{
+    let (width, height) = (resolution.0 as usize, resolution.1 as usize);
+
+    if height == 0 || data.len() < row_bytes * height {
+        return Err(Error::FrameDecodeError(format!(
+            "{} bytes is too small for a {}x{} frame",
+            data.len(), width, height
+        )));
+    }
+
+    let stride = (data.len() / height).max(row_bytes);
+    let mut pixels = Vec::with_capacity(width * height);
+
+    for row in data.chunks(stride).take(height) {
+        let row_start = pixels.len();
+
+        decode_row(&row[..row_bytes], &mut pixels);
+        pixels.truncate(row_start + width);
+    }
+
+    Ok(GrayFloatImage(
+        ImageBuffer::from_raw(resolution.0, resolution.1, pixels)
+            .expect("Decoded frame did not produce enough pixels")
+    ))
+}
+
+/// Extract the luma channel of a YUYV frame.
+fn decode_yuyv_luma(data: &[u8], resolution: (u32, u32)) -> Result<GrayFloatImage> {
+    decode_rows(data, resolution, resolution.0 as usize * 2, |row, out| {
+        out.extend(row.iter().step_by(2).map(|&y| f32::from(y) / 255.0))
+    })
+}
+
+/// Shorthand for a Bayer format.
+fn bayer(pattern: BayerPattern, bits: u8) -> PixelFormat {
+    PixelFormat::Bayer { pattern, bits }
+}
+
+/// Convert a BT.601 limited range YUV sample into RGB.
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> Rgb<f32> {
+    let y = 1.164 * (f32::from(y) - 16.0);
+    let u = f32::from(u) - 128.0;
+    let v = f32::from(v) - 128.0;
+
+    Rgb([
+        ((y + 1.596 * v) / 255.0).clamp(0.0, 1.0),
+        ((y - 0.392 * u - 0.813 * v) / 255.0).clamp(0.0, 1.0),
+        ((y + 2.017 * u) / 255.0).clamp(0.0, 1.0)
+    ])
+}
+
+/// Convert a YUYV frame into RGB, sharing each pair's chroma between both pixels.
+fn decode_yuyv_rgb(data: &[u8], resolution: (u32, u32)) -> Result<RgbFloatImage> {
+    let width = resolution.0 as usize;
+
+    // Reuse the luma decoder to validate the frame size before indexing into it
+    decode_yuyv_luma(data, resolution)?;
+    let stride = data.len() / resolution.1 as usize;
+
+    Ok(RgbFloatImage(ImageBuffer::from_fn(resolution.0, resolution.1, |x, y| {
+        let (x, y) = (x as usize, y as usize);
+        let row = &data[y * stride..][..width * 2];
+        let pair = (x / 2) * 4;
+
+        // A final unpaired pixel has no V sample
+        yuv_to_rgb(row[x * 2], row[pair + 1], row.get(pair + 3).copied().unwrap_or(128))
+    })))
+}
+
+/// Get the row strides of the planes of a 4:2:0 frame, checking the frame is large enough.
+///
+/// As for the packed formats, the rows may be padded by the driver and the stride is inferred
+/// from the length of the frame. An interleaved chroma plane has the stride of the Y plane, and
+/// separate chroma planes half of it, as their rows hold half as many bytes.
+fn planar_strides(
+    data: &[u8],
+    resolution: (u32, u32),
+    layout: ChromaLayout
+) -> Result<PlanarStrides> {
+    let (width, height) = (resolution.0 as usize, resolution.1 as usize);
+    let chroma_rows = height.div_ceil(2);
+    let (planes, chroma_bytes) = match layout {
+        ChromaLayout::Interleaved => (1, 2 * width.div_ceil(2)),
+        ChromaLayout::Planar => (2, width.div_ceil(2))
+    };
+
+    let luma = (data.len() / (height + chroma_rows).max(1)).max(width);
+    let chroma = match layout {
+        ChromaLayout::Interleaved => luma,
+        ChromaLayout::Planar => luma / 2
+    }.max(chroma_bytes);
+
+    if height == 0 || data.len() < luma * height + planes * chroma * chroma_rows {
+        return Err(Error::FrameDecodeError(format!(
+            "{} bytes is too small for a {}x{} 4:2:0 frame",
+            data.len(), width, height
+        )));
+    }
+
+    Ok(PlanarStrides { luma, chroma })
+}
+
+/// Extract the Y plane of a 4:2:0 frame.
+fn decode_planar_luma(
+    data: &[u8],
+    resolution: (u32, u32),
+    layout: ChromaLayout
+) -> Result<GrayFloatImage> {
+    let strides = planar_strides(data, resolution, layout)?;
+
+    Ok(GrayFloatImage(ImageBuffer::from_fn(resolution.0, resolution.1, |x, y| {
+        Luma([f32::from(data[y as usize * strides.luma + x as usize]) / 255.0])
+    })))
+}
+
+/// Convert a 4:2:0 frame into RGB, with the chroma arranged according to `layout`.
+///
+/// If `swap_uv` is true the V samples come before the U samples.
+fn decode_planar_rgb(
+    data: &[u8],
+    resolution: (u32, u32),
+    layout: ChromaLayout,
+    swap_uv: bool
+) -> Result<RgbFloatImage> {
+    let strides = planar_strides(data, resolution, layout)?;
+    let height = resolution.1 as usize;
+    let chroma_len = strides.chroma * height.div_ceil(2);
+
+    let (luma, chroma) = data.split_at(strides.luma * height);
+
+    Ok(RgbFloatImage(ImageBuffer::from_fn(resolution.0, resolution.1, |x, y| {
+        let (x, y) = (x as usize, y as usize);
+        let row = (y / 2) * strides.chroma;
+
+        let (first, second) = match layout {
+            ChromaLayout::Interleaved => (chroma[row + 2 * (x / 2)], chroma[row + 2 * (x / 2) + 1]),
+            ChromaLayout::Planar => (chroma[row + x / 2], chroma[chroma_len + row + x / 2])
+        };
+
+        let (u, v) = if swap_uv { (second, first) } else { (first, second) };
+
+        yuv_to_rgb(luma[y * strides.luma + x], u, v)
+    })))
+}
+
+/// Decode an 8 bit greyscale frame.
+fn decode_grey(data: &[u8], resolution: (u32, u32)) -> Result<GrayFloatImage> {
+    decode_rows(data, resolution, resolution.0 as usize, |row, out| {
+        out.extend(row.iter().map(|&y| f32::from(y) / 255.0))
+    })
+}
+
+/// Decode a greyscale frame with each pixel stored in the low `bits` of a little endian 16 bit
+/// word.
+fn decode_grey16(data: &[u8], resolution: (u32, u32), bits: u32) -> Result<GrayFloatImage> {
+    let max = ((1u32 << bits) - 1) as f32;
+
+    decode_rows(data, resolution, resolution.0 as usize * 2, |row, out| {
+        out.extend(row
+            .chunks_exact(2)
+            .map(|p| f32::from(u16::from_le_bytes([p[0], p[1]])) / max)
+        )
+    })
+}
+
+/// Decode a MIPI CSI-2 packed 10 bit greyscale frame, in which each group of four pixels is
+/// stored as their high 8 bits followed by a byte holding their low 2 bits.
+fn decode_y10_packed(data: &[u8], resolution: (u32, u32)) -> Result<GrayFloatImage> {
+    let row_bytes = (resolution.0 as usize).div_ceil(4) * 5;
+
+    decode_rows(data, resolution, row_bytes, |row, out| {
+        for group in row.chunks_exact(5) {
+            out.extend((0..4).map(|i| {
+                let value = (u16::from(group[i]) << 2) | ((u16::from(group[4]) >> (2 * i)) & 0x3);
+
+                f32::from(value) / 1023.0
+            }))
+        }
+    })
+}
+
+/// Decode a MIPI CSI-2 packed 12 bit greyscale frame, in which each pair of pixels is stored as
+/// their high 8 bits followed by a byte holding their low 4 bits.
+fn decode_y12_packed(data: &[u8], resolution: (u32, u32)) -> Result<GrayFloatImage> {
+    let row_bytes = (resolution.0 as usize).div_ceil(2) * 3;
+
+    decode_rows(data, resolution, row_bytes, |row, out| {
+        for group in row.chunks_exact(3) {
+            out.extend((0..2).map(|i| {
+                let value = (u16::from(group[i]) << 4) | ((u16::from(group[2]) >> (4 * i)) & 0xF);
+
+                f32::from(value) / 4095.0
+            }))
+        }
+    })
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Test that the luma of a YUYV frame is extracted and normalised, including with padded rows
+    #[test]
+    fn test_yuyv() {
+        let data = [
+            0, 10, 255, 20,  51, 30, 102, 40,
+            204, 50, 153, 60,  0, 70, 255, 80
+        ];
+
+        let img = PixelFormat::Yuyv.decode_gray(&data, (4, 2)).unwrap();
+        assert_eq!(img.as_raw(), &vec![0.0, 1.0, 0.2, 0.4, 0.8, 0.6, 0.0, 1.0]);
+
+        let padded = [
+            0, 10, 255, 20,  1, 2,
+            204, 50, 153, 60,  1, 2
+        ];
+
+        let img = PixelFormat::Yuyv.decode_gray(&padded, (2, 2)).unwrap();
+        assert_eq!(img.as_raw(), &vec![0.0, 1.0, 0.8, 0.6]);
+
+        match PixelFormat::Yuyv.decode_gray(&data, (4, 4)) {
+            Err(Error::FrameDecodeError(_)) => (),
+            _ => panic!("Expected a FrameDecodeError for a short frame")
+        }
+    }
+
+    /// Test that greyscale formats are normalised according to their bit depth
+    #[test]
+    fn test_grey() {
+        let img = PixelFormat::Grey.decode_gray(&[0, 51, 255, 102], (2, 2)).unwrap();
+        assert_eq!(img.as_raw(), &vec![0.0, 0.2, 1.0, 0.4]);
+
+        let words = [0x00, 0x00, 0xFF, 0x03, 0xFF, 0x0F, 0xFF, 0xFF];
+
+        let img = PixelFormat::Y10.decode_gray(&words[..4], (2, 1)).unwrap();
+        assert_eq!(img.as_raw(), &vec![0.0, 1.0]);
+
+        let img = PixelFormat::Y12.decode_gray(&words[2..6], (1, 2)).unwrap();
+        assert_eq!(img.as_raw(), &vec![1023.0 / 4095.0, 1.0]);
+
+        let img = PixelFormat::Y16.decode_gray(&words, (4, 1)).unwrap();
+        assert_eq!(img.as_raw(), &vec![0.0, 1023.0 / 65535.0, 4095.0 / 65535.0, 1.0]);
+    }
+
+    /// Test that packed greyscale formats are unpacked, including incomplete groups
+    #[test]
+    fn test_grey_packed() {
+        // Pixels 0, 1023, 512 and 1, then a row with only two of four pixels used
+        let y10 = [
+            0x00, 0xFF, 0x80, 0x00, 0b01_00_11_00,
+            0xFF, 0x00, 0x00, 0x00, 0b00_00_00_11
+        ];
+
+        let img = PixelFormat::Y10Packed.decode_gray(&y10, (2, 2)).unwrap();
+        assert_eq!(img.as_raw(), &vec![0.0, 1.0, 1.0, 0.0]);
+
+        let img = PixelFormat::Y10Packed.decode_gray(&y10[..5], (4, 1)).unwrap();
+        assert_eq!(img.as_raw(), &vec![0.0, 1.0, 512.0 / 1023.0, 1.0 / 1023.0]);
+
+        // Pixels 4095, 1, 2048 and 0
+        let y12 = [0xFF, 0x00, 0x1F, 0x80, 0x00, 0x00];
+
+        let img = PixelFormat::Y12Packed.decode_gray(&y12, (4, 1)).unwrap();
+        assert_eq!(img.as_raw(), &vec![1.0, 1.0 / 4095.0, 2048.0 / 4095.0, 0.0]);
+
+        match PixelFormat::Y12Packed.decode_gray(&y12, (4, 2)) {
+            Err(Error::FrameDecodeError(_)) => (),
+            _ => panic!("Expected a FrameDecodeError for a short frame")
+        }
+    }
+
+    /// Test that the 4:2:0 formats decode their Y plane and share chroma between 2x2 blocks
+    #[test]
+    fn test_planar() {
+        let luma = [235, 235, 81, 81, 16, 16, 81, 81];
+        let (u, v) = ([128, 90], [128, 240]);
+
+        let frames = [
+            (PixelFormat::Nv12, [u[0], v[0], u[1], v[1]]),
+            (PixelFormat::Nv21, [v[0], u[0], v[1], u[1]]),
+            (PixelFormat::Yu12, [u[0], u[1], v[0], v[1]]),
+            (PixelFormat::Yv12, [v[0], v[1], u[0], u[1]])
+        ];
+
+        // The same frames with each row padded to 8 bytes, and each separate chroma plane to 4
+        let padded_luma = [&luma[..4], &[0xEE; 4], &luma[4..], &[0xEE; 4]].concat();
+        let padded_chroma = |format: &PixelFormat, chroma: &[u8]| match format {
+            PixelFormat::Nv12 | PixelFormat::Nv21 => [chroma, &[0xEE; 4]].concat(),
+            _ => [&chroma[..2], &[0xEE; 2], &chroma[2..], &[0xEE; 2]].concat()
+        };
+
+        let datas = frames.iter().flat_map(|(format, chroma)| vec![
+            (format, [&luma[..], &chroma[..]].concat()),
+            (format, [&padded_luma[..], &padded_chroma(format, chroma)[..]].concat())
+        ]);
+
+        for (format, data) in datas {
+            let gray = format.decode_gray(&data, (4, 2)).unwrap();
+            assert_eq!(gray.get(0, 0), 235.0 / 255.0);
+            assert_eq!(gray.get(0, 1), 16.0 / 255.0);
+
+            // White and black on the left, red on the right
+            let rgb = format.decode_rgb(&data, (4, 2)).unwrap();
+            assert!(rgb.get(1, 0).iter().all(|&c| c > 0.99));
+            assert!(rgb.get(1, 1).iter().all(|&c| c < 0.01));
+
+            for (x, y) in [(2, 0), (3, 0), (3, 1)].iter() {
+                let red = rgb.get(*x, *y);
+
+                assert!(red[0] > 0.99 && red[1] < 0.01 && red[2] < 0.01, "{:?}", format);
+            }
+
+            match format.decode_gray(&data[..9], (4, 2)) {
+                Err(Error::FrameDecodeError(_)) => (),
+                _ => panic!("Expected a FrameDecodeError for a short frame")
+            }
+        }
+    }
+
+    /// Test that Bayer FourCCs are recognised and decoded at their bit depth
+    #[test]
+    fn test_bayer() {
+        assert_eq!(
+            PixelFormat::from_fourcc(b"BA10"),
+            Some(PixelFormat::Bayer { pattern: BayerPattern::Grbg, bits: 10 })
+        );
+        assert_eq!(
+            PixelFormat::from_fourcc(b"BA81").unwrap().with_bayer_pattern(BayerPattern::Rggb),
+            PixelFormat::Bayer { pattern: BayerPattern::Rggb, bits: 8 }
+        );
+        assert_eq!(PixelFormat::Grey.with_bayer_pattern(BayerPattern::Rggb), PixelFormat::Grey);
+
+        // A 4x2 RGGB frame of pure red in 12 bit samples
+        let row_rg = [0xFF, 0x0F, 0, 0, 0xFF, 0x0F, 0, 0];
+        let row_gb = [0u8; 8];
+        let data = [&row_rg[..], &row_gb[..]].concat();
+
+        let rgb = PixelFormat::from_fourcc(b"RG12").unwrap().decode_rgb(&data, (4, 2)).unwrap();
+        for y in 0..2 {
+            for x in 0..4 {
+                assert_eq!(rgb.get(x, y), [1.0, 0.0, 0.0]);
+            }
+        }
+
+        let gray = PixelFormat::from_fourcc(b"RG12").unwrap().decode_gray(&data, (4, 2)).unwrap();
+        assert!((gray.get(1, 1) - 0.2126).abs() < 1e-6);
+    }
+
+    /// Test that every format with a FourCC maps back to the same code
+    #[test]
+    fn test_fourcc_round_trip() {
+        let codes: [&[u8; 4]; 32] = [
+            b"MJPG", b"PNG ", b"PNM ", b"BMP ", b"TIFF", b"YUYV", b"GREY", b"Y10 ", b"Y12 ",
+            b"Y16 ", b"Y10P", b"Y12P", b"NV12", b"NV21", b"YU12", b"YV12", b"BA81", b"GBRG",
+            b"GRBG", b"RGGB", b"BG10", b"GB10", b"BA10", b"RG10", b"BG12", b"GB12", b"BA12",
+            b"RG12", b"BYR2", b"GB16", b"GR16", b"RG16"
+        ];
+
+        for code in codes.iter() {
+            assert_eq!(PixelFormat::from_fourcc(*code).unwrap().fourcc(), Some(**code));
+        }
+        assert_eq!(PixelFormat::Encoded(ImageFormat::Gif).fourcc(), None);
+    }
+
+    /// Test that YUYV frames are decoded by both mono and stereo streams
+    #[test]
+    fn test_yuyv_streams() {
+        use crate::{CamStream, CamStreamBuilder, RawFrame, RawFrameWriter, RawReplaySource};
+
+        let path = std::env::temp_dir().join("cv_camstream_yuyv_streams.cvraw");
+
+        // Record frames whose luma is the frame index, with constant chroma
+        let mut writer = RawFrameWriter::create(&path, *b"YUYV", (8, 4)).unwrap();
+        for i in 0..3u8 {
+            writer.write(&RawFrame {
+                data: [i * 100, 128].repeat(8 * 4),
+                format: PixelFormat::Yuyv,
+                resolution: (8, 4),
+                timestamp: i as u64
+            }).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut mono = CamStreamBuilder::new()
+            .mono()
+            .replay_raw(RawReplaySource::new(&path))
+            .build()
+            .expect("Cannot build mono stream");
+
+        let mut stereo = CamStreamBuilder::new()
+            .stereo()
+            .replay_raw(RawReplaySource::new(&path), RawReplaySource::new(&path))
+            .build()
+            .expect("Cannot build stereo stream");
+
+        for i in 0..3u8 {
+            let expected = vec![f32::from(i * 100) / 255.0; 8 * 4];
+
+            assert_eq!(mono.capture().unwrap().as_raw(), &expected);
+
+            let frame = stereo.capture().unwrap();
+            assert_eq!(frame.left.as_raw(), &expected);
+            assert_eq!(frame.right.as_raw(), &expected);
+        }
+
+        stereo.stop().expect("Cannot stop stereo stream");
+    }
+}
@@ -1,5 +1,5 @@
 use derive_more::{Deref, DerefMut};
-use image::{imageops, DynamicImage, ImageBuffer, Luma};
+use image::{imageops, DynamicImage, GenericImageView, ImageBuffer, Luma, Rgb};
 use log::*;
 use ndarray::{Array2, ArrayView2, ArrayViewMut2};
 use nshare::{MutNdarray2, RefNdarray2};
@@ -136,6 +136,162 @@ impl GrayFloatImage {
 
         DynamicImage::ImageLuma8(new)
     }
+
+    /// Converts the image into a dynamic Luma16 image.
+    ///
+    /// Values from 8 and 16 bit sources survive a round trip through `from_dynamic` exactly.
+    pub fn to_dynamic_luma16(&self) -> DynamicImage {
+        DynamicImage::ImageLuma16(ImageBuffer::from_fn(
+            self.width() as u32,
+            self.height() as u32,
+            |x, y| {
+                let val = self.get(x as usize, y as usize).clamp(0.0, 1.0);
+
+                Luma([(val * 65535.0).round() as u16])
+            }
+        ))
+    }
+}
+
+/// The colour image type we use in this library.
+///
+/// Like [`GrayFloatImage`] this wraps a contiguous f32 vector, holding interleaved red, green and
+/// blue values between 0 and 1.
+#[derive(Debug, Clone, Deref, DerefMut)]
+pub struct RgbFloatImage(pub ImageBuffer<Rgb<f32>, Vec<f32>>);
+
+impl RgbFloatImage {
+    /// Create a unit float colour image from the image crate's DynamicImage type.
+    ///
+    /// 16 bit images keep their full precision, all other images are converted to 8 bit RGB.
+    pub fn from_dynamic(input_image: &DynamicImage) -> Self {
+        Self(match input_image {
+            DynamicImage::ImageLuma16(gray_image) => {
+                ImageBuffer::from_fn(gray_image.width(), gray_image.height(), |x, y| {
+                    Rgb([f32::from(gray_image[(x, y)][0]) / 65535f32; 3])
+                })
+            }
+            DynamicImage::ImageRgb16(rgb_image) => {
+                ImageBuffer::from_fn(rgb_image.width(), rgb_image.height(), |x, y| {
+                    let p = rgb_image[(x, y)];
+
+                    Rgb([
+                        f32::from(p[0]) / 65535f32,
+                        f32::from(p[1]) / 65535f32,
+                        f32::from(p[2]) / 65535f32
+                    ])
+                })
+            }
+            _ => {
+                let (width, height) = input_image.dimensions();
+
+                ImageBuffer::from_fn(width, height, |x, y| {
+                    let p = input_image.get_pixel(x, y);
+
+                    Rgb([
+                        f32::from(p[0]) / 255f32,
+                        f32::from(p[1]) / 255f32,
+                        f32::from(p[2]) / 255f32
+                    ])
+                })
+            }
+        })
+    }
+
+    /// Create a colour image with all three channels equal to the given greyscale image.
+    pub fn from_gray(gray: &GrayFloatImage) -> Self {
+        Self(ImageBuffer::from_fn(gray.width() as u32, gray.height() as u32, |x, y| {
+            Rgb([gray.get(x as usize, y as usize); 3])
+        }))
+    }
+
+    pub fn width(&self) -> usize {
+        self.0.width() as usize
+    }
+
+    pub fn height(&self) -> usize {
+        self.0.height() as usize
+    }
+
+    pub fn new(width: usize, height: usize) -> Self {
+        Self(ImageBuffer::from_pixel(
+            width as u32,
+            height as u32,
+            Rgb([0.0; 3]),
+        ))
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> [f32; 3] {
+        self.get_pixel(x as u32, y as u32).0
+    }
+
+    pub fn put(&mut self, x: usize, y: usize, pixel_value: [f32; 3]) {
+        self.put_pixel(x as u32, y as u32, Rgb(pixel_value));
+    }
+
+    /// Get a single channel of the image, 0 for red, 1 for green and 2 for blue.
+    pub fn channel(&self, channel: usize) -> GrayFloatImage {
+        GrayFloatImage(ImageBuffer::from_fn(self.0.width(), self.0.height(), |x, y| {
+            Luma([self.get_pixel(x, y)[channel]])
+        }))
+    }
+
+    /// Create a colour image from separate red, green and blue channels of the same size.
+    pub fn from_channels(
+        red: &GrayFloatImage,
+        green: &GrayFloatImage,
+        blue: &GrayFloatImage
+    ) -> Self {
+        Self(ImageBuffer::from_fn(red.width() as u32, red.height() as u32, |x, y| {
+            let (x, y) = (x as usize, y as usize);
+
+            Rgb([red.get(x, y), green.get(x, y), blue.get(x, y)])
+        }))
+    }
+
+    /// Converts the image into a greyscale image using the Rec. 709 luma weights, matching the
+    /// image crate's `grayscale`.
+    pub fn to_gray(&self) -> GrayFloatImage {
+        GrayFloatImage(ImageBuffer::from_fn(self.0.width(), self.0.height(), |x, y| {
+            let p = self.get_pixel(x, y);
+
+            Luma([0.2126 * p[0] + 0.7152 * p[1] + 0.0722 * p[2]])
+        }))
+    }
+
+    /// Converts the image into a dynamic Rgb16 image.
+    pub fn to_dynamic_rgb16(&self) -> DynamicImage {
+        DynamicImage::ImageRgb16(ImageBuffer::from_fn(
+            self.0.width(),
+            self.0.height(),
+            |x, y| {
+                let p = self.get_pixel(x, y);
+
+                Rgb([
+                    (p[0].clamp(0.0, 1.0) * 65535.0).round() as u16,
+                    (p[1].clamp(0.0, 1.0) * 65535.0).round() as u16,
+                    (p[2].clamp(0.0, 1.0) * 65535.0).round() as u16
+                ])
+            }
+        ))
+    }
+
+    /// Converts the image into a dynamic Rgb8 image.
+    pub fn to_dynamic_rgb8(&self) -> DynamicImage {
+        DynamicImage::ImageRgb8(ImageBuffer::from_fn(
+            self.0.width(),
+            self.0.height(),
+            |x, y| {
+                let p = self.get_pixel(x, y);
+
+                Rgb([
+                    (p[0].clamp(0.0, 1.0) * 255.0).round() as u8,
+                    (p[1].clamp(0.0, 1.0) * 255.0).round() as u8,
+                    (p[2].clamp(0.0, 1.0) * 255.0).round() as u8
+                ])
+            }
+        ))
+    }
 }
 
 /// Fill border with neighboring pixels. A way of preventing instability
@@ -0,0 +1,357 @@
+//! # Interpolation Module
+//!
+//! Samples greyscale images at non-integer positions, used to resample images during
+//! rectification.
+//!
+//! Positions are continuous pixel coordinates, in which pixel `(x, y)` covers the area from
+//! `(x, y)` to `(x + 1, y + 1)`, so its value lies at its centre `(x + 0.5, y + 0.5)`.
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::f64::consts::PI;
+
+use crate::GrayFloatImage;
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Number of lobes of the Lanczos kernel either side of the sample.
+const LANCZOS_LOBES: i64 = 3;
+
+/// Maximum number of pixels along each axis which contribute to a sample.
+const MAX_TAPS: usize = 2 * LANCZOS_LOBES as usize;
+
+// -----------------------------------------------------------------------------------------------
+// ENUMERATIONS
+// -----------------------------------------------------------------------------------------------
+
+/// The method used to compute the value of an image between pixel centres.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Interpolation {
+    /// Use the value of the pixel containing the sample
+    Nearest,
+
+    /// Weight the four nearest pixels by their distance from the sample
+    #[default]
+    Bilinear,
+
+    /// Fit a Catmull-Rom cubic through the nearest 4x4 pixels, which is sharper than bilinear
+    /// but may overshoot at edges
+    Bicubic,
+
+    /// Weight the nearest 6x6 pixels by a three lobed Lanczos window, which preserves the most
+    /// detail at the highest cost
+    Lanczos
+}
+
+/// How pixels outside the source image are treated when they contribute to a sample.
+///
+/// The default is a constant value of zero, so that areas of a rectified image with no
+/// corresponding source pixels are black.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BorderMode {
+    /// Pixels outside the image have the given value
+    Constant(f32),
+
+    /// Pixels outside the image take the value of the nearest edge pixel
+    Replicate,
+
+    /// The image is mirrored about its edge pixels, without repeating them
+    Reflect
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl Interpolation {
+    /// Get the distance from the edge of an image within which samples depend on pixels outside
+    /// it, and so on the border mode.
+    pub(crate) fn border_margin(&self) -> f64 {
+        match *self {
+            Interpolation::Nearest => 0.0,
+            Interpolation::Bilinear => 0.5,
+            Interpolation::Bicubic => 1.5,
+            Interpolation::Lanczos => LANCZOS_LOBES as f64 - 0.5
+        }
+    }
+
+    /// Get the offset of the first contributing pixel from the pixel at or before the sample,
+    /// and the weights of each contributing pixel given the fractional position of the sample.
+    ///
+    /// Returns the offset, the weights and the number of weights used.
+    fn weights(&self, t: f64) -> (i64, [f32; MAX_TAPS], usize) {
+        let mut w = [0.0; MAX_TAPS];
+
+        match *self {
+            Interpolation::Nearest => {
+                w[0] = 1.0;
+
+                (if t < 0.5 { 0 } else { 1 }, w, 1)
+            },
+            Interpolation::Bilinear => {
+                w[0] = (1.0 - t) as f32;
+                w[1] = t as f32;
+
+                (0, w, 2)
+            },
+            Interpolation::Bicubic => {
+                for (i, wi) in w.iter_mut().take(4).enumerate() {
+                    *wi = catmull_rom(t - (i as f64 - 1.0)) as f32;
+                }
+
+                (-1, w, 4)
+            },
+            Interpolation::Lanczos => {
+                let mut sum = 0.0;
+                for (i, wi) in w.iter_mut().enumerate() {
+                    let weight = lanczos(t - (i as f64 - (LANCZOS_LOBES - 1) as f64));
+
+                    *wi = weight as f32;
+                    sum += weight;
+                }
+
+                // Normalise so that flat regions keep their value
+                for wi in w.iter_mut() {
+                    *wi /= sum as f32;
+                }
+
+                (1 - LANCZOS_LOBES, w, MAX_TAPS)
+            }
+        }
+    }
+}
+
+impl Default for BorderMode {
+    fn default() -> Self {
+        BorderMode::Constant(0.0)
+    }
+}
+
+impl BorderMode {
+    /// Get the value of the pixel at the given position, which may be outside the image.
+    fn get(&self, img: &GrayFloatImage, x: i64, y: i64) -> f32 {
+        let (width, height) = (img.width() as i64, img.height() as i64);
+
+        if x >= 0 && x < width && y >= 0 && y < height {
+            return img.get(x as usize, y as usize);
+        }
+
+        match *self {
+            BorderMode::Constant(value) => value,
+            BorderMode::Replicate => img.get(
+                x.max(0).min(width - 1) as usize,
+                y.max(0).min(height - 1) as usize
+            ),
+            BorderMode::Reflect => img.get(reflect(x, width), reflect(y, height))
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// PUBLIC FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Sample an image at a position in continuous pixel coordinates.
+///
+/// Pixels needed by the interpolation which lie outside the image are given by `border`, as are
+/// positions which are not finite.
+pub fn sample(
+    img: &GrayFloatImage,
+    x: f64,
+    y: f64,
+    interpolation: Interpolation,
+    border: BorderMode
+) -> f32 {
+    // Shift to coordinates with pixel centres on the integers, keeping positions far outside the
+    // image just beyond the reach of the kernel so that they cannot overflow. NaN becomes the
+    // lower limit, as `max` returns its other argument.
+    let limit = |t: f64, size: usize| t.max(-(MAX_TAPS as f64)).min((size + MAX_TAPS) as f64);
+    let (u, v) = (limit(x - 0.5, img.width()), limit(y - 0.5, img.height()));
+    let (u0, v0) = (u.floor(), v.floor());
+
+    let (off_x, wx, n) = interpolation.weights(u - u0);
+    let (off_y, wy, _) = interpolation.weights(v - v0);
+    let (x0, y0) = (u0 as i64 + off_x, v0 as i64 + off_y);
+
+    let mut value = 0.0;
+    for (j, wj) in wy.iter().take(n).enumerate() {
+        let mut row = 0.0;
+        for (i, wi) in wx.iter().take(n).enumerate() {
+            row += wi * border.get(img, x0 + i as i64, y0 + j as i64);
+        }
+
+        value += wj * row;
+    }
+
+    value
+}
+
+// -----------------------------------------------------------------------------------------------
+// PRIVATE FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// The Catmull-Rom cubic convolution kernel.
+fn catmull_rom(t: f64) -> f64 {
+    let t = t.abs();
+
+    if t < 1.0 {
+        1.5 * t * t * t - 2.5 * t * t + 1.0
+    } else if t < 2.0 {
+        -0.5 * t * t * t + 2.5 * t * t - 4.0 * t + 2.0
+    } else {
+        0.0
+    }
+}
+
+/// The Lanczos windowed sinc kernel.
+fn lanczos(t: f64) -> f64 {
+    let a = LANCZOS_LOBES as f64;
+
+    if t == 0.0 {
+        1.0
+    } else if t.abs() < a {
+        let pt = PI * t;
+
+        a * pt.sin() * (pt / a).sin() / (pt * pt)
+    } else {
+        0.0
+    }
+}
+
+/// Mirror a coordinate into `0..len` without repeating the edge pixel.
+fn reflect(i: i64, len: i64) -> usize {
+    if len == 1 {
+        return 0;
+    }
+
+    // The mirrored image repeats with this period
+    let period = 2 * (len - 1);
+    let i = i.rem_euclid(period);
+
+    (if i < len { i } else { period - i }) as usize
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const METHODS: [Interpolation; 4] = [
+        Interpolation::Nearest,
+        Interpolation::Bilinear,
+        Interpolation::Bicubic,
+        Interpolation::Lanczos
+    ];
+
+    /// A 4x3 image with a different value in every pixel.
+    fn test_image() -> GrayFloatImage {
+        let mut img = GrayFloatImage::new(4, 3);
+        for y in 0..3 {
+            for x in 0..4 {
+                img.put(x, y, (x + 4 * y) as f32 / 12.0);
+            }
+        }
+
+        img
+    }
+
+    /// Test that every method returns the pixel values exactly at pixel centres
+    #[test]
+    fn test_pixel_centres() {
+        let img = test_image();
+
+        for method in METHODS.iter().copied() {
+            for y in 0..3 {
+                for x in 0..4 {
+                    let value = sample(
+                        &img, x as f64 + 0.5, y as f64 + 0.5, method, BorderMode::Reflect
+                    );
+
+                    assert!((value - img.get(x, y)).abs() < 1e-6, "{:?}", method);
+                }
+            }
+        }
+    }
+
+    /// Test that bilinear interpolation weights all four neighbours, including the last row and
+    /// column of the image
+    #[test]
+    fn test_bilinear() {
+        let img = test_image();
+        let border = BorderMode::Constant(0.0);
+
+        // Halfway between the centres of the bottom right 2x2 pixels
+        let value = sample(&img, 3.0, 2.0, Interpolation::Bilinear, border);
+        let mean = (img.get(2, 1) + img.get(3, 1) + img.get(2, 2) + img.get(3, 2)) / 4.0;
+        assert!((value - mean).abs() < 1e-6);
+
+        // A quarter of the way between two pixels
+        let value = sample(&img, 1.75, 0.5, Interpolation::Bilinear, border);
+        assert!((value - (0.75 * img.get(1, 0) + 0.25 * img.get(2, 0))).abs() < 1e-6);
+
+        // Smooth methods reproduce a linear ramp exactly away from the borders
+        for method in [Interpolation::Bilinear, Interpolation::Bicubic].iter().copied() {
+            let value = sample(&img, 2.2, 1.5, method, BorderMode::Reflect);
+            assert!((value - (1.7 + 4.0) / 12.0).abs() < 1e-6, "{:?}", method);
+        }
+    }
+
+    /// Test the value of samples outside the image for each border mode
+    #[test]
+    fn test_border_modes() {
+        let img = test_image();
+
+        let constant = sample(&img, -3.5, 1.5, Interpolation::Nearest, BorderMode::Constant(0.25));
+        assert_eq!(constant, 0.25);
+
+        let replicate = sample(&img, -3.5, 1.5, Interpolation::Nearest, BorderMode::Replicate);
+        assert_eq!(replicate, img.get(0, 1));
+
+        // One pixel before the first mirrors onto the second, and one after the last onto the
+        // second to last
+        let reflect = sample(&img, -0.5, 1.5, Interpolation::Nearest, BorderMode::Reflect);
+        assert_eq!(reflect, img.get(1, 1));
+        let reflect = sample(&img, 4.5, 3.5, Interpolation::Nearest, BorderMode::Reflect);
+        assert_eq!(reflect, img.get(2, 1));
+
+        // A flat image stays flat up to the border for every method
+        let mut flat = GrayFloatImage::new(4, 4);
+        for y in 0..4 {
+            for x in 0..4 {
+                flat.put(x, y, 0.5);
+            }
+        }
+
+        for method in METHODS.iter().copied() {
+            let value = sample(&flat, 0.1, 3.9, method, BorderMode::Replicate);
+            assert!((value - 0.5).abs() < 1e-6, "{:?}", method);
+        }
+    }
+
+    /// Test that positions far outside the image or not finite give the border's value
+    #[test]
+    fn test_distant_positions() {
+        let img = test_image();
+
+        for method in METHODS.iter().copied() {
+            let value = sample(&img, 1e300, 1.5, method, BorderMode::Replicate);
+            assert!((value - img.get(3, 1)).abs() < 1e-6, "{:?}", method);
+            let value = sample(&img, 1.5, f64::NEG_INFINITY, method, BorderMode::Replicate);
+            assert!((value - img.get(1, 0)).abs() < 1e-6, "{:?}", method);
+
+            for (x, y) in [(f64::NAN, 1.5), (-1e300, f64::INFINITY), (1.5, 1e20)].iter() {
+                let value = sample(&img, *x, *y, method, BorderMode::Constant(0.25));
+                assert_eq!(value, 0.25, "{:?}", method);
+            }
+            sample(&img, 1e300, -1e300, method, BorderMode::Reflect);
+        }
+    }
+}
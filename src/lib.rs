@@ -30,9 +30,11 @@
 //!
 //! This crate provides support for mono and stereo cameras through a builder API `CamStream`.
 //!
-//! ```rust
+//! ```no_run
+//! # use cv_camstream::prelude::*;
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
 //! // Example mono camera builder pattern
-//! let camera = CamStreamBuilder::new()
+//! let mut camera = CamStreamBuilder::new()
 //!     // Create a mono camera object
 //!     .mono()
 //!     // The path that the device can be found at, which returns a result
@@ -43,26 +45,153 @@
 //!     .rectif_params_from_file("mono_rectif_params.toml")
 //!     .expect("Cannot find rectification parameters file")
 //!     // Set rscam parameters, like interval, resolution, and format
-//!     .interval(1, 30)
-//!     .resolution(640, 480)
+//!     .interval((1, 30))
+//!     .resolution((640, 480))
 //!     .format(b"MJPG")
+//!     .expect("Unsupported image format")
 //!     // Construct the object
 //!     .build()
 //!     .expect("Failed to open camera");
+//!
+//! // Once the camera object has been built it is accessed through:
+//! let img = camera.capture().expect("Failed to get camera image");
+//! # Ok(())
+//! # }
 //! ```
 //!
-//! Once the camera object has been built it is accessed through:
+//! where `capture` returns a [`GrayFloatImage`] result.
 //!
-//! ```rust
-//! let img = camera.capture().expect("Failed to get camera image")
-//! ```
+//! Rectification is applied through a `RemapTable`, which maps each pixel of the rectified image
+//! to its position in the captured one. Streams compute the table once when they are built and
+//! reuse it for every frame, only rebuilding it if the frame resolution changes. A table can also
+//! be built directly with `RectifParams::remap_table(...)` to rectify stored images.
+//!
+//! Rectification parameters may give a single distortion coefficient `k1`, which uses the division
+//! model of `cv-pinhole`, or the full Brown-Conrady model produced by most calibration tools with
+//! `k1`, `k2`, `k3`, `p1` and `p2` (plus `k4`, `k5` and `k6` for the rational model). The model is
+//! inferred from the coefficients present in the file, or can be set with `distortion_model`
+//! (`"division"`, `"plumb_bob"` or `"rational_polynomial"`). Points can be undistorted directly
+//! with `RectifParams::undistort_point(...)`.
+//!
+//! Fisheye lenses are supported with the Kannala-Brandt equidistant model by setting
+//! `distortion_model = "equidistant"` alongside the coefficients `k1` to `k4`. Fisheye images are
+//! rectified to a pinhole camera with the same intrinsics by default, the focal length or
+//! horizontal field of view of the rectified images can be chosen with `.output_focal(...)` or
+//! `.output_fov(...)` on either builder.
+//!
+//! Stereo parameter files can also give the `rotation` (a row major 3x3 matrix) and `translation`
+//! from the left camera to the right, as produced by stereo calibration. The images of both cameras
+//! are then rotated so that corresponding points lie on the same row of `StereoFrame::left` and
+//! `StereoFrame::right`, using Bouguet's method. The rectifying rotations and shared intrinsics are
+//! available from `StereoRectifParams::rectification(...)`.
+//!
+//! Streams describe the cameras their rectified images come from. `MonoCamStream::intrinsics()`
+//! and `camera_matrix()` return the rectified intrinsics as `cv-pinhole` and `nalgebra` types, and
+//! `StereoCamStream` adds the rectified projection matrices, the baseline and the disparity to depth
+//! reprojection matrix `Q` through `projection_matrices()`, `baseline()` and
+//! `reprojection_matrix()`.
+//!
+//! The scale of rectified images can also be chosen with `.alpha(...)` on either builder, which
+//! works like OpenCV's free scaling parameter: at 0 the images are zoomed so that every pixel is
+//! valid, and at 1 every pixel of the captured image is kept. `.output_resolution(...)` gives the
+//! rectified images a different size from the captured ones. The matching camera is available from
+//! `RectifParams::optimal_intrinsics(...)`.
+//!
+//! Rectified pixels which sample outside the captured image are filled by the border mode, so
+//! `RemapTable::valid_mask()` marks which pixels are genuine and `valid_roi()` gives the largest
+//! rectangle containing only valid pixels. Streams return the same through `valid_mask()` and
+//! `valid_roi()`, and `StereoCamStream` adds `valid_rois()` and the rectangle valid in both images
+//! with `common_roi()`. Calling `.crop_to_roi()` on either builder crops every rectified frame to
+//! that rectangle, adjusting the principal point to match.
+//!
+//! Calibration files written by OpenCV's `FileStorage` can be loaded directly with
+//! `rectif_params_from_file(...)`, in either YAML (with the `%YAML:1.0` header and `!!opencv-matrix`
+//! tags) or XML. Mono files give `camera_matrix` and `distortion_coefficients`, and stereo files the
+//! `M1`, `D1`, `M2`, `D2`, `R` and `T` written by `stereoCalibrate`. Distortion the crate cannot model,
+//! such as non-zero thin prism coefficients, is reported as `Error::UnsupportedDistortionModel`. The
+//! same loaders are available through the `CalibrationParams` trait, for example
+//! `RectifParams::from_opencv_str(...)`.
+//!
+//! ROS and Kalibr calibrations are detected by `rectif_params_from_file(...)` in the same way. ROS
+//! `camera_info` files from `camera_calibration` (or `CameraInfo` messages with `K`, `D`, `R` and `P`)
+//! load as mono parameters, and a stereo pair can be loaded from its left and right files with
+//! `StereoRectifParams::from_ros_files(...)`, recovering the extrinsics from the rectification and
+//! projection matrices. Kalibr `camchain.yaml` files give `cam0` as a mono camera, or `cam0` and
+//! `cam1` with their `T_cn_cnm1` transform as a stereo pair.
+//!
+//! `RectifParams` and `StereoRectifParams` can also be written out, so that parameters which have
+//! been computed or converted can be shared with other tools. `to_file(...)` writes the crate's own
+//! format as TOML, JSON or YAML according to the file extension, which loads back unchanged, while
+//! `to_opencv_string()` gives an OpenCV `FileStorage` YAML document and `to_ros_string(...)` a ROS
+//! `camera_info` document for the given resolution. `StereoRectifParams::to_ros_files(...)` writes
+//! the separate left and right files produced by `camera_calibration`.
 //!
-//! which returns an [`GrayFloatImage`] result.
+//! Cameras can also be calibrated without external tools. A `MonoCalibrator` finds a `Chessboard`
+//! target in each image added to it with `add_image(...)`, refining its corners to sub-pixel
+//! accuracy, and `calibrate()` estimates the focal lengths, principal point, skew and distortion
+//! with Zhang's method followed by Levenberg-Marquardt refinement. The resulting `MonoCalibration`
+//! holds the `RectifParams` along with the reprojection error of each image, so that poor views can
+//! be found and removed. Other targets can be used by implementing `CalibrationTarget`.
+//!
+//! Stereo rigs are calibrated in the same way with a `StereoCalibrator`, which takes unrectified
+//! `StereoFrame`s through `add_frame(...)`. Both cameras and the rotation and translation between
+//! them are estimated together, views which fit much worse than the rest are rejected as outliers,
+//! and the resulting `StereoCalibration` gives a complete `StereoRectifParams` along with the RMS
+//! reprojection and epipolar errors, ready to be saved with `to_file(...)`.
+//!
+//! Either calibrator also accepts a `CharucoBoard`, a chessboard with ArUco markers in its white
+//! squares. Each corner is identified by the markers beside it, so views which only show part of
+//! the board, or where it is partly covered, still contribute. The markers themselves are found by
+//! an `ArucoDetector`, which returns the identifier and sub-pixel corners of every `ArucoMarker` in
+//! an image, using `ArucoDictionary::original()` or a dictionary built from custom codes.
+//!
+//! Rectified images are resampled with bilinear interpolation by default. Either builder can
+//! select nearest, bicubic or Lanczos sampling instead with `.interpolation(...)`, and
+//! `.border_mode(...)` chooses whether samples outside the captured image take a constant value
+//! (black by default), replicate the nearest edge pixel, or reflect the image about its edges.
+//!
+//! Frames can be captured as `MJPG`, which is decoded by the `image` crate, or as uncompressed
+//! `YUYV` (the V4L2 default), whose luma channel is unpacked directly into the `GrayFloatImage`.
+//! Greyscale cameras are supported in `GREY`, `Y10`, `Y12` and `Y16` formats, including the MIPI
+//! packed `Y10P` and `Y12P`, which are normalised to between 0 and 1 according to their bit depth.
+//! The 4:2:0 YUV formats `NV12`, `NV21`, `YU12` and `YV12` are decoded from their Y plane, and all
+//! formats can also be decoded to colour as an `RgbFloatImage`.
 //! 
+//! Bayer sensors are supported in 8, 10, 12 and 16 bit formats (`BA81`, `RGGB`, `BG10`, `RG16` and
+//! so on), with frames demosaiced by an edge-aware interpolation. The colour filter arrangement is
+//! inferred from the FourCC, and can be overridden on either builder with `.bayer_pattern(...)`.
+//!
+//! Streams produce greyscale images by default. Calling `.rgb()` on either builder selects colour
+//! frames instead, so that `capture()` returns an `RgbFloatImage` (or a `StereoFrame` of them)
+//! with each channel rectified separately. Colour stereo frames can be converted to 8 bit RGB or
+//! luma pairs, or to a greyscale `StereoFrame` with `to_gray()`.
+//!
 //! For stereo cameras the process is similar, although you must specify the left and right path
 //! seperately through `left_path` and `right_path`. A `StereoCamStream` object returns `StereoFrame`s,
 //! a struct which contains the left and right image respectively. Convenience functions are provided
 //! to convert a frame into pairs (tuples) of different types of images from the `image` crate.
+//!
+//! Frames are acquired through a `CamBackend`, which is a V4L2 device by default. Other sources can
+//! be plugged in by implementing `CamBackend` and selecting it on either builder with
+//! `.backend::<MyBackend>()`, then giving its source with `.source(...)` (or `.left_source(...)`
+//! and `.right_source(...)` for stereo).
+//!
+//! For testing without hardware both builders provide `.synthetic(...)`, which replaces the cameras
+//! with virtual ones rendering a deterministic `TestPattern`. The stereo version also takes a
+//! horizontal shift between the left and right images to simulate disparity.
+//!
+//! Recorded datasets can be replayed through the same API with `.playback(...)`, which reads the
+//! images in a directory (or a left and right directory for stereo) in file name order, taking
+//! timestamps from the configured interval, a CSV file, or the file names.
+//!
+//! Any stream can be saved to disk by wrapping it in a `Recorder`, which writes each raw or
+//! rectified image as a 16 bit PNG with a timestamp index from a background thread. Each camera's
+//! directory in a recording can be replayed with `.playback(...)`.
+//!
+//! To log frames exactly as the camera produced them, for example the JPEG data of an MJPEG
+//! camera, call `.record_raw(...)` on a builder before building it. The undecoded frames and their
+//! timestamps are written to a single indexed container file, which can be replayed as a stream
+//! with `.replay_raw(...)` or read frame by frame with `RawFrameReader`.
 
 #[deny(missing_docs)]
 
@@ -70,22 +199,48 @@
 // EXPORTS
 // -----------------------------------------------------------------------------------------------
 
+pub use backend::{CamBackend, CamConfig, RawFrame, V4l2Backend};
+pub use backend::{PlaybackBackend, PlaybackSource, PlaybackTimestamps};
+pub use backend::{RawFrameReader, RawFrameWriter};
+pub use backend::{RawRecordingBackend, RawRecordingSource, RawReplayBackend, RawReplaySource};
+pub use backend::{SyntheticBackend, SyntheticSource, TestPattern};
+pub use bayer::{demosaic, BayerPattern, DemosaicMethod};
 pub use builder::{CamStreamBuilder, Rectifiable};
-pub use camstream::{CamStream, MonoCamStream, StereoCamStream, StereoFrame};
-pub use crate::image::GrayFloatImage;
+pub use calib_file::CalibrationParams;
+pub use calibration::{refine_corners, CalibrationTarget, Chessboard, TargetDetection};
+pub use calibration::{ArucoDetector, ArucoDictionary, ArucoMarker, CharucoBoard};
+pub use calibration::{MonoCalibration, MonoCalibrator, StereoCalibration, StereoCalibrator};
+pub use camstream::{CamStream, MonoCamStream, RecordableStream, StereoCamStream, StereoFrame};
+pub use camstream::{RecordedFrame, StreamImage, TimestampedImage};
+pub use crate::image::{GrayFloatImage, RgbFloatImage};
+pub use distortion::{BrownConrady, DistortionModel, KannalaBrandt, LensModel};
+pub use error::{Error, Result};
+pub use interpolation::{sample, BorderMode, Interpolation};
+pub use format::PixelFormat;
+pub use recorder::{RecordMode, Recorder};
+pub use rectification::{RectifParams, RemapTable, StereoRectification, StereoRectifParams};
+pub use rectification::{Roi, StereoSide};
 
 // -----------------------------------------------------------------------------------------------
 // MODULES
 // -----------------------------------------------------------------------------------------------
 
+mod backend;
+mod bayer;
 mod builder;
+mod calib_file;
+mod calibration;
 mod camstream;
+mod distortion;
 mod error;
+mod format;
 mod image;
+mod interpolation;
+mod recorder;
 mod rectification;
 
 pub mod prelude {
     pub use crate::{CamStreamBuilder, Rectifiable};
     pub use crate::{CamStream, MonoCamStream, StereoCamStream, StereoFrame};
-    pub use crate::GrayFloatImage;
+    pub use crate::{GrayFloatImage, RgbFloatImage};
 }
\ No newline at end of file
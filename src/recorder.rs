@@ -0,0 +1,399 @@
+//! # Recorder Module
+//!
+//! This module provides a wrapper around camera streams which saves every captured image to disk,
+//! along with its timestamp, without stalling capture.
+//!
+//! A recording is laid out as:
+//!
+//! ```text
+//! <dir>/recording.toml           - metadata, written by Recorder::finish
+//! <dir>/<camera>/000000.png      - 16 bit greyscale or RGB image of each frame
+//! <dir>/<camera>/timestamps.csv  - "file,timestamp" row for each frame
+//! ```
+//!
+//! where `<camera>` is `mono`, or `left` and `right`. Each camera directory can be replayed with a
+//! [`PlaybackSource`](crate::PlaybackSource) using [`PlaybackTimestamps::Csv`].
+//!
+//! [`PlaybackTimestamps::Csv`]: crate::PlaybackTimestamps::Csv
+
+// -----------------------------------------------------------------------------------------------
+// IMPORTS
+// -----------------------------------------------------------------------------------------------
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+
+use serde::Serialize;
+
+use crate::camstream::{CamStream, RecordableStream, StreamImage, TimestampedImage};
+use crate::error::{Error, Result};
+
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Default number of frames which can be waiting to be written before capture fails.
+pub const DEFAULT_QUEUE_LEN: usize = 32;
+
+/// Name of the metadata file in the recording directory.
+const METADATA_FILE: &str = "recording.toml";
+
+/// Name of the timestamp index file in each camera's directory.
+const TIMESTAMPS_FILE: &str = "timestamps.csv";
+
+// -----------------------------------------------------------------------------------------------
+// ENUMERATIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Which images a [`Recorder`] saves.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordMode {
+    /// Save images as decoded from the camera, before rectification
+    Raw,
+
+    /// Save images as returned by the stream, after rectification
+    Rectified
+}
+
+// -----------------------------------------------------------------------------------------------
+// DATA STRUCTURES
+// -----------------------------------------------------------------------------------------------
+
+/// Wraps a camera stream, saving every captured image to disk.
+///
+/// Images are written by a background thread, so disk I/O never blocks `capture`. If the writer
+/// falls behind by more than the queue length, `capture` returns [`Error::RecorderOverflow`]
+/// and that frame is neither returned nor recorded. Call [`Recorder::finish`] to wait for all
+/// queued images to be written and get the stream back.
+pub struct Recorder<S: RecordableStream> {
+    stream: S,
+
+    dir: PathBuf,
+
+    mode: RecordMode,
+
+    queue_len: usize,
+
+    frame_index: u64,
+
+    job_tx: Option<SyncSender<RecordJob<S::Image>>>,
+
+    writer_jh: Option<JoinHandle<Result<u64>>>
+}
+
+/// A captured frame waiting to be written.
+struct RecordJob<I> {
+    /// Index of the frame in the recording
+    index: u64,
+
+    /// Image and timestamp from each camera
+    images: Vec<TimestampedImage<I>>
+}
+
+/// Contents of the recording's metadata file.
+#[derive(Serialize)]
+struct RecordingMetadata {
+    mode: RecordMode,
+
+    cameras: Vec<String>,
+
+    frames: u64
+}
+
+// -----------------------------------------------------------------------------------------------
+// IMPLEMENTATIONS
+// -----------------------------------------------------------------------------------------------
+
+impl<S: RecordableStream> Recorder<S> {
+    /// Start recording the stream into the given directory, which is created if it doesn't exist.
+    pub fn new<P: AsRef<Path>>(stream: S, dir: P, mode: RecordMode) -> Result<Self> {
+        Self::with_queue_len(stream, dir, mode, DEFAULT_QUEUE_LEN)
+    }
+
+    /// Start recording the stream, allowing up to `queue_len` frames to wait to be written.
+    pub fn with_queue_len<P: AsRef<Path>>(
+        stream: S,
+        dir: P,
+        mode: RecordMode,
+        queue_len: usize
+    ) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let cameras = stream.camera_names();
+
+        // Create the directory and index for each camera now so that failures are reported early
+        let mut indices = Vec::with_capacity(cameras.len());
+
+        for camera in cameras {
+            let camera_dir = dir.join(camera);
+            fs::create_dir_all(&camera_dir).map_err(|e| Error::recording(&camera_dir, e))?;
+
+            let index_path = camera_dir.join(TIMESTAMPS_FILE);
+            let mut index = File::create(&index_path)
+                .map(BufWriter::new)
+                .map_err(|e| Error::recording(&index_path, e))?;
+            writeln!(index, "file,timestamp").map_err(|e| Error::recording(&index_path, e))?;
+
+            indices.push((camera_dir, index));
+        }
+
+        let (job_tx, job_rx) = sync_channel(queue_len);
+        let writer_jh = writer_thread(job_rx, indices);
+
+        Ok(Self {
+            stream,
+            dir,
+            mode,
+            queue_len,
+            frame_index: 0,
+            job_tx: Some(job_tx),
+            writer_jh: Some(writer_jh)
+        })
+    }
+
+    /// Wait for all queued images to be written, write the recording's metadata, and return the
+    /// wrapped stream.
+    pub fn finish(mut self) -> Result<S> {
+        // Closing the channel lets the writer finish once the queue is empty
+        self.job_tx = None;
+        let frames = self.join_writer()?;
+
+        let metadata = RecordingMetadata {
+            mode: self.mode,
+            cameras: self.stream.camera_names().iter().map(|c| c.to_string()).collect(),
+            frames
+        };
+
+        serde_any::to_file(self.dir.join(METADATA_FILE), &metadata)
+            .map_err(Error::SerialisationError)?;
+
+        Ok(self.stream)
+    }
+
+    /// Wait for the writer thread to exit, returning the number of frames it wrote.
+    fn join_writer(&mut self) -> Result<u64> {
+        match self.writer_jh.take() {
+            Some(jh) => jh.join().map_err(|_| Error::ThreadJoinError)?,
+            None => Err(Error::RecordingError(String::from("The writer thread has already exited")))
+        }
+    }
+}
+
+impl<S: RecordableStream> CamStream for Recorder<S> {
+    type Frame = S::Frame;
+
+    /// Capture a frame from the wrapped stream and queue its images to be written.
+    fn capture(&mut self) -> Result<Self::Frame> {
+        let (frame, images) = self.stream.capture_recorded(self.mode == RecordMode::Raw)?;
+
+        let job_tx = match self.job_tx {
+            Some(ref tx) => tx,
+            None => return Err(Error::ChannelSendError)
+        };
+
+        match job_tx.try_send(RecordJob { index: self.frame_index, images }) {
+            Ok(()) => {
+                self.frame_index += 1;
+
+                Ok(frame)
+            },
+            Err(TrySendError::Full(_)) => Err(Error::RecorderOverflow(self.queue_len)),
+            // The writer only hangs up if it failed, so report why
+            Err(TrySendError::Disconnected(_)) => {
+                self.job_tx = None;
+
+                match self.join_writer() {
+                    Err(e) => Err(e),
+                    Ok(_) => Err(Error::ChannelSendError)
+                }
+            }
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// PRIVATE FUNCTIONS
+// -----------------------------------------------------------------------------------------------
+
+/// Write queued frames to disk in a separate thread, returning the number of frames written.
+///
+/// `indices` holds the directory and timestamp index of each camera, in the same order as the
+/// images in each job.
+fn writer_thread<I: StreamImage>(
+    job_rx: Receiver<RecordJob<I>>,
+    mut indices: Vec<(PathBuf, BufWriter<File>)>
+) -> JoinHandle<Result<u64>> {
+    thread::spawn(move || {
+        let mut frames = 0;
+
+        for job in job_rx.iter() {
+            let file = format!("{:06}.png", job.index);
+
+            for ((img, timestamp), (dir, index)) in job.images.iter().zip(indices.iter_mut()) {
+                let path = dir.join(&file);
+
+                img.to_dynamic16()
+                    .save(&path)
+                    .map_err(|e| Error::recording(&path, e))?;
+
+                writeln!(index, "{},{}", file, timestamp).map_err(|e| Error::recording(dir, e))?;
+            }
+
+            frames += 1;
+        }
+
+        for (dir, index) in indices.iter_mut() {
+            index.flush().map_err(|e| Error::recording(dir, e))?;
+        }
+
+        Ok(frames)
+    })
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{CamStreamBuilder, GrayFloatImage, PlaybackSource, PlaybackTimestamps, TestPattern};
+    use crate::RgbFloatImage;
+    use crate::rectification::RectifParams;
+    use crate::backend::tests::test_dir;
+    use crate::builder::Rectifiable;
+
+    /// Test that a recording of a stereo stream can be played back exactly
+    #[test]
+    fn test_record_and_playback() {
+        let dir = test_dir("record_and_playback");
+
+        let stream = CamStreamBuilder::new()
+            .stereo()
+            .synthetic(TestPattern::Noise(7), 4)
+            .resolution((64, 48))
+            .build()
+            .expect("Cannot build synthetic stream");
+
+        let mut recorder = Recorder::new(stream, &dir, RecordMode::Rectified)
+            .expect("Cannot start recorder");
+
+        let frames: Vec<_> = (0..3)
+            .map(|_| recorder.capture().expect("Cannot capture frame"))
+            .collect();
+
+        recorder.finish().expect("Cannot finish recording").stop().unwrap();
+
+        let metadata = fs::read_to_string(dir.join(METADATA_FILE)).unwrap();
+        assert!(metadata.contains("frames = 3"));
+
+        let mut playback = CamStreamBuilder::new()
+            .stereo()
+            .playback(
+                PlaybackSource::new(dir.join("left"))
+                    .timestamps(PlaybackTimestamps::Csv(dir.join("left").join(TIMESTAMPS_FILE))),
+                PlaybackSource::new(dir.join("right"))
+                    .timestamps(PlaybackTimestamps::Csv(dir.join("right").join(TIMESTAMPS_FILE)))
+            )
+            .build()
+            .expect("Cannot build playback stream");
+
+        for frame in frames {
+            let played = playback.capture().expect("Cannot play frame");
+
+            assert_eq!(played.left_timestamp, frame.left_timestamp);
+            assert_eq!(played.right_timestamp, frame.right_timestamp);
+            assert_eq!(played.left.0, frame.left.0);
+            assert_eq!(played.right.0, frame.right.0);
+        }
+    }
+
+    /// Test that raw mode records images before rectification
+    #[test]
+    fn test_record_raw() {
+        let dir = test_dir("record_raw");
+        let params = RectifParams {
+            focals: [60.0, 60.0],
+            principal_point: [32.0, 24.0],
+            skew: 0.0,
+            k1: Some(-0.3),
+            ..RectifParams::default()
+        };
+
+        let stream = CamStreamBuilder::new()
+            .mono()
+            .synthetic(TestPattern::Checkerboard(8))
+            .resolution((64, 48))
+            .rectif_params(params)
+            .build()
+            .expect("Cannot build synthetic stream");
+
+        let mut recorder = Recorder::new(stream, &dir, RecordMode::Raw)
+            .expect("Cannot start recorder");
+        let rectified = recorder.capture().expect("Cannot capture frame");
+        recorder.finish().expect("Cannot finish recording");
+
+        let raw = GrayFloatImage::from_dynamic(&image::open(dir.join("mono/000000.png")).unwrap());
+
+        // The recorded image is the unrectified checkerboard, with a white top left square
+        assert_eq!(raw.get(0, 0), 1.0);
+        assert_eq!(raw.get(8, 0), 0.0);
+        assert!(raw.0 != rectified.0);
+    }
+
+    /// Test that colour streams are recorded as 16 bit RGB images
+    #[test]
+    fn test_record_rgb() {
+        let dir = test_dir("record_rgb");
+
+        let stream = CamStreamBuilder::new()
+            .mono()
+            .synthetic(TestPattern::GradientRamp)
+            .rgb()
+            .resolution((64, 48))
+            .build()
+            .expect("Cannot build synthetic stream");
+
+        let mut recorder = Recorder::new(stream, &dir, RecordMode::Rectified)
+            .expect("Cannot start recorder");
+        let img = recorder.capture().expect("Cannot capture frame");
+        recorder.finish().expect("Cannot finish recording");
+
+        let recorded = image::open(dir.join("mono/000000.png")).unwrap();
+
+        match recorded {
+            image::DynamicImage::ImageRgb16(_) => (),
+            _ => panic!("Expected a 16 bit RGB image")
+        }
+        assert_eq!(RgbFloatImage::from_dynamic(&recorded).0, img.0);
+    }
+
+    /// Test that capture fails clearly when the writer cannot keep up
+    #[test]
+    fn test_overflow() {
+        let dir = test_dir("record_overflow");
+
+        let stream = CamStreamBuilder::new()
+            .mono()
+            .synthetic(TestPattern::Noise(3))
+            .resolution((1280, 960))
+            .build()
+            .expect("Cannot build synthetic stream");
+
+        let mut recorder = Recorder::with_queue_len(stream, &dir, RecordMode::Rectified, 1)
+            .expect("Cannot start recorder");
+
+        let overflowed = (0..50).any(|_| match recorder.capture() {
+            Err(Error::RecorderOverflow(1)) => true,
+            Ok(_) => false,
+            Err(e) => panic!("Unexpected error {}", e)
+        });
+
+        assert!(overflowed);
+        recorder.finish().expect("Cannot finish recording");
+    }
+}
@@ -6,23 +6,42 @@
 // IMPORTS
 // -----------------------------------------------------------------------------------------------
 
-use nalgebra::{Vector2, Point2};
+use nalgebra::{Matrix3, Matrix3x4, Matrix4, Point2, Rotation3, Vector2, Vector3};
 use cv_pinhole::{CameraIntrinsics, CameraIntrinsicsK1Distortion, NormalizedKeyPoint};
 use cv_core::{KeyPoint, CameraModel};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
+use crate::distortion::{BrownConrady, DistortionModel, KannalaBrandt, LensModel};
 use crate::error::{Result, Error};
+use crate::interpolation::{sample, BorderMode, Interpolation};
 use crate::GrayFloatImage;
 
+// -----------------------------------------------------------------------------------------------
+// CONSTANTS
+// -----------------------------------------------------------------------------------------------
+
+/// Source coordinate given to rectified pixels whose ray points behind the original camera, far
+/// enough outside the image that every interpolation samples only the border.
+const BEHIND_CAMERA: f64 = -1.0e6;
+
+/// Number of points along each edge of an image used to find the extent of its undistorted
+/// pixels.
+const EXTENT_GRID_SIZE: usize = 9;
+
 // -----------------------------------------------------------------------------------------------
 // DATA STRUCTURES
 // -----------------------------------------------------------------------------------------------
 
 /// Recitication parameters for a single camera.
 ///
-/// These items map directly to the [`CameraIntrinsics`] structs, with the option of including a
-/// k1 parameter for radial distortion.
-#[derive(Deserialize, Debug, Copy, Clone)]
+/// These items map directly to the [`CameraIntrinsics`] structs, with optional distortion
+/// coefficients. A lone `k1` uses the division model of [`CameraIntrinsicsK1Distortion`], while
+/// any of `k2`, `k3`, `p1` or `p2` select the Brown-Conrady plumb bob model and any of `k4`, `k5`
+/// or `k6` its rational variant, see [`DistortionModel`]. The model can also be given explicitly
+/// with `distortion_model`, for example to use the plumb bob model with only `k1`.
+///
+/// Coefficients which are not given are left out when the parameters are serialised.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq)]
 pub struct RectifParams {
     /// Focal lengths (normalised by X and Y pixel sizes)
     pub focals: [f64; 2],
@@ -34,12 +53,60 @@ pub struct RectifParams {
     pub skew: f64,
     
     /// First distortion coefficient
-    pub k1: Option<f64>
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub k1: Option<f64>,
+
+    /// Second radial distortion coefficient
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub k2: Option<f64>,
+
+    /// Third radial distortion coefficient
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub k3: Option<f64>,
+
+    /// First radial distortion coefficient of the denominator of the rational model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub k4: Option<f64>,
+
+    /// Second radial distortion coefficient of the denominator of the rational model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub k5: Option<f64>,
+
+    /// Third radial distortion coefficient of the denominator of the rational model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub k6: Option<f64>,
+
+    /// First tangential distortion coefficient
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p1: Option<f64>,
+
+    /// Second tangential distortion coefficient
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p2: Option<f64>,
+
+    /// The distortion model, inferred from the coefficients which are given if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distortion_model: Option<DistortionModel>
 }
 
 /// Rectification parameters for a pair of stereo cameras
-#[derive(Deserialize, Debug, Copy, Clone)]
+///
+/// If the rotation and translation between the cameras are given the images are rotated so that
+/// corresponding points lie on the same row of both images, otherwise each image is only
+/// undistorted.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
 pub struct StereoRectifParams {
+    // The extrinsics come before the cameras as TOML must write values before tables
+
+    /// Rotation from the left camera's frame to the right camera's frame, as a row major matrix
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<[[f64; 3]; 3]>,
+
+    /// Translation from the left camera's frame to the right camera's frame, so that a point
+    /// `X` in the left frame is at `R·X + T` in the right frame
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub translation: Option<[f64; 3]>,
+
     /// Left hand camera parameters
     pub left: RectifParams,
 
@@ -47,6 +114,130 @@ pub struct StereoRectifParams {
     pub right: RectifParams
 }
 
+/// The result of stereo rectification, describing how to rotate each camera so that their image
+/// rows are aligned, and the pinhole camera both rectified images are projected into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StereoRectification {
+    /// Rotation from the original left camera frame to the rectified left camera frame
+    pub left_rotation: Matrix3<f64>,
+
+    /// Rotation from the original right camera frame to the rectified right camera frame
+    pub right_rotation: Matrix3<f64>,
+
+    /// Intrinsics shared by both rectified images
+    pub intrinsics: CameraIntrinsics,
+
+    /// Translation from the rectified left camera frame to the rectified right camera frame, in
+    /// the units of the stereo translation. This lies along the X axis (or the Y axis for a
+    /// vertical rig), and for a right camera to the right of the left its X value is negative,
+    /// as in OpenCV.
+    pub translation: Vector3<f64>
+}
+
+/// An axis aligned rectangle of pixels in an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Roi {
+    /// Column of the left edge of the rectangle
+    pub x: u32,
+
+    /// Row of the top edge of the rectangle
+    pub y: u32,
+
+    /// Width of the rectangle in pixels
+    pub width: u32,
+
+    /// Height of the rectangle in pixels
+    pub height: u32
+}
+
+/// A lookup table mapping each pixel of a rectified image to the position it is sampled from in
+/// the original image.
+///
+/// Computing the table is much more expensive than applying it, so streams build one table per
+/// camera and reuse it for every frame of the same resolution.
+#[derive(Debug, Clone)]
+pub struct RemapTable {
+    /// Resolution of the rectified images
+    resolution: (u32, u32),
+
+    /// Resolution of the original images
+    source_resolution: (u32, u32),
+
+    /// Source position of each pixel, in row major order
+    coords: Vec<KeyPoint>,
+
+    /// Intrinsics of the camera the rectified image is projected into
+    intrinsics: CameraIntrinsics,
+
+    interpolation: Interpolation,
+
+    border_mode: BorderMode
+}
+
+/// Options controlling how streams resample their images during rectification.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct RectifOptions {
+    pub(crate) interpolation: Interpolation,
+
+    pub(crate) border_mode: BorderMode,
+
+    /// Scale of the rectified images, if not the default for the distortion model
+    pub(crate) output_scale: Option<OutputScale>,
+
+    /// Resolution of the rectified images, if not that of the original images
+    pub(crate) output_resolution: Option<(u32, u32)>,
+
+    /// Whether to crop the rectified images to the largest rectangle of valid pixels, which for
+    /// stereo cameras is the rectangle valid in both images
+    pub(crate) crop_to_roi: bool
+}
+
+/// The extent of an image in normalised coordinates.
+#[derive(Debug, Clone, Copy)]
+struct Extent {
+    min: Vector2<f64>,
+
+    max: Vector2<f64>
+}
+
+// -----------------------------------------------------------------------------------------------
+// ENUMERATIONS
+// -----------------------------------------------------------------------------------------------
+
+/// One of the cameras of a stereo pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StereoSide {
+    /// The left hand camera
+    Left,
+
+    /// The right hand camera
+    Right
+}
+
+/// The parameters a stream uses to rectify the images of one of its cameras.
+#[derive(Debug, Clone)]
+pub(crate) enum Rectifier {
+    /// The camera of a mono stream
+    Mono(RectifParams),
+
+    /// One side of a stereo stream, which depends on the parameters of both cameras
+    Stereo(Box<StereoRectifParams>, StereoSide)
+}
+
+/// Ways of choosing the scale of rectified images.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum OutputScale {
+    /// A focal length in pixels, centred on the image
+    Focal(f64),
+
+    /// A horizontal field of view in degrees, centred on the image
+    FieldOfView(f64),
+
+    /// A free scaling parameter between 0, where only valid pixels are visible, and 1, where
+    /// every pixel of the original image is visible
+    Alpha(f64)
+}
+
 // -----------------------------------------------------------------------------------------------
 // IMPLEMENTATIONS
 // -----------------------------------------------------------------------------------------------
@@ -58,7 +249,10 @@ impl RectifParams {
     /// The conversion will fail if `self.k1` is not `None`, as this would discard the value 
     /// possibly resulting in an incorrect rectification.
     pub fn to_pinhole_intrisics(&self) -> Result<CameraIntrinsics> {
-        if self.k1.is_none() {
+        if self.distortion_model() != DistortionModel::Division {
+            Err(Error::RectifDistortionModelError(self.distortion_model()))
+        }
+        else if self.k1.is_none() {
             Ok(CameraIntrinsics {
                 focals: Vector2::from(self.focals),
                 principal_point: Point2::from(self.principal_point),
@@ -74,7 +268,10 @@ impl RectifParams {
     ///
     /// The conversion will fail if `self.k1` is `None`.
     pub fn to_pinhole_intrisics_k1(&self) -> Result<CameraIntrinsicsK1Distortion> {
-        if self.k1.is_some() {
+        if self.distortion_model() != DistortionModel::Division {
+            Err(Error::RectifDistortionModelError(self.distortion_model()))
+        }
+        else if self.k1.is_some() {
             Ok(CameraIntrinsicsK1Distortion {
                 simple_intrinsics: CameraIntrinsics {
                     focals: Vector2::from(self.focals),
@@ -89,76 +286,977 @@ impl RectifParams {
         }
     }
 
-    /// Rectify an image using these parameters
+    /// Rectify an image using these parameters.
+    ///
+    /// This builds a new [`RemapTable`] for the image on every call, so when rectifying many
+    /// images of the same size build the table once with [`RectifParams::remap_table`] instead.
     pub fn rectify(&self, img: &GrayFloatImage) -> GrayFloatImage {
+        self.remap_table((img.width() as u32, img.height() as u32)).remap(img)
+    }
+
+    /// Get the distortion model described by these parameters.
+    ///
+    /// This is `distortion_model` if it is set, otherwise the simplest model which uses all of
+    /// the coefficients that are given.
+    pub fn distortion_model(&self) -> DistortionModel {
+        if let Some(model) = self.distortion_model {
+            model
+        }
+        else if self.k4.is_some() || self.k5.is_some() || self.k6.is_some() {
+            DistortionModel::RationalPolynomial
+        }
+        else if self.k2.is_some() || self.k3.is_some() || self.p1.is_some() || self.p2.is_some() {
+            DistortionModel::PlumbBob
+        }
+        else {
+            DistortionModel::Division
+        }
+    }
+
+    /// Convert the rectification parameters into a [`BrownConrady`] camera model, treating any
+    /// coefficients which are not given as zero.
+    ///
+    /// The conversion will fail if the parameters use the division model.
+    pub fn to_brown_conrady(&self) -> Result<BrownConrady> {
+        let coeff = |c: Option<f64>| c.unwrap_or(0.0);
+
+        // The denominator coefficients are only used by the rational model
+        let rational = |c: Option<f64>| match self.distortion_model() {
+            DistortionModel::RationalPolynomial => coeff(c),
+            _ => 0.0
+        };
+
+        match self.distortion_model() {
+            model @ DistortionModel::Division | model @ DistortionModel::Equidistant => {
+                Err(Error::RectifDistortionModelError(model))
+            },
+            DistortionModel::PlumbBob | DistortionModel::RationalPolynomial => Ok(BrownConrady {
+                intrinsics: self.intrinsics(),
+                radial: [
+                    coeff(self.k1), coeff(self.k2), coeff(self.k3),
+                    rational(self.k4), rational(self.k5), rational(self.k6)
+                ],
+                tangential: [coeff(self.p1), coeff(self.p2)]
+            })
+        }
+    }
+
+    /// Convert the rectification parameters into a [`KannalaBrandt`] fisheye camera model,
+    /// treating any of `k1` to `k4` which are not given as zero.
+    ///
+    /// The conversion will fail if the parameters don't use the equidistant model.
+    pub fn to_kannala_brandt(&self) -> Result<KannalaBrandt> {
+        let coeff = |c: Option<f64>| c.unwrap_or(0.0);
+
+        match self.distortion_model() {
+            DistortionModel::Equidistant => Ok(KannalaBrandt {
+                intrinsics: self.intrinsics(),
+                coeffs: [coeff(self.k1), coeff(self.k2), coeff(self.k3), coeff(self.k4)]
+            }),
+            model => Err(Error::RectifDistortionModelError(model))
+        }
+    }
+
+    /// Get the camera model described by these parameters, including its distortion.
+    pub fn lens_model(&self) -> LensModel {
+        match self.distortion_model() {
+            DistortionModel::Division => match self.k1 {
+                Some(k1) => LensModel::K1(CameraIntrinsicsK1Distortion::new(
+                    self.intrinsics(), k1
+                )),
+                None => LensModel::Pinhole(self.intrinsics())
+            },
+            DistortionModel::PlumbBob | DistortionModel::RationalPolynomial => {
+                LensModel::BrownConrady(self.to_brown_conrady().unwrap())
+            },
+            DistortionModel::Equidistant => {
+                LensModel::KannalaBrandt(self.to_kannala_brandt().unwrap())
+            }
+        }
+    }
+
+    /// Find the undistorted normalised coordinates of a point in the original image.
+    ///
+    /// For the Brown-Conrady models this is found iteratively.
+    pub fn undistort_point(&self, point: KeyPoint) -> NormalizedKeyPoint {
+        self.lens_model().calibrate(point)
+    }
+
+    /// Find the point in the original image which the given undistorted normalised coordinates
+    /// are imaged at.
+    pub fn distort_point(&self, point: NormalizedKeyPoint) -> KeyPoint {
+        self.lens_model().uncalibrate(point)
+    }
+
+    /// Get the pinhole camera which rectified images of the given resolution are projected into
+    /// by default.
+    ///
+    /// For the fisheye model this has the same intrinsics as the original camera, which keeps
+    /// the scale at the centre of the image. For the other models it is chosen so that the
+    /// undistorted corners of the original image lie at the corners of the rectified image.
+    pub fn output_intrinsics(&self, resolution: (u32, u32)) -> CameraIntrinsics {
+        self.output_intrinsics_sized(resolution, resolution)
+    }
+
+    /// Get the pinhole camera which rectified images are projected into by default, when the
+    /// original and rectified images have different resolutions.
+    ///
+    /// This is as [`RectifParams::output_intrinsics`], except that for the fisheye model the
+    /// principal point is moved to keep the same offset from the centre of the image.
+    pub fn output_intrinsics_sized(
+        &self,
+        source_resolution: (u32, u32),
+        output_resolution: (u32, u32)
+    ) -> CameraIntrinsics {
+        if self.distortion_model() == DistortionModel::Equidistant {
+            let mut intrinsics = self.intrinsics();
+            intrinsics.principal_point += Vector2::new(
+                (output_resolution.0 as f64 - source_resolution.0 as f64) / 2.0,
+                (output_resolution.1 as f64 - source_resolution.1 as f64) / 2.0
+            );
+
+            return intrinsics;
+        }
+
+        let (width, height) = (source_resolution.0 as f64, source_resolution.1 as f64);
+        let model = self.lens_model();
+
+        // Get top left and bottom right corners of the image in normalised coordinates.
+        let tl_normkp = model.calibrate(KeyPoint(Point2::from([0.0, 0.0])));
+        let br_normkp = model.calibrate(KeyPoint(Point2::from([width, height])));
+
+        Extent {
+            min: tl_normkp.0.coords,
+            max: br_normkp.0.coords
+        }.fit(output_resolution)
+    }
+
+    /// Get the pinhole camera which rectified images should be projected into to show the
+    /// chosen proportion of the original image, as OpenCV's `getOptimalNewCameraMatrix`.
+    ///
+    /// With `alpha` of 0 the rectified image is scaled so that it only contains valid pixels,
+    /// and with an `alpha` of 1 so that it contains every pixel of the original image, leaving
+    /// invalid areas around it. Values in between blend the two.
+    pub fn optimal_intrinsics(
+        &self,
+        source_resolution: (u32, u32),
+        alpha: f64,
+        output_resolution: (u32, u32)
+    ) -> CameraIntrinsics {
+        let (inner, outer) = Extent::of_undistorted(&self.lens_model(), None, source_resolution);
+
+        blend_intrinsics(
+            &inner.fit(output_resolution),
+            &outer.fit(output_resolution),
+            alpha
+        )
+    }
+
+    /// Build the table mapping each pixel of a rectified image of the given resolution to its
+    /// position in the original image, using the default [`RectifParams::output_intrinsics`].
+    pub fn remap_table(&self, resolution: (u32, u32)) -> RemapTable {
+        self.remap_table_to(resolution, &self.output_intrinsics(resolution))
+    }
+
+    /// Build the table mapping each pixel of a rectified image of the given resolution to its
+    /// position in the original image, where the rectified image is projected into the pinhole
+    /// camera `output`.
+    pub fn remap_table_to(&self, resolution: (u32, u32), output: &CameraIntrinsics) -> RemapTable {
+        self.remap_table_into(resolution, resolution, output)
+    }
+
+    /// Build the table mapping each pixel of a rectified image of the output resolution to its
+    /// position in an original image of the source resolution, where the rectified image is
+    /// projected into the pinhole camera `output`.
+    pub fn remap_table_into(
+        &self,
+        source_resolution: (u32, u32),
+        output_resolution: (u32, u32),
+        output: &CameraIntrinsics
+    ) -> RemapTable {
+        RemapTable::new(&self.lens_model(), output, None, source_resolution, output_resolution)
+    }
+
+    /// The distortion free intrinsics of the camera.
+    fn intrinsics(&self) -> CameraIntrinsics {
+        CameraIntrinsics {
+            focals: Vector2::from(self.focals),
+            principal_point: Point2::from(self.principal_point),
+            skew: self.skew
+        }
+    }
+}
+
+impl StereoRectifParams {
+
+    /// Get the parameters of one of the cameras.
+    pub fn side(&self, side: StereoSide) -> &RectifParams {
+        match side {
+            StereoSide::Left => &self.left,
+            StereoSide::Right => &self.right
+        }
+    }
+
+    /// Get the rotation and translation from the left camera to the right camera, if both are
+    /// given.
+    pub fn extrinsics(&self) -> Option<(Matrix3<f64>, Vector3<f64>)> {
+        match (self.rotation, self.translation) {
+            (Some(r), Some(t)) => Some((
+                Matrix3::from_fn(|i, j| r[i][j]),
+                Vector3::from(t)
+            )),
+            _ => None
+        }
+    }
+
+    /// Compute the rectification of images of the given resolution, if the extrinsics are
+    /// given.
+    ///
+    /// The rotation between the cameras is split evenly between them, then both are rotated so
+    /// that the baseline lies along their X axes, as in Bouguet's method. The focal length of
+    /// the rectified images is the smaller of the vertical focal lengths of the cameras, and
+    /// both share a principal point which centres the undistorted images.
+    pub fn rectification(&self, resolution: (u32, u32)) -> Option<StereoRectification> {
+        self.uncropped_rectification(resolution, &RectifOptions::default())
+    }
+
+    /// Build the tables which rectify images of the given resolution from the left and right
+    /// cameras.
+    ///
+    /// Without extrinsics each image is undistorted independently, as with
+    /// [`RectifParams::remap_table`].
+    pub fn remap_tables(&self, resolution: (u32, u32)) -> (RemapTable, RemapTable) {
+        self.remap_tables_with(resolution, &RectifOptions::default())
+    }
+
+    /// Get the largest rectangle of pixels which are valid in both rectified images, for images
+    /// of the given resolution.
+    ///
+    /// The rectangles valid in each image are given by [`RemapTable::valid_roi`] on the tables
+    /// from [`StereoRectifParams::remap_tables`].
+    pub fn common_roi(&self, resolution: (u32, u32)) -> Roi {
+        let (left, right) = self.uncropped_remap_tables(resolution, &RectifOptions::default());
+
+        left.common_roi(&right)
+    }
+
+    /// Build the tables which rectify images from both cameras with the given options, cropped to
+    /// the rectangle valid in both if the options ask for it.
+    ///
+    /// The rectification and the uncropped tables are only computed once for both cameras.
+    pub(crate) fn remap_tables_with(
+        &self,
+        resolution: (u32, u32),
+        options: &RectifOptions
+    ) -> (RemapTable, RemapTable) {
+        let (left, right) = self.uncropped_remap_tables(resolution, options);
+
+        let (left, right) = if options.crop_to_roi {
+            let roi = left.common_roi(&right);
+            (left.crop(roi), right.crop(roi))
+        } else {
+            (left, right)
+        };
+
+        (left.border_mode(options.border_mode), right.border_mode(options.border_mode))
+    }
+
+    /// Compute the rectification of images with the given options, ignoring any cropping.
+    ///
+    /// The intrinsics of cropped images are those of the cropped tables.
+    pub(crate) fn uncropped_rectification(
+        &self,
+        resolution: (u32, u32),
+        options: &RectifOptions
+    ) -> Option<StereoRectification> {
+        let (rotation, translation) = self.extrinsics()?;
+
+        // Rotate each camera half way towards the other, leaving them parallel
+        let half = Rotation3::from_matrix(&rotation).scaled_axis() * -0.5;
+        let r_r = Rotation3::new(half).into_inner();
+        let t = r_r * translation;
+
+        // Then rotate both about the axis which brings the baseline onto the X axis, or the Y
+        // axis for a vertical rig
+        let idx = if t.x.abs() > t.y.abs() { 0 } else { 1 };
+        let mut uu = Vector3::zeros();
+        uu[idx] = if t[idx] > 0.0 { 1.0 } else { -1.0 };
+
+        let ww = t.cross(&uu);
+        let nw = ww.norm();
+        let ww = if nw > 0.0 { ww * ((t[idx].abs() / t.norm()).acos() / nw) } else { ww };
+        let w_r = Rotation3::new(ww).into_inner();
+
+        let left_rotation = w_r * r_r.transpose();
+        let right_rotation = w_r * r_r;
+
+        let output_resolution = options.output_resolution(resolution);
+        let cameras = [
+            (&self.left, &left_rotation),
+            (&self.right, &right_rotation)
+        ];
+
+        let intrinsics = match options.output_scale {
+            Some(OutputScale::Alpha(alpha)) => {
+                // Only pixels valid in both images are kept at 0, and every pixel of either
+                // image at 1
+                let (inner, outer) = cameras.iter()
+                    .map(|(params, rotation)| {
+                        Extent::of_undistorted(&params.lens_model(), Some(rotation), resolution)
+                    })
+                    .fold(None, |acc: Option<(Extent, Extent)>, (inner, outer)| match acc {
+                        Some((i, o)) => Some((i.intersection(&inner), o.union(&outer))),
+                        None => Some((inner, outer))
+                    })
+                    .unwrap();
+
+                blend_intrinsics(
+                    &inner.fit(output_resolution),
+                    &outer.fit(output_resolution),
+                    alpha
+                )
+            },
+            scale => {
+                let focal = match scale {
+                    Some(scale) => scale.focal(output_resolution),
+
+                    // Use the focal length across the baseline, so that no rows are lost,
+                    // scaled with the output resolution
+                    None => {
+                        let (l, r) = (self.left.focals, self.right.focals);
+
+                        l[1 - idx].min(r[1 - idx])
+                            * output_resolution.0 as f64 / resolution.0 as f64
+                    }
+                };
+
+                // Find the principal point which centres each undistorted image, and use their
+                // mean
+                let (width, height) = (resolution.0 as f64, resolution.1 as f64);
+                let corners = [(0.0, 0.0), (width, 0.0), (0.0, height), (width, height)];
+                let out_centre = Vector2::new(
+                    output_resolution.0 as f64 / 2.0,
+                    output_resolution.1 as f64 / 2.0
+                );
+
+                let centre = cameras.iter()
+                    .map(|(params, rotation)| {
+                        let model = params.lens_model();
+
+                        let mean = corners.iter()
+                            .map(|&(x, y)| {
+                                let n = model.calibrate(KeyPoint(Point2::new(x, y)));
+                                let p = *rotation * Vector3::new(n.0.x, n.0.y, 1.0);
+
+                                Vector2::new(p.x / p.z, p.y / p.z) * focal
+                            })
+                            .fold(Vector2::zeros(), |sum, p| sum + p) / corners.len() as f64;
+
+                        out_centre - mean
+                    })
+                    .fold(Vector2::zeros(), |sum, c| sum + c) / 2.0;
+
+                CameraIntrinsics {
+                    focals: Vector2::new(focal, focal),
+                    principal_point: Point2::from(centre),
+                    skew: 0.0
+                }
+            }
+        };
+
+        Some(StereoRectification {
+            left_rotation,
+            right_rotation,
+            intrinsics,
+            translation: right_rotation * translation
+        })
+    }
+
+    /// Build the table which rectifies images from one of the cameras.
+    ///
+    /// Cropping needs the table of the other camera to find the rectangle valid in both, which
+    /// is built from the same rectification.
+    pub(crate) fn side_remap_table(
+        &self,
+        side: StereoSide,
+        resolution: (u32, u32),
+        options: &RectifOptions
+    ) -> RemapTable {
+        let rect = self.uncropped_rectification(resolution, options);
+        let table = self.uncropped_remap_table(side, rect.as_ref(), resolution, options);
+
+        if options.crop_to_roi {
+            let other = match side {
+                StereoSide::Left => StereoSide::Right,
+                StereoSide::Right => StereoSide::Left
+            };
+            let roi = table.common_roi(
+                &self.uncropped_remap_table(other, rect.as_ref(), resolution, options)
+            );
+
+            table.crop(roi)
+        } else {
+            table
+        }
+    }
+
+    /// Build the tables which rectify images from both cameras, ignoring any cropping.
+    fn uncropped_remap_tables(
+        &self,
+        resolution: (u32, u32),
+        options: &RectifOptions
+    ) -> (RemapTable, RemapTable) {
+        let rect = self.uncropped_rectification(resolution, options);
+
+        (
+            self.uncropped_remap_table(StereoSide::Left, rect.as_ref(), resolution, options),
+            self.uncropped_remap_table(StereoSide::Right, rect.as_ref(), resolution, options)
+        )
+    }
+
+    /// Build the table which rectifies images from one of the cameras with the uncropped
+    /// rectification of the same options, or undistorts them without one.
+    ///
+    /// The table uses the interpolation of the options, which determines its valid pixels.
+    fn uncropped_remap_table(
+        &self,
+        side: StereoSide,
+        rectification: Option<&StereoRectification>,
+        resolution: (u32, u32),
+        options: &RectifOptions
+    ) -> RemapTable {
+        let params = self.side(side);
+
+        let table = match rectification {
+            Some(rect) => RemapTable::new(
+                &params.lens_model(),
+                &rect.intrinsics,
+                Some(&rect.rotation(side)),
+                resolution,
+                options.output_resolution(resolution)
+            ),
+            None => params.remap_table_into(
+                resolution,
+                options.output_resolution(resolution),
+                &options.mono_intrinsics(params, resolution)
+            )
+        };
+
+        table.interpolation(options.interpolation)
+    }
+}
+
+impl StereoRectification {
+
+    /// Get the rotation from the original frame of one of the cameras to its rectified frame.
+    pub fn rotation(&self, side: StereoSide) -> Matrix3<f64> {
+        match side {
+            StereoSide::Left => self.left_rotation,
+            StereoSide::Right => self.right_rotation
+        }
+    }
+
+    /// Find where a point in an original image appears in the rectified image of that camera.
+    pub fn rectify_point(
+        &self,
+        params: &StereoRectifParams,
+        side: StereoSide,
+        point: KeyPoint
+    ) -> KeyPoint {
+        let n = params.side(side).undistort_point(point);
+        let p = self.rotation(side) * Vector3::new(n.0.x, n.0.y, 1.0);
+
+        self.intrinsics.uncalibrate(NormalizedKeyPoint(Point2::new(p.x / p.z, p.y / p.z)))
+    }
+
+    /// Get the camera matrix shared by both rectified images.
+    pub fn camera_matrix(&self) -> Matrix3<f64> {
+        self.intrinsics.matrix()
+    }
+
+    /// Get the projection matrices of the left and right rectified cameras, which project points
+    /// in the rectified left camera frame into each rectified image.
+    pub fn projection_matrices(&self) -> (Matrix3x4<f64>, Matrix3x4<f64>) {
+        let k = self.camera_matrix();
+
+        let mut left = Matrix3x4::zeros();
+        left.fixed_slice_mut::<nalgebra::U3, nalgebra::U3>(0, 0).copy_from(&k);
+
+        let mut right = left;
+        right.set_column(3, &(k * self.translation));
+
+        (left, right)
+    }
+
+    /// Get the distance between the cameras, in the units of the stereo translation.
+    pub fn baseline(&self) -> f64 {
+        self.translation.norm()
+    }
+
+    /// Get the disparity to depth reprojection matrix `Q`.
+    ///
+    /// A position `(x, y)` in the left rectified image with disparity `d` is at the point
+    /// `(X/W, Y/W, Z/W)` in the rectified left camera frame, where `[X, Y, Z, W] = Q·[x, y, d, 1]`.
+    /// Positions are in the crate's pixel coordinates, so the centre of the pixel in column `i`
+    /// and row `j` is `(i + 0.5, j + 0.5)`, not `(i, j)` as in OpenCV. For a vertical rig the
+    /// disparity is measured along the columns instead.
+    pub fn reprojection_matrix(&self) -> Matrix4<f64> {
+        let f = self.intrinsics.focals.x;
+        let c = self.intrinsics.principal_point;
+
+        // The component of the translation along the baseline
+        let t = if self.translation.x.abs() > self.translation.y.abs() {
+            self.translation.x
+        } else {
+            self.translation.y
+        };
+
+        Matrix4::new(
+            1.0, 0.0, 0.0, -c.x,
+            0.0, 1.0, 0.0, -c.y,
+            0.0, 0.0, 0.0, f,
+            0.0, 0.0, -1.0 / t, 0.0
+        )
+    }
+}
+
+impl RemapTable {
+
+    /// Compute the source position in an image from the camera `model` of every pixel in an
+    /// image of the given resolution from the camera `output`.
+    ///
+    /// If a rotation is given the output camera is rotated by it from the original camera, that
+    /// is it transforms points from the original camera's frame into the output camera's frame.
+    fn new<C>(
+        model: &C,
+        output: &CameraIntrinsics,
+        rotation: Option<&Matrix3<f64>>,
+        source_resolution: (u32, u32),
+        resolution: (u32, u32)
+    ) -> Self
+    where
+        C: CameraModel<Projection = NormalizedKeyPoint>
+    {
+        let (width, height) = resolution;
+
+        let mut coords = Vec::with_capacity(width as usize * height as usize);
+
+        for y in 0..height {
+            for x in 0..width {
+                // Get the normalised keypoint value for the centre of this pixel
+                let normkp = output.calibrate(KeyPoint(Point2::new(
+                    x as f64 + 0.5,
+                    y as f64 + 0.5
+                )));
+
+                // Rotate the ray back into the original camera's frame
+                let normkp = match rotation {
+                    Some(r) => {
+                        let ray = r.transpose() * Vector3::new(normkp.0.x, normkp.0.y, 1.0);
+
+                        // Rays behind the original camera can't be imaged at all
+                        if ray.z <= 0.0 {
+                            coords.push(KeyPoint(Point2::new(BEHIND_CAMERA, BEHIND_CAMERA)));
+                            continue;
+                        }
+
+                        NormalizedKeyPoint(Point2::new(ray.x / ray.z, ray.y / ray.z))
+                    },
+                    None => normkp
+                };
+
+                // Reproject to find the keypoint coordinates
+                coords.push(model.uncalibrate(normkp));
+            }
+        }
+
+        Self {
+            resolution,
+            source_resolution,
+            coords,
+            intrinsics: *output,
+            interpolation: Interpolation::default(),
+            border_mode: BorderMode::default()
+        }
+    }
 
-        // New empty image of equal size and colour space to the input image
+    /// Set the method used to sample the original image, default is
+    /// [`Interpolation::Bilinear`].
+    pub fn interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+
+        self
+    }
+
+    /// Set how samples which fall outside the original image are treated, default is
+    /// [`BorderMode::Constant`] with a value of zero.
+    pub fn border_mode(mut self, border_mode: BorderMode) -> Self {
+        self.border_mode = border_mode;
+
+        self
+    }
+
+    /// Get the intrinsics of the pinhole camera the rectified images are projected into.
+    pub fn intrinsics(&self) -> CameraIntrinsics {
+        self.intrinsics
+    }
+
+    /// Get the resolution of the rectified images this table produces.
+    pub fn resolution(&self) -> (u32, u32) {
+        self.resolution
+    }
+
+    /// Get the resolution of the original images this table applies to.
+    pub fn source_resolution(&self) -> (u32, u32) {
+        self.source_resolution
+    }
+
+    /// Get the position in the original image which the given pixel of the rectified image is
+    /// sampled from.
+    pub fn source(&self, x: u32, y: u32) -> KeyPoint {
+        self.coords[(y * self.resolution.0 + x) as usize]
+    }
+
+    /// Check whether the given pixel of the rectified image is sampled from within the original
+    /// image, rather than being filled by the border mode.
+    ///
+    /// A pixel is only valid if every original pixel its interpolation reads lies within the
+    /// image, so the valid area shrinks by half a pixel for bilinear sampling and by up to two and
+    /// a half pixels for Lanczos sampling.
+    pub fn is_valid(&self, x: u32, y: u32) -> bool {
+        self.is_valid_index((y * self.resolution.0 + x) as usize)
+    }
+
+    /// Get a mask of the valid pixels of the rectified image, which are 1 where the pixel is
+    /// sampled from within the original image and 0 elsewhere.
+    pub fn valid_mask(&self) -> GrayFloatImage {
+        let mut mask = GrayFloatImage::new(
+            self.resolution.0 as usize,
+            self.resolution.1 as usize
+        );
+
+        for (i, px) in mask.0.pixels_mut().enumerate() {
+            px.0[0] = if self.is_valid_index(i) { 1.0 } else { 0.0 };
+        }
+
+        mask
+    }
+
+    /// Get the largest axis aligned rectangle of the rectified image containing only valid
+    /// pixels.
+    pub fn valid_roi(&self) -> Roi {
+        Roi::largest(self.resolution, |i| self.is_valid_index(i))
+    }
+
+    /// Get the largest axis aligned rectangle containing only pixels which are valid in both this
+    /// and another table of the same resolution, such as the tables of a stereo pair.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tables have different resolutions.
+    pub fn common_roi(&self, other: &RemapTable) -> Roi {
+        assert_eq!(self.resolution, other.resolution, "Remap table resolutions differ");
+
+        Roi::largest(self.resolution, |i| self.is_valid_index(i) && other.is_valid_index(i))
+    }
+
+    /// Crop the rectified images to the given rectangle, which is also removed from the principal
+    /// point of the output camera.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rectangle does not lie within the rectified image.
+    pub fn crop(mut self, roi: Roi) -> Self {
+        assert!(
+            roi.x + roi.width <= self.resolution.0 && roi.y + roi.height <= self.resolution.1,
+            "Crop rectangle lies outside the rectified image"
+        );
+
+        let width = self.resolution.0 as usize;
+        self.coords = (roi.y as usize..(roi.y + roi.height) as usize)
+            .flat_map(|y| {
+                let start = y * width + roi.x as usize;
+                self.coords[start..start + roi.width as usize].to_vec()
+            })
+            .collect();
+
+        self.resolution = (roi.width, roi.height);
+        self.intrinsics.principal_point -= Vector2::new(roi.x as f64, roi.y as f64);
+
+        self
+    }
+
+    /// Check whether the pixel with the given row major index is sampled from within the original
+    /// image, including the support of the interpolation.
+    fn is_valid_index(&self, i: usize) -> bool {
+        let kp = self.coords[i];
+        let margin = self.interpolation.border_margin();
+
+        kp.0.x >= margin
+            && kp.0.y >= margin
+            && kp.0.x <= self.source_resolution.0 as f64 - margin
+            && kp.0.y <= self.source_resolution.1 as f64 - margin
+    }
+
+    /// Rectify an image by sampling each pixel from its position in the table.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the image does not have the source resolution of the table.
+    pub fn remap(&self, img: &GrayFloatImage) -> GrayFloatImage {
+        assert_eq!(
+            (img.width() as u32, img.height() as u32),
+            self.source_resolution,
+            "Image resolution does not match the remap table"
+        );
+
+        // New empty image of the rectified size
         let mut rect_img = GrayFloatImage::new(
-            img.width() as usize, 
-            img.height() as usize
+            self.resolution.0 as usize,
+            self.resolution.1 as usize
         );
 
-        // Depending on whether or not there is a k1 value
-        match self.k1 {
-            Some(_) => {
-                // If there is a k1 value use the radial distorsion coefficient as well.
-                let intrinsics = self.to_pinhole_intrisics_k1().unwrap();
+        for (px, kp) in rect_img.0.pixels_mut().zip(self.coords.iter()) {
+            // Set the pixel value for the new image
+            px.0[0] = sample(img, kp.0.x, kp.0.y, self.interpolation, self.border_mode);
+        }
 
-                // Get top left and bottom right corners of the image in normalised coordinates.
-                let tl_normkp = intrinsics.calibrate(KeyPoint(Point2::from([0.0, 0.0])));
-                let br_normkp = intrinsics.calibrate(KeyPoint(Point2::from(
-                    [img.width() as f64, img.height() as f64]
-                )));
+        rect_img
+    }
+}
+
+impl Roi {
+
+    /// Get the number of pixels in the rectangle.
+    pub fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    /// Get the rectangle covered by both rectangles, which is empty if they don't overlap.
+    pub fn intersection(&self, other: &Roi) -> Roi {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+
+        if right <= x || bottom <= y {
+            return Roi::default();
+        }
+
+        Roi {
+            x,
+            y,
+            width: right - x,
+            height: bottom - y
+        }
+    }
+
+    /// Find the largest rectangle in an image of the given resolution containing only pixels
+    /// whose row major index is valid.
+    fn largest<F: Fn(usize) -> bool>(resolution: (u32, u32), valid: F) -> Roi {
+        let (width, height) = (resolution.0 as usize, resolution.1 as usize);
+
+        // Number of valid pixels in each column ending at the current row
+        let mut heights = vec![0u32; width];
+        let mut stack: Vec<usize> = Vec::with_capacity(width + 1);
+        let mut best = Roi::default();
+
+        for y in 0..height {
+            for (x, h) in heights.iter_mut().enumerate() {
+                *h = if valid(y * width + x) { *h + 1 } else { 0 };
+            }
 
-                for y in 0..rect_img.height() as u32 {
-                    for x in 0..rect_img.width() as u32 {
-                        // Get the normalised keypoint value for this position
-                        let normkp = image_xy_to_normkp(
-                            x, y,
-                            rect_img.width() as u32, rect_img.height() as u32,
-                            tl_normkp, br_normkp
-                        );
-
-                        // Reproject to find the keypoint coordinates
-                        let kp = intrinsics.uncalibrate(normkp);
-
-                        // Set the pixel value for the new image
-                        *rect_img.0.get_pixel_mut(x, y) = linterp_pixels(kp, img);
-                    }   
+            // Find the widest rectangle of each height, which is bounded on either side by the
+            // nearest shorter columns
+            stack.clear();
+            for x in 0..=width {
+                let h = heights.get(x).copied().unwrap_or(0);
+
+                while let Some(&top) = stack.last() {
+                    if heights[top] < h {
+                        break;
+                    }
+                    stack.pop();
+
+                    let left = stack.last().map_or(0, |&l| l + 1);
+                    let roi = Roi {
+                        x: left as u32,
+                        y: y as u32 + 1 - heights[top],
+                        width: (x - left) as u32,
+                        height: heights[top]
+                    };
+
+                    if roi.area() > best.area() {
+                        best = roi;
+                    }
                 }
 
-                rect_img
+                stack.push(x);
+            }
+        }
 
+        best
+    }
+}
+
+impl OutputScale {
+
+    /// Get the focal length in pixels for an image of the given resolution.
+    ///
+    /// # Panics
+    ///
+    /// Panics for [`OutputScale::Alpha`], which doesn't give a focal length on its own.
+    fn focal(&self, resolution: (u32, u32)) -> f64 {
+        match *self {
+            OutputScale::Focal(focal) => focal,
+            OutputScale::FieldOfView(fov) => {
+                (resolution.0 as f64 / 2.0) / (fov.to_radians() / 2.0).tan()
             },
-            None => {
-                // If no k1 value use a simple pinhole model
-                let intrinsics = self.to_pinhole_intrisics().unwrap();
+            OutputScale::Alpha(_) => unreachable!("Alpha scaling has no fixed focal length")
+        }
+    }
+}
 
-                let tl_normkp = intrinsics.calibrate(KeyPoint(Point2::from([0.0, 0.0])));
-                let br_normkp = intrinsics.calibrate(KeyPoint(Point2::from(
-                    [img.width() as f64, img.height() as f64]
-                )));
+impl Extent {
 
-                for y in 0..rect_img.height() as u32 {
-                    for x in 0..rect_img.width() as u32 {
-                        // Get the normalised keypoint value for this position
-                        let normkp = image_xy_to_normkp(
-                            x, y,
-                            rect_img.width() as u32, rect_img.height() as u32,
-                            tl_normkp, br_normkp
-                        );
-
-                        // Reproject to find the keypoint coordinates
-                        let kp = intrinsics.uncalibrate(normkp);
-
-                        // Set the pixel value for the new image
-                        *rect_img.0.get_pixel_mut(x, y) = linterp_pixels(kp, img);
-                    }   
-                }
+    /// Find the largest extent containing only undistorted pixels of an image, and the smallest
+    /// containing all of them, in the frame of a camera with the given rotation from the
+    /// original.
+    fn of_undistorted(
+        model: &LensModel,
+        rotation: Option<&Matrix3<f64>>,
+        resolution: (u32, u32)
+    ) -> (Extent, Extent) {
+        let n = EXTENT_GRID_SIZE;
+        let mut inner = Extent {
+            min: Vector2::repeat(f64::NEG_INFINITY),
+            max: Vector2::repeat(f64::INFINITY)
+        };
+        let mut outer = Extent {
+            min: Vector2::repeat(f64::INFINITY),
+            max: Vector2::repeat(f64::NEG_INFINITY)
+        };
+
+        for j in 0..n {
+            for i in 0..n {
+                let point = KeyPoint(Point2::new(
+                    resolution.0 as f64 * i as f64 / (n - 1) as f64,
+                    resolution.1 as f64 * j as f64 / (n - 1) as f64
+                ));
+
+                let norm = model.calibrate(point).0.coords;
+                let norm = match rotation {
+                    Some(r) => {
+                        let p = r * Vector3::new(norm.x, norm.y, 1.0);
+
+                        Vector2::new(p.x / p.z, p.y / p.z)
+                    },
+                    None => norm
+                };
 
-                rect_img
+                outer.min = outer.min.inf(&norm);
+                outer.max = outer.max.sup(&norm);
+
+                // The inner extent is bounded by the points on each edge of the image
+                if i == 0 {
+                    inner.min.x = inner.min.x.max(norm.x);
+                }
+                if i == n - 1 {
+                    inner.max.x = inner.max.x.min(norm.x);
+                }
+                if j == 0 {
+                    inner.min.y = inner.min.y.max(norm.y);
+                }
+                if j == n - 1 {
+                    inner.max.y = inner.max.y.min(norm.y);
+                }
             }
         }
+
+        (inner, outer)
+    }
+
+    /// Get the extent covered by both extents.
+    fn intersection(&self, other: &Extent) -> Extent {
+        Extent {
+            min: self.min.sup(&other.min),
+            max: self.max.inf(&other.max)
+        }
+    }
+
+    /// Get the extent covering both extents.
+    fn union(&self, other: &Extent) -> Extent {
+        Extent {
+            min: self.min.inf(&other.min),
+            max: self.max.sup(&other.max)
+        }
+    }
+
+    /// Get the pinhole camera which projects this extent onto an image of the given resolution.
+    fn fit(&self, resolution: (u32, u32)) -> CameraIntrinsics {
+        let focals = Vector2::new(
+            resolution.0 as f64 / (self.max.x - self.min.x),
+            resolution.1 as f64 / (self.max.y - self.min.y)
+        );
+
+        CameraIntrinsics {
+            focals,
+            principal_point: Point2::new(-self.min.x * focals.x, -self.min.y * focals.y),
+            skew: 0.0
+        }
+    }
+}
+
+impl RectifOptions {
+
+    /// Get the resolution of the rectified images of an original image of the given resolution.
+    pub(crate) fn output_resolution(&self, resolution: (u32, u32)) -> (u32, u32) {
+        self.output_resolution.unwrap_or(resolution)
+    }
+
+    /// Get the intrinsics of rectified images from a single camera.
+    pub(crate) fn mono_intrinsics(
+        &self,
+        params: &RectifParams,
+        resolution: (u32, u32)
+    ) -> CameraIntrinsics {
+        let output_resolution = self.output_resolution(resolution);
+
+        match self.output_scale {
+            Some(OutputScale::Alpha(alpha)) => {
+                params.optimal_intrinsics(resolution, alpha, output_resolution)
+            },
+            Some(scale) => {
+                centred_intrinsics(output_resolution, scale.focal(output_resolution))
+            },
+            None => params.output_intrinsics_sized(resolution, output_resolution)
+        }
+    }
+
+    /// Build the remap table for the given camera and resolution with these options.
+    pub(crate) fn remap_table(&self, rectifier: &Rectifier, resolution: (u32, u32)) -> RemapTable {
+        let table = match *rectifier {
+            Rectifier::Stereo(ref params, side) => params.side_remap_table(side, resolution, self),
+            Rectifier::Mono(ref params) => {
+                // The interpolation is set before cropping as it decides which pixels are valid
+                let table = params.remap_table_into(
+                    resolution,
+                    self.output_resolution(resolution),
+                    &self.mono_intrinsics(params, resolution)
+                ).interpolation(self.interpolation);
+
+                if self.crop_to_roi {
+                    let roi = table.valid_roi();
+                    table.crop(roi)
+                } else {
+                    table
+                }
+            }
+        };
+
+        table
+            .interpolation(self.interpolation)
+            .border_mode(self.border_mode)
     }
 }
 
@@ -166,63 +1264,373 @@ impl RectifParams {
 // PRIVATE FUNCTIONS
 // -----------------------------------------------------------------------------------------------
 
-/// Converts an (x, y) integer pixel coordinate into a normalised keypoint coordinate.
-///
-/// This function conceptually places the integer coordinates at the centre of the pixel, not the
-/// top left.
-#[inline]
-fn image_xy_to_normkp(
-    x: u32, y: u32,
-    width: u32, height: u32,
-    tl_normkp: NormalizedKeyPoint,
-    br_normkp: NormalizedKeyPoint
-) -> NormalizedKeyPoint {
-    
-    // Get width and height in normalised coordinates
-    let nw = tl_normkp.0.x - br_normkp.0.x;
-    let nh = tl_normkp.0.y - br_normkp.0.y;
-
-    // Calculate pixel size in normalised coordinates
-    let nhx = nw / width as f64;
-    let nhy = nh / height as f64;
-
-    // Finally the normalised point is the top_left - how ever many normalised pixels away we are.
-    // This is negative because the sense is reversed. 0.5 is added to move the coordinate to the
-    // centre of the pixel, not the top-left.
-    NormalizedKeyPoint(Point2::from([
-        tl_normkp.0.x - nhx*(x as f64 + 0.5),
-        tl_normkp.0.y - nhy*(y as f64 + 0.5)
-    ]))
-}
-
-#[inline]
-fn linterp_pixels(kp: KeyPoint, img: &GrayFloatImage) -> image::Luma<f32>
-{
-    // If the keypoint is negative return black
-    if kp.0.x < 0.0 || kp.0.y < 0.0 {
-        return image::Luma::from([0.0])
-    }
-
-    // Get the keypoint in u32 coordas
-    let x = kp.0.x.floor() as usize;
-    let y = kp.0.y.floor() as usize;
-
-    // If the keypoints are outside the image return black
-    if x >= img.width() - 1 || y >= img.height() - 1 {
-        return image::Luma::from([0.0])
-    }
-
-    // Get the fractional parts of the coordinates, which will affect how much light from 
-    // neigbouring pixels is used.
-    let fract_x = kp.0.x.fract() as f32;
-    let fract_y = kp.0.y.fract() as f32;
-
-    // Interpolate the pixel value
-    let brightness = 0.5 * (
-        img.get(x, y) * (2.0 - fract_x - fract_y)
-        + (img.get(x + 1, y) * fract_x)
-        + (img.get(x, y + 1) * fract_y)
-    );
-    
-    image::Luma([brightness])
-}
\ No newline at end of file
+/// Linearly interpolate between two pinhole cameras, from `a` at 0 to `b` at 1.
+fn blend_intrinsics(a: &CameraIntrinsics, b: &CameraIntrinsics, t: f64) -> CameraIntrinsics {
+    CameraIntrinsics {
+        focals: a.focals * (1.0 - t) + b.focals * t,
+        principal_point: Point2::from(
+            a.principal_point.coords * (1.0 - t) + b.principal_point.coords * t
+        ),
+        skew: 0.0
+    }
+}
+
+/// A pinhole camera with equal focal lengths whose principal point is at the centre of an image
+/// of the given resolution.
+fn centred_intrinsics(resolution: (u32, u32), focal: f64) -> CameraIntrinsics {
+    CameraIntrinsics {
+        focals: Vector2::new(focal, focal),
+        principal_point: Point2::new(resolution.0 as f64 / 2.0, resolution.1 as f64 / 2.0),
+        skew: 0.0
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+// TESTS
+// -----------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Test that an undistorted camera centred on the image samples every pixel at its centre
+    #[test]
+    fn test_remap_table() {
+        let params = RectifParams {
+            focals: [50.0, 50.0],
+            principal_point: [16.0, 12.0],
+            skew: 0.0,
+            k1: None,
+            ..RectifParams::default()
+        };
+
+        let table = params.remap_table((32, 24));
+        assert_eq!(table.resolution(), (32, 24));
+
+        for (x, y) in [(0, 0), (5, 7), (31, 23)].iter().copied() {
+            let kp = table.source(x, y);
+
+            assert!((kp.0.x - (x as f64 + 0.5)).abs() < 1e-9);
+            assert!((kp.0.y - (y as f64 + 0.5)).abs() < 1e-9);
+        }
+    }
+
+    /// Test that the distortion model is read from a TOML file or inferred from its coefficients
+    #[test]
+    fn test_distortion_models() {
+        let path = std::env::temp_dir().join("cv_camstream_plumb_bob.toml");
+        std::fs::write(&path, "\
+            focals = [600.0, 610.0]\n\
+            principal_point = [320.0, 240.0]\n\
+            skew = 0.0\n\
+            k1 = -0.28\n\
+            k2 = 0.07\n\
+            p1 = 0.001\n\
+            p2 = -0.0005\n\
+            k3 = -0.01\n\
+        ").unwrap();
+
+        let params: RectifParams = serde_any::from_file(&path).unwrap();
+        assert_eq!(params.distortion_model(), DistortionModel::PlumbBob);
+        assert!(params.to_pinhole_intrisics_k1().is_err());
+
+        let model = params.to_brown_conrady().unwrap();
+        assert_eq!(model.radial, [-0.28, 0.07, -0.01, 0.0, 0.0, 0.0]);
+        assert_eq!(model.tangential, [0.001, -0.0005]);
+
+        // Point queries invert each other
+        let kp = KeyPoint(Point2::new(12.0, 470.0));
+        let round_trip = params.distort_point(params.undistort_point(kp));
+        assert!((round_trip.0 - kp.0).norm() < 1e-6);
+
+        // The table samples the distorted position of each pixel
+        let table = params.remap_table((640, 480));
+        let tl = params.undistort_point(KeyPoint(Point2::new(0.0, 0.0)));
+        let br = params.undistort_point(KeyPoint(Point2::new(640.0, 480.0)));
+        let centre = NormalizedKeyPoint(Point2::new(
+            tl.0.x + (br.0.x - tl.0.x) * 0.5 / 640.0,
+            tl.0.y + (br.0.y - tl.0.y) * 0.5 / 480.0
+        ));
+        assert!((table.source(0, 0).0 - params.distort_point(centre).0).norm() < 1e-9);
+
+        // A lone k1 keeps the division model unless the model is given
+        let k1_only = RectifParams {
+            focals: [600.0, 610.0],
+            principal_point: [320.0, 240.0],
+            k1: Some(-0.28),
+            ..RectifParams::default()
+        };
+        assert_eq!(k1_only.distortion_model(), DistortionModel::Division);
+        assert!(k1_only.to_pinhole_intrisics_k1().is_ok());
+
+        let k1_plumb_bob = RectifParams {
+            distortion_model: Some(DistortionModel::PlumbBob),
+            ..k1_only
+        };
+        assert_eq!(k1_plumb_bob.to_brown_conrady().unwrap().radial[0], -0.28);
+
+        let rational = RectifParams { k4: Some(0.1), ..k1_only };
+        assert_eq!(rational.distortion_model(), DistortionModel::RationalPolynomial);
+    }
+
+    /// Test that fisheye parameters load from a file and rectify into the requested output camera
+    #[test]
+    fn test_fisheye_output() {
+        let path = std::env::temp_dir().join("cv_camstream_fisheye.toml");
+        std::fs::write(&path, "\
+            focals = [285.0, 285.5]\n\
+            principal_point = [424.0, 400.0]\n\
+            skew = 0.0\n\
+            k1 = -0.006\n\
+            k2 = 0.04\n\
+            k3 = -0.037\n\
+            k4 = 0.006\n\
+            distortion_model = \"equidistant\"\n\
+        ").unwrap();
+
+        let params: RectifParams = serde_any::from_file(&path).unwrap();
+        assert_eq!(params.distortion_model(), DistortionModel::Equidistant);
+        assert_eq!(params.to_kannala_brandt().unwrap().coeffs, [-0.006, 0.04, -0.037, 0.006]);
+        assert!(params.to_brown_conrady().is_err());
+
+        // By default the output keeps the camera's intrinsics, so the principal point is fixed
+        let table = params.remap_table((848, 800));
+        assert!((table.source(424, 400).0 - Point2::new(424.5, 400.5)).norm() < 0.01);
+
+        // A 90 degree field of view puts the edge of the output at 45 degrees from the axis
+        let options = RectifOptions {
+            output_scale: Some(OutputScale::FieldOfView(90.0)),
+            ..RectifOptions::default()
+        };
+        let table = options.remap_table(&Rectifier::Mono(params), (848, 800));
+        let edge = params.undistort_point(table.source(0, 400));
+        assert!((edge.0.x + 1.0).abs() < 0.01);
+        assert!(edge.0.y.abs() < 0.01);
+    }
+
+    /// Test that alpha scaling keeps only valid pixels at 0 and every pixel at 1, and that the
+    /// output resolution sets the size of the rectified images
+    #[test]
+    fn test_alpha_scaling() {
+        let params = RectifParams {
+            focals: [300.0, 300.0],
+            principal_point: [320.0, 240.0],
+            k1: Some(-0.2),
+            k2: Some(0.05),
+            ..RectifParams::default()
+        };
+        let resolution = (640, 480);
+        let inside = |kp: KeyPoint| {
+            kp.0.x >= -1e-6 && kp.0.x <= 640.0 + 1e-6 && kp.0.y >= -1e-6 && kp.0.y <= 480.0 + 1e-6
+        };
+
+        // With alpha 0 every rectified pixel samples the original image
+        let options = RectifOptions {
+            output_scale: Some(OutputScale::Alpha(0.0)),
+            ..RectifOptions::default()
+        };
+        let table = options.remap_table(&Rectifier::Mono(params), resolution);
+        for &(x, y) in [(0, 0), (639, 0), (0, 479), (639, 479), (320, 0), (0, 240)].iter() {
+            assert!(inside(table.source(x, y)), "({}, {})", x, y);
+        }
+
+        // With alpha 1 every original pixel is visible in the rectified image, so its corners
+        // sample outside the original image
+        let intrinsics = params.optimal_intrinsics(resolution, 1.0, resolution);
+        let model = params.lens_model();
+        for &(x, y) in [(0.0, 0.0), (640.0, 0.0), (0.0, 480.0), (640.0, 480.0)].iter() {
+            let rect = intrinsics.uncalibrate(model.calibrate(KeyPoint(Point2::new(x, y))));
+            assert!(inside(rect), "({}, {})", x, y);
+        }
+        let options = RectifOptions {
+            output_scale: Some(OutputScale::Alpha(1.0)),
+            output_resolution: Some((320, 240)),
+            ..RectifOptions::default()
+        };
+        let table = options.remap_table(&Rectifier::Mono(params), resolution);
+        assert!(!inside(table.source(0, 0)));
+
+        // The table produces images of the output resolution from images of the source
+        assert_eq!(table.resolution(), (320, 240));
+        assert_eq!(table.source_resolution(), resolution);
+        let rect_img = table.remap(&GrayFloatImage::new(640, 480));
+        assert_eq!((rect_img.width(), rect_img.height()), (320, 240));
+    }
+
+    /// Test the valid pixel mask and rectangle of a table, and cropping the table to it
+    #[test]
+    fn test_valid_roi() {
+        // The largest rectangle in a hand drawn mask, where the tallest column is not part of it
+        let mask = [
+            0, 1, 0, 0, 0,
+            0, 1, 1, 1, 0,
+            1, 1, 1, 1, 0,
+            0, 1, 1, 1, 1
+        ];
+        let roi = Roi::largest((5, 4), |i| mask[i] == 1);
+        assert_eq!(roi, Roi { x: 1, y: 1, width: 3, height: 3 });
+        assert_eq!(roi.intersection(&Roi { x: 3, y: 0, width: 4, height: 2 }),
+            Roi { x: 3, y: 1, width: 1, height: 1 });
+
+        // A barrel distorted image scaled to keep every pixel has invalid areas at the middle of
+        // each edge
+        let params = RectifParams {
+            focals: [300.0, 300.0],
+            principal_point: [320.0, 240.0],
+            k1: Some(-0.2),
+            ..RectifParams::default()
+        };
+        let resolution = (640, 480);
+        let table = params.remap_table_to(
+            resolution,
+            &params.optimal_intrinsics(resolution, 1.0, resolution)
+        );
+        let mask = table.valid_mask();
+        assert_eq!(mask.get(0, 240), 0.0);
+        assert_eq!(mask.get(320, 0), 0.0);
+        assert_eq!(mask.get(320, 240), 1.0);
+
+        let roi = table.valid_roi();
+        assert!(roi.width < 640 && roi.height < 480);
+        for &(x, y) in [(0, 0), (roi.width - 1, 0), (0, roi.height - 1)].iter() {
+            assert!(table.is_valid(roi.x + x, roi.y + y));
+        }
+        assert_eq!(table.common_roi(&table), roi);
+
+        // Cropping keeps the same source positions and shifts the principal point
+        let cropped = table.clone().crop(roi);
+        assert_eq!(cropped.resolution(), (roi.width, roi.height));
+        assert_eq!(cropped.source(0, 0), table.source(roi.x, roi.y));
+        assert_eq!(
+            cropped.intrinsics().principal_point.x,
+            table.intrinsics().principal_point.x - roi.x as f64
+        );
+        assert_eq!(cropped.valid_roi().area(), cropped.resolution().0 as u64 * roi.height as u64);
+    }
+
+    /// Test that images cropped to the valid rectangle take no values from the border mode, for
+    /// each interpolation
+    #[test]
+    fn test_crop_without_border() {
+        let params = RectifParams {
+            focals: [75.0, 75.0],
+            principal_point: [80.0, 60.0],
+            k1: Some(-0.2),
+            ..RectifParams::default()
+        };
+        let resolution = (160, 120);
+        let options = RectifOptions {
+            output_scale: Some(OutputScale::Alpha(1.0)),
+            crop_to_roi: true,
+            ..RectifOptions::default()
+        };
+        let mut white = GrayFloatImage::new(160, 120);
+        white.mut_array2().fill(1.0);
+
+        let mut widths = Vec::new();
+        for &interpolation in [
+            Interpolation::Nearest,
+            Interpolation::Bilinear,
+            Interpolation::Bicubic,
+            Interpolation::Lanczos
+        ].iter() {
+            let options = RectifOptions { interpolation, ..options };
+            let table = options.remap_table(&Rectifier::Mono(params), resolution);
+
+            let rect_img = table.remap(&white);
+            for y in 0..rect_img.height() {
+                for x in 0..rect_img.width() {
+                    assert!((rect_img.get(x, y) - 1.0).abs() < 1e-5, "{:?}", interpolation);
+                }
+            }
+
+            widths.push(table.resolution().0);
+        }
+
+        // Wider interpolation kernels leave a smaller valid rectangle
+        assert!(widths.windows(2).all(|w| w[0] >= w[1]) && widths[0] > widths[3]);
+    }
+
+    /// Test that stereo rectification puts corresponding points on the same row
+    #[test]
+    fn test_stereo_rectification() {
+        let camera = |focals, principal_point| RectifParams {
+            focals,
+            principal_point,
+            k1: Some(-0.2),
+            k2: Some(0.05),
+            p1: Some(0.001),
+            ..RectifParams::default()
+        };
+
+        let rotation = Rotation3::from_euler_angles(0.02, -0.05, 0.01).into_inner();
+        let params = StereoRectifParams {
+            left: camera([600.0, 605.0], [318.0, 243.0]),
+            right: camera([610.0, 612.0], [325.0, 236.0]),
+            rotation: Some([
+                [rotation[(0, 0)], rotation[(0, 1)], rotation[(0, 2)]],
+                [rotation[(1, 0)], rotation[(1, 1)], rotation[(1, 2)]],
+                [rotation[(2, 0)], rotation[(2, 1)], rotation[(2, 2)]]
+            ]),
+            translation: Some([-0.12, 0.004, 0.002])
+        };
+
+        let rect = params.rectification((640, 480)).unwrap();
+        assert!((rect.translation.x + 0.1201).abs() < 1e-3);
+        assert!(rect.translation.y.abs() < 1e-12 && rect.translation.z.abs() < 1e-12);
+
+        // Project points in front of the cameras into both original images
+        let points = [
+            Vector3::new(0.0, 0.0, 2.0),
+            Vector3::new(-0.5, 0.3, 3.0),
+            Vector3::new(0.4, -0.35, 1.5)
+        ];
+
+        let translation = Vector3::new(-0.12, 0.004, 0.002);
+        for point in points.iter() {
+            let right = rotation * point + translation;
+            let project = |p: &Vector3<f64>| NormalizedKeyPoint(Point2::new(p.x / p.z, p.y / p.z));
+
+            let left_kp = params.left.distort_point(project(point));
+            let right_kp = params.right.distort_point(project(&right));
+
+            let left_rect = rect.rectify_point(&params, StereoSide::Left, left_kp);
+            let right_rect = rect.rectify_point(&params, StereoSide::Right, right_kp);
+
+            assert!((left_rect.0.y - right_rect.0.y).abs() < 1e-6, "{:?}", point);
+            assert!(left_rect.0.x > right_rect.0.x);
+        }
+
+        // The tables sample the original position of each rectified pixel
+        let (left_table, _) = params.remap_tables((640, 480));
+        let source = left_table.source(100, 200);
+        let back = rect.rectify_point(&params, StereoSide::Left, source);
+        assert!((back.0 - Point2::new(100.5, 200.5)).norm() < 1e-6);
+
+        // Tables cropped together match those built for each side, both cropped to the common
+        // ROI with the principal point moved by it
+        let options = RectifOptions { crop_to_roi: true, ..RectifOptions::default() };
+        let roi = params.common_roi((640, 480));
+        let (left_cropped, right_cropped) = params.remap_tables_with((640, 480), &options);
+        let sides = [(StereoSide::Left, &left_cropped), (StereoSide::Right, &right_cropped)];
+        for (side, table) in sides.iter() {
+            let single = params.side_remap_table(*side, (640, 480), &options);
+            assert_eq!(table.resolution(), (roi.width, roi.height));
+            assert_eq!(table.source(5, 7), single.source(5, 7));
+            assert_eq!(table.intrinsics(), single.intrinsics());
+        }
+        assert_eq!(
+            left_cropped.intrinsics().principal_point,
+            rect.intrinsics.principal_point - Vector2::new(roi.x as f64, roi.y as f64)
+        );
+
+        // Without extrinsics each camera is only undistorted
+        let unrotated = StereoRectifParams { rotation: None, translation: None, ..params };
+        assert!(unrotated.rectification((640, 480)).is_none());
+        assert_eq!(
+            unrotated.remap_tables((640, 480)).1.source(10, 10),
+            params.right.remap_table((640, 480)).source(10, 10)
+        );
+    }
+}
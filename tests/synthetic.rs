@@ -0,0 +1,43 @@
+//! # Synthetic Camera Test
+//!
+//! Runs the full stereo pipeline, including rectification, on synthetic cameras so that it can be
+//! tested without any capture hardware.
+
+use cv_camstream::prelude::*;
+use cv_camstream::TestPattern;
+
+const NUM_FRAMES: u64 = 10;
+
+// -----------------------------------------------------------------------------------------------
+// MAIN
+// -----------------------------------------------------------------------------------------------
+
+#[test]
+fn synthetic_stereo() -> Result<(), Box<dyn std::error::Error>> {
+
+    let mut camstream = CamStreamBuilder::new()
+        .stereo()
+        .synthetic(TestPattern::Checkerboard(40), 16)
+        .rectif_params_from_file("tests/stereo_bench_drh_01.toml")?
+        .interval((1, 30))
+        .resolution((640, 480))
+        .build()?;
+
+    let mut last_timestamp = None;
+
+    for _ in 0..NUM_FRAMES {
+        let frame = camstream.capture()?;
+
+        assert_eq!((frame.width(), frame.height()), (640, 480));
+        assert_eq!(frame.left_timestamp, frame.right_timestamp);
+
+        if let Some(t) = last_timestamp {
+            assert!(frame.left_timestamp > t);
+        }
+        last_timestamp = Some(frame.left_timestamp);
+    }
+
+    camstream.stop()?;
+
+    Ok(())
+}